@@ -0,0 +1,236 @@
+//! Diagnostics reported by parsers, transforms and generators.
+
+use std::fmt::{self, Write};
+
+use rhi_concord_core::SourceLocation;
+
+/// A `$ref` names a schema that does not exist.
+pub const UNRESOLVED_REF: &str = "C0001";
+/// A `$ref` points outside `#/components/schemas`.
+pub const UNSUPPORTED_REF: &str = "C0002";
+/// A schema construct has no IR equivalent and was approximated.
+pub const UNSUPPORTED_SCHEMA: &str = "C0003";
+/// A parameter uses `content` instead of `schema`.
+pub const PARAMETER_CONTENT: &str = "C0004";
+/// A referenced parameter, request body, response or path item was skipped.
+pub const SKIPPED_REF: &str = "C0005";
+/// A request or response body has no `application/json` content.
+pub const NON_JSON_CONTENT: &str = "C0006";
+/// `allOf` members declare a property with different types.
+pub const CONFLICTING_MEMBERS: &str = "C0007";
+/// An inline object has no name to render it by.
+pub const ANONYMOUS_STRUCT: &str = "C0008";
+/// An intersection could not be merged and has no rendering.
+pub const UNMERGED_INTERSECTION: &str = "C0009";
+/// A generated item's confidence is below the review threshold.
+pub const LOW_CONFIDENCE: &str = "C0010";
+/// Two names map to the same target identifier; one was renamed.
+pub const RENAMED: &str = "C0011";
+/// A C declaration or construct has no IR equivalent.
+pub const UNSUPPORTED_DECLARATION: &str = "C0012";
+/// An `#include`, `#if` or `#error` could not be honoured.
+pub const PREPROCESSOR: &str = "C0013";
+/// A C declaration could not be parsed and was skipped.
+pub const SYNTAX_ERROR: &str = "C0014";
+/// A C type name was never declared.
+pub const UNKNOWN_TYPE: &str = "C0015";
+/// A type has no exact equivalent in the target FFI and was approximated.
+pub const UNSUPPORTED_FFI_TYPE: &str = "C0016";
+/// A rule given on the command line matched nothing.
+pub const UNMATCHED_RULE: &str = "C0017";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<SourceLocation>,
+}
+
+impl Diagnostic {
+    /// Render in the style of rustc, quoting the source line when available.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        let Some(location) = &self.location else {
+            return out;
+        };
+
+        let position = match (location.line, location.column) {
+            (Some(line), Some(column)) => format!(":{line}:{column}"),
+            _ => String::new(),
+        };
+        let pointer = location
+            .pointer
+            .as_deref()
+            .map_or_else(String::new, |p| format!(" ({p})"));
+
+        let snippet = location
+            .line
+            .zip(location.column)
+            .and_then(|(line, column)| {
+                let text = source?.lines().nth(line.checked_sub(1)? as usize)?;
+                Some((line, column, text))
+            });
+        let Some((line, column, text)) = snippet else {
+            let _ = writeln!(out, "  --> {}{position}{pointer}", location.file);
+            return out;
+        };
+
+        let gutter = " ".repeat(line.to_string().len());
+        let start = column.saturating_sub(1) as usize;
+        let width = text
+            .chars()
+            .skip(start)
+            .take_while(|c| !c.is_whitespace() && *c != ':')
+            .count()
+            .max(1);
+        let _ = writeln!(out, "{gutter}--> {}{position}{pointer}", location.file);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line} | {text}");
+        let _ = writeln!(out, "{gutter} | {}{}", " ".repeat(start), "^".repeat(width));
+        out
+    }
+}
+
+/// Diagnostics collected over a run.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn error(
+        &mut self,
+        code: &'static str,
+        location: Option<SourceLocation>,
+        message: impl Into<String>,
+    ) {
+        self.report(code, Severity::Error, location, message);
+    }
+
+    pub fn note(
+        &mut self,
+        code: &'static str,
+        location: Option<SourceLocation>,
+        message: impl Into<String>,
+    ) {
+        self.report(code, Severity::Note, location, message);
+    }
+
+    pub fn warning(
+        &mut self,
+        code: &'static str,
+        location: Option<SourceLocation>,
+        message: impl Into<String>,
+    ) {
+        self.report(code, Severity::Warning, location, message);
+    }
+
+    fn report(
+        &mut self,
+        code: &'static str,
+        severity: Severity,
+        location: Option<SourceLocation>,
+        message: impl Into<String>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            code,
+            severity,
+            message: message.into(),
+            location,
+        });
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(location: Option<SourceLocation>) -> Diagnostic {
+        Diagnostic {
+            code: UNRESOLVED_REF,
+            severity: Severity::Error,
+            message: "schema `Pet` not found".to_string(),
+            location,
+        }
+    }
+
+    fn location(line: Option<u32>, column: Option<u32>) -> SourceLocation {
+        SourceLocation {
+            file: "spec.yaml".to_string(),
+            pointer: Some("#/paths/~1pets".to_string()),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn render_quotes_the_source_line() {
+        let source = "paths:\n  /pets:\n    $ref: '#/components/schemas/Pet'\n";
+        let rendered = diagnostic(Some(location(Some(3), Some(5)))).render(Some(source));
+        assert_eq!(
+            rendered,
+            "error[C0001]: schema `Pet` not found\n \
+             --> spec.yaml:3:5 (#/paths/~1pets)\n  \
+             |\n\
+             3 |     $ref: '#/components/schemas/Pet'\n  \
+             |     ^^^^\n"
+        );
+    }
+
+    #[test]
+    fn render_without_a_position_names_the_file() {
+        let rendered = diagnostic(Some(location(None, None))).render(None);
+        assert_eq!(
+            rendered,
+            "error[C0001]: schema `Pet` not found\n  --> spec.yaml (#/paths/~1pets)\n"
+        );
+        assert_eq!(
+            diagnostic(None).render(None),
+            "error[C0001]: schema `Pet` not found\n"
+        );
+    }
+
+    #[test]
+    fn counts_by_severity() {
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.warning(RENAMED, None, "a");
+        diagnostics.note(LOW_CONFIDENCE, None, "b");
+        diagnostics.warning(RENAMED, None, "c");
+        assert_eq!(diagnostics.count(Severity::Warning), 2);
+        assert_eq!(diagnostics.count(Severity::Note), 1);
+        assert_eq!(diagnostics.count(Severity::Error), 0);
+    }
+}
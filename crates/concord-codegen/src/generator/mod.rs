@@ -1 +1,2 @@
 pub mod rust;
+pub mod rust_ffi;
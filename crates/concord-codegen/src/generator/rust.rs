@@ -1,60 +1,438 @@
 //! Rust code generator.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Write;
 use std::fs;
 use std::path::Path;
 
-use anyhow::{Context, Result};
-use rhi_concord_core::{Field, Function, Item, Module, Type, TypeKind};
+use anyhow::{Context as _, Result};
+use rhi_concord_core::naming::{Case, Names, RUST};
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Field, Function, Item, Metadata, Module, Type, TypeKind, Value,
+    Variant,
+};
+
+use crate::diagnostics::{self, Diagnostics};
+use crate::transform::{self, Style, confidence};
+
+/// Rust naming conventions per kind of item.
+pub(super) const STYLE: Style = Style {
+    target: RUST,
+    types: Case::Pascal,
+    functions: Case::Snake,
+    fields: Case::Snake,
+    params: Case::Snake,
+    variants: Case::Pascal,
+};
+
+/// Options controlling Rust generation.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Emit a test module round-tripping every example through its type.
+    pub example_tests: bool,
+    /// How `read_only` and `write_only` fields are rendered.
+    pub field_access: FieldAccess,
+    /// Items and fields generated with lower confidence are marked for review.
+    pub review_threshold: f32,
+}
+
+/// Rendering of `read_only` (response-only) and `write_only` (request-only) fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldAccess {
+    /// Render fields as declared, one shape for both directions.
+    #[default]
+    Preserve,
+    /// Make such fields optional and skip them when unset.
+    Optional,
+    /// Emit separate response (`Foo`) and request (`FooRequest`) shapes.
+    Split,
+}
 
 /// Generate Rust code from IR.
-pub fn generate(module: &Module, output: &Path) -> Result<()> {
-    fs::create_dir_all(output)?;
+///
+/// The root module is written to `mod.rs`; each submodule gets its own file
+/// (or directory, if it has submodules of its own).
+pub fn generate(
+    module: &Module,
+    output: &Path,
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> Result<()> {
+    let mut module = apply_extensions(module, diagnostics);
+    transform::name_unions(&mut module);
+    if options.field_access == FieldAccess::Split {
+        module = split_access(&module);
+    }
+    transform::resolve_collisions(&mut module, &STYLE, diagnostics);
+    lint_module(&mut module, diagnostics);
+    transform::propagate_confidence(&mut module);
+    review_module(&module, options.review_threshold, diagnostics);
+    let module = &module;
+
+    let mut ctx = Context {
+        paths: HashMap::new(),
+        types: HashMap::new(),
+        deprecated: has_deprecated(module),
+        options,
+    };
+    ctx.paths.insert("ApiError".to_string(), Vec::new());
+    collect_symbols(module, &mut Vec::new(), &mut ctx);
+
+    generate_module(module, &[], &output.join("mod.rs"), &ctx)
+}
+
+/// Report types the generator can only approximate, lowering their confidence.
+fn lint_module(module: &mut Module, diagnostics: &mut Diagnostics) {
+    for item in &mut module.items {
+        match item {
+            Item::Type(typ) => lint_definition(typ, diagnostics),
+            Item::Function(func) => {
+                for arg in &mut func.args {
+                    lint_type(&mut arg.typ, diagnostics);
+                }
+                lint_type(&mut func.ret, diagnostics);
+            }
+            Item::Const { .. } => {}
+        }
+    }
+    for sub in &mut module.submodules {
+        lint_module(sub, diagnostics);
+    }
+}
+
+/// Lint the children of a named definition.
+fn lint_definition(typ: &mut Type, diagnostics: &mut Diagnostics) {
+    match &mut typ.kind {
+        TypeKind::Struct { fields } => {
+            for field in fields {
+                lint_type(&mut field.typ, diagnostics);
+            }
+        }
+        TypeKind::Enum { variants } => {
+            for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                lint_type(&mut field.typ, diagnostics);
+            }
+        }
+        TypeKind::Intersection { .. } => {
+            diagnostics.warning(
+                diagnostics::UNMERGED_INTERSECTION,
+                typ.metadata.source.clone(),
+                format!(
+                    "intersection `{}` could not be merged and is not generated",
+                    typ.name.as_deref().unwrap_or_default()
+                ),
+            );
+            confidence::lower(&mut typ.metadata, confidence::PLACEHOLDER);
+        }
+        TypeKind::Union { members } => {
+            for member in members {
+                lint_type(member, diagnostics);
+            }
+        }
+        TypeKind::Ref { .. } | TypeKind::Function { .. } => lint_type(typ, diagnostics),
+    }
+}
+
+/// Lint a type in field, parameter or return position.
+fn lint_type(typ: &mut Type, diagnostics: &mut Diagnostics) {
+    match &mut typ.kind {
+        TypeKind::Struct { .. } if typ.name.is_none() => {
+            diagnostics.warning(
+                diagnostics::ANONYMOUS_STRUCT,
+                typ.metadata.source.clone(),
+                "inline object has no name and is rendered as `AnonymousStruct`",
+            );
+            confidence::lower(&mut typ.metadata, confidence::PLACEHOLDER);
+        }
+        TypeKind::Intersection { .. } if typ.name.is_none() => {
+            diagnostics.warning(
+                diagnostics::UNMERGED_INTERSECTION,
+                typ.metadata.source.clone(),
+                "intersection could not be merged; only its first member is used",
+            );
+            confidence::lower(&mut typ.metadata, confidence::FIRST_MEMBER);
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                lint_type(&mut param.typ, diagnostics);
+            }
+            lint_type(ret, diagnostics);
+        }
+        TypeKind::Union { members } if typ.name.is_none() => {
+            for member in members {
+                lint_type(member, diagnostics);
+            }
+        }
+        _ => {}
+    }
+    for arg in &mut typ.args {
+        lint_type(arg, diagnostics);
+    }
+}
+
+/// Note every type and function whose confidence is below `threshold`.
+pub(super) fn review_module(module: &Module, threshold: f32, diagnostics: &mut Diagnostics) {
+    for item in &module.items {
+        let (kind, name, metadata) = match item {
+            Item::Type(Type {
+                name: Some(name),
+                metadata,
+                ..
+            }) => ("type", name, metadata),
+            Item::Function(func) => ("function", &func.name, &func.metadata),
+            Item::Type(_) | Item::Const { .. } => continue,
+        };
+        if let Some(note) = review_note(metadata, threshold) {
+            diagnostics.note(
+                diagnostics::LOW_CONFIDENCE,
+                metadata.source.clone(),
+                format!("{kind} `{name}` needs review: {note}"),
+            );
+        }
+    }
+    for sub in &module.submodules {
+        review_module(sub, threshold, diagnostics);
+    }
+}
+
+/// Why an item below the review threshold was flagged.
+pub(super) fn review_note(metadata: &Metadata, threshold: f32) -> Option<String> {
+    let confidence = metadata.confidence.filter(|c| *c < threshold)?;
+    let mut note = format!("generated with confidence {confidence:.2}");
+    if let Some(Value::String(pointer)) = metadata.extra.get("confidence_source") {
+        let _ = write!(note, ", lowest at `{pointer}`");
+    }
+    Some(note)
+}
+
+/// Weakest confidence within a type, not following references.
+fn type_confidence(typ: &Type) -> Option<f32> {
+    let nested: Vec<&Type> = match &typ.kind {
+        TypeKind::Struct { .. } | TypeKind::Enum { .. } | TypeKind::Ref { .. } => Vec::new(),
+        TypeKind::Function { params, ret } => {
+            params.iter().map(|p| &p.typ).chain([&**ret]).collect()
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            members.iter().collect()
+        }
+    };
+    nested
+        .into_iter()
+        .chain(&typ.args)
+        .filter_map(type_confidence)
+        .chain(typ.metadata.confidence)
+        .reduce(f32::min)
+}
+
+/// Options plus the names visible across the generated module tree.
+struct Context<'a> {
+    /// Module path of every named type and constant.
+    paths: HashMap<String, Vec<String>>,
+    /// Definitions of named types.
+    types: HashMap<&'a str, &'a Type>,
+    /// Whether anything in the tree is deprecated.
+    deprecated: bool,
+    options: &'a Options,
+}
+
+/// Record the module path of every named type and constant.
+fn collect_symbols<'a>(module: &'a Module, path: &mut Vec<String>, ctx: &mut Context<'a>) {
+    for item in &module.items {
+        if let Item::Type(_) | Item::Const { .. } = item
+            && let Some(name) = item.name()
+        {
+            ctx.paths.insert(name.to_string(), path.clone());
+        }
+        if let Item::Type(typ) = item
+            && let Some(name) = &typ.name
+        {
+            ctx.types.insert(name, typ);
+        }
+    }
+    for sub in &module.submodules {
+        path.push(snake_ident(&sub.name));
+        collect_symbols(sub, path, ctx);
+        path.pop();
+    }
+}
+
+/// Whether any item, field or argument in the tree is marked `deprecated`.
+fn has_deprecated(module: &Module) -> bool {
+    let deprecated = |annotations: &[Annotation]| has_annotation(annotations, "deprecated");
+    module.items.iter().any(|item| match item {
+        Item::Type(typ) => {
+            deprecated(&typ.annotations)
+                || matches!(&typ.kind, TypeKind::Struct { fields } if fields.iter().any(|f| deprecated(&f.annotations)))
+        }
+        Item::Function(func) => {
+            deprecated(&func.annotations) || func.args.iter().any(|a| deprecated(&a.annotations))
+        }
+        Item::Const { .. } => false,
+    }) || module.submodules.iter().any(has_deprecated)
+}
+
+fn generate_module(module: &Module, path: &[String], file: &Path, ctx: &Context) -> Result<()> {
+    let mut body = String::new();
+
+    // Preamble
+    if path.is_empty() {
+        writeln!(body, "/// API error type.")?;
+        writeln!(
+            body,
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
+        )?;
+        writeln!(body, "pub struct ApiError {{")?;
+        writeln!(body, "    pub message: String,")?;
+        writeln!(body, "    pub code: Option<String>,")?;
+        writeln!(body, "}}")?;
+        writeln!(body)?;
+    }
+
+    // Generate items; inbound functions are grouped into handlers below
+    let mut handlers: Vec<(&str, Vec<&Function>)> = Vec::new();
+    for item in &module.items {
+        match item {
+            Item::Type(typ) => generate_type(&mut body, typ, ctx)?,
+            Item::Function(func) if has_annotation(&func.annotations, "inbound") => {
+                let set = string_annotation(&func.annotations, "webhook_set").unwrap_or("Webhooks");
+                match handlers.iter_mut().find(|(s, _)| *s == set) {
+                    Some((_, funcs)) => funcs.push(func),
+                    None => handlers.push((set, vec![func])),
+                }
+            }
+            Item::Function(func) => generate_function(&mut body, func, ctx)?,
+            Item::Const {
+                name, typ, value, ..
+            } => {
+                let (typ_str, val_str) = const_to_rust(typ, value);
+                writeln!(body, "pub const {name}: {typ_str} = {val_str};")?;
+                writeln!(body)?;
+            }
+        }
+    }
+
+    for (set, funcs) in &handlers {
+        generate_handler(&mut body, set, funcs)?;
+    }
+
+    if ctx.options.example_tests {
+        generate_example_tests(&mut body, module, ctx)?;
+    }
 
     let mut code = String::new();
 
     // Module doc comment
     if let Some(docs) = &module.metadata.docs {
-        writeln!(code, "//! {docs}")?;
+        for line in docs.lines() {
+            writeln!(code, "//! {line}")?;
+        }
         writeln!(code)?;
     }
 
-    // Preamble
-    writeln!(code, "use serde::{{Deserialize, Serialize}};")?;
-    writeln!(code)?;
-    writeln!(code, "/// API error type.")?;
-    writeln!(
-        code,
-        "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
-    )?;
-    writeln!(code, "pub struct ApiError {{")?;
-    writeln!(code, "    pub message: String,")?;
-    writeln!(code, "    pub code: Option<String>,")?;
-    writeln!(code, "}}")?;
-    writeln!(code)?;
+    // Generated code may use deprecated items itself; callers still get warned
+    if ctx.deprecated {
+        writeln!(code, "#![allow(deprecated)]")?;
+        writeln!(code)?;
+    }
+
+    if !module.submodules.is_empty() {
+        for sub in &module.submodules {
+            writeln!(code, "pub mod {};", snake_ident(&sub.name))?;
+        }
+        writeln!(code)?;
+    }
+
+    // Imports
+    if body.contains("Serialize") {
+        writeln!(code, "use serde::{{Deserialize, Serialize}};")?;
+    }
+    for line in imports(module, path, &ctx.paths) {
+        writeln!(code, "{line}")?;
+    }
+    if !code.is_empty() && !code.ends_with("\n\n") {
+        writeln!(code)?;
+    }
+
+    code.push_str(&body);
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, &code).with_context(|| format!("Failed to write {}", file.display()))?;
+
+    let dir = file.parent().unwrap_or(Path::new("."));
+    for sub in &module.submodules {
+        let mut sub_path = path.to_vec();
+        let sub_name = snake_ident(&sub.name);
+        // `mod r#type;` is still read from `type.rs`
+        let file_stem = sub_name.trim_start_matches("r#");
+        let sub_file = if sub.submodules.is_empty() {
+            dir.join(format!("{file_stem}.rs"))
+        } else {
+            dir.join(file_stem).join("mod.rs")
+        };
+        sub_path.push(sub_name);
+        generate_module(sub, &sub_path, &sub_file, ctx)?;
+    }
 
-    // Generate items
+    Ok(())
+}
+
+/// Build `use` lines for symbols referenced from `module` but defined elsewhere.
+pub(super) fn imports(
+    module: &Module,
+    path: &[String],
+    paths: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut referenced = BTreeSet::new();
     for item in &module.items {
-        match item {
-            Item::Type(typ) => generate_type(&mut code, typ)?,
-            Item::Function(func) => generate_function(&mut code, func)?,
-            Item::Const { name, typ, value } => {
-                let typ_str = type_to_rust(typ);
-                let val_str = format!("{value:?}");
-                writeln!(code, "pub const {name}: {typ_str} = {val_str};")?;
-                writeln!(code)?;
+        item.visit_refs(&mut |r| {
+            referenced.insert(r.to_string());
+        });
+        // Names the generator emits without an IR reference
+        if let Item::Type(typ) = item
+            && has_annotation(&typ.annotations, "servers")
+        {
+            referenced.insert("ApiError".to_string());
+            if let TypeKind::Enum { variants } = &typ.kind {
+                for variant in variants {
+                    if let Some(c) = string_annotation(&variant.annotations, "server_url_const") {
+                        referenced.insert(c.to_string());
+                    }
+                }
             }
         }
     }
 
-    let output_file = output.join("mod.rs");
-    fs::write(&output_file, &code)
-        .with_context(|| format!("Failed to write {}", output_file.display()))?;
+    let mut by_module: BTreeMap<&[String], Vec<String>> = BTreeMap::new();
+    for name in referenced {
+        if let Some(target) = paths.get(&name)
+            && target.as_slice() != path
+        {
+            by_module.entry(target.as_slice()).or_default().push(name);
+        }
+    }
 
-    Ok(())
+    by_module
+        .into_iter()
+        .map(|(target, names)| {
+            let mut segments: Vec<&str> = if path.is_empty() {
+                vec!["self"]
+            } else {
+                vec!["super"; path.len()]
+            };
+            segments.extend(target.iter().map(String::as_str));
+            let prefix = segments.join("::");
+            if let [name] = names.as_slice() {
+                format!("use {prefix}::{name};")
+            } else {
+                format!("use {prefix}::{{{}}};", names.join(", "))
+            }
+        })
+        .collect()
 }
 
-fn generate_type(out: &mut String, typ: &Type) -> Result<()> {
+fn generate_type(out: &mut String, typ: &Type, ctx: &Context) -> Result<()> {
     let Some(name) = &typ.name else {
         return Ok(());
     };
@@ -66,29 +444,60 @@ fn generate_type(out: &mut String, typ: &Type) -> Result<()> {
         }
     }
 
+    if let Some(conflicts) = list_annotation(&typ.annotations, "conflicts") {
+        let names: Vec<_> = conflicts
+            .iter()
+            .filter_map(|c| match c {
+                AnnotationValue::String(name) => Some(format!("`{name}`")),
+                _ => None,
+            })
+            .collect();
+        if typ.metadata.docs.is_some() {
+            writeln!(out, "///")?;
+        }
+        writeln!(
+            out,
+            "/// Conflicting member types for {}; the first declaration is used.",
+            names.join(", ")
+        )?;
+    }
+
+    if let Some(note) = review_note(&typ.metadata, ctx.options.review_threshold) {
+        if typ.metadata.docs.is_some() || has_annotation(&typ.annotations, "conflicts") {
+            writeln!(out, "///")?;
+        }
+        writeln!(out, "/// Review: {note}.")?;
+    }
+
+    if let Some(attr) = deprecated_attr(&typ.annotations)
+        && !matches!(typ.kind, TypeKind::Intersection { .. })
+    {
+        writeln!(out, "{attr}")?;
+    }
+
+    // The schema maps onto an existing Rust type
+    if let Some(Value::String(rust_type)) = typ.metadata.extra.get("x-rust-type") {
+        writeln!(out, "pub type {name} = {rust_type};")?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
     match &typ.kind {
         TypeKind::Struct { fields } => {
-            generate_struct(out, name, &typ.params, fields)?;
+            let type_default = typ.metadata.extra.contains_key("default");
+            generate_struct(out, name, &typ.params, fields, !type_default, ctx)?;
+        }
+        TypeKind::Enum { variants } if has_annotation(&typ.annotations, "flags") => {
+            generate_flag_set(out, name, variants)?;
+        }
+        TypeKind::Enum { variants } if has_annotation(&typ.annotations, "servers") => {
+            generate_server_enum(out, name, variants)?;
+            return Ok(());
         }
         TypeKind::Enum { variants } => {
             generate_enum(out, name, &typ.params, variants)?;
         }
-        TypeKind::Union { members } => {
-            // Generate as enum with variants for each member
-            writeln!(
-                out,
-                "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
-            )?;
-            writeln!(out, "#[serde(untagged)]")?;
-            writeln!(out, "pub enum {name} {{")?;
-            for (i, member) in members.iter().enumerate() {
-                let variant_name = member.name.clone().unwrap_or_else(|| format!("Variant{i}"));
-                let typ_str = type_to_rust(member);
-                writeln!(out, "    {variant_name}({typ_str}),")?;
-            }
-            writeln!(out, "}}")?;
-            writeln!(out)?;
-        }
+        TypeKind::Union { members } => generate_union(out, name, members)?,
         TypeKind::Ref { name: target } => {
             // Type alias
             let args_str = if typ.args.is_empty() {
@@ -119,14 +528,35 @@ fn generate_type(out: &mut String, typ: &Type) -> Result<()> {
         }
     }
 
+    // Type-level default
+    if let Some(default) = typ.metadata.extra.get("default")
+        && typ.params.is_empty()
+        && matches!(typ.kind, TypeKind::Struct { .. } | TypeKind::Enum { .. })
+    {
+        let expr = default_to_rust(default, &Type::reference(name.as_str()), ctx);
+        writeln!(out, "impl Default for {name} {{")?;
+        writeln!(out, "    fn default() -> Self {{")?;
+        writeln!(out, "        {expr}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+    }
+
     Ok(())
 }
 
+/// Generate a struct.
+///
+/// Fields with a default deserialize through a `default_<field>` helper when
+/// omitted. If every field has a default or is optional, a `Default` impl is
+/// emitted too (unless `derive_default` is false because the type has its own).
 fn generate_struct(
     out: &mut String,
     name: &str,
     params: &[rhi_concord_core::TypeParam],
     fields: &[Field],
+    derive_default: bool,
+    ctx: &Context,
 ) -> Result<()> {
     writeln!(
         out,
@@ -140,6 +570,9 @@ fn generate_struct(
         format!("<{}>", names.join(", "))
     };
 
+    // (field name, helper name, type, default expression)
+    let mut defaults = Vec::new();
+
     if fields.is_empty() {
         writeln!(out, "pub struct {name}{generics};")?;
     } else if fields.iter().all(|f| f.name.is_none()) {
@@ -154,30 +587,129 @@ fn generate_struct(
         // Named struct
         writeln!(out, "pub struct {name}{generics} {{")?;
         for field in fields {
-            let field_name = field.name.as_deref().unwrap_or("_");
-            let field_name = to_snake_case(field_name);
-            let typ_str = type_to_rust(&field.typ);
+            defaults.extend(generate_named_field(out, name, field, ctx)?);
+        }
+        writeln!(out, "}}")?;
+    }
+    writeln!(out)?;
 
-            // Rename annotation for serde if name changed
-            let original = field.name.as_deref().unwrap_or("_");
-            if field_name != original {
-                writeln!(out, "    #[serde(rename = \"{original}\")]")?;
-            }
+    if defaults.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "impl{generics} {name}{generics} {{")?;
+    for (_, helper, typ_str, expr) in &defaults {
+        writeln!(out, "    fn {helper}() -> {typ_str} {{")?;
+        writeln!(out, "        {expr}")?;
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
 
-            writeln!(out, "    pub {field_name}: {typ_str},")?;
+    let all_defaulted = fields.iter().all(|f| {
+        let field_name = snake_ident(f.name.as_deref().unwrap_or("_"));
+        defaults.iter().any(|(n, ..)| *n == field_name) || is_option(&f.typ)
+    });
+    if derive_default && all_defaulted {
+        writeln!(out, "impl{generics} Default for {name}{generics} {{")?;
+        writeln!(out, "    fn default() -> Self {{")?;
+        writeln!(out, "        Self {{")?;
+        for field in fields {
+            let field_name = snake_ident(field.name.as_deref().unwrap_or("_"));
+            let value = defaults
+                .iter()
+                .find(|(n, ..)| *n == field_name)
+                .map_or_else(
+                    || "None".to_string(),
+                    |(_, helper, ..)| format!("Self::{helper}()"),
+                );
+            writeln!(out, "            {field_name}: {value},")?;
         }
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
         writeln!(out, "}}")?;
+        writeln!(out)?;
     }
-    writeln!(out)?;
 
     Ok(())
 }
 
+/// Generate a named struct field, returning its default helper if it has one.
+fn generate_named_field(
+    out: &mut String,
+    struct_name: &str,
+    field: &Field,
+    ctx: &Context,
+) -> Result<Option<(String, String, String, String)>> {
+    let field_name = field.name.as_deref().unwrap_or("_");
+    let field_name = snake_ident(field_name);
+
+    let access_optional = ctx.options.field_access == FieldAccess::Optional
+        && (has_annotation(&field.annotations, "read_only")
+            || has_annotation(&field.annotations, "write_only"));
+
+    // A documented default replaces the `Option` wrapper
+    let default = field
+        .default
+        .as_ref()
+        .filter(|d| **d != Value::Null && !access_optional);
+    let typ = if default.is_some() {
+        unwrap_option(&field.typ)
+    } else {
+        &field.typ
+    };
+    let typ_str = if access_optional && !is_option(typ) {
+        format!("Option<{}>", type_to_rust(typ))
+    } else {
+        type_to_rust(typ)
+    };
+
+    // Rename annotation for serde if name changed
+    let original = wire_name(field);
+    if has_annotation(&field.annotations, "flatten") {
+        writeln!(out, "    #[serde(flatten)]")?;
+    } else if field_name != original {
+        writeln!(out, "    #[serde(rename = \"{original}\")]")?;
+    }
+
+    if let Some(confidence) = type_confidence(&field.typ)
+        && confidence < ctx.options.review_threshold
+    {
+        writeln!(
+            out,
+            "    /// Review: generated with confidence {confidence:.2}."
+        )?;
+    }
+
+    if let Some(attr) = deprecated_attr(&field.annotations) {
+        writeln!(out, "    {attr}")?;
+    }
+
+    if access_optional {
+        writeln!(
+            out,
+            "    #[serde(default, skip_serializing_if = \"Option::is_none\")]"
+        )?;
+    }
+
+    let helper = default.map(|default| {
+        let helper = format!("default_{}", field_name.trim_start_matches("r#"));
+        let expr = default_to_rust(default, typ, ctx);
+        (helper, expr)
+    });
+    if let Some((helper, _)) = &helper {
+        writeln!(out, "    #[serde(default = \"{struct_name}::{helper}\")]")?;
+    }
+
+    writeln!(out, "    pub {field_name}: {typ_str},")?;
+    Ok(helper.map(|(helper, expr)| (field_name, helper, typ_str, expr)))
+}
+
 fn generate_enum(
     out: &mut String,
     name: &str,
     params: &[rhi_concord_core::TypeParam],
-    variants: &[rhi_concord_core::Variant],
+    variants: &[Variant],
 ) -> Result<()> {
     writeln!(
         out,
@@ -193,19 +725,9 @@ fn generate_enum(
 
     writeln!(out, "pub enum {name}{generics} {{")?;
     for variant in variants {
-        let variant_name = to_pascal_case(&variant.name);
+        let variant_name = pascal_ident(&variant.name);
 
-        // Check for serde_rename annotation
-        let rename = variant.annotations.iter().find_map(|a| {
-            if a.kind == "serde_rename" {
-                match &a.value {
-                    Some(rhi_concord_core::AnnotationValue::String(s)) => Some(s.as_str()),
-                    _ => None,
-                }
-            } else {
-                None
-            }
-        });
+        let rename = string_annotation(&variant.annotations, "serde_rename");
 
         if variant.fields.is_empty() {
             if let Some(original) = rename
@@ -227,7 +749,7 @@ fn generate_enum(
             writeln!(out, "    {variant_name} {{")?;
             for field in &variant.fields {
                 let field_name = field.name.as_deref().unwrap_or("_");
-                let field_name = to_snake_case(field_name);
+                let field_name = snake_ident(field_name);
                 let typ_str = type_to_rust(&field.typ);
                 writeln!(out, "        {field_name}: {typ_str},")?;
             }
@@ -240,80 +762,1404 @@ fn generate_enum(
     Ok(())
 }
 
-fn generate_function(out: &mut String, func: &Function) -> Result<()> {
-    // Doc comment
-    if let Some(docs) = &func.metadata.docs {
-        for line in docs.lines() {
-            writeln!(out, "/// {line}")?;
-        }
-    }
-
-    // Extract HTTP annotations
-    let method = func
-        .annotations
+/// Generate a `flags` enum as a set of bits that serializes as the list of
+/// its flags' wire names, or as its bits when the variants have `value`s.
+/// `serialize_names` and `serialize_bits` pick one form for a field with
+/// `#[serde(serialize_with)]`; either form is accepted when deserializing.
+#[allow(clippy::cast_possible_truncation)]
+fn generate_flag_set(out: &mut String, name: &str, variants: &[Variant]) -> Result<()> {
+    let mut idents = Names::default();
+    idents.reserve("FLAGS");
+    let values = variants
         .iter()
-        .find(|a| a.kind == "http_method")
-        .and_then(|a| match &a.value {
-            Some(rhi_concord_core::AnnotationValue::String(s)) => Some(s.as_str()),
-            _ => None,
-        });
-    let path = func
-        .annotations
+        .all(|v| number_annotation(&v.annotations, "value").is_some());
+    let flags: Vec<_> = variants
         .iter()
-        .find(|a| a.kind == "http_path")
-        .and_then(|a| match &a.value {
-            Some(rhi_concord_core::AnnotationValue::String(s)) => Some(s.as_str()),
-            _ => None,
-        });
-
-    if let (Some(method), Some(path)) = (method, path) {
-        writeln!(out, "/// HTTP: {method} {path}")?;
-    }
-
-    let func_name = to_snake_case(&func.name);
-
-    let generics = if func.params.is_empty() {
-        String::new()
+        .enumerate()
+        .map(|(i, variant)| Flag {
+            ident: idents.claim(&RUST.ident(&variant.name, Case::ScreamingSnake)),
+            name: string_annotation(&variant.annotations, "serde_rename").unwrap_or(&variant.name),
+            bits: number_annotation(&variant.annotations, "value")
+                .filter(|_| values)
+                .map_or(1 << i, |n| n as i128),
+            variant,
+        })
+        .collect();
+    let repr = if flags.iter().any(|f| f.bits < 0) {
+        "i64"
     } else {
-        let names: Vec<_> = func.params.iter().map(|p| p.name.as_str()).collect();
-        format!("<{}>", names.join(", "))
+        "u64"
     };
+    writeln!(out, "#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]")?;
+    generate_flags(out, name, repr, &flags)?;
+    generate_flag_serde(out, name, repr, values)
+}
 
-    let args = func
-        .args
-        .iter()
-        .map(|p| {
-            let name = p.name.as_deref().unwrap_or("_");
-            let name = to_snake_case(name);
-            let typ = type_to_rust(&p.typ);
-            format!("{name}: {typ}")
-        })
-        .collect::<Vec<_>>()
-        .join(", ");
-
-    let ret = type_to_rust(&func.ret);
-
+/// Generate the serde impls of a flag set, and `serialize_bits` and
+/// `serialize_names` helpers; `Serialize` uses the bits when `values` is set.
+fn generate_flag_serde(out: &mut String, name: &str, repr: &str, values: bool) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "impl {name} {{")?;
     writeln!(
         out,
-        "pub async fn {func_name}{generics}({args}) -> {ret} {{"
+        "    /// Serialize as the bits of the set, for `#[serde(serialize_with)]`."
     )?;
-    writeln!(out, "    todo!()")?;
+    writeln!(
+        out,
+        "    pub fn serialize_bits<S: serde::Serializer>(flags: &Self, serializer: S) -> Result<S::Ok, S::Error> {{"
+    )?;
+    writeln!(out, "        serializer.serialize_{repr}(flags.bits())")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Serialize as the list of flag names, for `#[serde(serialize_with)]`."
+    )?;
+    writeln!(
+        out,
+        "    pub fn serialize_names<S: serde::Serializer>(flags: &Self, serializer: S) -> Result<S::Ok, S::Error> {{"
+    )?;
+    writeln!(
+        out,
+        "        serializer.collect_seq(flags.iter_names().map(|(name, _)| name))"
+    )?;
+    writeln!(out, "    }}")?;
     writeln!(out, "}}")?;
     writeln!(out)?;
-
-    Ok(())
-}
-
-fn type_to_rust(typ: &Type) -> String {
-    match &typ.kind {
-        TypeKind::Ref { name } => {
-            let base = match name.as_str() {
-                "String" => "String",
-                "i64" => "i64",
-                "f64" => "f64",
+    writeln!(out, "impl Serialize for {name} {{")?;
+    writeln!(
+        out,
+        "    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {{"
+    )?;
+    let form = if values { "bits" } else { "names" };
+    writeln!(out, "        Self::serialize_{form}(self, serializer)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl<'de> Deserialize<'de> for {name} {{")?;
+    writeln!(
+        out,
+        "    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {{"
+    )?;
+    writeln!(out, "        #[derive(Deserialize)]")?;
+    writeln!(out, "        #[serde(untagged)]")?;
+    writeln!(out, "        enum Repr {{")?;
+    writeln!(out, "            Bits({repr}),")?;
+    writeln!(out, "            Names(Vec<String>),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        match Repr::deserialize(deserializer)? {{")?;
+    writeln!(
+        out,
+        "            Repr::Bits(bits) => Self::from_bits(bits).ok_or_else(|| {{"
+    )?;
+    writeln!(
+        out,
+        "                serde::de::Error::custom(format_args!(\"unknown bits in {{bits:#x}}\"))"
+    )?;
+    writeln!(out, "            }}),")?;
+    writeln!(out, "            Repr::Names(names) => names")?;
+    writeln!(out, "                .iter()")?;
+    writeln!(out, "                .map(|name| {{")?;
+    writeln!(
+        out,
+        "                    Self::from_name(name).ok_or_else(|| {{"
+    )?;
+    writeln!(
+        out,
+        "                        serde::de::Error::custom(format_args!(\"unknown flag `{{name}}`\"))"
+    )?;
+    writeln!(out, "                    }})")?;
+    writeln!(out, "                }})")?;
+    writeln!(out, "                .collect(),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// A flag of a set generated by [`generate_flags`].
+pub(super) struct Flag<'a> {
+    /// Name of its associated constant.
+    pub ident: String,
+    /// Name listed in `FLAGS`, as written in the source.
+    pub name: &'a str,
+    pub bits: i128,
+    pub variant: &'a Variant,
+}
+
+/// Generate a bitflags-style struct over the integer type `repr`, after the
+/// attributes the caller wrote.
+///
+/// Each flag is an associated constant; `FLAGS` lists them by name. The set
+/// supports `|`, `&`, `^`, `-` and `!`, `contains`, `insert` and `remove`,
+/// iteration over the flags it contains and conversions from bits and names.
+pub(super) fn generate_flags(
+    out: &mut String,
+    name: &str,
+    repr: &str,
+    flags: &[Flag],
+) -> Result<()> {
+    let all = flags.iter().fold(0, |all, f| all | f.bits);
+    writeln!(out, "pub struct {name}({repr});")?;
+    writeln!(out)?;
+    writeln!(out, "impl {name} {{")?;
+    for flag in flags {
+        if let Some(attr) = deprecated_attr(&flag.variant.annotations) {
+            writeln!(out, "    {attr}")?;
+        }
+        writeln!(
+            out,
+            "    pub const {}: Self = Self({});",
+            flag.ident,
+            bits_literal(flag.bits)
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(out, "    /// Every flag, with its name.")?;
+    writeln!(out, "    pub const FLAGS: &[(&str, Self)] = &[")?;
+    for flag in flags {
+        writeln!(out, "        ({:?}, Self::{}),", flag.name, flag.ident)?;
+    }
+    writeln!(out, "    ];")?;
+    let all = bits_literal(all);
+    for (doc, signature, body) in [
+        ("No flags set.", "empty() -> Self".to_string(), "Self(0)"),
+        (
+            "Every flag set.",
+            "all() -> Self".to_string(),
+            &format!("Self({all})"),
+        ),
+        ("The raw bits.", format!("bits(self) -> {repr}"), "self.0"),
+        (
+            "The flags in `bits`, or `None` if any bit is no flag's.",
+            format!("from_bits(bits: {repr}) -> Option<Self>"),
+            "if bits & !Self::all().0 == 0 {\n            Some(Self(bits))\n        } else {\n            None\n        }",
+        ),
+        (
+            "The flags in `bits`, dropping bits that are no flag's.",
+            format!("from_bits_truncate(bits: {repr}) -> Self"),
+            "Self(bits & Self::all().0)",
+        ),
+        (
+            "`bits` as they are, including bits that are no flag's.",
+            format!("from_bits_retain(bits: {repr}) -> Self"),
+            "Self(bits)",
+        ),
+        (
+            "Whether no flag is set.",
+            "is_empty(self) -> bool".to_string(),
+            "self.0 == 0",
+        ),
+        (
+            "Whether every flag of `other` is set.",
+            "contains(self, other: Self) -> bool".to_string(),
+            "self.0 & other.0 == other.0",
+        ),
+        (
+            "Whether any flag of `other` is set.",
+            "intersects(self, other: Self) -> bool".to_string(),
+            "self.0 & other.0 != 0",
+        ),
+    ] {
+        writeln!(out)?;
+        writeln!(out, "    /// {doc}")?;
+        writeln!(out, "    pub const fn {signature} {{")?;
+        writeln!(out, "        {body}")?;
+        writeln!(out, "    }}")?;
+    }
+    generate_flag_methods(out)?;
+    generate_flag_traits(out, name)
+}
+
+/// Mutating, lookup and iteration methods of a flags struct, closing its
+/// `impl` block.
+fn generate_flag_methods(out: &mut String) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "    /// Set the flags of `other`.")?;
+    writeln!(out, "    pub fn insert(&mut self, other: Self) {{")?;
+    writeln!(out, "        self.0 |= other.0;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    /// Clear the flags of `other`.")?;
+    writeln!(out, "    pub fn remove(&mut self, other: Self) {{")?;
+    writeln!(out, "        self.0 &= !other.0;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    /// The flag called `name` in `FLAGS`.")?;
+    writeln!(out, "    pub fn from_name(name: &str) -> Option<Self> {{")?;
+    writeln!(
+        out,
+        "        Self::FLAGS.iter().find(|(n, _)| *n == name).map(|&(_, flag)| flag)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// The flags set in `self` with their names, each bit reported once."
+    )?;
+    writeln!(
+        out,
+        "    pub fn iter_names(self) -> impl Iterator<Item = (&'static str, Self)> {{"
+    )?;
+    writeln!(out, "        let mut remaining = self.0;")?;
+    writeln!(
+        out,
+        "        Self::FLAGS.iter().filter_map(move |&(name, flag)| {{"
+    )?;
+    writeln!(
+        out,
+        "            let new = remaining & flag.0 != 0 && self.contains(flag);"
+    )?;
+    writeln!(out, "            if new {{")?;
+    writeln!(out, "                remaining &= !flag.0;")?;
+    writeln!(out, "            }}")?;
+    writeln!(out, "            new.then_some((name, flag))")?;
+    writeln!(out, "        }})")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    /// The flags set in `self`.")?;
+    writeln!(
+        out,
+        "    pub fn iter(self) -> impl Iterator<Item = Self> {{"
+    )?;
+    writeln!(out, "        self.iter_names().map(|(_, flag)| flag)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Operators, collection and `Debug` impls of a flags struct.
+fn generate_flag_traits(out: &mut String, name: &str) -> Result<()> {
+    for (op, method, body) in [
+        ("BitOr", "bitor", "self.0 | rhs.0"),
+        ("BitAnd", "bitand", "self.0 & rhs.0"),
+        ("BitXor", "bitxor", "self.0 ^ rhs.0"),
+        ("Sub", "sub", "self.0 & !rhs.0"),
+    ] {
+        writeln!(out)?;
+        writeln!(out, "impl ::core::ops::{op} for {name} {{")?;
+        writeln!(out, "    type Output = Self;")?;
+        writeln!(out)?;
+        writeln!(out, "    fn {method}(self, rhs: Self) -> Self {{")?;
+        writeln!(out, "        Self({body})")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+        writeln!(out, "impl ::core::ops::{op}Assign for {name} {{")?;
+        writeln!(out, "    fn {method}_assign(&mut self, rhs: Self) {{")?;
+        writeln!(
+            out,
+            "        *self = ::core::ops::{op}::{method}(*self, rhs);"
+        )?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+    writeln!(out)?;
+    writeln!(out, "impl ::core::ops::Not for {name} {{")?;
+    writeln!(out, "    type Output = Self;")?;
+    writeln!(out)?;
+    writeln!(out, "    fn not(self) -> Self {{")?;
+    writeln!(out, "        Self(!self.0 & Self::all().0)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::core::iter::FromIterator<Self> for {name} {{")?;
+    writeln!(
+        out,
+        "    fn from_iter<I: IntoIterator<Item = Self>>(iter: I) -> Self {{"
+    )?;
+    writeln!(
+        out,
+        "        iter.into_iter().fold(Self::empty(), |all, flag| all | flag)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::core::iter::Extend<Self> for {name} {{")?;
+    writeln!(
+        out,
+        "    fn extend<I: IntoIterator<Item = Self>>(&mut self, iter: I) {{"
+    )?;
+    writeln!(out, "        for flag in iter {{")?;
+    writeln!(out, "            self.insert(flag);")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::core::fmt::Debug for {name} {{")?;
+    writeln!(
+        out,
+        "    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{"
+    )?;
+    writeln!(out, "        f.write_str(\"{name}(\")?;")?;
+    writeln!(out, "        let mut remaining = self.0;")?;
+    writeln!(
+        out,
+        "        for (i, (name, flag)) in self.iter_names().enumerate() {{"
+    )?;
+    writeln!(out, "            if i > 0 {{")?;
+    writeln!(out, "                f.write_str(\" | \")?;")?;
+    writeln!(out, "            }}")?;
+    writeln!(out, "            f.write_str(name)?;")?;
+    writeln!(out, "            remaining &= !flag.0;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        if remaining != 0 || self.0 == 0 {{")?;
+    writeln!(out, "            if remaining != self.0 {{")?;
+    writeln!(out, "                f.write_str(\" | \")?;")?;
+    writeln!(out, "            }}")?;
+    writeln!(out, "            write!(f, \"{{remaining:#x}}\")?;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        f.write_str(\")\")")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// A flag's bits as a Rust literal: a shift for single bits, hex otherwise.
+fn bits_literal(bits: i128) -> String {
+    if bits > 0 && bits.count_ones() == 1 && bits.trailing_zeros() < 63 {
+        format!("1 << {}", bits.trailing_zeros())
+    } else if bits > 0 {
+        format!("{bits:#x}")
+    } else {
+        bits.to_string()
+    }
+}
+
+/// Generate a server enum with a base URL builder.
+///
+/// Each variant carries its URL variables; `base_url` substitutes them into
+/// the template and rejects values outside an `enum` restriction.
+fn generate_server_enum(out: &mut String, name: &str, variants: &[Variant]) -> Result<()> {
+    writeln!(out, "#[derive(Debug, Clone, PartialEq, Eq)]")?;
+    writeln!(out, "pub enum {name} {{")?;
+    for variant in variants {
+        if let Some(description) = string_annotation(&variant.annotations, "description") {
+            for line in description.lines() {
+                writeln!(out, "    /// {line}")?;
+            }
+        }
+        if variant.fields.is_empty() {
+            writeln!(out, "    {},", variant.name)?;
+        } else {
+            writeln!(out, "    {} {{", variant.name)?;
+            for field in &variant.fields {
+                let field_name = snake_ident(field.name.as_deref().unwrap_or("_"));
+                writeln!(out, "        {field_name}: {},", type_to_rust(&field.typ))?;
+            }
+            writeln!(out, "    }},")?;
+        }
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    writeln!(out, "impl {name} {{")?;
+    writeln!(
+        out,
+        "    /// Resolve the base URL, substituting server variables."
+    )?;
+    writeln!(out, "    ///")?;
+    writeln!(out, "    /// # Errors")?;
+    writeln!(out, "    ///")?;
+    writeln!(
+        out,
+        "    /// Returns an error if a variable is not one of its allowed values."
+    )?;
+    writeln!(
+        out,
+        "    pub fn base_url(&self) -> Result<String, ApiError> {{"
+    )?;
+    writeln!(out, "        match self {{")?;
+    for variant in variants {
+        generate_server_url_arm(out, variant)?;
+    }
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    // The first server with default variable values is the default
+    if let Some(first) = variants.first() {
+        writeln!(out, "impl Default for {name} {{")?;
+        writeln!(out, "    fn default() -> Self {{")?;
+        if first.fields.is_empty() {
+            writeln!(out, "        Self::{}", first.name)?;
+        } else {
+            writeln!(out, "        Self::{} {{", first.name)?;
+            for field in &first.fields {
+                let field_name = snake_ident(field.name.as_deref().unwrap_or("_"));
+                let default = match &field.default {
+                    Some(Value::String(s)) => s.as_str(),
+                    _ => "",
+                };
+                writeln!(out, "            {field_name}: {default:?}.to_string(),")?;
+            }
+            writeln!(out, "        }}")?;
+        }
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Generate the `base_url` match arm for one server variant.
+fn generate_server_url_arm(out: &mut String, variant: &Variant) -> Result<()> {
+    let url_const = string_annotation(&variant.annotations, "server_url_const").map_or_else(
+        || {
+            format!(
+                "{:?}",
+                string_annotation(&variant.annotations, "server_url").unwrap_or("")
+            )
+        },
+        str::to_string,
+    );
+    if variant.fields.is_empty() {
+        writeln!(
+            out,
+            "            Self::{} => Ok({url_const}.to_string()),",
+            variant.name
+        )?;
+        return Ok(());
+    }
+
+    let bindings: Vec<_> = variant
+        .fields
+        .iter()
+        .map(|f| snake_ident(f.name.as_deref().unwrap_or("_")))
+        .collect();
+    writeln!(
+        out,
+        "            Self::{} {{ {} }} => {{",
+        variant.name,
+        bindings.join(", ")
+    )?;
+    writeln!(
+        out,
+        "                let mut url = {url_const}.to_string();"
+    )?;
+    for (field, binding) in variant.fields.iter().zip(&bindings) {
+        let original = wire_name(field);
+        if let Some(allowed) = list_annotation(&field.annotations, "enum") {
+            let allowed: Vec<_> = allowed
+                .iter()
+                .filter_map(|v| match v {
+                    AnnotationValue::String(s) => Some(format!("{s:?}")),
+                    _ => None,
+                })
+                .collect();
+            writeln!(
+                out,
+                "                if ![{}].contains(&{binding}.as_str()) {{",
+                allowed.join(", ")
+            )?;
+            writeln!(out, "                    return Err(ApiError {{")?;
+            writeln!(
+                out,
+                "                        message: format!(\"invalid value {{{binding}:?}} for server variable `{original}`\"),"
+            )?;
+            writeln!(out, "                        code: None,")?;
+            writeln!(out, "                    }});")?;
+            writeln!(out, "                }}")?;
+        }
+        writeln!(
+            out,
+            "                url = url.replace(\"{{{original}}}\", {binding});"
+        )?;
+    }
+    writeln!(out, "                Ok(url)")?;
+    writeln!(out, "            }}")?;
+
+    Ok(())
+}
+
+fn generate_function(out: &mut String, func: &Function, ctx: &Context) -> Result<()> {
+    // Doc comment
+    if let Some(docs) = &func.metadata.docs {
+        for line in docs.lines() {
+            writeln!(out, "/// {line}")?;
+        }
+    }
+
+    // Extract HTTP annotations
+    let method = string_annotation(&func.annotations, "http_method");
+    let path = string_annotation(&func.annotations, "http_path");
+
+    if let (Some(method), Some(path)) = (method, path) {
+        writeln!(out, "/// HTTP: {method} {path}")?;
+    }
+
+    for arg in &func.args {
+        if let (Some(name), Some(default)) = (&arg.name, &arg.default) {
+            writeln!(out, "/// Default `{name}`: `{}`", value_to_json(default))?;
+        }
+    }
+
+    // Arguments cannot carry `#[deprecated]`, so document it instead
+    for arg in &func.args {
+        if let Some(name) = &arg.name
+            && has_annotation(&arg.annotations, "deprecated")
+        {
+            match deprecation_note(&arg.annotations) {
+                Some(note) => writeln!(out, "/// Deprecated `{name}`: {note}")?,
+                None => writeln!(out, "/// Deprecated `{name}`")?,
+            }
+        }
+    }
+
+    if let Some(server) = type_annotation(&func.annotations, "server") {
+        writeln!(out, "/// Server: [`{}`]", type_to_rust(server))?;
+    }
+
+    let threshold = ctx.options.review_threshold;
+    if let Some(note) = review_note(&func.metadata, threshold) {
+        writeln!(out, "/// Review: {note}.")?;
+    }
+    for arg in &func.args {
+        if let Some(name) = &arg.name
+            && let Some(confidence) = type_confidence(&arg.typ).filter(|c| *c < threshold)
+        {
+            writeln!(
+                out,
+                "/// Review `{name}`: generated with confidence {confidence:.2}."
+            )?;
+        }
+    }
+
+    if let Some(attr) = deprecated_attr(&func.annotations) {
+        writeln!(out, "{attr}")?;
+    }
+
+    let func_name = snake_ident(&func.name);
+
+    let generics = if func.params.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<_> = func.params.iter().map(|p| p.name.as_str()).collect();
+        format!("<{}>", names.join(", "))
+    };
+
+    let args = func
+        .args
+        .iter()
+        .map(|p| {
+            let name = p.name.as_deref().unwrap_or("_");
+            let name = snake_ident(name);
+            let typ = type_to_rust(&p.typ);
+            format!("{name}: {typ}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let ret = type_to_rust(&func.ret);
+
+    writeln!(
+        out,
+        "pub async fn {func_name}{generics}({args}) -> {ret} {{"
+    )?;
+    writeln!(out, "    todo!()")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// An inbound operation within a webhook set.
+struct InboundEvent<'a> {
+    func: &'a Function,
+    variant: String,
+    /// Webhook or callback name.
+    key: &'a str,
+    method: &'a str,
+    /// Rust type of the request body, if any.
+    body: Option<String>,
+}
+
+/// Generate an event enum, handler trait and dispatcher for a webhook set.
+///
+/// Events are keyed by webhook or callback name plus HTTP method; only the
+/// request body is passed to the handler.
+fn generate_handler(out: &mut String, set: &str, funcs: &[&Function]) -> Result<()> {
+    let events: Vec<_> = funcs
+        .iter()
+        .map(|func| InboundEvent {
+            func,
+            variant: pascal_ident(&func.name),
+            key: string_annotation(&func.annotations, "webhook")
+                .or_else(|| string_annotation(&func.annotations, "callback"))
+                .unwrap_or(&func.name),
+            method: string_annotation(&func.annotations, "http_method").unwrap_or("POST"),
+            body: func
+                .args
+                .iter()
+                .find(|a| a.name.as_deref() == Some("body"))
+                .map(|a| type_to_rust(&a.typ)),
+        })
+        .collect();
+
+    generate_event_enum(out, set, &events)?;
+    generate_handler_trait(out, set, &events)?;
+    generate_dispatcher(out, set, &events)
+}
+
+/// Generate the event enum and its `parse` constructor.
+fn generate_event_enum(out: &mut String, set: &str, events: &[InboundEvent]) -> Result<()> {
+    let event = format!("{set}Event");
+    let handler = format!("{set}Handler");
+
+    writeln!(out, "/// Events delivered to [`{handler}`].")?;
+    writeln!(out, "#[derive(Debug, Clone, PartialEq)]")?;
+    writeln!(out, "pub enum {event} {{")?;
+    for InboundEvent {
+        func,
+        variant,
+        body,
+        ..
+    } in events
+    {
+        if let Some(docs) = &func.metadata.docs {
+            for line in docs.lines() {
+                writeln!(out, "    /// {line}")?;
+            }
+        }
+        match body {
+            Some(body) => writeln!(out, "    {variant}({body}),")?,
+            None => writeln!(out, "    {variant},")?,
+        }
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    writeln!(out, "impl {event} {{")?;
+    writeln!(
+        out,
+        "    /// Deserialize an incoming request body into a typed event."
+    )?;
+    writeln!(out, "    ///")?;
+    writeln!(
+        out,
+        "    /// `key` is the webhook or callback name and `method` the HTTP method."
+    )?;
+    writeln!(out, "    ///")?;
+    writeln!(out, "    /// # Errors")?;
+    writeln!(out, "    ///")?;
+    writeln!(
+        out,
+        "    /// Returns an error if the event is unknown or the body does not match its schema."
+    )?;
+    writeln!(
+        out,
+        "    pub fn parse(key: &str, method: &str, body: &[u8]) -> Result<Self, ApiError> {{"
+    )?;
+    writeln!(out, "        match (key, method) {{")?;
+    for InboundEvent {
+        variant,
+        key,
+        method,
+        body,
+        ..
+    } in events
+    {
+        if body.is_some() {
+            writeln!(
+                out,
+                "            ({key:?}, {method:?}) => serde_json::from_slice(body)"
+            )?;
+            writeln!(out, "                .map(Self::{variant})")?;
+            writeln!(out, "                .map_err(|e| ApiError {{")?;
+            writeln!(out, "                    message: e.to_string(),")?;
+            writeln!(
+                out,
+                "                    code: Some(\"invalid_body\".to_string()),"
+            )?;
+            writeln!(out, "                }}),")?;
+        } else {
+            writeln!(
+                out,
+                "            ({key:?}, {method:?}) => Ok(Self::{variant}),"
+            )?;
+        }
+    }
+    writeln!(out, "            _ => Err(ApiError {{")?;
+    writeln!(
+        out,
+        "                message: format!(\"unknown event {{method}} {{key}}\"),"
+    )?;
+    writeln!(
+        out,
+        "                code: Some(\"unknown_event\".to_string()),"
+    )?;
+    writeln!(out, "            }}),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Generate the handler trait with one method per event.
+fn generate_handler_trait(out: &mut String, set: &str, events: &[InboundEvent]) -> Result<()> {
+    let handler = format!("{set}Handler");
+
+    writeln!(out, "/// Handler for inbound `{set}` requests.")?;
+    writeln!(out, "pub trait {handler} {{")?;
+    for InboundEvent {
+        func,
+        key,
+        method,
+        body,
+        ..
+    } in events
+    {
+        if let Some(docs) = &func.metadata.docs {
+            for line in docs.lines() {
+                writeln!(out, "    /// {line}")?;
+            }
+        }
+        writeln!(out, "    /// Event: {method} {key}")?;
+        if let Some(attr) = deprecated_attr(&func.annotations) {
+            writeln!(out, "    {attr}")?;
+        }
+        let arg = body
+            .as_ref()
+            .map_or_else(String::new, |b| format!(", body: {b}"));
+        writeln!(
+            out,
+            "    fn {}(&self{arg}) -> impl std::future::Future<Output = {}> + Send;",
+            snake_ident(&func.name),
+            type_to_rust(&func.ret)
+        )?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Generate the function routing a raw request to the handler.
+fn generate_dispatcher(out: &mut String, set: &str, events: &[InboundEvent]) -> Result<()> {
+    let event = format!("{set}Event");
+    let handler = format!("{set}Handler");
+
+    writeln!(
+        out,
+        "/// Parse an incoming request and call the matching [`{handler}`] method."
+    )?;
+    writeln!(out, "///")?;
+    writeln!(
+        out,
+        "/// Returns the handler's response serialized as JSON."
+    )?;
+    writeln!(out, "///")?;
+    writeln!(out, "/// # Errors")?;
+    writeln!(out, "///")?;
+    writeln!(
+        out,
+        "/// Returns an error if parsing fails or the handler returns one."
+    )?;
+    writeln!(
+        out,
+        "pub async fn dispatch_{}<H: {handler}>(handler: &H, key: &str, method: &str, body: &[u8]) -> Result<serde_json::Value, ApiError> {{",
+        snake_ident(set)
+    )?;
+    writeln!(
+        out,
+        "    let response = match {event}::parse(key, method, body)? {{"
+    )?;
+    for InboundEvent {
+        func,
+        variant,
+        body,
+        ..
+    } in events
+    {
+        let name = snake_ident(&func.name);
+        if body.is_some() {
+            writeln!(
+                out,
+                "        {event}::{variant}(body) => serde_json::to_value(handler.{name}(body).await?),"
+            )?;
+        } else {
+            writeln!(
+                out,
+                "        {event}::{variant} => serde_json::to_value(handler.{name}().await?),"
+            )?;
+        }
+    }
+    writeln!(out, "    }};")?;
+    writeln!(out, "    response.map_err(|e| ApiError {{")?;
+    writeln!(out, "        message: e.to_string(),")?;
+    writeln!(out, "        code: None,")?;
+    writeln!(out, "    }})")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Apply well-known vendor extensions that reshape the IR.
+///
+/// - `x-rust-skip: true` drops a schema, property, operation or parameter.
+///   References left to a skipped schema become `Any`.
+/// - `x-enum-varnames` renames the variants of an enum, in order.
+///
+/// `x-rust-type` is honoured while rendering types.
+fn apply_extensions(module: &Module, diagnostics: &mut Diagnostics) -> Module {
+    let mut types = Vec::new();
+    collect_types(module, &mut types);
+
+    let mut module = reshape(module);
+    let mut referenced = HashSet::new();
+    visit_module_refs(&module, &mut |r| {
+        referenced.insert(r.to_string());
+    });
+
+    let mut dangling = HashSet::new();
+    for typ in types {
+        if let Some(name) = &typ.name
+            && is_skipped(&typ.metadata.extra)
+            && referenced.contains(name)
+        {
+            diagnostics.warning(
+                diagnostics::UNSUPPORTED_SCHEMA,
+                typ.metadata.source.clone(),
+                format!(
+                    "schema `{name}` is skipped by `x-rust-skip` but still referenced; using `serde_json::Value` there"
+                ),
+            );
+            dangling.insert(name.clone());
+        }
+    }
+    if !dangling.is_empty() {
+        erase_refs(&mut module, &dangling);
+    }
+    module
+}
+
+fn is_skipped(extra: &indexmap::IndexMap<String, Value>) -> bool {
+    extra.get("x-rust-skip") == Some(&Value::Bool(true))
+}
+
+/// Drop skipped items, fields and arguments and rename enum variants.
+fn reshape(module: &Module) -> Module {
+    let items = module
+        .items
+        .iter()
+        .filter(|item| match item {
+            Item::Type(typ) => !is_skipped(&typ.metadata.extra),
+            Item::Function(func) => !is_skipped(&func.metadata.extra),
+            Item::Const { .. } => true,
+        })
+        .cloned()
+        .map(|mut item| {
+            match &mut item {
+                Item::Type(typ) => {
+                    let varnames = typ.metadata.extra.get("x-enum-varnames").cloned();
+                    match &mut typ.kind {
+                        TypeKind::Struct { fields } => {
+                            fields.retain(|f| !is_skipped(&f.metadata.extra));
+                        }
+                        TypeKind::Enum { variants } => {
+                            if let Some(Value::List(names)) = varnames
+                                && names.len() == variants.len()
+                            {
+                                for (variant, name) in variants.iter_mut().zip(names) {
+                                    if let Value::String(name) = name {
+                                        variant.name = name;
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Item::Function(func) => func.args.retain(|a| !is_skipped(&a.metadata.extra)),
+                Item::Const { .. } => {}
+            }
+            item
+        })
+        .collect();
+
+    Module {
+        items,
+        submodules: module.submodules.iter().map(reshape).collect(),
+        ..module.clone()
+    }
+}
+
+fn visit_module_refs(module: &Module, f: &mut impl FnMut(&str)) {
+    for item in &module.items {
+        item.visit_refs(f);
+    }
+    for sub in &module.submodules {
+        visit_module_refs(sub, f);
+    }
+}
+
+/// Replace references to the `erased` types with `Any`.
+fn erase_refs(module: &mut Module, erased: &HashSet<String>) {
+    let rename = |name: &str| erased.contains(name).then(|| "Any".to_string());
+    for item in &mut module.items {
+        match item {
+            Item::Type(typ) | Item::Const { typ, .. } => rename_refs(typ, &rename),
+            Item::Function(func) => {
+                for arg in &mut func.args {
+                    rename_refs(&mut arg.typ, &rename);
+                }
+                rename_refs(&mut func.ret, &rename);
+            }
+        }
+    }
+    for sub in &mut module.submodules {
+        erase_refs(sub, erased);
+    }
+}
+
+/// Split types with `read_only`/`write_only` fields into response and request shapes.
+///
+/// `Foo` keeps read-only fields and drops write-only ones; `FooRequest` does
+/// the opposite. Types referencing a split type are split too, and outbound
+/// function arguments switch to the request shapes.
+fn split_access(module: &Module) -> Module {
+    let mut types = Vec::new();
+    collect_types(module, &mut types);
+
+    let mut split: HashSet<String> = types
+        .iter()
+        .filter(|t| match &t.kind {
+            TypeKind::Struct { fields } => fields.iter().any(|f| {
+                has_annotation(&f.annotations, "read_only")
+                    || has_annotation(&f.annotations, "write_only")
+            }),
+            _ => false,
+        })
+        .filter_map(|t| t.name.clone())
+        .collect();
+
+    // Anything reaching a split type needs a request shape as well
+    loop {
+        let before = split.len();
+        for typ in &types {
+            if let Some(name) = &typ.name
+                && !split.contains(name)
+            {
+                let mut reaches = false;
+                typ.visit_refs(&mut |r| reaches |= split.contains(r));
+                if reaches {
+                    split.insert(name.clone());
+                }
+            }
+        }
+        if split.len() == before {
+            break;
+        }
+    }
+
+    rewrite_access(module, &split)
+}
+
+fn collect_types<'a>(module: &'a Module, types: &mut Vec<&'a Type>) {
+    for item in &module.items {
+        if let Item::Type(typ) = item {
+            types.push(typ);
+        }
+    }
+    for sub in &module.submodules {
+        collect_types(sub, types);
+    }
+}
+
+fn rewrite_access(module: &Module, split: &HashSet<String>) -> Module {
+    let to_request = |name: &str| split.contains(name).then(|| format!("{name}Request"));
+
+    let mut items = Vec::new();
+    for item in &module.items {
+        match item {
+            Item::Type(typ) if typ.name.as_ref().is_some_and(|n| split.contains(n)) => {
+                let mut response = typ.clone();
+                retain_fields(&mut response, "write_only");
+                items.push(Item::Type(response));
+
+                let mut request = typ.clone();
+                retain_fields(&mut request, "read_only");
+                rename_refs(&mut request, &to_request);
+                request.name = request.name.as_deref().and_then(to_request);
+                items.push(Item::Type(request));
+            }
+            Item::Function(func) if !has_annotation(&func.annotations, "inbound") => {
+                let mut func = func.clone();
+                for arg in &mut func.args {
+                    rename_refs(&mut arg.typ, &to_request);
+                }
+                items.push(Item::Function(func));
+            }
+            other => items.push(other.clone()),
+        }
+    }
+
+    Module {
+        items,
+        submodules: module
+            .submodules
+            .iter()
+            .map(|sub| rewrite_access(sub, split))
+            .collect(),
+        ..module.clone()
+    }
+}
+
+/// Drop struct fields carrying the `excluded` annotation.
+fn retain_fields(typ: &mut Type, excluded: &str) {
+    if let TypeKind::Struct { fields } = &mut typ.kind {
+        fields.retain(|f| !has_annotation(&f.annotations, excluded));
+    }
+}
+
+/// Rewrite type references for which `rename` returns a new name.
+fn rename_refs(typ: &mut Type, rename: &impl Fn(&str) -> Option<String>) {
+    match &mut typ.kind {
+        TypeKind::Ref { name } => {
+            if let Some(new) = rename(name) {
+                *name = new;
+            }
+        }
+        TypeKind::Struct { fields } => {
+            for field in fields {
+                rename_refs(&mut field.typ, rename);
+            }
+        }
+        TypeKind::Enum { variants } => {
+            for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                rename_refs(&mut field.typ, rename);
+            }
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                rename_refs(&mut param.typ, rename);
+            }
+            rename_refs(ret, rename);
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            for member in members {
+                rename_refs(member, rename);
+            }
+        }
+    }
+    for arg in &mut typ.args {
+        rename_refs(arg, rename);
+    }
+}
+
+/// Generate a test module deserializing each example into its generated type.
+///
+/// Each example is parsed, serialized and parsed again, so both directions
+/// of the mapping are checked against the spec. Examples of schemas rendered
+/// as primitives or collections would only exercise `serde` itself, so only
+/// those of generated structs, enums and unions get a test.
+fn generate_example_tests(out: &mut String, module: &Module, ctx: &Context) -> Result<()> {
+    let mut cases = Vec::new();
+    for item in &module.items {
+        match item {
+            Item::Type(typ) => {
+                let Some(name) = &typ.name else {
+                    continue;
+                };
+                if !typ.params.is_empty() || !is_generated(typ, ctx) {
+                    continue;
+                }
+                push_example_cases(&mut cases, &[name], name, &typ.metadata, "examples");
+                if let TypeKind::Struct { fields } = &typ.kind {
+                    for field in fields {
+                        let inner = unwrap_option(&field.typ);
+                        if is_generated(inner, ctx) {
+                            let field_name = field.name.as_deref().unwrap_or("field");
+                            push_example_cases(
+                                &mut cases,
+                                &[name, field_name],
+                                &type_to_rust(inner),
+                                &inner.metadata,
+                                "examples",
+                            );
+                        }
+                    }
+                }
+            }
+            Item::Function(func) => {
+                for arg in &func.args {
+                    let inner = unwrap_option(&arg.typ);
+                    if is_generated(inner, ctx) {
+                        let arg_name = arg.name.as_deref().unwrap_or("arg");
+                        push_example_cases(
+                            &mut cases,
+                            &[&func.name, arg_name],
+                            &type_to_rust(inner),
+                            &arg.metadata,
+                            "examples",
+                        );
+                    }
+                }
+                if let Some(ok) = func.ret.args.first()
+                    && is_generated(ok, ctx)
+                {
+                    push_example_cases(
+                        &mut cases,
+                        &[&func.name, "response"],
+                        &type_to_rust(ok),
+                        &func.metadata,
+                        "response_examples",
+                    );
+                }
+            }
+            Item::Const { .. } => {}
+        }
+    }
+
+    if cases.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "#[cfg(test)]")?;
+    writeln!(out, "mod example_tests {{")?;
+    writeln!(out, "    use super::*;")?;
+    for (test_name, rust_type, json) in cases {
+        writeln!(out)?;
+        writeln!(out, "    #[test]")?;
+        writeln!(out, "    fn {test_name}() {{")?;
+        writeln!(
+            out,
+            "        let value: {rust_type} = serde_json::from_str({}).unwrap();",
+            raw_string_literal(&json)
+        )?;
+        writeln!(
+            out,
+            "        let round_trip: {rust_type} = serde_json::from_value(serde_json::to_value(&value).unwrap()).unwrap();"
+        )?;
+        writeln!(out, "        assert_eq!(value, round_trip);")?;
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Add one `(test name, type, json)` case per example stored under `key`.
+fn push_example_cases(
+    cases: &mut Vec<(String, String, String)>,
+    path: &[&str],
+    rust_type: &str,
+    metadata: &rhi_concord_core::Metadata,
+    key: &str,
+) {
+    let Some(Value::List(examples)) = metadata.extra.get(key) else {
+        return;
+    };
+    let prefix: Vec<_> = path
+        .iter()
+        .map(|p| snake_ident(p).trim_start_matches("r#").to_string())
+        .collect();
+    for (i, example) in examples.iter().enumerate() {
+        let test_name = format!("{}_example_{i}", prefix.join("_"));
+        cases.push((
+            test_name,
+            rust_type.to_string(),
+            value_to_json(example).to_string(),
+        ));
+    }
+}
+
+/// Generate an untagged enum with one variant per union member.
+///
+/// Variants are named after their member types; a `Null` member becomes a unit
+/// variant, which untagged deserialization matches against `null`.
+fn generate_union(out: &mut String, name: &str, members: &[Type]) -> Result<()> {
+    writeln!(
+        out,
+        "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
+    )?;
+    writeln!(out, "#[serde(untagged)]")?;
+    writeln!(out, "pub enum {name} {{")?;
+    let mut names = Names::default();
+    for member in members {
+        let variant_name = names.claim(&union_variant_name(member));
+        if member_is_null(member) {
+            // Untagged unit variants match `null`
+            writeln!(out, "    {variant_name},")?;
+        } else {
+            writeln!(out, "    {variant_name}({}),", type_to_rust(member))?;
+        }
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Name a union variant after its member type.
+fn union_variant_name(member: &Type) -> String {
+    match &member.kind {
+        TypeKind::Ref { name } => match (name.as_str(), member.args.as_slice()) {
+            ("String", _) => "String".to_string(),
+            ("i64", _) => "Integer".to_string(),
+            ("f64", _) => "Number".to_string(),
+            ("bool", _) => "Boolean".to_string(),
+            ("Any", _) => "Value".to_string(),
+            ("Null", _) => "Null".to_string(),
+            ("Vec", [item]) => format!("{}List", union_variant_name(item)),
+            ("Option", [inner]) => union_variant_name(inner),
+            (other, _) => pascal_ident(other),
+        },
+        _ => member.name.as_deref().map_or_else(
+            || match member.kind {
+                TypeKind::Struct { .. } => "Object".to_string(),
+                TypeKind::Enum { .. } => "String".to_string(),
+                _ => "Variant".to_string(),
+            },
+            pascal_ident,
+        ),
+    }
+}
+
+fn member_is_null(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "Null")
+}
+
+fn is_option(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "Option")
+}
+
+/// Strip an `Option<T>` wrapper.
+fn unwrap_option(typ: &Type) -> &Type {
+    match (&typ.kind, typ.args.as_slice()) {
+        (TypeKind::Ref { name }, [inner]) if name == "Option" => inner,
+        _ => typ,
+    }
+}
+
+/// Whether `typ` is, or refers to, a struct, enum or union emitted as its own
+/// Rust type.
+fn is_generated(typ: &Type, ctx: &Context) -> bool {
+    let definition = match &typ.kind {
+        TypeKind::Ref { name } if typ.args.is_empty() => match ctx.types.get(name.as_str()) {
+            Some(definition) => *definition,
+            None => return false,
+        },
+        _ => typ,
+    };
+    definition.name.is_some()
+        && matches!(
+            definition.kind,
+            TypeKind::Struct { .. } | TypeKind::Enum { .. } | TypeKind::Union { .. }
+        )
+}
+
+/// Render `value` as a Rust expression of type `typ`.
+///
+/// Primitives, vectors, options and named enum variants become literals;
+/// anything else is deserialized from its JSON form at runtime.
+#[allow(clippy::cast_possible_truncation)]
+fn default_to_rust(value: &Value, typ: &Type, ctx: &Context) -> String {
+    // Anonymous enums are rendered as `String`
+    if let (TypeKind::Enum { .. }, None, Value::String(s)) = (&typ.kind, &typ.name, value) {
+        return format!("{s:?}.to_string()");
+    }
+    if let TypeKind::Ref { name } = &typ.kind {
+        match (name.as_str(), value, typ.args.as_slice()) {
+            ("Option", Value::Null, _) => return "None".to_string(),
+            ("Option", value, [inner]) => {
+                return format!("Some({})", default_to_rust(value, inner, ctx));
+            }
+            ("String", Value::String(s), _) => return format!("{s:?}.to_string()"),
+            ("bool", Value::Bool(b), _) => return b.to_string(),
+            ("f32" | "f64", Value::Number(n), _) => return format!("{n:?}"),
+            ("i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64", Value::Number(n), _)
+                if n.fract() == 0.0 =>
+            {
+                return format!("{}", *n as i64);
+            }
+            ("Vec", Value::List(items), [inner]) => {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|v| default_to_rust(v, inner, ctx))
+                    .collect();
+                return format!("vec![{}]", items.join(", "));
+            }
+            // Flag sets are built from lists of names instead
+            (other, Value::String(s), _) => {
+                if let Some(typ) = ctx.types.get(other)
+                    && let TypeKind::Enum { variants } = &typ.kind
+                    && !has_annotation(&typ.annotations, "flags")
+                    && let Some(variant) = variants.iter().find(|v| {
+                        string_annotation(&v.annotations, "serde_rename").unwrap_or(&v.name) == s
+                    })
+                {
+                    return format!("{other}::{}", pascal_ident(&variant.name));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    format!(
+        "serde_json::from_str::<{}>({}).expect(\"valid default\")",
+        type_to_rust(typ),
+        raw_string_literal(&value_to_json(value).to_string())
+    )
+}
+
+/// Convert an IR value to JSON, keeping integral numbers as integers.
+#[allow(clippy::cast_possible_truncation)]
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 9e15 => {
+            serde_json::Value::from(*n as i64)
+        }
+        Value::Number(n) => serde_json::Value::from(*n),
+        // Integers beyond 64 bits have no JSON form
+        Value::Int(n) => {
+            serde_json::Number::from_i128(*n).map_or(serde_json::Value::Null, Into::into)
+        }
+        Value::String(s) => serde_json::Value::String(s.clone()),
+        Value::List(items) => serde_json::Value::Array(items.iter().map(value_to_json).collect()),
+        Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), value_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Quote `s` as a raw string literal with enough `#`s to be unambiguous.
+fn raw_string_literal(s: &str) -> String {
+    let mut hashes = 1;
+    while s.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{hashes}\"{s}\"{hashes}")
+}
+
+fn type_to_rust(typ: &Type) -> String {
+    if typ.name.is_none()
+        && let Some(Value::String(rust_type)) = typ.metadata.extra.get("x-rust-type")
+    {
+        return rust_type.clone();
+    }
+
+    match &typ.kind {
+        TypeKind::Ref { name } => {
+            let base = match name.as_str() {
+                "String" => "String",
+                "i64" => "i64",
+                "f64" => "f64",
                 "bool" => "bool",
                 "Any" => "serde_json::Value",
-                "Unit" => "()",
+                "Unit" | "Null" => "()",
                 "Never" => "!",
                 other => other,
             };
@@ -356,70 +2202,511 @@ fn type_to_rust(typ: &Type) -> String {
     }
 }
 
-fn to_snake_case(s: &str) -> String {
-    let mut result = String::new();
-    let mut prev_lower = false;
-
-    for c in s.chars() {
-        if c.is_uppercase() {
-            if prev_lower {
-                result.push('_');
+/// Render a constant's type and value as Rust.
+///
+/// Numbers are integer or float literals depending on the type and value,
+/// `null` is `None` and lists are slices. Objects and lists mixing kinds of
+/// values have no `const` form and are kept as their JSON text.
+#[allow(clippy::cast_possible_truncation)]
+fn const_to_rust(typ: &Type, value: &Value) -> (String, String) {
+    let inner = match &typ.kind {
+        TypeKind::Ref { name } if matches!(name.as_str(), "Option" | "Vec") => typ.args.first(),
+        _ => None,
+    };
+    match value {
+        Value::String(s) => ("&str".to_string(), format!("{s:?}")),
+        Value::Bool(b) => ("bool".to_string(), b.to_string()),
+        Value::Int(n) => {
+            let rust_type = type_to_rust(typ);
+            let rust_type = match rust_type.as_str() {
+                "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {
+                    rust_type
+                }
+                _ => "i64".to_string(),
+            };
+            (rust_type, n.to_string())
+        }
+        Value::Number(n) => {
+            let rust_type = type_to_rust(typ);
+            let float = matches!(rust_type.as_str(), "f32" | "f64");
+            let integer = n.fract() == 0.0 && n.abs() < 9e15;
+            let rust_type = match rust_type.as_str() {
+                "f32" | "f64" | "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32"
+                | "u64" | "usize"
+                    if float || integer =>
+                {
+                    rust_type
+                }
+                _ if integer => "i64".to_string(),
+                _ => "f64".to_string(),
+            };
+            let literal = if n.is_nan() {
+                format!("{rust_type}::NAN")
+            } else if n.is_infinite() {
+                let sign = if *n < 0.0 { "NEG_" } else { "" };
+                format!("{rust_type}::{sign}INFINITY")
+            } else if rust_type.starts_with('f') {
+                format!("{n:?}")
+            } else {
+                (*n as i64).to_string()
+            };
+            (rust_type, literal)
+        }
+        Value::Null => {
+            let inner = inner.map_or_else(|| "()".to_string(), type_to_rust);
+            (format!("Option<{inner}>"), "None".to_string())
+        }
+        Value::List(items) => {
+            let element = Type::reference("Any");
+            let element = inner.unwrap_or(&element);
+            let items: Vec<_> = items.iter().map(|v| const_to_rust(element, v)).collect();
+            let types: Vec<_> = items.iter().map(|(t, _)| t).collect();
+            if types.windows(2).all(|w| w[0] == w[1]) {
+                let element = types.first().map_or("()", |t| t.as_str());
+                let values: Vec<_> = items.iter().map(|(_, v)| v.as_str()).collect();
+                (format!("&[{element}]"), format!("&[{}]", values.join(", ")))
+            } else {
+                json_const(value)
             }
-            result.push(c.to_lowercase().next().unwrap());
-            prev_lower = false;
-        } else if c == '-' || c == ' ' {
-            result.push('_');
-            prev_lower = false;
-        } else {
-            result.push(c);
-            prev_lower = c.is_lowercase();
-        }
-    }
-
-    // Handle Rust keywords
-    match result.as_str() {
-        "type" => "r#type".to_string(),
-        "self" => "r#self".to_string(),
-        "super" => "r#super".to_string(),
-        "crate" => "r#crate".to_string(),
-        "mod" => "r#mod".to_string(),
-        "fn" => "r#fn".to_string(),
-        "let" => "r#let".to_string(),
-        "if" => "r#if".to_string(),
-        "else" => "r#else".to_string(),
-        "match" => "r#match".to_string(),
-        "loop" => "r#loop".to_string(),
-        "while" => "r#while".to_string(),
-        "for" => "r#for".to_string(),
-        "in" => "r#in".to_string(),
-        "ref" => "r#ref".to_string(),
-        "mut" => "r#mut".to_string(),
-        "const" => "r#const".to_string(),
-        "static" => "r#static".to_string(),
-        "async" => "r#async".to_string(),
-        "await" => "r#await".to_string(),
-        "move" => "r#move".to_string(),
-        "return" => "r#return".to_string(),
-        "break" => "r#break".to_string(),
-        "continue" => "r#continue".to_string(),
-        _ => result,
-    }
-}
-
-fn to_pascal_case(s: &str) -> String {
-    let mut result = String::new();
-    let mut capitalize_next = true;
-
-    for c in s.chars() {
-        if c == '_' || c == '-' || c == ' ' {
-            capitalize_next = true;
-        } else if capitalize_next {
-            result.push(c.to_uppercase().next().unwrap());
-            capitalize_next = false;
-        } else {
-            result.push(c);
         }
+        Value::Object(_) => json_const(value),
+    }
+}
+
+/// A constant kept as its JSON text.
+fn json_const(value: &Value) -> (String, String) {
+    let json = value_to_json(value).to_string();
+    ("&str".to_string(), raw_string_literal(&json))
+}
+
+/// `#[deprecated]` attribute for annotated items, with a note when known.
+pub(super) fn deprecated_attr(annotations: &[Annotation]) -> Option<String> {
+    if !has_annotation(annotations, "deprecated") {
+        return None;
+    }
+    Some(deprecation_note(annotations).map_or_else(
+        || "#[deprecated]".to_string(),
+        |note| format!("#[deprecated(note = {note:?})]"),
+    ))
+}
+
+/// Describe `deprecated_since` and `sunset` annotations.
+fn deprecation_note(annotations: &[Annotation]) -> Option<String> {
+    let since = string_annotation(annotations, "deprecated_since");
+    let sunset = string_annotation(annotations, "sunset");
+    match (since, sunset) {
+        (Some(since), Some(sunset)) => Some(format!("deprecated since {since}, sunset {sunset}")),
+        (Some(since), None) => Some(format!("deprecated since {since}")),
+        (None, Some(sunset)) => Some(format!("sunset {sunset}")),
+        (None, None) => None,
+    }
+}
+
+pub(super) fn has_annotation(annotations: &[Annotation], kind: &str) -> bool {
+    annotations.iter().any(|a| a.kind == kind)
+}
+
+pub(super) fn string_annotation<'a>(annotations: &'a [Annotation], kind: &str) -> Option<&'a str> {
+    annotations
+        .iter()
+        .find(|a| a.kind == kind)
+        .and_then(|a| match &a.value {
+            Some(AnnotationValue::String(s)) => Some(s.as_str()),
+            _ => None,
+        })
+}
+
+pub(super) fn number_annotation(annotations: &[Annotation], kind: &str) -> Option<f64> {
+    annotations
+        .iter()
+        .find(|a| a.kind == kind)
+        .and_then(|a| match a.value {
+            Some(AnnotationValue::Number(n)) => Some(n),
+            _ => None,
+        })
+}
+
+pub(super) fn type_annotation<'a>(annotations: &'a [Annotation], kind: &str) -> Option<&'a Type> {
+    annotations
+        .iter()
+        .find(|a| a.kind == kind)
+        .and_then(|a| match &a.value {
+            Some(AnnotationValue::Type(t)) => Some(t.as_ref()),
+            _ => None,
+        })
+}
+
+fn list_annotation<'a>(annotations: &'a [Annotation], kind: &str) -> Option<&'a [AnnotationValue]> {
+    annotations
+        .iter()
+        .find(|a| a.kind == kind)
+        .and_then(|a| match &a.value {
+            Some(AnnotationValue::List(l)) => Some(l.as_slice()),
+            _ => None,
+        })
+}
+
+/// Serialized name of a field, which a rename may have moved into `serde_rename`.
+fn wire_name(field: &Field) -> &str {
+    string_annotation(&field.annotations, "serde_rename")
+        .or(field.name.as_deref())
+        .unwrap_or("_")
+}
+
+/// `snake_case` Rust identifier for a source name.
+pub(super) fn snake_ident(s: &str) -> String {
+    RUST.ident(s, Case::Snake)
+}
+
+/// `PascalCase` Rust identifier for a source name.
+pub(super) fn pascal_ident(s: &str) -> String {
+    RUST.ident(s, Case::Pascal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::openapi;
+
+    /// Generate Rust for the YAML `spec` with `options`, returning the root
+    /// module and the diagnostics of both steps.
+    fn generate_spec(name: &str, spec: &str, options: &Options) -> (String, Diagnostics) {
+        let (module, mut diagnostics) =
+            openapi::parse_spec(name, spec, &openapi::Options::default());
+        let output = tempfile::tempdir().unwrap();
+        generate(&module, output.path(), options, &mut diagnostics).unwrap();
+        (
+            fs::read_to_string(output.path().join("mod.rs")).unwrap(),
+            diagnostics,
+        )
+    }
+
+    fn variant(name: &str, value: Option<f64>) -> Variant {
+        Variant {
+            name: name.to_string(),
+            fields: Vec::new(),
+            annotations: value
+                .map(|v| Annotation::with_number("value", v))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn flag_sets_offer_both_serialized_forms() {
+        let mut out = String::new();
+        let variants = [variant("read", None), variant("write", None)];
+        generate_flag_set(&mut out, "Perms", &variants).unwrap();
+        assert!(out.contains("pub const READ: Self = Self(1 << 0);"));
+        assert!(out.contains("pub fn serialize_bits<S: serde::Serializer>(flags: &Self"));
+        assert!(out.contains("pub fn serialize_names<S: serde::Serializer>(flags: &Self"));
+        assert!(out.contains("Self::serialize_names(self, serializer)"));
+
+        let mut out = String::new();
+        let variants = [variant("read", Some(4.0)), variant("write", Some(2.0))];
+        generate_flag_set(&mut out, "Mode", &variants).unwrap();
+        assert!(out.contains("pub const READ: Self = Self(1 << 2);"));
+        assert!(out.contains("Self::serialize_bits(self, serializer)"));
+    }
+
+    #[test]
+    fn webhook_sets_get_an_event_enum_and_handler_trait() {
+        let (out, _) = generate_spec(
+            "handlers",
+            r#"
+openapi: 3.1.0
+info: {title: t, version: "1"}
+paths: {}
+webhooks:
+  newPet:
+    post:
+      operationId: newPet
+      requestBody:
+        content:
+          application/json:
+            schema: {type: string}
+      responses: {"200": {description: ok}}
+"#,
+            &Options::default(),
+        );
+        assert!(out.contains("pub enum WebhooksEvent {"));
+        assert!(out.contains("pub trait WebhooksHandler {"));
+        assert!(out.contains("pub async fn dispatch_webhooks<H: WebhooksHandler>("));
+        assert!(out.contains("    /// Event: POST newPet"), "{out}");
+        assert!(out.contains("fn new_pet(&self, body: String)"), "{out}");
+    }
+
+    #[test]
+    fn example_tests_cover_generated_types_only() {
+        let options = Options {
+            example_tests: true,
+            ..Options::default()
+        };
+        let (out, _) = generate_spec(
+            "example_tests",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      parameters:
+        - name: limit
+          in: query
+          schema: {type: integer}
+          example: 3
+        - name: kind
+          in: query
+          schema: {$ref: '#/components/schemas/Kind'}
+          example: dog
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Pet'}
+              example: {name: Rex}
+components:
+  schemas:
+    Kind: {type: string, enum: [dog, cat]}
+    Name: {type: string, example: Rex}
+    Pet:
+      type: object
+      example: {name: Rex}
+      required: [name]
+      properties:
+        name: {type: string, example: Rex}
+        kind: {$ref: '#/components/schemas/Kind'}
+"#,
+            &options,
+        );
+        let tests: Vec<_> = out
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("fn "))
+            .collect();
+        assert_eq!(
+            tests,
+            [
+                "pet_example_0() {",
+                "list_pets_kind_example_0() {",
+                "list_pets_response_example_0() {"
+            ]
+        );
+        assert!(out.contains(r##"serde_json::from_str(r#"{"name":"Rex"}"#)"##));
+    }
+
+    #[test]
+    fn defaults_become_serde_defaults() {
+        let (out, _) = generate_spec("defaults", openapi::tests::DEFAULTS, &Options::default());
+        for expected in [
+            "    #[serde(default = \"Pet::default_name\")]\n    pub name: String,",
+            "    fn default_name() -> String {\n        \"Rex\".to_string()",
+            "    fn default_size() -> f64 {\n        1.5",
+            "impl Default for Kind {\n    fn default() -> Self {\n        Kind::Cat",
+            "/// Default `limit`: `20`",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+        assert!(!out.contains("Pet::default_kind"));
+    }
+
+    const READ_WRITE_ONLY: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /pets:
+    post:
+      operationId: createPet
+      requestBody:
+        content:
+          application/json:
+            schema: {$ref: '#/components/schemas/Pet'}
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Pet'}
+components:
+  schemas:
+    Pet:
+      type: object
+      required: [id, name, password]
+      properties:
+        id: {type: integer, readOnly: true}
+        name: {type: string}
+        password: {type: string, writeOnly: true}
+"#;
+
+    fn generate_access(field_access: FieldAccess) -> String {
+        let options = Options {
+            field_access,
+            ..Options::default()
+        };
+        let name = format!("access_{field_access:?}").to_lowercase();
+        generate_spec(&name, READ_WRITE_ONLY, &options).0
+    }
+
+    #[test]
+    fn split_access_emits_request_shapes() {
+        let out = generate_access(FieldAccess::Split);
+        assert!(out.contains("pub struct Pet {\n    pub id: i64,\n    pub name: String,\n}"));
+        assert!(out.contains(
+            "pub struct PetRequest {\n    pub name: String,\n    pub password: String,\n}"
+        ));
+        assert!(out.contains("pub async fn create_pet(body: PetRequest) -> Result<Pet, ApiError>"));
+    }
+
+    #[test]
+    fn optional_access_makes_one_sided_fields_optional() {
+        let out = generate_access(FieldAccess::Optional);
+        assert!(out.contains("    pub id: Option<i64>,\n    pub name: String,\n"));
+        assert!(out.contains("    pub password: Option<String>,"));
+        assert!(!out.contains("PetRequest"));
+
+        let out = generate_access(FieldAccess::Preserve);
+        assert!(out.contains("    pub id: i64,\n    pub name: String,\n    pub password: String,"));
+    }
+
+    #[test]
+    fn deprecated_items_get_attributes() {
+        let (out, _) = generate_spec(
+            "deprecated",
+            openapi::tests::DEPRECATED,
+            &Options::default(),
+        );
+        for expected in [
+            "#[deprecated]\n#[derive(",
+            "    #[deprecated]\n    pub name: Option<String>,",
+            "#[deprecated(note = \"deprecated since 2.0, sunset 2025-01-01\")]\npub async fn old_list()",
+            "/// Deprecated `page`\npub async fn new_list(",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+    }
+
+    #[test]
+    fn rust_extensions_shape_the_output() {
+        let (out, diagnostics) = generate_spec(
+            "extensions",
+            openapi::tests::EXTENSIONS,
+            &Options::default(),
+        );
+        for expected in [
+            "    #[serde(rename = \"lo\")]\n    Low,",
+            "    pub born: Option<chrono::NaiveDate>,",
+            "    pub tag: Option<serde_json::Value>,",
+            "Result<Vec<serde_json::Value>, ApiError>",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+        assert!(!out.contains("secret"));
+        assert!(!out.contains("Unused"));
+        assert!(!out.contains("struct Tag"));
+        let skipped: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == diagnostics::UNSUPPORTED_SCHEMA)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                "schema `Tag` is skipped by `x-rust-skip` but still referenced; using `serde_json::Value` there"
+            ]
+        );
     }
 
-    result
+    #[test]
+    fn anonymous_unions_render_as_untagged_enums() {
+        let (out, _) = generate_spec(
+            "unions",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths: {}
+components:
+  schemas:
+    Dup:
+      oneOf: [{type: string}, {type: string, description: other}]
+    NullDup:
+      oneOf: [{type: string}, {type: string}, {type: "null"}]
+    Pet:
+      type: object
+      properties:
+        a:
+          oneOf: [{type: string}, {type: string}]
+        b:
+          nullable: true
+          oneOf: [{type: integer}, {type: integer}, {$ref: '#/components/schemas/Dup'}]
+        c:
+          oneOf: [{type: integer}, {type: string}, {type: integer}]
+"#,
+            &Options::default(),
+        );
+        for expected in [
+            "pub type Dup = String;",
+            "pub type NullDup = Option<String>;",
+            "    pub b: Option<PetB>,",
+            "#[serde(untagged)]\npub enum PetC {\n    Integer(i64),\n    String(String),\n}",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+    }
+
+    #[test]
+    fn low_confidence_items_are_marked_for_review() {
+        let options = Options {
+            review_threshold: 0.5,
+            ..Options::default()
+        };
+        let (out, diagnostics) = generate_spec(
+            "review",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /stores:
+    get:
+      operationId: getStore
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Store'}
+components:
+  schemas:
+    Pet:
+      type: object
+      properties:
+        owner: {$ref: '#/components/schemas/Owner'}
+    Store:
+      type: object
+      properties:
+        pets: {type: array, items: {$ref: '#/components/schemas/Pet'}}
+    Fine:
+      type: object
+      properties:
+        name: {type: string}
+"#,
+            &options,
+        );
+        let marker = "/// Review: generated with confidence 0.10, lowest at \
+                      `#/components/schemas/Pet/properties/owner`.";
+        let derive = "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]";
+        assert!(out.contains(&format!("{marker}\n{derive}\npub struct Pet {{")));
+        assert!(out.contains(&format!("{marker}\npub async fn get_store()")));
+        assert!(out.contains("    /// Review: generated with confidence 0.10.\n    pub owner:"));
+        assert!(out.contains(&format!("}}\n\n{derive}\npub struct Fine {{")));
+        let reviews: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == diagnostics::LOW_CONFIDENCE)
+            .map(|d| d.message.split(" needs review").next().unwrap())
+            .collect();
+        assert_eq!(
+            reviews,
+            ["type `Pet`", "type `Store`", "function `getStore`"]
+        );
+    }
 }
@@ -0,0 +1,2228 @@
+//! Raw Rust FFI bindings (`extern "C"`) for modules from the C front-end.
+//!
+//! Structs and unions become `#[repr(C)]` definitions, enums C-compatible
+//! Rust enums, typedefs type aliases and functions declarations in
+//! `unsafe extern` blocks. `Ptr` and `Array` references map to raw pointers
+//! and fixed-size arrays; nullable function pointers are wrapped in `Option`.
+//! Records whose C layout is known get bitfield accessors and compile-time
+//! layout assertions. Variadic functions end in `...`, and items, fields and
+//! variants with a `cfg` predicate are only compiled where it holds.
+//!
+//! Types with a `destructor` also get an owning `Owned{Type}` wrapper that
+//! releases them on drop, and method wrappers turn `ownership`, `nullable`
+//! and `nonnull` pointers into wrappers, references and `NonNull`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use rhi_concord_core::naming::{Case, Names, RUST};
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Field, Function, Item, Module, Param, Type, TypeKind, Value,
+    Variant,
+};
+
+use super::rust::{
+    Flag, STYLE, deprecated_attr, generate_flags, has_annotation, imports, number_annotation,
+    pascal_ident, review_module, review_note, snake_ident, string_annotation, type_annotation,
+};
+use crate::diagnostics::{self, Diagnostics};
+use crate::transform::{self, confidence};
+
+/// Options controlling FFI generation.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Library named in `#[link]` on every extern block, if any.
+    pub link: Option<String>,
+    /// Items generated with lower confidence are marked for review.
+    pub review_threshold: f32,
+}
+
+/// C types from `core::ffi`, imported where used.
+const C_TYPES: &[&str] = &[
+    "c_char",
+    "c_double",
+    "c_float",
+    "c_int",
+    "c_long",
+    "c_longlong",
+    "c_schar",
+    "c_short",
+    "c_uchar",
+    "c_uint",
+    "c_ulong",
+    "c_ulonglong",
+    "c_ushort",
+    "c_void",
+];
+
+/// Annotation holding the C symbol a function links against.
+const SYMBOL: &str = "c_symbol";
+
+/// Generate raw FFI bindings from IR.
+///
+/// Files are laid out as by [`super::rust::generate`]: the root module in
+/// `mod.rs` and each submodule in its own file.
+pub fn generate(
+    module: &Module,
+    output: &Path,
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> Result<()> {
+    let mut module = module.clone();
+    // Renaming below must not change which symbol a function links against
+    record_symbols(&mut module);
+    transform::resolve_collisions(&mut module, &STYLE, diagnostics);
+    lint_module(&mut module, diagnostics);
+    transform::propagate_confidence(&mut module);
+    review_module(&module, options.review_threshold, diagnostics);
+    let module = &module;
+
+    let mut ctx = Context {
+        paths: HashMap::new(),
+        types: HashMap::new(),
+        functions: HashMap::new(),
+        options,
+    };
+    collect_symbols(module, &mut Vec::new(), &mut ctx);
+
+    generate_module(module, &[], &output.join("mod.rs"), &ctx)
+}
+
+/// Record each function's C symbol before anything renames it.
+fn record_symbols(module: &mut Module) {
+    for item in &mut module.items {
+        if let Item::Function(func) = item
+            && !has_annotation(&func.annotations, SYMBOL)
+        {
+            func.annotations
+                .push(Annotation::with_string(SYMBOL, &func.name));
+        }
+    }
+    for sub in &mut module.submodules {
+        record_symbols(sub);
+    }
+}
+
+/// Report types with no exact Rust equivalent, lowering their confidence.
+fn lint_module(module: &mut Module, diagnostics: &mut Diagnostics) {
+    for item in &mut module.items {
+        match item {
+            Item::Type(typ) => match &mut typ.kind {
+                TypeKind::Struct { fields } => {
+                    let known = has_annotation(&typ.annotations, "size");
+                    for field in fields {
+                        lint_type(&mut field.typ, diagnostics);
+                        if !known && has_annotation(&field.annotations, "bitfield_width") {
+                            diagnostics.warning(
+                                diagnostics::UNSUPPORTED_FFI_TYPE,
+                                field.metadata.source.clone(),
+                                format!(
+                                    "bitfield `{}` has no known layout and is rendered as a whole field",
+                                    field.name.as_deref().unwrap_or_default()
+                                ),
+                            );
+                            confidence::lower(&mut field.metadata, confidence::PLACEHOLDER);
+                        }
+                    }
+                }
+                TypeKind::Enum { .. } => {}
+                _ => lint_type(typ, diagnostics),
+            },
+            Item::Function(func) => {
+                for arg in &mut func.args {
+                    lint_type(&mut arg.typ, diagnostics);
+                }
+                lint_type(&mut func.ret, diagnostics);
+            }
+            Item::Const {
+                name, typ, value, ..
+            } => {
+                if const_value(typ, value).is_none() {
+                    diagnostics.warning(
+                        diagnostics::UNSUPPORTED_FFI_TYPE,
+                        typ.metadata.source.clone(),
+                        format!("constant `{name}` has no Rust literal; skipping it"),
+                    );
+                }
+            }
+        }
+    }
+    for sub in &mut module.submodules {
+        lint_module(sub, diagnostics);
+    }
+}
+
+/// Lint a type in field, parameter or return position.
+fn lint_type(typ: &mut Type, diagnostics: &mut Diagnostics) {
+    let approximation = match &mut typ.kind {
+        TypeKind::Ref { name } if name == "c_longdouble" => {
+            Some("`long double` has no Rust equivalent and is rendered as `f64`")
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                lint_type(&mut param.typ, diagnostics);
+            }
+            lint_type(ret, diagnostics);
+            None
+        }
+        TypeKind::Ref { .. } => None,
+        _ if typ.name.is_some() => None,
+        _ => Some("inline type has no name and is rendered as `c_void`"),
+    };
+    if let Some(message) = approximation {
+        diagnostics.warning(
+            diagnostics::UNSUPPORTED_FFI_TYPE,
+            typ.metadata.source.clone(),
+            message,
+        );
+        confidence::lower(&mut typ.metadata, confidence::PLACEHOLDER);
+    }
+    for arg in &mut typ.args {
+        lint_type(arg, diagnostics);
+    }
+}
+
+/// Options plus the names visible across the generated module tree.
+struct Context<'a> {
+    /// Module path of every named type.
+    paths: HashMap<String, Vec<String>>,
+    /// Definitions of named types.
+    types: HashMap<&'a str, &'a Type>,
+    /// Functions by C symbol, with their module path.
+    functions: HashMap<&'a str, (&'a Function, Vec<String>)>,
+    options: &'a Options,
+}
+
+/// Record the module path and definition of every named type and function.
+fn collect_symbols<'a>(module: &'a Module, path: &mut Vec<String>, ctx: &mut Context<'a>) {
+    for item in &module.items {
+        match item {
+            Item::Type(typ) => {
+                if let Some(name) = &typ.name {
+                    ctx.paths.insert(name.clone(), path.clone());
+                    ctx.types.insert(name, typ);
+                }
+            }
+            Item::Function(func) => {
+                let symbol = string_annotation(&func.annotations, SYMBOL).unwrap_or(&func.name);
+                ctx.functions.insert(symbol, (func, path.clone()));
+            }
+            Item::Const { .. } => {}
+        }
+    }
+    for sub in &module.submodules {
+        path.push(snake_ident(&sub.name));
+        collect_symbols(sub, path, ctx);
+        path.pop();
+    }
+}
+
+fn generate_module(module: &Module, path: &[String], file: &Path, ctx: &Context) -> Result<()> {
+    let mut body = String::new();
+    let mut functions = Vec::new();
+    for item in &module.items {
+        match item {
+            Item::Type(typ) => generate_type(&mut body, typ, path, ctx)?,
+            Item::Function(func) => functions.push(func),
+            Item::Const {
+                name,
+                typ,
+                value,
+                annotations,
+            } => {
+                let Some(literal) = const_value(typ, value) else {
+                    continue;
+                };
+                let typ = match value {
+                    Value::String(_) => "&::core::ffi::CStr".to_string(),
+                    _ => ffi_type(typ, ctx),
+                };
+                if let Some(attr) = cfg_attr(annotations) {
+                    writeln!(body, "{attr}")?;
+                }
+                writeln!(body, "pub const {}: {typ} = {literal};", const_ident(name))?;
+                writeln!(body)?;
+            }
+        }
+    }
+    if contains_word(&body, "bitfield_get") {
+        write_bitfield_helpers(&mut body)?;
+    }
+    generate_externs(&mut body, &functions, ctx)?;
+    generate_impls(&mut body, &functions, path, ctx)?;
+    generate_callback_wrappers(&mut body, &functions, path, ctx)?;
+
+    let mut code = String::new();
+    if let Some(docs) = &module.metadata.docs {
+        for line in docs.lines() {
+            writeln!(code, "//! {line}")?;
+        }
+        writeln!(code)?;
+    }
+    if !module.submodules.is_empty() {
+        for sub in &module.submodules {
+            writeln!(code, "pub mod {};", snake_ident(&sub.name))?;
+        }
+        writeln!(code)?;
+    }
+
+    // Imports, some of which only items for other platforms may use
+    let used: Vec<&str> = C_TYPES
+        .iter()
+        .copied()
+        .filter(|c| contains_word(&body, c))
+        .collect();
+    let mut lines = match used.as_slice() {
+        [] => Vec::new(),
+        [c] => vec![format!("use core::ffi::{c};")],
+        _ => vec![format!("use core::ffi::{{{}}};", used.join(", "))],
+    };
+    lines.extend(imports(module, path, &ctx.paths));
+    let conditional = has_conditions(module);
+    for line in lines {
+        if conditional {
+            writeln!(code, "#[allow(unused_imports)]")?;
+        }
+        writeln!(code, "{line}")?;
+    }
+    if !code.is_empty() && !code.ends_with("\n\n") {
+        writeln!(code)?;
+    }
+    code.push_str(&body);
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, &code).with_context(|| format!("Failed to write {}", file.display()))?;
+
+    let dir = file.parent().unwrap_or(Path::new("."));
+    for sub in &module.submodules {
+        let sub_name = snake_ident(&sub.name);
+        let file_stem = sub_name.trim_start_matches("r#");
+        let sub_file = if sub.submodules.is_empty() {
+            dir.join(format!("{file_stem}.rs"))
+        } else {
+            dir.join(file_stem).join("mod.rs")
+        };
+        let mut sub_path = path.to_vec();
+        sub_path.push(sub_name);
+        generate_module(sub, &sub_path, &sub_file, ctx)?;
+    }
+
+    Ok(())
+}
+
+/// Whether any item, field or variant of `module` has a `cfg` predicate.
+fn has_conditions(module: &Module) -> bool {
+    let conditional = |annotations: &[Annotation]| has_annotation(annotations, "cfg");
+    module.items.iter().any(|item| match item {
+        Item::Type(typ) => {
+            conditional(&typ.annotations)
+                || match &typ.kind {
+                    TypeKind::Struct { fields } => {
+                        fields.iter().any(|f| conditional(&f.annotations))
+                    }
+                    TypeKind::Enum { variants } => {
+                        variants.iter().any(|v| conditional(&v.annotations))
+                    }
+                    _ => false,
+                }
+        }
+        Item::Function(func) => conditional(&func.annotations),
+        Item::Const { annotations, .. } => conditional(annotations),
+    })
+}
+
+/// Whether `word` occurs in `code` as a whole identifier.
+fn contains_word(code: &str, word: &str) -> bool {
+    code.match_indices(word).any(|(i, _)| {
+        let ident = |c: char| c.is_alphanumeric() || c == '_';
+        !code[..i].ends_with(ident) && !code[i + word.len()..].starts_with(ident)
+    })
+}
+
+/// Doc comment, review note, `#[deprecated]` and `#[cfg]` shared by all
+/// items.
+fn write_header(
+    out: &mut String,
+    indent: &str,
+    docs: Option<&str>,
+    review: Option<String>,
+    annotations: &[Annotation],
+) -> Result<()> {
+    if let Some(docs) = docs {
+        for line in docs.lines() {
+            let space = if line.is_empty() { "" } else { " " };
+            writeln!(out, "{indent}///{space}{line}")?;
+        }
+    }
+    if let Some(note) = review {
+        if docs.is_some() {
+            writeln!(out, "{indent}///")?;
+        }
+        writeln!(out, "{indent}/// Review: {note}.")?;
+    }
+    if let Some(attr) = deprecated_attr(annotations) {
+        writeln!(out, "{indent}{attr}")?;
+    }
+    if let Some(attr) = cfg_attr(annotations) {
+        writeln!(out, "{indent}{attr}")?;
+    }
+    Ok(())
+}
+
+/// `#[cfg(...)]` for a `cfg` annotation.
+fn cfg_attr(annotations: &[Annotation]) -> Option<String> {
+    string_annotation(annotations, "cfg").map(|cfg| format!("#[cfg({cfg})]"))
+}
+
+/// `code` with `attr` before every item after the first, which its header
+/// already covers. Items start at the first column after a blank line.
+fn attribute_items(code: &str, attr: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut blank = false;
+    for line in code.lines() {
+        if blank && !line.is_empty() && !line.starts_with([' ', '}']) {
+            out.push_str(attr);
+            out.push('\n');
+        }
+        blank = line.is_empty();
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn generate_type(out: &mut String, typ: &Type, path: &[String], ctx: &Context) -> Result<()> {
+    let Some(name) = &typ.name else {
+        return Ok(());
+    };
+    let Some(attr) = cfg_attr(&typ.annotations) else {
+        return generate_item(out, name, typ, path, ctx);
+    };
+    // Accessors, wrappers and assertions exist where the type does
+    let mut code = String::new();
+    generate_item(&mut code, name, typ, path, ctx)?;
+    out.push_str(&attribute_items(&code, &attr));
+    Ok(())
+}
+
+fn generate_item(
+    out: &mut String,
+    name: &str,
+    typ: &Type,
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    write_header(
+        out,
+        "",
+        typ.metadata.docs.as_deref(),
+        review_note(&typ.metadata, ctx.options.review_threshold),
+        &typ.annotations,
+    )?;
+
+    match &typ.kind {
+        TypeKind::Struct { .. } if has_annotation(&typ.annotations, "opaque") => {
+            generate_opaque(out, name)?;
+            generate_owned(out, name, typ, path, ctx)?;
+        }
+        TypeKind::Struct { fields } => {
+            generate_record(out, name, typ, fields, ctx)?;
+            generate_owned(out, name, typ, path, ctx)?;
+        }
+        TypeKind::Enum { variants } if has_annotation(&typ.annotations, "flags") => {
+            generate_flag_set(out, name, variants)?;
+        }
+        TypeKind::Enum { variants } => generate_enum(out, name, variants)?,
+        _ => {
+            // Typedefs: render the aliased type, not the alias itself
+            let target = Type {
+                name: None,
+                ..typ.clone()
+            };
+            writeln!(out, "pub type {name} = {};", ffi_type(&target, ctx))?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// A type only ever handled through pointers.
+fn generate_opaque(out: &mut String, name: &str) -> Result<()> {
+    writeln!(out, "#[repr(C)]")?;
+    writeln!(out, "pub struct {name} {{")?;
+    writeln!(out, "    _data: [u8; 0],")?;
+    writeln!(
+        out,
+        "    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,"
+    )?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// An owning wrapper releasing a struct with its `destructor` when dropped.
+fn generate_owned(
+    out: &mut String,
+    name: &str,
+    typ: &Type,
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    let Some((destructor, destructor_path)) = string_annotation(&typ.annotations, "destructor")
+        .and_then(|symbol| ctx.functions.get(symbol))
+    else {
+        return Ok(());
+    };
+    let destructor_fn = qualify(&snake_ident(&destructor.name), destructor_path, path);
+    let ptr = format!("::core::ptr::NonNull<{name}>");
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// Owned [`{name}`], released with `{destructor_fn}` when dropped."
+    )?;
+    writeln!(out, "pub struct Owned{name}({ptr});")?;
+    writeln!(out)?;
+    writeln!(out, "impl Owned{name} {{")?;
+    writeln!(out, "    /// Take ownership of `ptr`.")?;
+    writeln!(out, "    ///")?;
+    writeln!(out, "    /// # Safety")?;
+    writeln!(out, "    ///")?;
+    writeln!(
+        out,
+        "    /// `ptr` must point to a live `{name}` that nothing else releases."
+    )?;
+    writeln!(out, "    pub unsafe fn from_raw(ptr: {ptr}) -> Self {{")?;
+    writeln!(out, "        Self(ptr)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Give up ownership without releasing the object."
+    )?;
+    writeln!(out, "    pub fn into_raw(self) -> {ptr} {{")?;
+    writeln!(out, "        let ptr = self.0;")?;
+    writeln!(out, "        ::core::mem::forget(self);")?;
+    writeln!(out, "        ptr")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    /// The object, still owned by `self`.")?;
+    writeln!(out, "    pub fn as_ptr(&self) -> *mut {name} {{")?;
+    writeln!(out, "        self.0.as_ptr()")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::core::ops::Deref for Owned{name} {{")?;
+    writeln!(out, "    type Target = {name};")?;
+    writeln!(out)?;
+    writeln!(out, "    fn deref(&self) -> &{name} {{")?;
+    writeln!(out, "        unsafe {{ self.0.as_ref() }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::core::ops::DerefMut for Owned{name} {{")?;
+    writeln!(out, "    fn deref_mut(&mut self) -> &mut {name} {{")?;
+    writeln!(out, "        unsafe {{ self.0.as_mut() }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl Drop for Owned{name} {{")?;
+    if has_annotation(&destructor.annotations, "deprecated") {
+        writeln!(out, "    #[allow(deprecated)]")?;
+    }
+    writeln!(out, "    fn drop(&mut self) {{")?;
+    writeln!(
+        out,
+        "        unsafe {{ {destructor_fn}(self.0.as_ptr()) }};"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// `name` as seen from the module at `path`, given the module it's in.
+fn qualify(name: &str, target: &[String], path: &[String]) -> String {
+    if target == path {
+        return name.to_string();
+    }
+    let mut segments: Vec<&str> = if path.is_empty() {
+        vec!["self"]
+    } else {
+        vec!["super"; path.len()]
+    };
+    segments.extend(target.iter().map(String::as_str));
+    segments.push(name);
+    segments.join("::")
+}
+
+/// A `#[repr(C)]` struct or union.
+///
+/// Where the C layout is known, bitfields are packed into byte arrays with
+/// accessors, padding C adds for alignment attributes becomes explicit and
+/// the layout is asserted at compile time.
+fn generate_record(
+    out: &mut String,
+    name: &str,
+    typ: &Type,
+    fields: &[Field],
+    ctx: &Context,
+) -> Result<()> {
+    let union = has_annotation(&typ.annotations, "union");
+    let plan = plan_record(typ, fields, ctx, 0);
+    let mut repr = vec![
+        string_annotation(&typ.annotations, "repr")
+            .unwrap_or("C")
+            .to_string(),
+    ];
+    match layout_annotation(&typ.annotations, "packed") {
+        Some(pack) if pack > 1 => repr.push(format!("packed({pack})")),
+        _ if has_annotation(&typ.annotations, "packed") => repr.push("packed".to_string()),
+        _ => {}
+    }
+    if let Some(align) = plan.as_ref().and_then(|p| p.align) {
+        repr.push(format!("align({align})"));
+    }
+    writeln!(out, "#[repr({})]", repr.join(", "))?;
+    if !union && derives(typ, ctx, true, 0) {
+        writeln!(out, "#[derive(Debug, Clone, Copy)]")?;
+    } else if derives(typ, ctx, false, 0) {
+        writeln!(out, "#[derive(Clone, Copy)]")?;
+    }
+    let keyword = if union { "union" } else { "struct" };
+    writeln!(out, "pub {keyword} {name} {{")?;
+    let members = plan.as_ref().map_or_else(
+        || {
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, f)| Member::Field(i, f))
+                .collect()
+        },
+        |p| p.members.clone(),
+    );
+    for member in &members {
+        let (i, field) = match member {
+            Member::Field(i, field) => (i, field),
+            Member::Bytes { ident, length } => {
+                writeln!(out, "    pub {ident}: [u8; {length}],")?;
+                continue;
+            }
+        };
+        write_header(
+            out,
+            "    ",
+            field.metadata.docs.as_deref(),
+            review_note(&field.metadata, ctx.options.review_threshold),
+            &field.annotations,
+        )?;
+        writeln!(
+            out,
+            "    pub {}: {},",
+            field_ident(*i, field),
+            ffi_type(&field.typ, ctx)
+        )?;
+    }
+    writeln!(out, "}}")?;
+
+    if let Some(plan) = &plan {
+        generate_bitfields(out, name, union, &plan.bitfields, ctx)?;
+        generate_layout_assertions(out, name, typ, union, &plan.members)?;
+    }
+    Ok(())
+}
+
+/// Getters and setters for the named bitfields of a record.
+///
+/// Bitfields of enum type are read and written as their integer value.
+fn generate_bitfields(
+    out: &mut String,
+    name: &str,
+    union: bool,
+    bitfields: &[Bitfield],
+    ctx: &Context,
+) -> Result<()> {
+    // Accessors work on at most 64 bits
+    let bitfields: Vec<&Bitfield> = bitfields.iter().filter(|b| b.width <= 64).collect();
+    if bitfields.is_empty() {
+        return Ok(());
+    }
+    writeln!(out)?;
+    writeln!(out, "impl {name} {{")?;
+    for (n, bitfield) in bitfields.iter().enumerate() {
+        let Bitfield {
+            field,
+            storage,
+            offset,
+            width,
+        } = bitfield;
+        let field_name = field.name.as_deref().unwrap_or_default();
+        let ident = snake_ident(field_name);
+        let (typ, signed) = bitfield_type(&field.typ, ctx);
+        // Union members can only be borrowed in `unsafe`
+        let (bytes, bytes_mut) = if union {
+            (
+                format!("unsafe {{ &self.{storage} }}"),
+                format!("unsafe {{ &mut self.{storage} }}"),
+            )
+        } else {
+            (format!("&self.{storage}"), format!("&mut self.{storage}"))
+        };
+        let get = format!("bitfield_get({bytes}, {offset}, {width})");
+        let (value, raw) = match typ.as_str() {
+            "bool" => (format!("{get} != 0"), "u64::from(value)".to_string()),
+            _ if signed => {
+                let shift = 64 - width;
+                (
+                    format!("(({get} << {shift}) as i64 >> {shift}) as {typ}"),
+                    "value as u64".to_string(),
+                )
+            }
+            _ => (format!("{get} as {typ}"), "value as u64".to_string()),
+        };
+
+        if n > 0 {
+            writeln!(out)?;
+        }
+        write_header(
+            out,
+            "    ",
+            field.metadata.docs.as_deref(),
+            review_note(&field.metadata, ctx.options.review_threshold),
+            &field.annotations,
+        )?;
+        if field.metadata.docs.is_none() {
+            writeln!(out, "    /// The `{field_name}` bitfield.")?;
+        }
+        writeln!(out, "    pub fn {ident}(&self) -> {typ} {{")?;
+        writeln!(out, "        {value}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        let bits = if *width == 1 { "bit" } else { "bits" };
+        writeln!(
+            out,
+            "    /// Set the `{field_name}` bitfield to the low {width} {bits} of `value`."
+        )?;
+        let setter = snake_ident(&format!("set_{field_name}"));
+        writeln!(out, "    pub fn {setter}(&mut self, value: {typ}) {{")?;
+        writeln!(
+            out,
+            "        bitfield_set({bytes_mut}, {offset}, {width}, {raw});"
+        )?;
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Rust type of a bitfield's accessors, and whether it is signed. Enums and
+/// anything else that isn't an integer are read as `c_uint`.
+fn bitfield_type(typ: &Type, ctx: &Context) -> (String, bool) {
+    let mut target = typ;
+    for _ in 0..32 {
+        let TypeKind::Ref { name } = &target.kind else {
+            break;
+        };
+        match name.as_str() {
+            "bool" | "c_uchar" | "c_ushort" | "c_uint" | "c_ulong" | "c_ulonglong" | "u8"
+            | "u16" | "u32" | "u64" | "usize" => return (ffi_type(typ, ctx), false),
+            // `char` bitfields are signed on the ABIs layouts are computed for
+            "c_char" | "c_schar" | "c_short" | "c_int" | "c_long" | "c_longlong" | "i8" | "i16"
+            | "i32" | "i64" | "isize" => return (ffi_type(typ, ctx), true),
+            name => match ctx.types.get(name) {
+                Some(definition) if matches!(definition.kind, TypeKind::Ref { .. }) => {
+                    target = definition;
+                }
+                _ => break,
+            },
+        }
+    }
+    ("c_uint".to_string(), false)
+}
+
+/// Bit accessors shared by every record with bitfields in a module.
+///
+/// Bits are numbered from the least significant bit of the first byte, as
+/// C compilers allocate them on little-endian targets.
+fn write_bitfield_helpers(out: &mut String) -> Result<()> {
+    writeln!(
+        out,
+        "/// Read `width` bits starting `offset` bits into `bytes`."
+    )?;
+    writeln!(
+        out,
+        "fn bitfield_get(bytes: &[u8], offset: usize, width: usize) -> u64 {{"
+    )?;
+    writeln!(out, "    (0..width).fold(0, |value, i| {{")?;
+    writeln!(out, "        let bit = offset + i;")?;
+    writeln!(
+        out,
+        "        value | u64::from(bytes[bit / 8] >> (bit % 8) & 1) << i"
+    )?;
+    writeln!(out, "    }})")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// Write the low `width` bits of `value` starting `offset` bits into `bytes`."
+    )?;
+    writeln!(
+        out,
+        "fn bitfield_set(bytes: &mut [u8], offset: usize, width: usize, value: u64) {{"
+    )?;
+    writeln!(out, "    for i in 0..width {{")?;
+    writeln!(out, "        let bit = offset + i;")?;
+    writeln!(out, "        let mask = 1 << (bit % 8);")?;
+    writeln!(out, "        if value >> i & 1 == 0 {{")?;
+    writeln!(out, "            bytes[bit / 8] &= !mask;")?;
+    writeln!(out, "        }} else {{")?;
+    writeln!(out, "            bytes[bit / 8] |= mask;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Compile-time checks that a record's Rust layout matches C's. Layouts are
+/// computed for LP64 targets, so other targets skip them.
+fn generate_layout_assertions(
+    out: &mut String,
+    name: &str,
+    typ: &Type,
+    union: bool,
+    members: &[Member],
+) -> Result<()> {
+    let (Some(size), Some(align)) = (
+        layout_annotation(&typ.annotations, "size"),
+        layout_annotation(&typ.annotations, "align"),
+    ) else {
+        return Ok(());
+    };
+    writeln!(out)?;
+    writeln!(
+        out,
+        "#[cfg(all(target_pointer_width = \"64\", not(windows)))]"
+    )?;
+    writeln!(out, "const _: () = {{")?;
+    writeln!(
+        out,
+        "    assert!(::core::mem::size_of::<{name}>() == {size});"
+    )?;
+    writeln!(
+        out,
+        "    assert!(::core::mem::align_of::<{name}>() == {align});"
+    )?;
+    // Every union member is at offset zero
+    if !union {
+        for member in members {
+            if let Member::Field(i, field) = member
+                && let Some(offset) = layout_annotation(&field.annotations, "offset")
+            {
+                writeln!(
+                    out,
+                    "    assert!(::core::mem::offset_of!({name}, {}) == {offset});",
+                    field_ident(*i, field)
+                )?;
+            }
+        }
+    }
+    writeln!(out, "}};")?;
+    Ok(())
+}
+
+/// Rust member for a C field; unnamed ones are numbered by position.
+fn field_ident(i: usize, field: &Field) -> String {
+    field
+        .name
+        .as_deref()
+        .map_or_else(|| format!("_unnamed{i}"), snake_ident)
+}
+
+/// A member of a generated struct or union.
+#[derive(Clone)]
+enum Member<'a> {
+    /// The C field at this index.
+    Field(usize, &'a Field),
+    /// Bitfield storage or padding.
+    Bytes { ident: String, length: u64 },
+}
+
+/// A bitfield within a byte array member.
+struct Bitfield<'a> {
+    field: &'a Field,
+    storage: String,
+    /// Offset in bits from the start of `storage`.
+    offset: u64,
+    width: u64,
+}
+
+/// How a record with a known C layout is laid out in Rust.
+struct Plan<'a> {
+    members: Vec<Member<'a>>,
+    bitfields: Vec<Bitfield<'a>>,
+    /// Alignment to request beyond that of the members.
+    align: Option<u64>,
+}
+
+impl<'a> Plan<'a> {
+    /// Add a byte array at byte `start` holding the bitfields `run`,
+    /// returning its length.
+    fn add_bitfields(&mut self, run: &[&'a Field], start: u64, storage: String) -> Option<u64> {
+        let mut length = 0;
+        for field in run {
+            let offset = layout_annotation(&field.annotations, "bit_offset")?;
+            let offset = offset.checked_sub(start * 8)?;
+            let width = layout_annotation(&field.annotations, "bitfield_width")?;
+            length = length.max((offset + width).div_ceil(8));
+            if field.name.is_some() && width > 0 {
+                self.bitfields.push(Bitfield {
+                    field,
+                    storage: storage.clone(),
+                    offset,
+                    width,
+                });
+            }
+        }
+        self.members.push(Member::Bytes {
+            ident: storage,
+            length,
+        });
+        Some(length)
+    }
+}
+
+/// Lay out a record from its C `size`, `align` and field offsets, or `None`
+/// where they aren't known or Rust can't reproduce them.
+fn plan_record<'a>(
+    typ: &Type,
+    fields: &'a [Field],
+    ctx: &Context,
+    depth: usize,
+) -> Option<Plan<'a>> {
+    // Definitions can't contain themselves by value; this only guards bad input
+    if depth > 32 {
+        return None;
+    }
+    let size = layout_annotation(&typ.annotations, "size")?;
+    let align = layout_annotation(&typ.annotations, "align")?;
+    let pack = has_annotation(&typ.annotations, "packed")
+        .then(|| layout_annotation(&typ.annotations, "packed").unwrap_or(1));
+    let union = has_annotation(&typ.annotations, "union");
+    let is_bitfield = |field: &Field| has_annotation(&field.annotations, "bitfield_width");
+
+    let mut plan = Plan {
+        members: Vec::new(),
+        bitfields: Vec::new(),
+        align: None,
+    };
+    let (mut storages, mut paddings) = (0, 0);
+    // End of the members so far, or the largest member of a union
+    let mut cursor = 0;
+    let mut natural = 1;
+    let mut i = 0;
+    while i < fields.len() {
+        let field = &fields[i];
+        if is_bitfield(field) {
+            // Bitfields next to each other share one byte array; in a union
+            // all of them do
+            let run: Vec<&Field> = match (union, storages) {
+                (true, 0) => fields.iter().filter(|f| is_bitfield(f)).collect(),
+                (true, _) => Vec::new(),
+                (false, _) => fields[i..].iter().take_while(|f| is_bitfield(f)).collect(),
+            };
+            i += if union { 1 } else { run.len() };
+            if run.is_empty() {
+                continue;
+            }
+            let start = if union { 0 } else { cursor };
+            storages += 1;
+            let length = plan.add_bitfields(&run, start, format!("_bitfield_{storages}"))?;
+            cursor = if union {
+                cursor.max(length)
+            } else {
+                start + length
+            };
+            continue;
+        }
+
+        let offset = layout_annotation(&field.annotations, "offset")?;
+        let (field_size, field_align) = rust_layout(&field.typ, ctx, depth + 1)?;
+        let field_align = pack.map_or(field_align, |pack| field_align.min(pack));
+        natural = natural.max(field_align);
+        if union {
+            if offset != 0 {
+                return None;
+            }
+            cursor = cursor.max(field_size);
+        } else {
+            // Alignment attributes on a field leave padding Rust must spell out
+            let placed = cursor.next_multiple_of(field_align);
+            if offset < placed {
+                return None;
+            }
+            if offset > placed {
+                paddings += 1;
+                plan.members.push(Member::Bytes {
+                    ident: format!("_padding_{paddings}"),
+                    length: offset - cursor,
+                });
+            }
+            cursor = offset + field_size;
+        }
+        plan.members.push(Member::Field(i, field));
+        i += 1;
+    }
+
+    // Rust can't raise the alignment of a packed type
+    if align > natural {
+        if pack.is_some() {
+            return None;
+        }
+        plan.align = Some(align);
+    }
+    let end = cursor.next_multiple_of(align.max(natural));
+    if size < end || align < natural {
+        return None;
+    }
+    if size > end {
+        paddings += 1;
+        plan.members.push(Member::Bytes {
+            ident: format!("_padding_{paddings}"),
+            length: if union { size } else { size - cursor },
+        });
+    }
+    Some(plan)
+}
+
+/// Size and alignment of a Rust field type, where they are certain to match
+/// C's on LP64 targets.
+fn rust_layout(typ: &Type, ctx: &Context, depth: usize) -> Option<(u64, u64)> {
+    if depth > 32 {
+        return None;
+    }
+    let scalar = |size| Some((size, size));
+    let TypeKind::Ref { name } = &typ.kind else {
+        // Inline types are rendered as `c_void`, functions only as pointers
+        return None;
+    };
+    match name.as_str() {
+        "Array" => {
+            let (size, align) = rust_layout(typ.args.first()?, ctx, depth + 1)?;
+            Some((size * array_length(typ) as u64, align))
+        }
+        "bool" | "c_char" | "c_schar" | "c_uchar" | "i8" | "u8" => scalar(1),
+        "c_short" | "c_ushort" | "i16" | "u16" => scalar(2),
+        "c_int" | "c_uint" | "c_float" | "i32" | "u32" => scalar(4),
+        "Ptr" | "c_long" | "c_ulong" | "c_longlong" | "c_ulonglong" | "c_double" | "i64"
+        | "u64" | "isize" | "usize" => scalar(8),
+        "i128" | "u128" => scalar(16),
+        // `long double` is approximated and `va_list` is a pointer in Rust
+        "c_longdouble" | "VaList" | "c_void" | "Unit" => None,
+        name => {
+            let definition = ctx.types.get(name)?;
+            match &definition.kind {
+                TypeKind::Struct { fields } => {
+                    plan_record(definition, fields, ctx, depth + 1)?;
+                    Some((
+                        layout_annotation(&definition.annotations, "size")?,
+                        layout_annotation(&definition.annotations, "align")?,
+                    ))
+                }
+                // Every enum repr and flag set is 4 bytes unless values need 8
+                TypeKind::Enum { variants } => {
+                    let values: Vec<i64> = variants.iter().map(enumerator_value).collect();
+                    let (min, max) = (values.iter().min(), values.iter().max());
+                    let int = min.is_none_or(|&v| v >= i64::from(i32::MIN))
+                        && max.is_none_or(|&v| v <= i64::from(i32::MAX));
+                    let uint = min.is_none_or(|&v| v >= 0)
+                        && max.is_none_or(|&v| v <= i64::from(u32::MAX));
+                    scalar(if int || uint { 4 } else { 8 })
+                }
+                TypeKind::Function { .. } => None,
+                _ => rust_layout(definition, ctx, depth + 1),
+            }
+        }
+    }
+}
+
+/// A non-negative integer layout annotation, such as `size` or `offset`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn layout_annotation(annotations: &[Annotation], kind: &str) -> Option<u64> {
+    number_annotation(annotations, kind)
+        .filter(|n| *n >= 0.0)
+        .map(|n| n as u64)
+}
+
+/// A C enum as a Rust enum with the same representation.
+///
+/// Enumerators repeating an earlier value become associated constants, since
+/// Rust discriminants must be unique.
+fn generate_enum(out: &mut String, name: &str, variants: &[Variant]) -> Result<()> {
+    let values: Vec<i64> = variants.iter().map(enumerator_value).collect();
+    let Some(repr) = enum_repr(&values) else {
+        writeln!(out, "pub type {name} = c_int;")?;
+        return Ok(());
+    };
+    writeln!(out, "#[repr({repr})]")?;
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]")?;
+    writeln!(out, "pub enum {name} {{")?;
+    let mut seen: HashMap<i64, String> = HashMap::new();
+    let mut aliases = Vec::new();
+    for (variant, value) in variants.iter().zip(&values) {
+        let ident = pascal_ident(&variant.name);
+        if let Some(original) = seen.get(value) {
+            aliases.push((variant, ident, original.clone()));
+            continue;
+        }
+        if let Some(attr) = deprecated_attr(&variant.annotations) {
+            writeln!(out, "    {attr}")?;
+        }
+        let cfg = cfg_attr(&variant.annotations);
+        if let Some(attr) = &cfg {
+            writeln!(out, "    {attr}")?;
+        }
+        writeln!(out, "    {ident} = {value},")?;
+        // Aliases can't refer to a variant that only exists on some platforms
+        if cfg.is_none() {
+            seen.insert(*value, ident);
+        }
+    }
+    writeln!(out, "}}")?;
+
+    if aliases.is_empty() {
+        return Ok(());
+    }
+    writeln!(out)?;
+    writeln!(out, "impl {name} {{")?;
+    for (variant, ident, original) in aliases {
+        if let Some(attr) = deprecated_attr(&variant.annotations) {
+            writeln!(out, "    {attr}")?;
+        }
+        if let Some(attr) = cfg_attr(&variant.annotations) {
+            writeln!(out, "    {attr}")?;
+        }
+        writeln!(out, "    #[allow(non_upper_case_globals)]")?;
+        writeln!(out, "    pub const {ident}: Self = Self::{original};")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Generate a `flags` enum as a transparent set of bits over the integer
+/// type C passes it as.
+fn generate_flag_set(out: &mut String, name: &str, variants: &[Variant]) -> Result<()> {
+    let values: Vec<i64> = variants.iter().map(enumerator_value).collect();
+    let (min, max) = (values.iter().min(), values.iter().max());
+    let repr = match (min, max) {
+        (Some(min), Some(max))
+            if *min < 0 && *min >= i64::from(i32::MIN) && *max <= i64::from(i32::MAX) =>
+        {
+            "c_int"
+        }
+        (Some(min), _) if *min < 0 => "i64",
+        (_, Some(max)) if *max > i64::from(u32::MAX) => "u64",
+        _ => "c_uint",
+    };
+    let mut idents = Names::default();
+    idents.reserve("FLAGS");
+    let flags: Vec<_> = variants
+        .iter()
+        .zip(values)
+        .map(|(variant, bits)| Flag {
+            ident: idents.claim(&const_ident(&variant.name)),
+            name: &variant.name,
+            bits: i128::from(bits),
+            variant,
+        })
+        .collect();
+    writeln!(out, "#[repr(transparent)]")?;
+    writeln!(out, "#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]")?;
+    generate_flags(out, name, repr, &flags)
+}
+
+/// Value of an enumerator, from its `value` annotation.
+#[allow(clippy::cast_possible_truncation)]
+fn enumerator_value(variant: &Variant) -> i64 {
+    variant
+        .annotations
+        .iter()
+        .find(|a| a.kind == "value")
+        .and_then(|a| match a.value {
+            Some(AnnotationValue::Number(n)) => Some(n as i64),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Representation fitting every enumerator, as a C compiler would pick it.
+fn enum_repr(values: &[i64]) -> Option<&'static str> {
+    let (min, max) = (values.iter().min()?, values.iter().max()?);
+    Some(
+        if *min >= i64::from(i32::MIN) && *max <= i64::from(i32::MAX) {
+            "C"
+        } else if *min >= 0 && *max <= i64::from(u32::MAX) {
+            "u32"
+        } else {
+            "i64"
+        },
+    )
+}
+
+/// Functions grouped into one `unsafe extern` block per calling convention.
+fn generate_externs(out: &mut String, functions: &[&Function], ctx: &Context) -> Result<()> {
+    let mut blocks: Vec<(&str, Vec<&Function>)> = Vec::new();
+    for func in functions {
+        let abi = abi(&func.annotations);
+        match blocks.iter_mut().find(|(a, _)| *a == abi) {
+            Some((_, funcs)) => funcs.push(func),
+            None => blocks.push((abi, vec![func])),
+        }
+    }
+
+    for (abi, funcs) in blocks {
+        if let Some(link) = &ctx.options.link {
+            writeln!(out, "#[link(name = {link:?})]")?;
+        }
+        writeln!(out, "unsafe extern {abi:?} {{")?;
+        for (i, func) in funcs.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            generate_function(out, func, ctx)?;
+        }
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn generate_function(out: &mut String, func: &Function, ctx: &Context) -> Result<()> {
+    write_header(
+        out,
+        "    ",
+        func.metadata.docs.as_deref(),
+        review_note(&func.metadata, ctx.options.review_threshold),
+        &func.annotations,
+    )?;
+    let ident = snake_ident(&func.name);
+    let symbol = string_annotation(&func.annotations, SYMBOL).unwrap_or(&func.name);
+    if ident != symbol {
+        writeln!(out, "    #[link_name = {symbol:?}]")?;
+    }
+
+    let mut names = Names::default();
+    let mut args: Vec<String> = func
+        .args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let name = arg
+                .name
+                .as_deref()
+                .map_or_else(|| format!("arg{i}"), snake_ident);
+            format!("{}: {}", names.claim(&name), ffi_type(&arg.typ, ctx))
+        })
+        .collect();
+    if has_annotation(&func.annotations, "variadic") {
+        args.push("...".to_string());
+    }
+    writeln!(
+        out,
+        "    pub fn {ident}({}){};",
+        args.join(", "),
+        ret_type(&func.ret, ctx)
+    )?;
+    Ok(())
+}
+
+/// `impl` blocks calling functions attached to types by `method_of`.
+fn generate_impls(
+    out: &mut String,
+    functions: &[&Function],
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    let mut impls: Vec<(String, Vec<&Function>)> = Vec::new();
+    for func in functions {
+        let Some(owner) = type_annotation(&func.annotations, "method_of") else {
+            continue;
+        };
+        let owner = ffi_type(owner, ctx);
+        match impls.iter_mut().find(|(o, _)| *o == owner) {
+            Some((_, funcs)) => funcs.push(func),
+            None => impls.push((owner, vec![func])),
+        }
+    }
+
+    for (owner, funcs) in impls {
+        // An owner only declared on some platforms has only methods declared
+        // there too
+        let cfg = cfg_attr(&funcs[0].annotations);
+        if let Some(attr) = &cfg
+            && funcs.iter().all(|f| cfg_attr(&f.annotations) == cfg)
+        {
+            writeln!(out, "{attr}")?;
+        }
+        writeln!(out, "impl {owner} {{")?;
+        for (i, func) in funcs.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            generate_method(out, func, path, ctx)?;
+        }
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// A method forwarding to the raw function, taking the handle as `self`.
+fn generate_method(
+    out: &mut String,
+    func: &Function,
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    let receiver = string_annotation(&func.annotations, "receiver").unwrap_or("static");
+    let name = string_annotation(&func.annotations, "method_name").unwrap_or(&func.name);
+    generate_wrapper(out, "    ", func, &snake_ident(name), receiver, path, ctx)
+}
+
+/// Wrappers for free functions taking callbacks, named `{function}_with`.
+fn generate_callback_wrappers(
+    out: &mut String,
+    functions: &[&Function],
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    for func in functions {
+        if type_annotation(&func.annotations, "method_of").is_none()
+            && func.args.iter().any(|a| callback(a, ctx).is_some())
+        {
+            let name = format!("{}_with", snake_ident(&func.name));
+            generate_wrapper(out, "", func, &name, "static", path, ctx)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// A C callback argument, taken by the wrapper as a closure.
+struct Callback<'a> {
+    /// Parameters of the C function type.
+    params: &'a [Param],
+    ret: &'a Type,
+    abi: &'static str,
+    user_data_arg: usize,
+    user_data_param: usize,
+    destroy_notify_arg: Option<usize>,
+    /// Only called during the call, so the closure can stay on the stack.
+    scoped: bool,
+}
+
+/// Callbacks among the arguments after the first `skip`, by index.
+fn callbacks<'a>(func: &'a Function, skip: usize, ctx: &Context<'a>) -> Vec<(usize, Callback<'a>)> {
+    func.args
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .filter_map(|(i, arg)| Some((i, callback(arg, ctx)?)))
+        .collect()
+}
+
+/// The callback an argument carries, as paired by `detect_callbacks`.
+fn callback<'a>(arg: &'a Param, ctx: &Context<'a>) -> Option<Callback<'a>> {
+    let index = |kind| {
+        arg.annotations.iter().find_map(|a| match a.value {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Some(AnnotationValue::Number(n)) if a.kind == kind => Some(n as usize),
+            _ => None,
+        })
+    };
+    let function = function_type(&arg.typ, ctx)?;
+    let TypeKind::Function { params, ret } = &function.kind else {
+        return None;
+    };
+    Some(Callback {
+        params,
+        ret,
+        abi: abi(&function.annotations),
+        user_data_arg: index("user_data_arg")?,
+        user_data_param: index("user_data_param")?,
+        destroy_notify_arg: index("destroy_notify_arg"),
+        scoped: string_annotation(&arg.typ.annotations, "ownership") == Some("borrowed"),
+    })
+}
+
+/// The function type a function pointer points to, through typedefs.
+fn function_type<'a>(typ: &'a Type, ctx: &Context<'a>) -> Option<&'a Type> {
+    let mut typ = typ;
+    // Bounded in case of a typedef cycle
+    for _ in 0..16 {
+        match &typ.kind {
+            TypeKind::Function { .. } => return Some(typ),
+            TypeKind::Ref { name } if name == "Ptr" => typ = typ.args.first()?,
+            TypeKind::Ref { name } if typ.args.is_empty() => typ = ctx.types.get(name.as_str())?,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// A function forwarding to the raw one, taking the handle as `self` unless
+/// `receiver` is `static`.
+///
+/// Pointers with a known `ownership` or nullability are passed and returned
+/// as owning wrappers, references or `NonNull`, and callbacks as closures
+/// called through a trampoline. Wrappers are `unsafe` when they release the
+/// handle, pass on raw pointers, rest on a guessed attachment or ownership
+/// (below [`confidence::METHOD_MATCHED`]) or hand C a closure to keep, since
+/// nothing stops C calling it from another thread or re-entering it.
+fn generate_wrapper(
+    out: &mut String,
+    indent: &str,
+    func: &Function,
+    name: &str,
+    receiver: &str,
+    path: &[String],
+    ctx: &Context,
+) -> Result<()> {
+    let self_param = match (receiver, func.args.first()) {
+        ("ref", Some(_)) => Some("&self"),
+        ("mut" | "owned", Some(_)) => Some("&mut self"),
+        _ => None,
+    };
+    let skip = usize::from(self_param.is_some());
+    let callbacks = callbacks(func, skip, ctx);
+    let passed_for_callback = |i: usize| {
+        callbacks
+            .iter()
+            .any(|(_, c)| c.user_data_arg == i || c.destroy_notify_arg == Some(i))
+    };
+
+    let mut names = Names::default();
+    names.reserve("self");
+    let mut params: Vec<String> = self_param.into_iter().map(String::from).collect();
+    let mut call: Vec<String> = self_param.map(|_| "self".to_string()).into_iter().collect();
+    let mut arg_names = HashMap::new();
+    let mut raw_pointers = false;
+    for (i, arg) in func.args.iter().enumerate().skip(skip) {
+        if passed_for_callback(i) {
+            continue;
+        }
+        let arg_name = arg
+            .name
+            .as_deref()
+            .map_or_else(|| format!("arg{}", i - skip), snake_ident);
+        let arg_name = names.claim(&arg_name);
+        arg_names.insert(i, arg_name.clone());
+        if let Some(n) = callbacks.iter().position(|(c, _)| *c == i) {
+            params.push(format!("{arg_name}: F{n}"));
+            continue;
+        }
+        let (param, value, safe) =
+            method_arg(&arg.typ, &arg_name, path, ctx).unwrap_or_else(|| {
+                let safe = !is_pointer(&arg.typ, ctx, 0);
+                (ffi_type(&arg.typ, ctx), arg_name.clone(), safe)
+            });
+        raw_pointers |= !safe;
+        params.push(format!("{arg_name}: {param}"));
+        call.push(value);
+    }
+
+    let Closures {
+        generics,
+        prelude,
+        retained,
+        leaked,
+        mut values,
+    } = closures(&callbacks, &arg_names, func, ctx);
+    // Arguments in C order, callbacks and their user data in place
+    let mut call = call.into_iter();
+    let call: Vec<String> = (0..func.args.len())
+        .filter_map(|i| values.remove(&i).or_else(|| call.next()))
+        .collect();
+    let (ret_type, convert) = match method_ret(&func.ret, receiver, path, ctx) {
+        Some((typ, convert)) => (format!(" -> {typ}"), Some(convert)),
+        None => (ret_type(&func.ret, ctx), None),
+    };
+
+    let released = receiver == "owned";
+    let docs = wrapper_docs(func, &retained, &leaked, released, raw_pointers);
+    write_header(
+        out,
+        indent,
+        docs.as_deref(),
+        review_note(&func.metadata, ctx.options.review_threshold),
+        &func.annotations,
+    )?;
+    if has_annotation(&func.annotations, "deprecated") {
+        writeln!(out, "{indent}#[allow(deprecated)]")?;
+    }
+
+    let unsafety = if released || raw_pointers || is_guessed(func) || !retained.is_empty() {
+        "unsafe "
+    } else {
+        ""
+    };
+    let generics = if generics.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", generics.join(", "))
+    };
+    writeln!(
+        out,
+        "{indent}pub {unsafety}fn {name}{generics}({}){ret_type} {{",
+        params.join(", "),
+    )?;
+    for line in prelude.iter().flat_map(|p| p.lines()) {
+        writeln!(out, "{indent}    {line}")?;
+    }
+    let call = format!(
+        "unsafe {{ {}({}) }}",
+        snake_ident(&func.name),
+        call.join(", ")
+    );
+    match convert {
+        Some(convert) => {
+            writeln!(out, "{indent}    let ret = {call};")?;
+            writeln!(out, "{indent}    {convert}")?;
+        }
+        None => writeln!(out, "{indent}    {call}")?,
+    }
+    writeln!(out, "{indent}}}")?;
+    Ok(())
+}
+
+/// Whether the method attachment or pointer ownership of `func` was guessed,
+/// so the C function may not treat its arguments as its wrapper does.
+fn is_guessed(func: &Function) -> bool {
+    func.metadata
+        .confidence
+        .is_some_and(|c| c < confidence::METHOD_MATCHED)
+}
+
+/// The function's docs plus notes on leaked closures and a `# Safety`
+/// section for wrappers releasing `self`, taking raw pointers, built on
+/// guesses or handing C a closure to keep.
+fn wrapper_docs(
+    func: &Function,
+    retained: &[String],
+    leaked: &[String],
+    released: bool,
+    raw_pointers: bool,
+) -> Option<String> {
+    let mut safety = Vec::new();
+    if released {
+        safety.push("`self` is released by this call and must not be used afterwards.".to_string());
+    }
+    if raw_pointers {
+        safety.push("Pointer arguments must be valid as the C function expects.".to_string());
+    }
+    if is_guessed(func) {
+        safety.push(
+            "The guessed method attachment and pointer ownership must hold: the C function \
+             must not release or keep borrowed arguments, and must hand over owned return \
+             values."
+                .to_string(),
+        );
+    }
+    if !retained.is_empty() {
+        safety.push(format!(
+            "The C library must only call the closure passed as {} from this thread, and not \
+             while a call to it is still running.",
+            retained.join(" or ")
+        ));
+    }
+    let raw = snake_ident(&func.name);
+    let mut docs = func.metadata.docs.clone();
+    let mut section = |text: &str| {
+        let docs = docs.get_or_insert_default();
+        if !docs.is_empty() {
+            docs.push_str("\n\n");
+        }
+        docs.push_str(text);
+    };
+    if !leaked.is_empty() {
+        section(&format!(
+            "The closure passed as {} is never released, since C doesn't say when it \
+             is done with it. Use the raw `{raw}` binding with user data of your own \
+             to control its lifetime.",
+            leaked.join(" or ")
+        ));
+    }
+    if !safety.is_empty() {
+        section(&format!("# Safety\n\n{}", safety.join("\n")));
+    }
+    docs
+}
+
+/// Closures taken in place of callbacks.
+struct Closures {
+    /// Generic parameters bounding each closure.
+    generics: Vec<String>,
+    /// Trampolines and statements preparing the user data.
+    prelude: Vec<String>,
+    /// Arguments whose closure C keeps after the call.
+    retained: Vec<String>,
+    /// Arguments whose closure is never released.
+    leaked: Vec<String>,
+    /// Values passed for callbacks, user data and destroy notifications.
+    values: HashMap<usize, String>,
+}
+
+/// Trampolines for `callbacks` and what to pass for them: the closure as
+/// user data, on the stack for scoped callbacks and boxed otherwise. A boxed
+/// closure is released by the destroy notification if there is one.
+fn closures(
+    callbacks: &[(usize, Callback)],
+    arg_names: &HashMap<usize, String>,
+    func: &Function,
+    ctx: &Context,
+) -> Closures {
+    let mut generics = Vec::new();
+    let mut prelude = Vec::new();
+    let mut retained = Vec::new();
+    let mut leaked = Vec::new();
+    let mut values = HashMap::new();
+    for (n, (i, callback)) in callbacks.iter().enumerate() {
+        let arg_name = &arg_names[i];
+        let closure = format!("F{n}");
+        let bound = closure_bound(callback, ctx);
+        let lifetime = if callback.scoped { "" } else { " + 'static" };
+        generics.push(format!("{closure}: {bound}{lifetime}"));
+        prelude.push(trampoline(n, callback, &bound, ctx));
+        values.insert(*i, format!("Some(trampoline{n}::<{closure}>)"));
+        let user_data = if callback.scoped {
+            prelude.push(format!("let mut {arg_name} = {arg_name};"));
+            format!("(&raw mut {arg_name}).cast::<c_void>()")
+        } else {
+            retained.push(format!("`{arg_name}`"));
+            prelude.push(format!(
+                "let {arg_name} = ::std::boxed::Box::into_raw(::std::boxed::Box::new({arg_name}));"
+            ));
+            format!("{arg_name}.cast::<c_void>()")
+        };
+        values.insert(callback.user_data_arg, user_data);
+        match callback.destroy_notify_arg {
+            Some(notify) if !callback.scoped => {
+                let abi =
+                    function_type(&func.args[notify].typ, ctx).map_or("C", |f| abi(&f.annotations));
+                prelude.push(
+                    [
+                        format!(
+                            "unsafe extern {abi:?} fn release{n}<{closure}>(user_data: *mut c_void) {{"
+                        ),
+                        format!(
+                            "    drop(unsafe {{ ::std::boxed::Box::from_raw(user_data.cast::<{closure}>()) }});"
+                        ),
+                        "}".to_string(),
+                    ]
+                    .join("\n"),
+                );
+                values.insert(notify, format!("Some(release{n}::<{closure}>)"));
+            }
+            Some(notify) => {
+                values.insert(notify, "None".to_string());
+            }
+            None if !callback.scoped => leaked.push(format!("`{arg_name}`")),
+            None => {}
+        }
+    }
+    Closures {
+        generics,
+        prelude,
+        retained,
+        leaked,
+        values,
+    }
+}
+
+/// `FnMut(...) -> R` for a callback's parameters minus its user data.
+fn closure_bound(callback: &Callback, ctx: &Context) -> String {
+    let params: Vec<String> = callback
+        .params
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| *j != callback.user_data_param)
+        .map(|(_, p)| ffi_type(&p.typ, ctx))
+        .collect();
+    format!(
+        "FnMut({}){}",
+        params.join(", "),
+        ret_type(callback.ret, ctx)
+    )
+}
+
+/// An `extern` function calling the closure passed as user data, aborting
+/// rather than unwinding into C if it panics.
+fn trampoline(n: usize, callback: &Callback, bound: &str, ctx: &Context) -> String {
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for (j, param) in callback.params.iter().enumerate() {
+        let name = if j == callback.user_data_param {
+            "user_data".to_string()
+        } else {
+            args.push(format!("arg{j}"));
+            format!("arg{j}")
+        };
+        params.push(format!("{name}: {}", ffi_type(&param.typ, ctx)));
+    }
+    let call = format!(
+        "::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| callback({})))",
+        args.join(", ")
+    );
+    [
+        format!(
+            "unsafe extern {:?} fn trampoline{n}<F{n}: {bound}>({}){} {{",
+            callback.abi,
+            params.join(", "),
+            ret_type(callback.ret, ctx),
+        ),
+        format!("    let callback = unsafe {{ &mut *(user_data as *mut F{n}) }};"),
+        format!("    match {call} {{"),
+        "        Ok(ret) => ret,".to_string(),
+        "        Err(_) => ::std::process::abort(),".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+    ]
+    .join("\n")
+}
+
+/// How a method takes a pointer argument with a known ownership or
+/// nullability: its parameter type, the expression passing it on and whether
+/// any value of that type is safe to pass.
+fn method_arg(
+    typ: &Type,
+    name: &str,
+    path: &[String],
+    ctx: &Context,
+) -> Option<(String, String, bool)> {
+    let (pointee, ptr) = data_pointer(typ, ctx)?;
+    let nullable = has_annotation(&typ.annotations, "nullable");
+    let constant = has_annotation(&pointee.annotations, "const");
+    let null = if constant { "null" } else { "null_mut" };
+    Some(match string_annotation(&typ.annotations, "ownership") {
+        Some("owned") => {
+            let owned = owned_wrapper(pointee, path, ctx)?;
+            if nullable {
+                (
+                    format!("::core::option::Option<{owned}>"),
+                    format!(
+                        "{name}.map_or(::core::ptr::null_mut(), |{name}| {name}.into_raw().as_ptr())"
+                    ),
+                    true,
+                )
+            } else {
+                (owned, format!("{name}.into_raw().as_ptr()"), true)
+            }
+        }
+        Some("borrowed") => {
+            let (reference, from) = if constant {
+                ("&", "from_ref")
+            } else {
+                ("&mut ", "from_mut")
+            };
+            let reference = format!("{reference}{ptr}");
+            if nullable {
+                (
+                    format!("::core::option::Option<{reference}>"),
+                    format!("{name}.map_or(::core::ptr::{null}(), ::core::ptr::{from})"),
+                    true,
+                )
+            } else {
+                (reference, name.to_string(), true)
+            }
+        }
+        _ if nullable => (
+            format!("::core::option::Option<::core::ptr::NonNull<{ptr}>>"),
+            format!("{name}.map_or(::core::ptr::null_mut(), ::core::ptr::NonNull::as_ptr)"),
+            false,
+        ),
+        _ if has_annotation(&typ.annotations, "nonnull") => (
+            format!("::core::ptr::NonNull<{ptr}>"),
+            format!("{name}.as_ptr()"),
+            false,
+        ),
+        _ => return None,
+    })
+}
+
+/// How a method returns a pointer with a known ownership or nullability:
+/// its return type and the expression converting the raw `ret`.
+///
+/// Borrowed pointers become references living as long as `self`, so only
+/// methods with a receiver return them.
+fn method_ret(
+    typ: &Type,
+    receiver: &str,
+    path: &[String],
+    ctx: &Context,
+) -> Option<(String, String)> {
+    let (pointee, ptr) = data_pointer(typ, ctx)?;
+    let nonnull = has_annotation(&typ.annotations, "nonnull");
+    let constant = has_annotation(&pointee.annotations, "const");
+    let raw = if constant { "ret.cast_mut()" } else { "ret" };
+    Some(match string_annotation(&typ.annotations, "ownership") {
+        Some("owned") => {
+            let owned = owned_wrapper(pointee, path, ctx)?;
+            if nonnull {
+                (
+                    owned.clone(),
+                    format!(
+                        "unsafe {{ {owned}::from_raw(::core::ptr::NonNull::new_unchecked({raw})) }}"
+                    ),
+                )
+            } else {
+                (
+                    format!("::core::option::Option<{owned}>"),
+                    format!(
+                        "::core::ptr::NonNull::new({raw}).map(|ptr| unsafe {{ {owned}::from_raw(ptr) }})"
+                    ),
+                )
+            }
+        }
+        Some("borrowed") if matches!(receiver, "ref" | "mut") => {
+            let mutable = receiver == "mut" && !constant;
+            let reference = if mutable { "&mut " } else { "&" };
+            let reference = format!("{reference}{ptr}");
+            let (deref, as_ref) = if mutable {
+                ("&mut *ret", "ret.as_mut()")
+            } else {
+                ("&*ret", "ret.as_ref()")
+            };
+            if nonnull {
+                (reference, format!("unsafe {{ {deref} }}"))
+            } else {
+                (
+                    format!("::core::option::Option<{reference}>"),
+                    format!("unsafe {{ {as_ref} }}"),
+                )
+            }
+        }
+        _ if nonnull => (
+            format!("::core::ptr::NonNull<{ptr}>"),
+            format!("unsafe {{ ::core::ptr::NonNull::new_unchecked({raw}) }}"),
+        ),
+        _ if has_annotation(&typ.annotations, "nullable") => (
+            format!("::core::option::Option<::core::ptr::NonNull<{ptr}>>"),
+            format!("::core::ptr::NonNull::new({raw})"),
+        ),
+        _ => return None,
+    })
+}
+
+/// The pointee of a `Ptr` to data, and its rendering; function pointers and
+/// `void *` are left to their raw types.
+fn data_pointer<'t>(typ: &'t Type, ctx: &Context) -> Option<(&'t Type, String)> {
+    let pointee = match &typ.kind {
+        TypeKind::Ref { name } if name == "Ptr" => typ.args.first()?,
+        _ => return None,
+    };
+    let rendered = ffi_type(pointee, ctx);
+    let data = !pointer_type(pointee, ctx).starts_with("::") && rendered != "c_void";
+    data.then_some((pointee, rendered))
+}
+
+/// The owning wrapper of the struct `pointee` names, following typedefs, as
+/// seen from `path`.
+fn owned_wrapper(pointee: &Type, path: &[String], ctx: &Context) -> Option<String> {
+    let TypeKind::Ref { name } = &pointee.kind else {
+        return None;
+    };
+    let mut name = name.as_str();
+    // Bounded in case of a typedef cycle
+    for _ in 0..16 {
+        let typ = ctx.types.get(name)?;
+        match &typ.kind {
+            TypeKind::Struct { .. } => {
+                let symbol = string_annotation(&typ.annotations, "destructor")?;
+                ctx.functions.get(symbol)?;
+                return Some(qualify(&format!("Owned{name}"), &ctx.paths[name], path));
+            }
+            TypeKind::Ref { name: target } if typ.args.is_empty() => name = target,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Whether `typ` is rendered as a raw or function pointer, through typedefs.
+fn is_pointer(typ: &Type, ctx: &Context, depth: usize) -> bool {
+    match &typ.kind {
+        TypeKind::Ref { name } => match name.as_str() {
+            "Ptr" | "Array" | "VaList" => true,
+            _ => {
+                depth < 16
+                    && ctx
+                        .types
+                        .get(name.as_str())
+                        .is_some_and(|t| is_pointer(t, ctx, depth + 1))
+            }
+        },
+        TypeKind::Function { .. } => true,
+        _ => false,
+    }
+}
+
+/// Rust ABI string for a `calling_convention` annotation.
+fn abi(annotations: &[Annotation]) -> &'static str {
+    let variadic = has_annotation(annotations, "variadic");
+    match string_annotation(annotations, "calling_convention") {
+        // The caller cleans up variadic arguments, so compilers call these
+        // as `cdecl` whatever they're declared with
+        Some("stdcall" | "fastcall" | "vectorcall" | "thiscall") if variadic => "C",
+        // Windows API convention on 32-bit x86, C everywhere else
+        Some("stdcall") => "system",
+        Some("fastcall") => "fastcall",
+        Some("vectorcall") => "vectorcall",
+        Some("thiscall") => "thiscall",
+        Some("ms_abi") => "win64",
+        Some("sysv_abi") => "sysv64",
+        _ => "C",
+    }
+}
+
+/// ` -> T` for a return type, or nothing for `void`.
+fn ret_type(typ: &Type, ctx: &Context) -> String {
+    match &typ.kind {
+        TypeKind::Ref { name } if name == "Unit" => String::new(),
+        _ => format!(" -> {}", ffi_type(typ, ctx)),
+    }
+}
+
+/// Render a type as it crosses the C ABI.
+fn ffi_type(typ: &Type, ctx: &Context) -> String {
+    match &typ.kind {
+        TypeKind::Ref { name } => match name.as_str() {
+            "Ptr" => typ
+                .args
+                .first()
+                .map_or_else(|| "*mut c_void".to_string(), |p| pointer_type(p, ctx)),
+            "Array" => {
+                let element = typ
+                    .args
+                    .first()
+                    .map_or_else(|| "u8".to_string(), |e| ffi_type(e, ctx));
+                format!("[{element}; {}]", array_length(typ))
+            }
+            "Unit" => "()".to_string(),
+            // `va_list` is passed as a pointer on every supported ABI
+            "VaList" => "*mut c_void".to_string(),
+            "c_longdouble" => "f64".to_string(),
+            other => other.to_string(),
+        },
+        TypeKind::Function { params, ret } => {
+            let mut params: Vec<String> = params.iter().map(|p| ffi_type(&p.typ, ctx)).collect();
+            if has_annotation(&typ.annotations, "variadic") {
+                params.push("...".to_string());
+            }
+            format!(
+                "unsafe extern {:?} fn({}){}",
+                abi(&typ.annotations),
+                params.join(", "),
+                ret_type(ret, ctx)
+            )
+        }
+        _ => typ.name.clone().unwrap_or_else(|| "c_void".to_string()),
+    }
+}
+
+/// `*const T` or `*mut T`, or a nullable function pointer.
+fn pointer_type(pointee: &Type, ctx: &Context) -> String {
+    let function = match &pointee.kind {
+        TypeKind::Function { .. } => true,
+        TypeKind::Ref { name } => ctx
+            .types
+            .get(name.as_str())
+            .is_some_and(|t| matches!(t.kind, TypeKind::Function { .. })),
+        _ => false,
+    };
+    // Function pointers are never null in Rust, so `NULL` needs `None`
+    if function {
+        return format!("::core::option::Option<{}>", ffi_type(pointee, ctx));
+    }
+    let mutability = if has_annotation(&pointee.annotations, "const") {
+        "const"
+    } else {
+        "mut"
+    };
+    format!("*{mutability} {}", ffi_type(pointee, ctx))
+}
+
+/// Length of an `Array`; flexible and unevaluated lengths become zero.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn array_length(typ: &Type) -> usize {
+    typ.annotations
+        .iter()
+        .find(|a| a.kind == "array_length")
+        .and_then(|a| match a.value {
+            Some(AnnotationValue::Number(n)) if n >= 0.0 => Some(n as usize),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a by-value `typ` can derive `Clone` and `Copy`, or with `debug`
+/// also `Debug`. Opaque types derive nothing, and unions no `Debug`.
+fn derives(typ: &Type, ctx: &Context, debug: bool, depth: usize) -> bool {
+    // Definitions can't contain themselves by value; this only guards bad input
+    if depth > 32 {
+        return false;
+    }
+    match &typ.kind {
+        TypeKind::Struct { fields } => {
+            let excluded = has_annotation(&typ.annotations, "opaque")
+                || (debug && has_annotation(&typ.annotations, "union"));
+            !excluded
+                && fields
+                    .iter()
+                    .all(|f| derives(&f.typ, ctx, debug, depth + 1))
+        }
+        TypeKind::Ref { name } if name == "Ptr" => true,
+        TypeKind::Ref { name } if name == "Array" => {
+            typ.args.iter().all(|a| derives(a, ctx, debug, depth + 1))
+        }
+        TypeKind::Ref { name } => ctx
+            .types
+            .get(name.as_str())
+            .is_none_or(|definition| derives(definition, ctx, debug, depth + 1)),
+        _ => true,
+    }
+}
+
+fn is_c_char(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "c_char")
+}
+
+/// Constant identifier for a C macro or enumerator name.
+fn const_ident(name: &str) -> String {
+    RUST.ident(name, Case::ScreamingSnake)
+}
+
+/// Render a constant's value as a Rust literal of its type. Strings become
+/// C string literals; those with a NUL and non-scalar values have none.
+#[allow(clippy::cast_possible_truncation)]
+fn const_value(typ: &Type, value: &Value) -> Option<String> {
+    let float = match &typ.kind {
+        TypeKind::Ref { name } => match name.as_str() {
+            "c_float" | "f32" => Some("f32"),
+            "c_double" | "c_longdouble" | "f64" => Some("f64"),
+            _ => None,
+        },
+        _ => None,
+    };
+    Some(match value {
+        Value::Number(n) if n.is_nan() => format!("{}::NAN", float?),
+        Value::Number(n) if n.is_infinite() => {
+            let sign = if *n < 0.0 { "NEG_" } else { "" };
+            format!("{}::{sign}INFINITY", float?)
+        }
+        Value::Number(n) if float.is_some() => format!("{n:?}"),
+        // `c_char` is unsigned on some targets, so negative ones are cast
+        Value::Int(n) if *n < 0 && is_c_char(typ) => format!("{n}i8 as c_char"),
+        Value::Int(n) => n.to_string(),
+        Value::Number(n) if n.fract() == 0.0 => (*n as i64).to_string(),
+        Value::Number(n) => format!("{n:?}"),
+        Value::String(s) if !s.contains('\0') => format!("c{s:?}"),
+        Value::Bool(b) => b.to_string(),
+        Value::String(_) | Value::Null | Value::List(_) | Value::Object(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(typ: &str, value: &Value) -> Option<String> {
+        const_value(&Type::reference(typ), value)
+    }
+
+    #[test]
+    fn constants_render_as_literals_of_their_type() {
+        assert_eq!(
+            value("c_ulong", &Value::Int(u64::MAX.into())).as_deref(),
+            Some("18446744073709551615")
+        );
+        assert_eq!(
+            value("c_longlong", &Value::Int(i64::MIN.into())).as_deref(),
+            Some("-9223372036854775808")
+        );
+        assert_eq!(value("c_char", &Value::Int(65)).as_deref(), Some("65"));
+        assert_eq!(
+            value("c_char", &Value::Int(-1)).as_deref(),
+            Some("-1i8 as c_char")
+        );
+        assert_eq!(
+            value("c_double", &Value::Number(2.0)).as_deref(),
+            Some("2.0")
+        );
+        assert_eq!(
+            value("c_float", &Value::Number(f64::NEG_INFINITY)).as_deref(),
+            Some("f32::NEG_INFINITY")
+        );
+        assert_eq!(
+            value("Ptr", &Value::String("ab".to_string())).as_deref(),
+            Some("c\"ab\"")
+        );
+        assert_eq!(value("Ptr", &Value::String("a\0b".to_string())), None);
+    }
+
+    /// Generate bindings linking `test` for the C `source`, returning the
+    /// root module.
+    fn bindings(name: &str, source: &str) -> String {
+        bindings_with(name, source, &[], false)
+    }
+
+    /// Like [`bindings`], inferring ownership with `rules`, pairing callbacks
+    /// and with `methods` attaching methods first, as `concord ffi` does.
+    fn bindings_with(name: &str, source: &str, rules: &[&str], methods: bool) -> String {
+        let options = crate::parser::c::Options::default();
+        let (mut module, mut diagnostics) = crate::parser::c::parse_source(name, source, &options);
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|r| transform::OwnershipRule::parse(r).unwrap())
+            .collect();
+        transform::infer_ownership(&mut module, &rules, &mut diagnostics);
+        transform::detect_callbacks(&mut module);
+        if methods {
+            transform::attach_methods(&mut module);
+        }
+        let output = tempfile::tempdir().unwrap();
+        let options = Options {
+            link: Some("test".to_string()),
+            review_threshold: 0.0,
+        };
+        generate(&module, output.path(), &options, &mut diagnostics).unwrap();
+        fs::read_to_string(output.path().join("mod.rs")).unwrap()
+    }
+
+    #[test]
+    fn records_with_known_layouts_assert_it() {
+        let out = bindings(
+            "layout",
+            "struct point { char tag; int x; double y; };\n\
+             union value { int i; double d; };\n\
+             struct opaque;\n\
+             struct holder { struct opaque inner; };\n",
+        );
+        for assertion in [
+            "size_of::<Point>() == 16",
+            "align_of::<Point>() == 8",
+            "offset_of!(Point, tag) == 0",
+            "offset_of!(Point, x) == 4",
+            "offset_of!(Point, y) == 8",
+            "size_of::<Value>() == 8",
+        ] {
+            assert!(out.contains(assertion), "missing `{assertion}` in:\n{out}");
+        }
+        assert!(!out.contains("offset_of!(Value"));
+        assert!(!out.contains("size_of::<Holder>"));
+        assert!(out.contains("#[cfg(all(target_pointer_width = \"64\", not(windows)))]"));
+    }
+
+    #[test]
+    fn declarations_become_ffi_items() {
+        let out = bindings(
+            "items",
+            "typedef struct widget widget;\n\
+             typedef enum { MODE_A, MODE_B = 4 } mode;\n\
+             struct point { int x; int y; };\n\
+             typedef unsigned long long size64;\n\
+             widget *widget_new(const char *name, struct point at);\n\
+             int widget_count(void);\n",
+        );
+        for expected in [
+            "use core::ffi::{c_char, c_int, c_ulonglong};",
+            "#[repr(C)]\npub struct Widget {\n    _data: [u8; 0],",
+            "#[repr(C)]\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\npub enum Mode {\n    ModeA = 0,\n    ModeB = 4,\n}",
+            "#[repr(C)]\n#[derive(Debug, Clone, Copy)]\npub struct Point {\n    pub x: c_int,\n    pub y: c_int,\n}",
+            "pub type Size64 = c_ulonglong;",
+            "#[link(name = \"test\")]\nunsafe extern \"C\" {",
+            "    pub fn widget_new(name: *const c_char, at: Point) -> *mut Widget;",
+            "    pub fn widget_count() -> c_int;",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+    }
+
+    #[test]
+    fn records_holding_opaque_types_derive_nothing() {
+        let out = bindings(
+            "derives",
+            "struct gz { unsigned have; off_t pos; };\n\
+             struct outer { struct gz g; };\n\
+             struct pointing { struct gz *p; };\n\
+             union either { int i; struct pointing p; };\n",
+        );
+        for expected in [
+            "#[repr(C)]\npub struct Gz {",
+            "#[repr(C)]\npub struct Outer {",
+            "#[repr(C)]\n#[derive(Debug, Clone, Copy)]\npub struct Pointing {",
+            "#[repr(C)]\n#[derive(Clone, Copy)]\npub union Either {",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+    }
+
+    const CALLBACKS: &str = "typedef void (*visit_fn)(int value, void *data);\n\
+                             typedef void (*free_fn)(void *data);\n\
+                             void list_each(visit_fn visit, void *data);\n\
+                             void list_watch(visit_fn visit, void *data, free_fn release);\n";
+
+    #[test]
+    fn retained_closures_make_wrappers_unsafe() {
+        let out = bindings("callbacks", CALLBACKS);
+        let each = "/// The closure passed as `visit` is never released, since C doesn't say when \
+                    it is done with it. Use the raw `list_each` binding with user data of your \
+                    own to control its lifetime.\n\
+                    ///\n\
+                    /// # Safety\n\
+                    ///\n\
+                    /// The C library must only call the closure passed as `visit` from this \
+                    thread, and not while a call to it is still running.\n\
+                    pub unsafe fn list_each_with<F0: FnMut(c_int) + 'static>(visit: F0) {";
+        assert!(out.contains(each), "missing {each:?} in:\n{out}");
+        // Released by the destroy notification, so not leaked
+        let watch = "/// # Safety\n\
+                     ///\n\
+                     /// The C library must only call the closure passed as `visit` from this \
+                     thread, and not while a call to it is still running.\n\
+                     pub unsafe fn list_watch_with<F0: FnMut(c_int) + 'static>(visit: F0) {";
+        assert!(out.contains(watch), "missing {watch:?} in:\n{out}");
+        assert!(out.contains("Some(release0::<F0>)"));
+    }
+
+    #[test]
+    fn borrowed_closures_stay_on_the_stack() {
+        let out = bindings_with("scoped", CALLBACKS, &["list_each.visit=borrowed"], false);
+        let each = "\npub fn list_each_with<F0: FnMut(c_int)>(visit: F0) {";
+        assert!(out.contains(each), "missing {each:?} in:\n{out}");
+        assert!(out.contains("(&raw mut visit).cast::<c_void>()"));
+    }
+
+    #[test]
+    fn guessed_methods_are_unsafe() {
+        let source = "typedef struct sqlite3 sqlite3;\n\
+                      int sqlite3_close(sqlite3 *db);\n\
+                      int sqlite3_close_v2(sqlite3 *db);\n\
+                      int sqlite3_changes(sqlite3 *db);\n";
+        let out = bindings_with("guessed", source, &[], true);
+        // Borrowing the handle is a guess, which `close_v2` breaks
+        for expected in [
+            "pub unsafe fn close(&mut self) -> c_int {",
+            "pub unsafe fn close_v2(&mut self) -> c_int {",
+            "pub unsafe fn changes(&mut self) -> c_int {",
+            "/// The guessed method attachment and pointer ownership must hold",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+        let out = bindings_with("declared", source, &["sqlite3_changes.db=borrowed"], true);
+        assert!(out.contains("    pub fn changes(&mut self) -> c_int {"));
+        assert!(out.contains("pub unsafe fn close_v2(&mut self) -> c_int {"));
+    }
+}
@@ -1,10 +1,18 @@
+use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use rhi_concord_core::Module;
 
+use crate::diagnostics::{Diagnostics, Severity};
+
+mod diagnostics;
 mod generator;
 mod parser;
+mod predicate;
+mod transform;
 
 #[derive(Parser)]
 #[command(name = "liana-codegen")]
@@ -12,6 +20,10 @@ mod parser;
 struct Cli {
     #[command(subcommand)]
     command: Command,
+
+    /// Treat a class of diagnostics as errors (only `warnings` is supported)
+    #[arg(long, global = true, value_name = "LINT")]
+    deny: Vec<String>,
 }
 
 #[derive(Subcommand)]
@@ -29,6 +41,78 @@ enum Command {
         /// Target language
         #[arg(short, long, default_value = "rust")]
         target: String,
+
+        /// Emit tests round-tripping schema examples through generated types
+        #[arg(long)]
+        example_tests: bool,
+
+        /// Handling of readOnly/writeOnly fields (preserve, optional, split)
+        #[arg(long, default_value = "preserve")]
+        field_access: String,
+
+        /// Mark bindings generated with lower confidence (0.0 - 1.0) for review
+        #[arg(long, default_value_t = 0.5)]
+        review_threshold: f32,
+
+        #[command(flatten)]
+        parse: OpenapiArgs,
+    },
+
+    /// Generate raw FFI bindings from C headers, or print their IR.
+    Ffi {
+        /// C header to bind (repeatable).
+        #[arg(long, required = true)]
+        header: Vec<PathBuf>,
+
+        /// Directory to search for `#include`s (repeatable).
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include: Vec<PathBuf>,
+
+        /// Macro to define, as `NAME` or `NAME=VALUE` (repeatable).
+        #[arg(short = 'D', long = "define", value_name = "MACRO")]
+        define: Vec<String>,
+
+        /// Platform macro to keep both sides of conditionals on, marking what
+        /// they declare with a Rust `cfg` predicate, as `MACRO` for those
+        /// compilers define (`_WIN32`, `__APPLE__`, ...) or `MACRO=PREDICATE`
+        /// (repeatable).
+        #[arg(long = "cfg", value_name = "MACRO")]
+        cfg: Vec<String>,
+
+        /// Library prefix to strip, as `PREFIX` or `PREFIX=MODULE` to also move
+        /// matching items into a submodule (repeatable).
+        #[arg(long, value_name = "PREFIX")]
+        strip_prefix: Vec<String>,
+
+        /// Declare a pointer's ownership or nullability, as
+        /// `FUNCTION[.ARG]=owned|borrowed|nullable|nonnull` (repeatable).
+        #[arg(long, value_name = "RULE")]
+        ownership: Vec<String>,
+
+        /// Generate the named C enum as a set of bit flags, in addition to enums
+        /// detected from their values (repeatable).
+        #[arg(long, value_name = "ENUM")]
+        flags: Vec<String>,
+
+        /// Attach functions taking or named after a struct to it as methods
+        #[arg(long)]
+        methods: bool,
+
+        /// Output directory for generated bindings; prints the IR when omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Library to link against (defaults to the first header's name; empty for none)
+        #[arg(long)]
+        link: Option<String>,
+
+        /// Mark bindings generated with lower confidence (0.0 - 1.0) for review
+        #[arg(long, default_value_t = 0.5)]
+        review_threshold: f32,
+
+        /// IR output format when printing (json or yaml)
+        #[arg(short, long, default_value = "json")]
+        format: String,
     },
 
     /// Dump IR for debugging.
@@ -40,40 +124,195 @@ enum Command {
         /// Output format (json or yaml)
         #[arg(short, long, default_value = "json")]
         format: String,
+
+        #[command(flatten)]
+        parse: OpenapiArgs,
     },
 }
 
+/// Options shared by commands that parse `OpenAPI` schemas.
+#[derive(Args)]
+struct OpenapiArgs {
+    /// Split operations into submodules by their first tag
+    #[arg(long)]
+    split_by_tag: bool,
+
+    /// Submodule for untagged operations when splitting by tag
+    #[arg(long, default_value = "default")]
+    untagged_module: String,
+
+    /// Leave deprecated operations out of the generated output
+    #[arg(long)]
+    exclude_deprecated: bool,
+}
+
+impl OpenapiArgs {
+    fn to_options(&self) -> parser::openapi::Options {
+        parser::openapi::Options {
+            split_by_tag: self.split_by_tag,
+            untagged_module: self.untagged_module.clone(),
+            exclude_deprecated: self.exclude_deprecated,
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let mut deny_warnings = false;
+    for lint in &cli.deny {
+        match lint.as_str() {
+            "warnings" => deny_warnings = true,
+            other => anyhow::bail!("Unknown lint: {other}"),
+        }
+    }
+    let mut diagnostics = Diagnostics::default();
 
     match cli.command {
         Command::Openapi {
             schema,
             output,
             target,
+            example_tests,
+            field_access,
+            review_threshold,
+            parse,
         } => {
-            let ir = parser::openapi::parse(&schema)?;
+            let ir = parser::openapi::parse(&schema, &parse.to_options(), &mut diagnostics)?;
 
             match target.as_str() {
-                "rust" => generator::rust::generate(&ir, &output)?,
+                "rust" => {
+                    let field_access = match field_access.as_str() {
+                        "preserve" => generator::rust::FieldAccess::Preserve,
+                        "optional" => generator::rust::FieldAccess::Optional,
+                        "split" => generator::rust::FieldAccess::Split,
+                        other => anyhow::bail!("Unknown field access mode: {other}"),
+                    };
+                    let options = generator::rust::Options {
+                        example_tests,
+                        field_access,
+                        review_threshold,
+                    };
+                    generator::rust::generate(&ir, &output, &options, &mut diagnostics)?;
+                }
                 other => anyhow::bail!("Unknown target: {other}"),
             }
 
+            report(&diagnostics, deny_warnings)?;
             println!("Generated {} bindings in {}", target, output.display());
         }
 
-        Command::DumpIr { schema, format } => {
-            let ir = parser::openapi::parse(&schema)?;
+        Command::Ffi {
+            header,
+            include,
+            define,
+            cfg,
+            strip_prefix,
+            ownership,
+            flags,
+            methods,
+            output,
+            link,
+            review_threshold,
+            format,
+        } => {
+            let options = parser::c::Options {
+                include_paths: include,
+                defines: define,
+                platforms: cfg,
+            };
+            let mut ir = parser::c::parse(&header, &options, &mut diagnostics)?;
+            transform::detect_flags(&mut ir, &flags, &mut diagnostics);
+            let rules: Vec<_> = strip_prefix
+                .iter()
+                .map(|spec| transform::PrefixRule::parse(spec))
+                .collect();
+            transform::strip_prefixes(&mut ir, &rules);
+            let rules = ownership
+                .iter()
+                .map(|spec| transform::OwnershipRule::parse(spec))
+                .collect::<Result<Vec<_>>>()?;
+            transform::infer_ownership(&mut ir, &rules, &mut diagnostics);
+            transform::detect_callbacks(&mut ir);
+            if methods {
+                transform::attach_methods(&mut ir);
+            }
 
-            let output = match format.as_str() {
-                "json" => serde_json::to_string_pretty(&ir)?,
-                "yaml" => serde_yaml::to_string(&ir)?,
-                other => anyhow::bail!("Unknown format: {other}"),
+            let Some(output) = output else {
+                report(&diagnostics, deny_warnings)?;
+                println!("{}", dump(&ir, &format)?);
+                return Ok(());
             };
+            let options = generator::rust_ffi::Options {
+                link: Some(link.unwrap_or_else(|| ir.name.clone())).filter(|l| !l.is_empty()),
+                review_threshold,
+            };
+            generator::rust_ffi::generate(&ir, &output, &options, &mut diagnostics)?;
+            report(&diagnostics, deny_warnings)?;
+            println!("Generated FFI bindings in {}", output.display());
+        }
 
-            println!("{output}");
+        Command::DumpIr {
+            schema,
+            format,
+            parse,
+        } => {
+            let ir = parser::openapi::parse(&schema, &parse.to_options(), &mut diagnostics)?;
+            report(&diagnostics, deny_warnings)?;
+            println!("{}", dump(&ir, &format)?);
         }
     }
 
     Ok(())
 }
+
+/// Serialize IR for printing.
+fn dump(ir: &Module, format: &str) -> Result<String> {
+    Ok(match format {
+        "json" => serde_json::to_string_pretty(ir)?,
+        "yaml" => serde_yaml::to_string(ir)?,
+        other => anyhow::bail!("Unknown format: {other}"),
+    })
+}
+
+/// Print diagnostics to stderr and fail if any of them are fatal.
+fn report(diagnostics: &Diagnostics, deny_warnings: bool) -> Result<()> {
+    let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+    for diagnostic in diagnostics.iter() {
+        let source = diagnostic.location.as_ref().and_then(|location| {
+            sources
+                .entry(location.file.as_str())
+                .or_insert_with(|| fs::read_to_string(&location.file).ok())
+                .as_deref()
+        });
+        eprintln!("{}", diagnostic.render(source));
+    }
+
+    let warnings = diagnostics.count(Severity::Warning);
+    let errors = diagnostics.count(Severity::Error);
+    let fatal = if deny_warnings {
+        errors + warnings
+    } else {
+        errors
+    };
+    if fatal > 0 {
+        anyhow::bail!("aborting due to {fatal} previous error{}", plural(fatal));
+    }
+    if warnings > 0 {
+        eprintln!("warning: {warnings} warning{} emitted", plural(warnings));
+    }
+    let flagged = diagnostics
+        .iter()
+        .filter(|d| d.code == diagnostics::LOW_CONFIDENCE)
+        .count();
+    if flagged > 0 {
+        eprintln!(
+            "note: {flagged} binding{} below the review threshold",
+            plural(flagged)
+        );
+    }
+    Ok(())
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
@@ -0,0 +1,1762 @@
+//! C declarations to IR.
+
+use std::collections::{HashMap, HashSet};
+
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Field, Function, Item, Metadata, Param, Type, TypeKind, Value,
+    Variant,
+};
+
+use super::expr::{self, Constant};
+use super::layout;
+use super::lexer::{Kind, Token};
+use super::preprocess::{Macros, join};
+use crate::diagnostics::{self, Diagnostics};
+use crate::transform::confidence;
+
+type PResult<T> = Result<T, String>;
+
+/// Words that combine into a primitive type.
+const PRIMITIVES: &[&str] = &[
+    "void",
+    "char",
+    "short",
+    "int",
+    "long",
+    "float",
+    "double",
+    "signed",
+    "unsigned",
+    "_Bool",
+    "_Complex",
+    "__int128",
+    "__signed",
+    "__signed__",
+];
+
+/// Keywords and extensions that don't change a declaration's type.
+const IGNORED: &[&str] = &[
+    "extern",
+    "inline",
+    "__inline",
+    "__inline__",
+    "__forceinline",
+    "_Noreturn",
+    "__extension__",
+    "register",
+    "auto",
+    "_Thread_local",
+    "thread_local",
+    "__thread",
+    "restrict",
+    "__restrict",
+    "__restrict__",
+    "_Atomic",
+];
+
+/// Keywords naming a calling convention.
+const CALLING_CONVENTIONS: &[&str] = &[
+    "__cdecl",
+    "_cdecl",
+    "__stdcall",
+    "_stdcall",
+    "__fastcall",
+    "_fastcall",
+    "__vectorcall",
+    "__thiscall",
+];
+
+/// Calling convention of functions that don't name one.
+const DEFAULT_CALLING_CONVENTION: &str = "cdecl";
+
+/// IR names of typedefs from the standard headers, which are usually not
+/// on the include path.
+pub(super) fn builtin(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "int8_t" => "i8",
+        "int16_t" => "i16",
+        "int32_t" => "i32",
+        "int64_t" => "i64",
+        "uint8_t" => "u8",
+        "uint16_t" | "char16_t" => "u16",
+        "uint32_t" | "char32_t" => "u32",
+        "uint64_t" => "u64",
+        "size_t" | "uintptr_t" => "usize",
+        "ssize_t" | "ptrdiff_t" | "intptr_t" => "isize",
+        "bool" => "bool",
+        "va_list" | "__builtin_va_list" | "__gnuc_va_list" => "VaList",
+        _ => return None,
+    })
+}
+
+/// IR name of a combination of primitive type words.
+pub(super) fn primitive(words: &[String]) -> &'static str {
+    let has = |word: &str| words.iter().any(|w| w == word);
+    let longs = words.iter().filter(|w| *w == "long").count();
+    let unsigned = has("unsigned");
+    if has("void") {
+        "c_void"
+    } else if has("_Bool") {
+        "bool"
+    } else if has("float") {
+        "c_float"
+    } else if has("double") {
+        if longs > 0 {
+            "c_longdouble"
+        } else {
+            "c_double"
+        }
+    } else if has("char") {
+        if unsigned {
+            "c_uchar"
+        } else if has("signed") || has("__signed") || has("__signed__") {
+            "c_schar"
+        } else {
+            "c_char"
+        }
+    } else if has("__int128") {
+        if unsigned { "u128" } else { "i128" }
+    } else if has("short") {
+        if unsigned { "c_ushort" } else { "c_short" }
+    } else {
+        match (longs, unsigned) {
+            (0, false) => "c_int",
+            (0, true) => "c_uint",
+            (1, false) => "c_long",
+            (1, true) => "c_ulong",
+            (_, false) => "c_longlong",
+            (_, true) => "c_ulonglong",
+        }
+    }
+}
+
+/// Declaration specifiers: storage class, base type and qualifiers.
+#[derive(Default)]
+struct Specs {
+    typedef: bool,
+    static_: bool,
+    /// Struct, union, enum or typedef name.
+    base: Option<Type>,
+    /// Whether `base` is a struct, union or enum defined here without a tag.
+    anonymous: bool,
+    /// An unknown identifier taken as the type name.
+    guessed: Option<Token>,
+    /// Primitive type words (`unsigned`, `long`, ...).
+    words: Vec<String>,
+    /// `const` and `volatile` on the base type.
+    qualifiers: Vec<Annotation>,
+    /// Attributes and calling conventions of the declaration.
+    attributes: Vec<Annotation>,
+}
+
+/// A type derivation, applied to the base type innermost first.
+enum Op {
+    Pointer(Vec<Annotation>),
+    Array(Option<Annotation>),
+    Function { params: Vec<Param>, variadic: bool },
+}
+
+struct Declarator {
+    name: Option<Token>,
+    ops: Vec<Op>,
+    /// Attributes and calling conventions within the declarator.
+    annotations: Vec<Annotation>,
+}
+
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    /// Token positions from which declarations are under a `cfg` predicate.
+    conditions: &'a [(usize, Option<String>)],
+    pos: usize,
+    items: Vec<Item>,
+    /// Index into `items` of each named type.
+    types: HashMap<String, usize>,
+    /// Names declared with `typedef`.
+    typedefs: HashSet<String>,
+    /// Enumerator values, for constant expressions.
+    constants: HashMap<String, i64>,
+    /// Declared functions and the predicate each is declared under.
+    functions: HashSet<(String, Option<&'a str>)>,
+    /// Maximum member alignment set by `#pragma pack`, and those pushed.
+    pack: Option<u64>,
+    packs: Vec<Option<u64>>,
+    /// Doc comment of the declaration being parsed, for its first item.
+    doc: Option<String>,
+    /// Predicate the declaration being parsed is under.
+    cfg: Option<&'a str>,
+    anonymous: usize,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(
+        tokens: &'a [Token],
+        conditions: &'a [(usize, Option<String>)],
+        diagnostics: &'a mut Diagnostics,
+    ) -> Self {
+        Self {
+            tokens,
+            conditions,
+            pos: 0,
+            items: Vec::new(),
+            types: HashMap::new(),
+            typedefs: HashSet::new(),
+            constants: HashMap::new(),
+            functions: HashSet::new(),
+            pack: None,
+            packs: Vec::new(),
+            doc: None,
+            cfg: None,
+            anonymous: 0,
+            diagnostics,
+        }
+    }
+
+    /// Parse every declaration, skipping and reporting those that fail, then
+    /// the object-like `macros` named in `defined` that evaluate to constants.
+    pub fn parse(mut self, macros: &Macros, defined: &[String]) -> Vec<Item> {
+        while self.pos < self.tokens.len() {
+            let start = self.pos;
+            if let Err(message) = self.external() {
+                let at = self.tokens.get(self.pos).or(self.tokens.last());
+                self.diagnostics.warning(
+                    diagnostics::SYNTAX_ERROR,
+                    at.map(Token::location),
+                    format!("{message}; skipping declaration"),
+                );
+                self.recover(start);
+            }
+        }
+        for name in defined {
+            self.macro_constant(name, macros);
+        }
+        self.items
+    }
+
+    /// Declare each definition of the macro `name` whose body is a constant
+    /// expression as a constant under its predicate. Reserved names, empty
+    /// bodies such as include guards and names already declared are skipped.
+    fn macro_constant(&mut self, name: &str, macros: &Macros) {
+        let declared = self.types.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.functions.iter().any(|(function, _)| function == name);
+        if name.starts_with('_') || declared {
+            return;
+        }
+        for definition in macros.definitions(name) {
+            if definition.params.is_some() || definition.body.is_empty() {
+                continue;
+            }
+            // Once per predicate the macros it uses are defined under
+            for cfg in macros.expansions(&definition.body, definition.cfg.as_deref()) {
+                let cfg = cfg.as_deref();
+                let value = expr::eval_constant(&definition.body, &|other| {
+                    self.macro_value(other, macros, cfg, &[name])
+                });
+                if let Some(value) = value {
+                    self.push_constant(name, value, cfg);
+                }
+            }
+        }
+    }
+
+    /// Declare `name` as the constant `value` under `cfg`.
+    fn push_constant(&mut self, name: &str, value: Constant, cfg: Option<&str>) {
+        let (typ, value) = match value {
+            Constant::Int(value, typ) => (Type::reference(typ.name), Value::Int(value)),
+            // `long double` has no Rust equivalent; its constants are `double`s
+            Constant::Float(value, "c_longdouble") => {
+                (Type::reference("c_double"), Value::Number(value))
+            }
+            Constant::Float(value, typ) => (Type::reference(typ), Value::Number(value)),
+            Constant::Str(text) => {
+                let mut pointee = Type::reference("c_char");
+                pointee.annotations.push(Annotation::flag("const"));
+                (Type::generic("Ptr", vec![pointee]), Value::String(text))
+            }
+        };
+        self.items.push(Item::Const {
+            name: name.to_string(),
+            typ,
+            value,
+            annotations: cfg
+                .iter()
+                .map(|cfg| Annotation::with_string("cfg", *cfg))
+                .collect(),
+        });
+    }
+
+    /// The value of the macro or enumerator `name` under `cfg`, unless it is
+    /// one of the macros being expanded in `hidden`.
+    fn macro_value(
+        &self,
+        name: &str,
+        macros: &Macros,
+        cfg: Option<&str>,
+        hidden: &[&str],
+    ) -> Option<Constant> {
+        if let Some(&value) = self.constants.get(name) {
+            return Some(Constant::enumerator(value));
+        }
+        let body = &macros.get(name, cfg).filter(|m| m.params.is_none())?.body;
+        // Bounded in case of deeply nested definitions
+        if hidden.contains(&name) || hidden.len() > 32 {
+            return None;
+        }
+        let hidden = [hidden, &[name]].concat();
+        expr::eval_constant(body, &|name| self.macro_value(name, macros, cfg, &hidden))
+    }
+
+    /// Predicate the token at `pos` is declared under.
+    fn condition_at(&self, pos: usize) -> Option<&'a str> {
+        let index = self.conditions.partition_point(|(start, _)| *start <= pos);
+        index
+            .checked_sub(1)
+            .and_then(|i| self.conditions[i].1.as_deref())
+    }
+
+    /// A `cfg` annotation for a member at `pos` declared under a different
+    /// predicate than its declaration.
+    fn member_cfg(&self, pos: usize) -> Option<Annotation> {
+        let cfg = self
+            .condition_at(pos)
+            .filter(|cfg| Some(*cfg) != self.cfg)?;
+        Some(Annotation::with_string("cfg", cfg))
+    }
+
+    /// Mark an item of the declaration being parsed with its predicate.
+    fn conditional(&self, annotations: &mut Vec<Annotation>) {
+        if let Some(cfg) = self.cfg {
+            annotations.push(Annotation::with_string("cfg", cfg));
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let matched = self.peek().is_some_and(|t| t.is(text));
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, text: &str) -> PResult<()> {
+        if self.eat(text) {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => format!("expected `{text}`, found `{}`", token.text),
+            None => format!("expected `{text}` at end of input"),
+        })
+    }
+
+    /// Skip past the `;` ending the declaration that started at `start`,
+    /// stopping before a `}` that closes an enclosing block.
+    fn recover(&mut self, start: usize) {
+        let mut depth = 0usize;
+        self.pos = start;
+        while let Some(token) = self.peek() {
+            self.pos += 1;
+            if token.is("{") || token.is("(") || token.is("[") {
+                depth += 1;
+            } else if token.is("}") || token.is(")") || token.is("]") {
+                if depth == 0 {
+                    self.pos -= 1;
+                    break;
+                }
+                depth -= 1;
+            } else if token.is(";") && depth == 0 {
+                break;
+            }
+        }
+        if self.pos == start {
+            self.pos += 1;
+        }
+    }
+
+    /// Tokens up to one of `ends` outside brackets; the end isn't consumed.
+    fn until(&mut self, ends: &[&str]) -> &'a [Token] {
+        let start = self.pos;
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            if depth == 0 && ends.iter().any(|e| token.is(e)) {
+                break;
+            }
+            if token.is("(") || token.is("[") || token.is("{") {
+                depth += 1;
+            } else if token.is(")") || token.is("]") || token.is("}") {
+                let Some(d) = depth.checked_sub(1) else {
+                    break;
+                };
+                depth = d;
+            }
+            self.pos += 1;
+        }
+        &self.tokens[start..self.pos]
+    }
+
+    /// Tokens inside the parenthesized group at the current position.
+    fn group(&mut self) -> PResult<&'a [Token]> {
+        self.expect("(")?;
+        let inner = self.until(&[")"]);
+        self.expect(")")?;
+        Ok(inner)
+    }
+
+    fn metadata(&mut self, token: &Token) -> Metadata {
+        Metadata {
+            docs: self.doc.take(),
+            source: Some(token.location()),
+            ..Metadata::default()
+        }
+    }
+
+    fn external(&mut self) -> PResult<()> {
+        let Some(first) = self.peek() else {
+            return Ok(());
+        };
+        // `}` closes an `extern "C" {` block
+        if first.is(";") || first.is("}") {
+            self.pos += 1;
+            return Ok(());
+        }
+        if first.is("#pragma") {
+            self.pos += 1;
+            return self.pragma();
+        }
+        if first.is("extern") && self.peek_at(1).is_some_and(|t| t.kind == Kind::Str) {
+            self.pos += 2;
+            self.eat("{");
+            return Ok(());
+        }
+        if ["_Static_assert", "static_assert", "__asm__", "asm"]
+            .iter()
+            .any(|k| first.is(k))
+        {
+            self.until(&[";"]);
+            return self.expect(";");
+        }
+
+        self.doc.clone_from(&first.doc);
+        self.cfg = self.condition_at(self.pos);
+        let mut specs = self.specifiers()?;
+        if self.eat(";") {
+            self.declare_bare(&mut specs);
+            return Ok(());
+        }
+        loop {
+            let mut decl = self.declarator(false)?;
+            while self.attribute(&mut decl.annotations)? {}
+            if self.peek().is_some_and(|t| t.is("{")) {
+                self.pos += 1;
+                self.until(&["}"]);
+                self.expect("}")?;
+                self.skip_definition(&decl);
+                return Ok(());
+            }
+            if self.eat("=") {
+                self.until(&[",", ";"]);
+            }
+            self.declare(&mut specs, &decl, first);
+            if !self.eat(",") {
+                return self.expect(";");
+            }
+        }
+    }
+
+    /// `pack(...)` after `#pragma`: `pack(N)`, `pack()`, `pack(push[, N])`
+    /// and `pack(pop)`, as MSVC and GCC accept them.
+    fn pragma(&mut self) -> PResult<()> {
+        self.expect("pack")?;
+        let args = self.group()?;
+        let mut words = args.split(|t| t.is(",")).filter_map(<[Token]>::first);
+        let size = |token: &Token| {
+            expr::parse_int(&token.text)
+                .and_then(|n| u64::try_from(n).ok())
+                .filter(|n| n.is_power_of_two())
+        };
+        match words.next() {
+            Some(word) if word.is("push") => {
+                self.packs.push(self.pack);
+                if let Some(size) = words.find_map(size) {
+                    self.pack = Some(size);
+                }
+            }
+            Some(word) if word.is("pop") => self.pack = self.packs.pop().flatten(),
+            Some(word) => {
+                if let Some(size) = size(word) {
+                    self.pack = Some(size);
+                }
+            }
+            None => self.pack = None,
+        }
+        Ok(())
+    }
+
+    fn specifiers(&mut self) -> PResult<Specs> {
+        let mut specs = Specs::default();
+        while let Some(token) = self.peek() {
+            if self.attribute(&mut specs.attributes)? {
+                continue;
+            }
+            if token.kind != Kind::Ident {
+                break;
+            }
+            match token.text.as_str() {
+                "typedef" => specs.typedef = true,
+                "static" => specs.static_ = true,
+                "const" | "volatile" | "__const" | "__volatile__" => {
+                    let kind = token.text.trim_start_matches('_').trim_end_matches('_');
+                    if !specs.qualifiers.iter().any(|q| q.kind == kind) {
+                        specs.qualifiers.push(Annotation::flag(kind));
+                    }
+                }
+                "struct" | "union" | "enum" => {
+                    self.pos += 1;
+                    let (typ, anonymous) = if token.is("enum") {
+                        self.enumeration(token)?
+                    } else {
+                        self.record(token)?
+                    };
+                    specs.guessed = None;
+                    specs.base = Some(typ);
+                    specs.anonymous = anonymous;
+                    continue;
+                }
+                word if PRIMITIVES.contains(&word) => {
+                    self.drop_guess(&mut specs);
+                    specs.words.push(word.to_string());
+                }
+                word if IGNORED.contains(&word) => {}
+                name => {
+                    if !self.type_name(&specs, name) {
+                        break;
+                    }
+                    self.drop_guess(&mut specs);
+                    if let Some(mapped) = builtin(name) {
+                        specs.base = Some(Type::reference(mapped));
+                    } else {
+                        if !self.typedefs.contains(name) {
+                            specs.guessed = Some(token.clone());
+                        }
+                        specs.base = Some(Type::reference(name));
+                    }
+                }
+            }
+            self.pos += 1;
+        }
+        Ok(specs)
+    }
+
+    /// Whether the identifier `name` at the current position names a type.
+    ///
+    /// Identifiers that aren't declared types count as type names where a
+    /// declaration needs one, such as before another identifier or `*`; an
+    /// earlier guess that turns out to be followed by the real type is
+    /// dropped (typically an export macro from a missing header).
+    fn type_name(&self, specs: &Specs, name: &str) -> bool {
+        let known = self.typedefs.contains(name) || builtin(name).is_some();
+        let next = self.peek_at(1);
+        let before_declarator = next.is_some_and(|t| t.kind == Kind::Ident || t.is("*"));
+        if specs.guessed.is_some() {
+            // In `off_t name(...)` the name is no type
+            return known || before_declarator;
+        }
+        if specs.base.is_some() || !specs.words.is_empty() {
+            return false;
+        }
+        known
+            || before_declarator
+            || next.is_some_and(|t| t.is("(") || t.is(")") || t.is(",") || t.is("["))
+    }
+
+    fn drop_guess(&mut self, specs: &mut Specs) {
+        if let Some(guess) = specs.guessed.take() {
+            specs.base = None;
+            self.diagnostics.warning(
+                diagnostics::UNKNOWN_TYPE,
+                Some(guess.location()),
+                format!(
+                    "unknown identifier `{}` before a type; ignoring it (missing #include or -D?)",
+                    guess.text
+                ),
+            );
+        }
+    }
+
+    /// Consume one attribute, calling convention or ignorable extension,
+    /// returning whether there was one.
+    fn attribute(&mut self, annotations: &mut Vec<Annotation>) -> PResult<bool> {
+        let Some(token) = self.peek() else {
+            return Ok(false);
+        };
+        let text = token.text.as_str();
+        if token.kind == Kind::Ident && CALLING_CONVENTIONS.contains(&text) {
+            self.pos += 1;
+            set_calling_convention(annotations, text.trim_start_matches('_'));
+            return Ok(true);
+        }
+        let attributes = match text {
+            "__attribute__" | "__attribute" | "__declspec" if token.kind == Kind::Ident => {
+                self.pos += 1;
+                let inner = self.group()?;
+                // `__attribute__((a, b))` nests one level deeper than `__declspec(a)`
+                match inner.split_first() {
+                    Some((open, rest)) if open.is("(") => &rest[..rest.len().saturating_sub(1)],
+                    _ => inner,
+                }
+            }
+            "[" if self.peek_at(1).is_some_and(|t| t.is("[")) => {
+                self.pos += 2;
+                let inner = self.until(&["]"]);
+                self.expect("]")?;
+                self.expect("]")?;
+                inner
+            }
+            "_Alignas" | "alignas" => {
+                self.pos += 1;
+                let inner = self.group()?;
+                self.alignment(token, inner, annotations);
+                return Ok(true);
+            }
+            "__asm__" | "__asm" | "asm" | "__typeof__" => {
+                self.pos += 1;
+                self.group()?;
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+        for (i, token) in attributes.iter().enumerate() {
+            let item_start = i == 0 || attributes[i - 1].is(",") || attributes[i - 1].is("::");
+            if token.kind != Kind::Ident || !item_start {
+                continue;
+            }
+            let name = token.text.trim_matches('_');
+            match name {
+                "deprecated" if !annotations.iter().any(|a| a.kind == "deprecated") => {
+                    annotations.push(Annotation::flag("deprecated"));
+                }
+                "cdecl" | "stdcall" | "fastcall" | "vectorcall" | "thiscall" | "ms_abi"
+                | "sysv_abi" => set_calling_convention(annotations, name),
+                // Argument positions are 1-based; none means every pointer
+                "nonnull" => {
+                    let positions = match attributes.get(i + 1) {
+                        Some(open) if open.is("(") => attributes[i + 2..]
+                            .iter()
+                            .take_while(|t| !t.is(")"))
+                            .filter_map(|t| expr::parse_int(&t.text))
+                            .map(|n| {
+                                #[expect(
+                                    clippy::cast_precision_loss,
+                                    reason = "positions are small"
+                                )]
+                                AnnotationValue::Number(n as f64)
+                            })
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    annotations.push(Annotation {
+                        kind: "nonnull".to_string(),
+                        value: Some(AnnotationValue::List(positions)),
+                    });
+                }
+                "returns_nonnull" => annotations.push(Annotation::flag("returns_nonnull")),
+                "packed" if !annotations.iter().any(|a| a.kind == "packed") => {
+                    annotations.push(Annotation::flag("packed"));
+                }
+                // `aligned` alone is the largest alignment of any type
+                "aligned" => match attributes.get(i + 1) {
+                    Some(open) if open.is("(") => {
+                        let args = enclosed(&attributes[i + 2..]);
+                        self.alignment(token, args, annotations);
+                    }
+                    _ => set_alignment(annotations, 16),
+                },
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Record the alignment `tokens` evaluate to: a constant, a primitive
+    /// type or `_Alignof` one. Others are reported and kept as a string,
+    /// which leaves the layout unknown.
+    fn alignment(&mut self, at: &Token, tokens: &[Token], annotations: &mut Vec<Annotation>) {
+        let align = self
+            .constant(tokens)
+            .and_then(|n| u64::try_from(n).ok())
+            .or_else(|| type_alignment(tokens));
+        match align {
+            // `_Alignas(0)` has no effect
+            Some(0) => {}
+            Some(align) if align.is_power_of_two() => set_alignment(annotations, align),
+            _ => {
+                self.diagnostics.warning(
+                    diagnostics::UNSUPPORTED_DECLARATION,
+                    Some(at.location()),
+                    format!("cannot evaluate alignment `{}`", join(tokens)),
+                );
+                annotations.push(Annotation::with_string("align", join(tokens)));
+            }
+        }
+    }
+
+    /// `struct` or `union` after its keyword. Returns the type to use and
+    /// whether it is an inline definition without a tag.
+    fn record(&mut self, keyword: &Token) -> PResult<(Type, bool)> {
+        let mut annotations = Vec::new();
+        if keyword.is("union") {
+            annotations.push(Annotation::flag("union"));
+        }
+        while self.attribute(&mut annotations)? {}
+        let tag = self.peek().filter(|t| t.kind == Kind::Ident);
+        if tag.is_some() {
+            self.pos += 1;
+        }
+        if !self.eat("{") {
+            let tag = tag.ok_or_else(|| format!("expected a {} name or body", keyword.text))?;
+            self.forward(&tag.text, tag, keyword.is("union"));
+            return Ok((Type::reference(&tag.text), false));
+        }
+
+        let metadata = self.metadata(keyword);
+        let fields = self.fields()?;
+        while self.attribute(&mut annotations)? {}
+        annotations.push(Annotation::with_string("repr", "C"));
+        if let Some(pack) = self.pack
+            && !annotations.iter().any(|a| a.kind == "packed")
+        {
+            #[expect(clippy::cast_precision_loss, reason = "alignments are small")]
+            annotations.push(Annotation::with_number("packed", pack as f64));
+        }
+        let typ = Type {
+            kind: TypeKind::Struct { fields },
+            name: None,
+            params: Vec::new(),
+            args: Vec::new(),
+            annotations,
+            metadata,
+        };
+        Ok(self.tagged(typ, tag))
+    }
+
+    /// Define `typ` under `tag`, or return it inline when there is none.
+    fn tagged(&mut self, mut typ: Type, tag: Option<&Token>) -> (Type, bool) {
+        match tag {
+            Some(tag) => {
+                typ.name = Some(tag.text.clone());
+                self.define(typ);
+                (Type::reference(&tag.text), false)
+            }
+            None => (typ, true),
+        }
+    }
+
+    /// Members of a struct or union, after its `{`.
+    fn fields(&mut self) -> PResult<Vec<Field>> {
+        let mut fields = Vec::new();
+        while !self.eat("}") {
+            let Some(first) = self.peek() else {
+                return Err("unterminated struct or union".to_string());
+            };
+            if self.eat(";") {
+                continue;
+            }
+            if self.eat("#pragma") {
+                self.pragma()?;
+                continue;
+            }
+            let cfg = self.member_cfg(self.pos);
+            let mut specs = self.specifiers()?;
+            if self.eat(";") {
+                // C11 anonymous struct or union member
+                if specs.anonymous {
+                    let n = fields.iter().filter(|f: &&Field| is_anonymous(f)).count() + 1;
+                    let mut annotations = vec![Annotation::flag("anonymous")];
+                    annotations.extend(cfg);
+                    fields.push(Field {
+                        name: Some(format!("anon{n}")),
+                        typ: self.base_type(&mut specs, None),
+                        default: None,
+                        annotations,
+                        metadata: field_metadata(first),
+                    });
+                }
+                continue;
+            }
+            loop {
+                let mut decl = self.declarator(true)?;
+                let mut annotations = Vec::new();
+                if self.eat(":") {
+                    let width = self.until(&[",", ";"]);
+                    match self.constant(width) {
+                        #[expect(clippy::cast_precision_loss, reason = "widths are small")]
+                        Some(n) if n >= 0 => {
+                            annotations.push(Annotation::with_number("bitfield_width", n as f64));
+                        }
+                        _ => self.diagnostics.warning(
+                            diagnostics::UNSUPPORTED_DECLARATION,
+                            Some(first.location()),
+                            format!(
+                                "cannot evaluate bitfield width `{}`; the layout will differ",
+                                join(width)
+                            ),
+                        ),
+                    }
+                }
+                while self.attribute(&mut decl.annotations)? {}
+                let typ = self.declared_type(&mut specs, &decl, None);
+                annotations.extend(deprecated(&decl.annotations));
+                annotations.extend(
+                    decl.annotations
+                        .iter()
+                        .chain(&specs.attributes)
+                        .filter(|a| a.kind == "packed" || a.kind == "align")
+                        .cloned(),
+                );
+                annotations.extend(cfg.clone());
+                fields.push(Field {
+                    name: decl.name.map(|t| t.text.clone()),
+                    typ,
+                    default: None,
+                    annotations,
+                    metadata: field_metadata(first),
+                });
+                if !self.eat(",") {
+                    self.expect(";")?;
+                    break;
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// `enum` after its keyword.
+    fn enumeration(&mut self, keyword: &Token) -> PResult<(Type, bool)> {
+        let mut annotations = Vec::new();
+        while self.attribute(&mut annotations)? {}
+        let tag = self.peek().filter(|t| t.kind == Kind::Ident);
+        if tag.is_some() {
+            self.pos += 1;
+        }
+        if self.eat(":") {
+            // C23 fixed underlying type
+            self.specifiers()?;
+        }
+        if !self.eat("{") {
+            let tag = tag.ok_or("expected an enum name or body")?;
+            return Ok((Type::reference(&tag.text), false));
+        }
+
+        let metadata = self.metadata(keyword);
+        let mut variants = Vec::new();
+        let mut next = 0i64;
+        // Predicate of the previous enumerator, and whether one only declared
+        // on some platforms was numbered past
+        let mut previous = None;
+        let mut reported = false;
+        while !self.eat("}") {
+            let name = self
+                .peek()
+                .filter(|t| t.kind == Kind::Ident)
+                .ok_or("expected an enumerator")?;
+            let cfg = self.condition_at(self.pos);
+            let mut variant_annotations: Vec<_> = self.member_cfg(self.pos).into_iter().collect();
+            self.pos += 1;
+            while self.attribute(&mut variant_annotations)? {}
+            let explicit = self.peek().is_some_and(|t| t.is("="));
+            if !explicit && !reported && previous.is_some() && previous != cfg {
+                reported = true;
+                self.diagnostics.warning(
+                    diagnostics::UNSUPPORTED_DECLARATION,
+                    Some(name.location()),
+                    format!(
+                        "`{}` follows enumerators only declared on some platforms; numbering it {next} as if they all were",
+                        name.text
+                    ),
+                );
+            }
+            previous = cfg;
+            if self.eat("=") {
+                let value = self.until(&[",", "}"]);
+                next = self.constant(value).unwrap_or_else(|| {
+                    self.diagnostics.warning(
+                        diagnostics::UNSUPPORTED_DECLARATION,
+                        Some(name.location()),
+                        format!(
+                            "cannot evaluate `{}` for `{}`; numbering it {next}",
+                            join(value),
+                            name.text
+                        ),
+                    );
+                    next
+                });
+            }
+            self.constants.insert(name.text.clone(), next);
+            #[expect(clippy::cast_precision_loss, reason = "enumerators are small")]
+            variant_annotations.insert(0, Annotation::with_number("value", next as f64));
+            variants.push(Variant {
+                name: name.text.clone(),
+                fields: Vec::new(),
+                annotations: variant_annotations,
+            });
+            next = next.wrapping_add(1);
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        while self.attribute(&mut annotations)? {}
+        let typ = Type {
+            kind: TypeKind::Enum { variants },
+            name: None,
+            params: Vec::new(),
+            args: Vec::new(),
+            annotations,
+            metadata,
+        };
+        Ok(self.tagged(typ, tag))
+    }
+
+    fn constant(&self, tokens: &[Token]) -> Option<i64> {
+        expr::eval(tokens, &|name| self.constants.get(name).copied())
+    }
+
+    fn declarator(&mut self, abstract_ok: bool) -> PResult<Declarator> {
+        let mut annotations = Vec::new();
+        let mut ops = Vec::new();
+        loop {
+            if self.eat("*") {
+                ops.push(Op::Pointer(self.qualifiers(&mut annotations)?));
+            } else if !self.attribute(&mut annotations)? {
+                break;
+            }
+        }
+
+        let mut inner = None;
+        let mut name = None;
+        match self.peek() {
+            Some(token) if token.kind == Kind::Ident => {
+                self.pos += 1;
+                name = Some(token.clone());
+            }
+            Some(token) if token.is("(") && self.nested_declarator() => {
+                self.pos += 1;
+                let nested = self.declarator(abstract_ok)?;
+                self.expect(")")?;
+                inner = Some(nested);
+            }
+            Some(token) if !abstract_ok => {
+                return Err(format!("expected a name, found `{}`", token.text));
+            }
+            _ => {}
+        }
+
+        let mut suffixes = Vec::new();
+        loop {
+            if self.eat("[") {
+                let length = self.until(&["]"]);
+                self.expect("]")?;
+                suffixes.push(Op::Array(self.array_length(length)));
+            } else if self.eat("(") {
+                let (params, variadic) = self.params()?;
+                suffixes.push(Op::Function { params, variadic });
+            } else {
+                break;
+            }
+        }
+        ops.extend(suffixes.into_iter().rev());
+        if let Some(inner) = inner {
+            name = inner.name;
+            ops.extend(inner.ops);
+            annotations.extend(inner.annotations);
+        }
+        Ok(Declarator {
+            name,
+            ops,
+            annotations,
+        })
+    }
+
+    /// Whether the `(` at the current position opens a nested declarator
+    /// such as `(*callback)` rather than a parameter list.
+    fn nested_declarator(&self) -> bool {
+        self.peek_at(1).is_some_and(|t| {
+            t.is("*")
+                || t.is("(")
+                || t.is("[")
+                || t.is("__attribute__")
+                || CALLING_CONVENTIONS.contains(&t.text.as_str())
+                || (t.kind == Kind::Ident
+                    && !PRIMITIVES.contains(&t.text.as_str())
+                    && !IGNORED.contains(&t.text.as_str())
+                    && !["const", "volatile", "struct", "union", "enum"].contains(&t.text.as_str())
+                    && !self.typedefs.contains(&t.text)
+                    && builtin(&t.text).is_none()
+                    && !self
+                        .peek_at(2)
+                        .is_some_and(|n| n.kind == Kind::Ident || n.is("*")))
+        })
+    }
+
+    /// Qualifiers after a `*`, as annotations on the pointer.
+    fn qualifiers(&mut self, attributes: &mut Vec<Annotation>) -> PResult<Vec<Annotation>> {
+        let mut qualifiers = Vec::new();
+        while let Some(token) = self.peek() {
+            match token.text.as_str() {
+                "const" | "__const" => qualifiers.push(Annotation::flag("const")),
+                "volatile" | "__volatile__" => qualifiers.push(Annotation::flag("volatile")),
+                "_Nullable" | "__nullable" => qualifiers.push(Annotation::flag("nullable")),
+                "_Nonnull" | "__nonnull" => qualifiers.push(Annotation::flag("nonnull")),
+                "_Null_unspecified" | "__null_unspecified" => {}
+                word if IGNORED.contains(&word) => {}
+                _ => {
+                    if self.attribute(attributes)? {
+                        continue;
+                    }
+                    break;
+                }
+            }
+            self.pos += 1;
+        }
+        Ok(qualifiers)
+    }
+
+    fn array_length(&mut self, tokens: &[Token]) -> Option<Annotation> {
+        // Parameter arrays may carry qualifiers: `int a[static const 4]`
+        let start = tokens
+            .iter()
+            .position(|t| {
+                !["static", "const", "volatile", "restrict"]
+                    .iter()
+                    .any(|k| t.is(k))
+            })
+            .unwrap_or(tokens.len());
+        let tokens = &tokens[start..];
+        let first = tokens.first()?;
+        if let Some(length) = self.constant(tokens) {
+            #[expect(clippy::cast_precision_loss, reason = "array lengths are small")]
+            return Some(Annotation::with_number("array_length", length as f64));
+        }
+        self.diagnostics.warning(
+            diagnostics::UNSUPPORTED_DECLARATION,
+            Some(first.location()),
+            format!("cannot evaluate array length `{}`", join(tokens)),
+        );
+        Some(Annotation::with_string("array_length", join(tokens)))
+    }
+
+    /// Parameters after a `(`, and whether they end in `...`.
+    fn params(&mut self) -> PResult<(Vec<Param>, bool)> {
+        let mut params = Vec::new();
+        if self.eat(")") {
+            return Ok((params, false));
+        }
+        if self.peek().is_some_and(|t| t.is("void")) && self.peek_at(1).is_some_and(|t| t.is(")")) {
+            self.pos += 2;
+            return Ok((params, false));
+        }
+        loop {
+            if self.eat("...") {
+                self.expect(")")?;
+                return Ok((params, true));
+            }
+            let first = self.peek().ok_or("unterminated parameter list")?;
+            let mut specs = self.specifiers()?;
+            let mut decl = self.declarator(true)?;
+            while self.attribute(&mut decl.annotations)? {}
+            let typ = self.declared_type(&mut specs, &decl, None);
+            params.push(Param {
+                name: decl.name.map(|t| t.text.clone()),
+                typ: self.decay(typ),
+                default: None,
+                annotations: Vec::new(),
+                metadata: field_metadata(first),
+            });
+            if !self.eat(",") {
+                self.expect(")")?;
+                return Ok((params, false));
+            }
+        }
+    }
+
+    /// Parameters of array and function type are pointers, including those
+    /// whose type is a typedef of one.
+    fn decay(&self, typ: Type) -> Type {
+        match &typ.kind {
+            TypeKind::Ref { name } if name == "Array" => Type {
+                kind: TypeKind::Ref {
+                    name: "Ptr".to_string(),
+                },
+                ..typ
+            },
+            TypeKind::Function { .. } => Type::generic("Ptr", vec![typ]),
+            TypeKind::Ref { name } if typ.args.is_empty() => {
+                match self.typedef_target(name).map(|t| (&t.kind, t)) {
+                    Some((TypeKind::Ref { name }, array)) if name == "Array" => {
+                        // Qualifiers of an array type apply to its elements
+                        let mut element = array
+                            .args
+                            .first()
+                            .cloned()
+                            .unwrap_or_else(|| Type::reference("c_void"));
+                        let (qualifiers, annotations): (Vec<_>, Vec<_>) = typ
+                            .annotations
+                            .into_iter()
+                            .partition(|a| a.kind == "const" || a.kind == "volatile");
+                        for qualifier in qualifiers {
+                            if !element.annotations.contains(&qualifier) {
+                                element.annotations.push(qualifier);
+                            }
+                        }
+                        let mut pointer = Type::generic("Ptr", vec![element]);
+                        pointer.annotations.clone_from(&array.annotations);
+                        pointer.annotations.extend(annotations);
+                        pointer.metadata = typ.metadata;
+                        pointer
+                    }
+                    Some((TypeKind::Function { .. }, _)) => Type::generic("Ptr", vec![typ]),
+                    _ => typ,
+                }
+            }
+            _ => typ,
+        }
+    }
+
+    /// The definition the type `name` finally stands for, following typedefs
+    /// of typedefs.
+    fn typedef_target(&self, name: &str) -> Option<&Type> {
+        let mut name = name;
+        // A chain longer than the item list is a cycle of guessed names
+        for _ in 0..=self.items.len() {
+            let Some(Item::Type(definition)) = self.types.get(name).map(|&i| &self.items[i]) else {
+                return None;
+            };
+            match &definition.kind {
+                TypeKind::Ref { name: target } if definition.args.is_empty() => name = target,
+                _ => return Some(definition),
+            }
+        }
+        None
+    }
+
+    /// The base type of `specs`, naming an inline definition `name` (or a
+    /// generated name) and defining it.
+    fn base_type(&mut self, specs: &mut Specs, name: Option<&str>) -> Type {
+        if specs.anonymous
+            && let Some(name) = name
+        {
+            let mut typ = specs
+                .base
+                .take()
+                .unwrap_or_else(|| Type::reference("c_int"));
+            typ.name = Some(name.to_string());
+            self.define(typ);
+            specs.base = Some(Type::reference(name));
+            specs.anonymous = false;
+        }
+        if let Some(guess) = specs.guessed.take() {
+            self.unknown(&guess);
+        }
+        let mut typ = specs
+            .base
+            .clone()
+            .unwrap_or_else(|| Type::reference(primitive(&specs.words)));
+        typ.annotations.extend(specs.qualifiers.iter().cloned());
+        typ
+    }
+
+    /// Apply a declarator to the base type of `specs`.
+    fn declared_type(&mut self, specs: &mut Specs, decl: &Declarator, name: Option<&str>) -> Type {
+        let calling_convention = decl
+            .annotations
+            .iter()
+            .chain(&specs.attributes)
+            .find(|a| a.kind == "calling_convention")
+            .cloned()
+            .unwrap_or_else(|| {
+                Annotation::with_string("calling_convention", DEFAULT_CALLING_CONVENTION)
+            });
+        let mut typ = self.base_type(specs, name);
+        for op in &decl.ops {
+            typ = match op {
+                Op::Pointer(qualifiers) => {
+                    let mut pointer = Type::generic("Ptr", vec![typ]);
+                    pointer.annotations.clone_from(qualifiers);
+                    pointer
+                }
+                Op::Array(length) => {
+                    let mut array = Type::generic("Array", vec![typ]);
+                    if let Some(length) = length {
+                        if matches!(length.value, Some(AnnotationValue::String(_))) {
+                            confidence::lower(&mut array.metadata, confidence::PLACEHOLDER);
+                        }
+                        array.annotations.push(length.clone());
+                    }
+                    array
+                }
+                Op::Function { params, variadic } => {
+                    let mut function = Type {
+                        kind: TypeKind::Function {
+                            params: params.clone(),
+                            ret: Box::new(returned(typ)),
+                        },
+                        ..Type::reference("")
+                    };
+                    function.annotations.push(calling_convention.clone());
+                    if *variadic {
+                        function.annotations.push(Annotation::flag("variadic"));
+                    }
+                    function
+                }
+            };
+        }
+        typ
+    }
+
+    /// A declaration without declarators, such as `struct foo { ... };`.
+    fn declare_bare(&mut self, specs: &mut Specs) {
+        if !specs.anonymous {
+            return;
+        }
+        let Some(Type {
+            kind: TypeKind::Enum { variants },
+            ..
+        }) = &specs.base
+        else {
+            let name = self.anonymous_name();
+            self.base_type(specs, Some(&name));
+            return;
+        };
+        // `enum { A = 1, B = 2 };` declares integer constants
+        for variant in variants {
+            #[allow(clippy::cast_possible_truncation, reason = "enumerators are integers")]
+            let value = variant.annotations.iter().find_map(|a| match &a.value {
+                Some(AnnotationValue::Number(n)) if a.kind == "value" => Some(*n as i128),
+                _ => None,
+            });
+            let mut annotations: Vec<_> = variant
+                .annotations
+                .iter()
+                .filter(|a| a.kind == "cfg")
+                .cloned()
+                .collect();
+            if annotations.is_empty() {
+                self.conditional(&mut annotations);
+            }
+            self.items.push(Item::Const {
+                name: variant.name.clone(),
+                typ: Type::reference("c_int"),
+                value: Value::Int(value.unwrap_or_default()),
+                annotations,
+            });
+        }
+    }
+
+    fn declare(&mut self, specs: &mut Specs, decl: &Declarator, first: &Token) {
+        let Some(name) = decl.name.clone() else {
+            return;
+        };
+        if specs.typedef {
+            self.typedef(specs, decl, &name, first);
+            return;
+        }
+        if specs.anonymous {
+            let hoisted = self.anonymous_name();
+            self.base_type(specs, Some(&hoisted));
+        }
+        let typ = self.declared_type(specs, decl, None);
+        let TypeKind::Function { params, ret } = typ.kind else {
+            self.diagnostics.warning(
+                diagnostics::UNSUPPORTED_DECLARATION,
+                Some(name.location()),
+                format!(
+                    "global variable `{}` is not supported; skipping it",
+                    name.text
+                ),
+            );
+            return;
+        };
+        if specs.static_ {
+            self.diagnostics.note(
+                diagnostics::UNSUPPORTED_DECLARATION,
+                Some(name.location()),
+                format!(
+                    "static function `{}` has no symbol to link; skipping it",
+                    name.text
+                ),
+            );
+            return;
+        }
+        if !self.functions.insert((name.text.clone(), self.cfg)) {
+            return;
+        }
+        let mut metadata = self.metadata(first);
+        metadata.source = Some(name.location());
+        metadata.confidence = typ.metadata.confidence;
+        let mut annotations = typ.annotations;
+        annotations.extend(deprecated(&specs.attributes));
+        annotations.extend(deprecated(&decl.annotations));
+        self.conditional(&mut annotations);
+        let (mut args, mut ret) = (params, *ret);
+        apply_nonnull(
+            specs.attributes.iter().chain(&decl.annotations),
+            &mut args,
+            &mut ret,
+        );
+        self.items.push(Item::Function(Function {
+            name: name.text.clone(),
+            params: Vec::new(),
+            args,
+            ret,
+            annotations,
+            metadata,
+        }));
+    }
+
+    fn typedef(&mut self, specs: &mut Specs, decl: &Declarator, name: &Token, first: &Token) {
+        // Builtin names keep their mapping even where a header defines them
+        if builtin(&name.text).is_some() {
+            return;
+        }
+        self.typedefs.insert(name.text.clone());
+        if specs.anonymous && decl.ops.is_empty() {
+            // `typedef struct { ... } name;` names the struct itself
+            self.base_type(specs, Some(&name.text));
+            return;
+        }
+        if specs.anonymous {
+            let hoisted = format!("{}_target", name.text);
+            self.base_type(specs, Some(&hoisted));
+        }
+        let mut typ = self.declared_type(specs, decl, None);
+        // `typedef struct foo foo;` only makes the tag usable without `struct`
+        if matches!(&typ.kind, TypeKind::Ref { name: target } if *target == name.text) {
+            return;
+        }
+        typ.name = Some(name.text.clone());
+        let confidence = typ.metadata.confidence;
+        typ.metadata = self.metadata(first);
+        typ.metadata.source = Some(name.location());
+        typ.metadata.confidence = confidence;
+        typ.annotations.extend(deprecated(&decl.annotations));
+        self.define(typ);
+    }
+
+    fn skip_definition(&mut self, decl: &Declarator) {
+        if let Some(name) = &decl.name {
+            self.diagnostics.note(
+                diagnostics::UNSUPPORTED_DECLARATION,
+                Some(name.location()),
+                format!("function definition `{}` in a header is skipped", name.text),
+            );
+        }
+        self.doc = None;
+    }
+
+    fn anonymous_name(&mut self) -> String {
+        self.anonymous += 1;
+        format!("anon{}", self.anonymous)
+    }
+
+    /// Add a named type, hoisting inline types in its fields and replacing
+    /// an opaque declaration of the same name. Definitions of a name under
+    /// different predicates are kept as alternatives.
+    fn define(&mut self, mut typ: Type) {
+        let Some(name) = typ.name.clone() else {
+            return;
+        };
+        self.hoist(&mut typ, &name);
+        self.conditional(&mut typ.annotations);
+        let Some(&index) = self.types.get(&name) else {
+            self.types.insert(name, self.items.len());
+            self.items.push(Item::Type(typ));
+            return;
+        };
+        let Item::Type(existing) = &mut self.items[index] else {
+            return;
+        };
+        if existing.annotations.iter().any(|a| a.kind == "opaque") {
+            *existing = typ;
+        } else if self.cfg.is_some() && cfg(&existing.annotations) != self.cfg {
+            self.items.push(Item::Type(typ));
+        }
+    }
+
+    /// Name and define untagged structs, unions and enums within `typ`'s
+    /// fields after the field (`outer_field`).
+    fn hoist(&mut self, typ: &mut Type, outer: &str) {
+        let TypeKind::Struct { fields } = &mut typ.kind else {
+            return;
+        };
+        for field in fields {
+            let name = format!("{outer}_{}", field.name.as_deref().unwrap_or("anon"));
+            let mut inner = &mut field.typ;
+            // Arrays of and pointers to inline types
+            while matches!(&inner.kind, TypeKind::Ref { .. }) && inner.args.len() == 1 {
+                inner = &mut inner.args[0];
+            }
+            if matches!(inner.kind, TypeKind::Struct { .. } | TypeKind::Enum { .. })
+                && inner.name.is_none()
+            {
+                let mut hoisted = std::mem::replace(inner, Type::reference(&name));
+                // Qualifiers stay with the use; the rest describe the type
+                let (qualifiers, annotations) = std::mem::take(&mut hoisted.annotations)
+                    .into_iter()
+                    .partition(|a| a.kind == "const" || a.kind == "volatile");
+                inner.annotations = qualifiers;
+                hoisted.annotations = annotations;
+                hoisted.name = Some(name);
+                self.define(hoisted);
+            }
+        }
+    }
+
+    /// Declare `tag` as opaque unless it is already defined.
+    fn forward(&mut self, tag: &str, at: &Token, union: bool) {
+        if self.types.contains_key(tag) {
+            return;
+        }
+        let mut annotations = vec![Annotation::flag("opaque")];
+        if union {
+            annotations.push(Annotation::flag("union"));
+        }
+        self.types.insert(tag.to_string(), self.items.len());
+        self.items.push(Item::Type(Type {
+            kind: TypeKind::Struct { fields: Vec::new() },
+            name: Some(tag.to_string()),
+            annotations,
+            metadata: field_metadata(at),
+            ..Type::reference("")
+        }));
+    }
+
+    /// Declare a type name that was never declared as opaque, reporting it.
+    fn unknown(&mut self, at: &Token) {
+        if self.types.contains_key(&at.text) || self.typedefs.contains(&at.text) {
+            return;
+        }
+        self.diagnostics.warning(
+            diagnostics::UNKNOWN_TYPE,
+            Some(at.location()),
+            format!(
+                "unknown type `{}`; declaring it opaque (missing #include or -I?)",
+                at.text
+            ),
+        );
+        self.forward(&at.text, at, false);
+        if let Some(&index) = self.types.get(&at.text)
+            && let Item::Type(typ) = &mut self.items[index]
+        {
+            confidence::lower(&mut typ.metadata, confidence::PLACEHOLDER);
+        }
+    }
+}
+
+/// Predicate in a `cfg` annotation.
+fn cfg(annotations: &[Annotation]) -> Option<&str> {
+    annotations.iter().find_map(|a| match &a.value {
+        Some(AnnotationValue::String(cfg)) if a.kind == "cfg" => Some(cfg.as_str()),
+        _ => None,
+    })
+}
+
+fn set_calling_convention(annotations: &mut Vec<Annotation>, name: &str) {
+    annotations.retain(|a| a.kind != "calling_convention");
+    annotations.push(Annotation::with_string("calling_convention", name));
+}
+
+/// Tokens before the `)` closing the group they start in.
+fn enclosed(tokens: &[Token]) -> &[Token] {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is("(") {
+            depth += 1;
+        } else if token.is(")") {
+            let Some(d) = depth.checked_sub(1) else {
+                return &tokens[..i];
+            };
+            depth = d;
+        }
+    }
+    tokens
+}
+
+/// Alignment of a primitive type, alone or in `_Alignof(...)`.
+fn type_alignment(tokens: &[Token]) -> Option<u64> {
+    let tokens = match tokens {
+        [op, open, inner @ .., close]
+            if ["_Alignof", "alignof", "__alignof__", "__alignof"]
+                .iter()
+                .any(|k| op.is(k))
+                && open.is("(")
+                && close.is(")") =>
+        {
+            inner
+        }
+        _ => tokens,
+    };
+    let name = match tokens {
+        [token] if builtin(&token.text).is_some() => builtin(&token.text)?,
+        _ if !tokens.is_empty() && tokens.iter().all(|t| PRIMITIVES.contains(&t.text.as_str())) => {
+            let words: Vec<String> = tokens.iter().map(|t| t.text.clone()).collect();
+            primitive(&words)
+        }
+        _ => return None,
+    };
+    layout::primitive(name)
+}
+
+/// Raise the `align` in `annotations` to at least `align` bytes.
+#[expect(clippy::cast_precision_loss, reason = "alignments are small")]
+fn set_alignment(annotations: &mut Vec<Annotation>, align: u64) {
+    let current = annotations.iter_mut().find(|a| a.kind == "align");
+    match current {
+        Some(Annotation {
+            value: Some(AnnotationValue::Number(n)),
+            ..
+        }) => *n = n.max(align as f64),
+        _ => annotations.push(Annotation::with_number("align", align as f64)),
+    }
+}
+
+fn deprecated(annotations: &[Annotation]) -> Vec<Annotation> {
+    annotations
+        .iter()
+        .filter(|a| a.kind == "deprecated")
+        .cloned()
+        .collect()
+}
+
+/// Flag pointers named by `nonnull` and `returns_nonnull` attributes.
+fn apply_nonnull<'a>(
+    attributes: impl Iterator<Item = &'a Annotation>,
+    args: &mut [Param],
+    ret: &mut Type,
+) {
+    for attribute in attributes {
+        match (attribute.kind.as_str(), &attribute.value) {
+            ("nonnull", Some(AnnotationValue::List(positions))) => {
+                for (i, arg) in args.iter_mut().enumerate() {
+                    #[expect(clippy::cast_precision_loss, reason = "argument counts are small")]
+                    let position = AnnotationValue::Number((i + 1) as f64);
+                    if positions.is_empty() || positions.contains(&position) {
+                        flag_pointer(&mut arg.typ, "nonnull");
+                    }
+                }
+            }
+            ("returns_nonnull", _) => flag_pointer(ret, "nonnull"),
+            _ => {}
+        }
+    }
+}
+
+/// Add a flag to a `Ptr` type, once.
+fn flag_pointer(typ: &mut Type, flag: &str) {
+    let pointer = matches!(&typ.kind, TypeKind::Ref { name } if name == "Ptr");
+    if pointer && !typ.annotations.iter().any(|a| a.kind == flag) {
+        typ.annotations.push(Annotation::flag(flag));
+    }
+}
+
+fn is_anonymous(field: &Field) -> bool {
+    field.annotations.iter().any(|a| a.kind == "anonymous")
+}
+
+fn field_metadata(token: &Token) -> Metadata {
+    Metadata {
+        docs: token.doc.clone(),
+        source: Some(token.location()),
+        ..Metadata::default()
+    }
+}
+
+/// `void` as a return type is the unit type.
+fn returned(typ: Type) -> Type {
+    match &typ.kind {
+        TypeKind::Ref { name } if name == "c_void" && typ.args.is_empty() => {
+            Type::reference("Unit")
+        }
+        _ => typ,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::{AnnotationValue, Function, Item, Module, Type, TypeKind, Value};
+
+    use crate::parser::c::{Options, parse_source};
+
+    fn function<'a>(module: &'a Module, name: &str) -> &'a Function {
+        module
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Function(f) if f.name == name => Some(f),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// `typ` in a compact form: `Ptr<const c_int>`, `fn`, `struct`.
+    fn show(typ: &Type) -> String {
+        let base = match &typ.kind {
+            TypeKind::Ref { name } if typ.args.is_empty() => name.clone(),
+            TypeKind::Ref { name } => {
+                let args: Vec<_> = typ.args.iter().map(show).collect();
+                format!("{name}<{}>", args.join(", "))
+            }
+            TypeKind::Function { .. } => "fn".to_string(),
+            TypeKind::Struct { .. } => "struct".to_string(),
+            _ => "other".to_string(),
+        };
+        if typ.annotations.iter().any(|a| a.kind == "const") {
+            format!("const {base}")
+        } else {
+            base
+        }
+    }
+
+    fn args(function: &Function) -> Vec<String> {
+        function.args.iter().map(|a| show(&a.typ)).collect()
+    }
+
+    #[test]
+    fn array_parameters_decay_to_element_pointers() {
+        let (module, _) = parse_source(
+            "arrays",
+            "typedef int arr_t[4];\n\
+             typedef arr_t arr2_t;\n\
+             void f(int a[3], arr_t b, const arr_t c, arr2_t d);\n",
+            &Options::default(),
+        );
+        assert_eq!(
+            args(function(&module, "f")),
+            ["Ptr<c_int>", "Ptr<c_int>", "Ptr<const c_int>", "Ptr<c_int>"]
+        );
+    }
+
+    #[test]
+    fn typedef_of_struct_array_decays_like_gmp() {
+        let (module, _) = parse_source(
+            "gmp",
+            "typedef struct { int size; } mpz_struct;\n\
+             typedef mpz_struct mpz_t[1];\n\
+             void mpz_init(mpz_t x);\n",
+            &Options::default(),
+        );
+        assert_eq!(args(function(&module, "mpz_init")), ["Ptr<mpz_struct>"]);
+    }
+
+    #[test]
+    fn function_parameters_decay_to_function_pointers() {
+        let (module, _) = parse_source(
+            "callbacks",
+            "typedef void cb_t(int);\n\
+             typedef cb_t *cbp_t;\n\
+             void g(cb_t cb, cbp_t p, void h(int));\n",
+            &Options::default(),
+        );
+        assert_eq!(
+            args(function(&module, "g")),
+            ["Ptr<cb_t>", "cbp_t", "Ptr<fn>"]
+        );
+    }
+
+    #[test]
+    fn enumerators_count_on_from_the_previous_value() {
+        let (module, _) = parse_source(
+            "enums",
+            "enum color { RED = 5, GREEN, BLUE = 1 << 3, ALPHA };\n",
+            &Options::default(),
+        );
+        let Some(Item::Type(Type {
+            kind: TypeKind::Enum { variants },
+            ..
+        })) = module.items.first()
+        else {
+            panic!("expected an enum");
+        };
+        let values: Vec<_> = variants
+            .iter()
+            .map(|v| {
+                let value = v.annotations.iter().find(|a| a.kind == "value");
+                (v.name.as_str(), value.and_then(|a| a.value.clone()))
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("RED", Some(AnnotationValue::Number(5.0))),
+                ("GREEN", Some(AnnotationValue::Number(6.0))),
+                ("BLUE", Some(AnnotationValue::Number(8.0))),
+                ("ALPHA", Some(AnnotationValue::Number(9.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_constants_are_exact() {
+        let (module, _) = parse_source(
+            "macros",
+            "#define MASK 0xFFFFFFFFFFFFFFFF\n\
+             #define NEG (-9223372036854775807LL - 1)\n\
+             #define ESC '\\377'\n\
+             #define BYTE ((char)-1)\n\
+             #define RATIO 0.5\n\
+             #define NAME \"concord\"\n\
+             #define CALL(x) (x)\n",
+            &Options::default(),
+        );
+        let constants: Vec<_> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Const {
+                    name, typ, value, ..
+                } => Some((name.as_str(), show(typ), value.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            constants,
+            [
+                ("MASK", "c_ulong".to_string(), Value::Int(u64::MAX.into())),
+                ("NEG", "c_longlong".to_string(), Value::Int(i64::MIN.into())),
+                ("ESC", "c_int".to_string(), Value::Int(-1)),
+                ("BYTE", "c_char".to_string(), Value::Int(-1)),
+                ("RATIO", "c_double".to_string(), Value::Number(0.5)),
+                (
+                    "NAME",
+                    "Ptr<const c_char>".to_string(),
+                    Value::String("concord".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn variadic_functions_are_flagged() {
+        let (module, _) = parse_source(
+            "variadic",
+            "int log_msg(const char *fmt, ...);\nint log_count(int n);\n",
+            &Options::default(),
+        );
+        let variadic = |name| {
+            function(&module, name)
+                .annotations
+                .iter()
+                .any(|a| a.kind == "variadic")
+        };
+        assert_eq!(args(function(&module, "log_msg")), ["Ptr<const c_char>"]);
+        assert!(variadic("log_msg"));
+        assert!(!variadic("log_count"));
+    }
+}
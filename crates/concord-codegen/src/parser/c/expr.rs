@@ -0,0 +1,679 @@
+//! Constant expressions, as used by `#if`, enumerators, array lengths and
+//! object-like macros.
+//!
+//! Values carry their C type, following the usual arithmetic conversions of
+//! an LP64 target (`long` is 64 bits wide).
+
+use super::decl::{builtin, primitive};
+use super::lexer::{Kind, Token};
+
+/// The value of a constant expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// An integer of the given type, already wrapped to its width.
+    Int(i128, IntType),
+    /// A floating-point number and the IR name of its type.
+    Float(f64, &'static str),
+    /// A string literal, adjacent literals concatenated.
+    Str(String),
+}
+
+impl Constant {
+    /// The value of an enumerator: an `int`, or a `long` if it doesn't fit.
+    pub fn enumerator(value: i64) -> Self {
+        let value = i128::from(value);
+        Self::Int(value, if INT.wrap(value) == value { INT } else { LONG })
+    }
+}
+
+/// An integer type: its IR name and what the arithmetic conversions need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntType {
+    pub name: &'static str,
+    /// Conversion rank; types ranked below `int` are promoted to it.
+    rank: i8,
+    unsigned: bool,
+    bits: u32,
+}
+
+const INT: IntType = int_type("c_int", 0, false, 32);
+const UINT: IntType = int_type("c_uint", 0, true, 32);
+const LONG: IntType = int_type("c_long", 1, false, 64);
+const ULONG: IntType = int_type("c_ulong", 1, true, 64);
+const LONGLONG: IntType = int_type("c_longlong", 2, false, 64);
+const ULONGLONG: IntType = int_type("c_ulonglong", 2, true, 64);
+
+const fn int_type(name: &'static str, rank: i8, unsigned: bool, bits: u32) -> IntType {
+    IntType {
+        name,
+        rank,
+        unsigned,
+        bits,
+    }
+}
+
+/// Integer types by IR name: rank, signedness and width.
+const INT_TYPES: &[(&str, i8, bool, u32)] = &[
+    ("bool", -3, true, 1),
+    ("c_char", -2, false, 8),
+    ("c_schar", -2, false, 8),
+    ("i8", -2, false, 8),
+    ("c_uchar", -2, true, 8),
+    ("u8", -2, true, 8),
+    ("c_short", -1, false, 16),
+    ("i16", -1, false, 16),
+    ("c_ushort", -1, true, 16),
+    ("u16", -1, true, 16),
+    ("c_int", 0, false, 32),
+    ("i32", 0, false, 32),
+    ("c_uint", 0, true, 32),
+    ("u32", 0, true, 32),
+    ("c_long", 1, false, 64),
+    ("i64", 1, false, 64),
+    ("isize", 1, false, 64),
+    ("c_ulong", 1, true, 64),
+    ("u64", 1, true, 64),
+    ("usize", 1, true, 64),
+    ("c_longlong", 2, false, 64),
+    ("c_ulonglong", 2, true, 64),
+];
+
+impl IntType {
+    /// The integer type an IR primitive name denotes.
+    fn of(name: &str) -> Option<Self> {
+        INT_TYPES
+            .iter()
+            .find(|(n, ..)| *n == name)
+            .map(|&(name, rank, unsigned, bits)| int_type(name, rank, unsigned, bits))
+    }
+
+    /// Integer promotion: anything narrower than `int` becomes `int`.
+    fn promoted(self) -> Self {
+        if self.rank < 0 { INT } else { self }
+    }
+
+    /// The common type of two promoted operands.
+    fn common(self, other: Self) -> Self {
+        let (a, b) = (self.promoted(), other.promoted());
+        // At equal rank, unsigned wins
+        if a.rank > b.rank || a.rank == b.rank && !b.unsigned {
+            a
+        } else {
+            b
+        }
+    }
+
+    /// `value` wrapped to this type's width.
+    fn wrap(self, value: i128) -> i128 {
+        if self.bits == 1 {
+            return i128::from(value != 0);
+        }
+        let modulus = 1i128 << self.bits;
+        let value = value.rem_euclid(modulus);
+        if !self.unsigned && value >= modulus / 2 {
+            value - modulus
+        } else {
+            value
+        }
+    }
+}
+
+/// Evaluate `tokens` as an integer constant expression, as `#if` does:
+/// every integer is an `intmax_t` or `uintmax_t`.
+///
+/// Identifiers are looked up with `lookup`; casts to integer types convert.
+/// Returns `None` for anything that isn't an integer constant. Unsigned
+/// 64-bit values keep their bit pattern.
+#[allow(clippy::cast_possible_truncation)]
+pub fn eval(tokens: &[Token], lookup: &dyn Fn(&str) -> Option<i64>) -> Option<i64> {
+    let lookup = |name: &str| lookup(name).map(|v| Constant::Int(i128::from(v), LONGLONG));
+    let mut eval = Eval {
+        tokens,
+        pos: 0,
+        lookup: &lookup,
+        intmax: true,
+    };
+    let value = eval.conditional()?;
+    match value {
+        Constant::Int(value, _) if eval.pos == tokens.len() => Some(value as i64),
+        _ => None,
+    }
+}
+
+/// Evaluate `tokens` as an arithmetic or string constant expression, with
+/// identifiers looked up by `lookup`.
+pub fn eval_constant(
+    tokens: &[Token],
+    lookup: &dyn Fn(&str) -> Option<Constant>,
+) -> Option<Constant> {
+    let mut eval = Eval {
+        tokens,
+        pos: 0,
+        lookup,
+        intmax: false,
+    };
+    let value = eval.conditional()?;
+    (eval.pos == tokens.len()).then_some(value)
+}
+
+struct Eval<'t> {
+    tokens: &'t [Token],
+    pos: usize,
+    lookup: &'t dyn Fn(&str) -> Option<Constant>,
+    /// Type literals as `intmax_t`, as `#if` does.
+    intmax: bool,
+}
+
+/// Binary operators by precedence, loosest first.
+const BINARY: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Words that can make up an arithmetic type name in a cast.
+const TYPE_WORDS: &[&str] = &[
+    "signed", "unsigned", "char", "short", "int", "long", "const", "_Bool", "float", "double",
+];
+
+impl Eval<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let matched = self.peek().is_some_and(|t| t.is(text));
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn conditional(&mut self) -> Option<Constant> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Some(condition);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let otherwise = self.conditional()?;
+        Some(if truthy(&condition)? { then } else { otherwise })
+    }
+
+    fn binary(&mut self, level: usize) -> Option<Constant> {
+        let Some(ops) = BINARY.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self
+            .peek()
+            .filter(|t| t.kind == Kind::Punct)
+            .and_then(|t| ops.iter().find(|op| t.text == **op))
+        {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = apply(op, left, right)?;
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Constant> {
+        if self.eat("-") {
+            return match self.unary()? {
+                Constant::Int(value, typ) => {
+                    let typ = typ.promoted();
+                    Some(Constant::Int(typ.wrap(-value), typ))
+                }
+                Constant::Float(value, typ) => Some(Constant::Float(-value, typ)),
+                Constant::Str(_) => None,
+            };
+        }
+        if self.eat("+") {
+            return match self.unary()? {
+                Constant::Int(value, typ) => Some(Constant::Int(value, typ.promoted())),
+                other => Some(other),
+            };
+        }
+        if self.eat("!") {
+            return Some(Constant::Int(i128::from(!truthy(&self.unary()?)?), INT));
+        }
+        if self.eat("~") {
+            return match self.unary()? {
+                Constant::Int(value, typ) => {
+                    let typ = typ.promoted();
+                    Some(Constant::Int(typ.wrap(!value), typ))
+                }
+                _ => None,
+            };
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Constant> {
+        let token = self.peek()?.clone();
+        self.pos += 1;
+        match token.kind {
+            Kind::Number => number(&token.text, self.intmax),
+            Kind::Char => parse_char(&token.text).map(|c| Constant::Int(i128::from(c), INT)),
+            Kind::Str => {
+                let mut text = string(&token.text)?;
+                while let Some(next) = self.peek().filter(|t| t.kind == Kind::Str) {
+                    text.push_str(&string(&next.text)?);
+                    self.pos += 1;
+                }
+                Some(Constant::Str(text))
+            }
+            Kind::Ident => (self.lookup)(&token.text),
+            Kind::Punct if token.text == "(" => {
+                if let Some(cast) = self.cast() {
+                    let value = self.unary()?;
+                    return match cast {
+                        Cast::To(target) => convert(&value, target),
+                        Cast::Unknown => Some(value),
+                    };
+                }
+                let value = self.conditional()?;
+                self.eat(")").then_some(value)
+            }
+            Kind::Punct => None,
+        }
+    }
+
+    /// Parse a cast such as `(unsigned long)` after its opening parenthesis,
+    /// returning the type it converts to.
+    fn cast(&mut self) -> Option<Cast> {
+        let start = self.pos;
+        let mut words = Vec::new();
+        let mut typedef = None;
+        while let Some(t) = self.peek().filter(|t| t.kind == Kind::Ident) {
+            if TYPE_WORDS.contains(&t.text.as_str()) {
+                words.push(t.text.clone());
+            } else if typedef.is_none()
+                && words.is_empty()
+                && (t.text == "bool" || is_int_typedef(&t.text))
+            {
+                typedef = Some(t.text.clone());
+            } else {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos > start && self.eat(")") {
+            return Some(match typedef {
+                Some(name) => builtin(&name).map_or(Cast::Unknown, Cast::To),
+                None => Cast::To(primitive(&words)),
+            });
+        }
+        self.pos = start;
+        None
+    }
+}
+
+/// The target of a cast.
+enum Cast {
+    /// An arithmetic type, by IR name.
+    To(&'static str),
+    /// An integer typedef of unknown width; the cast is ignored.
+    Unknown,
+}
+
+/// Whether a scalar is nonzero; strings have no truth value here.
+fn truthy(value: &Constant) -> Option<bool> {
+    match value {
+        Constant::Int(value, _) => Some(*value != 0),
+        Constant::Float(value, _) => Some(*value != 0.0),
+        Constant::Str(_) => None,
+    }
+}
+
+/// Convert `value` to the arithmetic type named `target`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn convert(value: &Constant, target: &'static str) -> Option<Constant> {
+    let float = matches!(target, "c_float" | "c_double" | "c_longdouble");
+    match *value {
+        Constant::Int(value, _) if float => Some(Constant::Float(value as f64, target)),
+        Constant::Float(value, _) if float => Some(Constant::Float(value, target)),
+        Constant::Int(value, _) => {
+            let typ = IntType::of(target)?;
+            Some(Constant::Int(typ.wrap(value), typ))
+        }
+        Constant::Float(value, _) => {
+            let typ = IntType::of(target)?;
+            Some(Constant::Int(typ.wrap(value.trunc() as i128), typ))
+        }
+        Constant::Str(_) => None,
+    }
+}
+
+/// Fixed-width and size typedefs from `<stdint.h>` and `<stddef.h>`.
+fn is_int_typedef(name: &str) -> bool {
+    name.ends_with("_t")
+        && (name.starts_with("int") || name.starts_with("uint") || name.contains("size"))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn apply(op: &str, a: Constant, b: Constant) -> Option<Constant> {
+    let compare = |ordering: Option<std::cmp::Ordering>| {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+        let ordering = ordering?;
+        let result = match op {
+            "==" => ordering == Equal,
+            "!=" => ordering != Equal,
+            "<" => ordering == Less,
+            ">" => ordering == Greater,
+            "<=" => ordering != Greater,
+            ">=" => ordering != Less,
+            _ => return None,
+        };
+        Some(Constant::Int(i128::from(result), INT))
+    };
+    match op {
+        "||" => return Some(Constant::Int(i128::from(truthy(&a)? || truthy(&b)?), INT)),
+        "&&" => return Some(Constant::Int(i128::from(truthy(&a)? && truthy(&b)?), INT)),
+        _ => {}
+    }
+    match (a, b) {
+        (Constant::Int(a, ta), Constant::Int(b, tb)) => {
+            // Shifts keep the left operand's type; the rest convert both
+            let typ = if matches!(op, "<<" | ">>") {
+                ta.promoted()
+            } else {
+                ta.common(tb)
+            };
+            let (a, b) = (
+                typ.wrap(a),
+                if matches!(op, "<<" | ">>") {
+                    b
+                } else {
+                    typ.wrap(b)
+                },
+            );
+            if matches!(op, "==" | "!=" | "<" | ">" | "<=" | ">=") {
+                return compare(Some(a.cmp(&b)));
+            }
+            let shift = || u32::try_from(b).ok().filter(|&s| s < typ.bits);
+            let value = match op {
+                "|" => a | b,
+                "^" => a ^ b,
+                "&" => a & b,
+                "<<" => a.checked_shl(shift()?)?,
+                ">>" => a >> shift()?,
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a.checked_mul(b)?,
+                "/" => a.checked_div(b)?,
+                "%" => a.checked_rem(b)?,
+                _ => return None,
+            };
+            Some(Constant::Int(typ.wrap(value), typ))
+        }
+        (Constant::Str(_), _) | (_, Constant::Str(_)) => None,
+        (a, b) => {
+            let (a, ta) = float(&a);
+            let (b, tb) = float(&b);
+            // The wider of the two, `double` unless both are `float`
+            let typ = if ta == "c_float" && tb == "c_float" {
+                "c_float"
+            } else if ta == "c_longdouble" || tb == "c_longdouble" {
+                "c_longdouble"
+            } else {
+                "c_double"
+            };
+            let value = match op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => a / b,
+                _ => return compare(a.partial_cmp(&b)),
+            };
+            Some(Constant::Float(value, typ))
+        }
+    }
+}
+
+/// A number as a float and the name of its type, integers taking that of
+/// the other operand.
+#[allow(clippy::cast_precision_loss)]
+fn float(value: &Constant) -> (f64, &'static str) {
+    match *value {
+        Constant::Float(value, typ) => (value, typ),
+        Constant::Int(value, _) => (value as f64, "c_float"),
+        Constant::Str(_) => (f64::NAN, "c_double"),
+    }
+}
+
+/// An integer or floating-point literal, typed as C types it: the first of
+/// `int`, `long` and `long long` (unsigned ones too for hex and octal, or
+/// only unsigned ones with a `u` suffix) that holds it, starting at `long`
+/// for an `l` suffix. In `#if`, every integer is `intmax_t` or `uintmax_t`.
+fn number(text: &str, intmax: bool) -> Option<Constant> {
+    let lower = text.to_ascii_lowercase();
+    let hex = lower.starts_with("0x");
+    if !hex && (lower.contains('.') || lower.contains('e')) || hex && lower.contains('p') {
+        return float_literal(&lower);
+    }
+
+    let digits = lower.trim_end_matches(['u', 'l']);
+    let suffix = &lower[digits.len()..];
+    let value = i128::from(parse_unsigned(digits)?);
+    let unsigned = suffix.contains('u');
+    let octal = digits.len() > 1 && digits.starts_with('0');
+    let decimal = !hex && !octal;
+    let candidates: &[IntType] = if intmax {
+        &[LONGLONG, ULONGLONG]
+    } else {
+        match (suffix.matches('l').count(), unsigned) {
+            (0, false) if decimal => &[INT, LONG, LONGLONG],
+            (0, false) => &[INT, UINT, LONG, ULONG, LONGLONG, ULONGLONG],
+            (0, true) => &[UINT, ULONG, ULONGLONG],
+            (1, false) if decimal => &[LONG, LONGLONG],
+            (1, false) => &[LONG, ULONG, LONGLONG, ULONGLONG],
+            (1, true) => &[ULONG, ULONGLONG],
+            (_, false) if decimal => &[LONGLONG],
+            (_, false) => &[LONGLONG, ULONGLONG],
+            (_, true) => &[ULONGLONG],
+        }
+    };
+    let typ = candidates
+        .iter()
+        .copied()
+        .filter(|t| !unsigned || t.unsigned)
+        .find(|t| t.wrap(value) == value)
+        // Too large for any signed type: C takes the unsigned one
+        .unwrap_or(ULONGLONG);
+    Some(Constant::Int(typ.wrap(value), typ))
+}
+
+fn float_literal(lower: &str) -> Option<Constant> {
+    let (digits, typ) = if let Some(digits) = lower.strip_suffix('f') {
+        (digits, "c_float")
+    } else if let Some(digits) = lower.strip_suffix('l') {
+        (digits, "c_longdouble")
+    } else {
+        (lower, "c_double")
+    };
+    // Hexadecimal floats are rare in headers and not supported
+    let value: f64 = digits.replace('\'', "").parse().ok()?;
+    Some(Constant::Float(value, typ))
+}
+
+/// Digits of an integer literal without its suffix.
+fn parse_unsigned(digits: &str) -> Option<u64> {
+    let digits = digits.replace('\'', "");
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        (bin, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits.as_str(), 10)
+    };
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// Parse an integer literal, ignoring `u`/`l` suffixes.
+pub fn parse_int(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    // Unsigned literals such as 0xFFFFFFFFFFFFFFFF keep their bit pattern
+    parse_unsigned(lower.trim_end_matches(['u', 'l'])).map(u64::cast_signed)
+}
+
+/// Value of a character literal such as `'a'` or `'\n'`.
+///
+/// A plain literal has the value of a `char`, which is signed on the targets
+/// assumed here, so `'\377'` is -1; prefixed (wide) literals aren't narrowed.
+pub fn parse_char(text: &str) -> Option<i64> {
+    let quote = text.find('\'')?;
+    let body = text.get(quote + 1..text.len() - 1)?;
+    let narrow = |value: i64| {
+        if quote == 0 {
+            u8::try_from(value).ok().map(|b| i64::from(b.cast_signed()))
+        } else {
+            Some(value)
+        }
+    };
+    let mut chars = body.chars();
+    let value = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => 10,
+            't' => 9,
+            'r' => 13,
+            '0'..='7' => {
+                return narrow(i64::from_str_radix(&body[1..], 8).ok()?);
+            }
+            'x' => return narrow(i64::from_str_radix(&body[2..], 16).ok()?),
+            'a' => 7,
+            'b' => 8,
+            'f' => 12,
+            'v' => 11,
+            c => u32::from(c),
+        },
+        c => u32::from(c),
+    };
+    Some(i64::from(value))
+}
+
+/// Contents of a plain or `u8` string literal, with escapes resolved.
+/// Wide literals and escapes that aren't valid UTF-8 give `None`.
+fn string(text: &str) -> Option<String> {
+    let body = text.strip_prefix("u8").unwrap_or(text);
+    let body = body.strip_prefix('"')?.strip_suffix('"')?;
+    let mut bytes = Vec::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let escaped = chars.next()?;
+        let byte = match escaped {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 7,
+            'b' => 8,
+            'f' => 12,
+            'v' => 11,
+            'x' => {
+                let mut value = 0u32;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)) {
+                    value = value.checked_mul(16)? + digit;
+                    chars.next();
+                }
+                u8::try_from(value).ok()?
+            }
+            '0'..='7' => {
+                let mut value = escaped.to_digit(8)?;
+                for _ in 0..2 {
+                    let Some(digit) = chars.peek().and_then(|c| c.to_digit(8)) else {
+                        break;
+                    };
+                    value = value * 8 + digit;
+                    chars.next();
+                }
+                u8::try_from(value).ok()?
+            }
+            c if c.is_ascii() => u8::try_from(c).ok()?,
+            _ => return None,
+        };
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::parser::c::lexer::tokenize;
+
+    fn constant(source: &str) -> Option<Constant> {
+        let tokens = tokenize(&Rc::from("test.h"), source);
+        eval_constant(&tokens, &|_| None)
+    }
+
+    fn int(value: i128, typ: &str) -> Constant {
+        Constant::Int(value, IntType::of(typ).unwrap())
+    }
+
+    #[test]
+    fn char_literals_are_signed_unless_prefixed() {
+        assert_eq!(parse_char("'A'"), Some(65));
+        assert_eq!(parse_char("'\\n'"), Some(10));
+        assert_eq!(parse_char("'\\377'"), Some(-1));
+        assert_eq!(parse_char("'\\x80'"), Some(-128));
+        assert_eq!(parse_char("'\\x7f'"), Some(127));
+        assert_eq!(parse_char("L'\\xff'"), Some(255));
+        assert_eq!(constant("'\\377'"), Some(int(-1, "c_int")));
+    }
+
+    #[test]
+    fn integers_keep_their_exact_value_and_type() {
+        assert_eq!(constant("42"), Some(int(42, "c_int")));
+        assert_eq!(constant("3000000000"), Some(int(3_000_000_000, "c_long")));
+        assert_eq!(
+            constant("0xFFFFFFFFFFFFFFFF"),
+            Some(int(i128::from(u64::MAX), "c_ulong"))
+        );
+        assert_eq!(constant("(1ULL << 63)"), Some(int(1 << 63, "c_ulonglong")));
+        assert_eq!(
+            constant("(-9223372036854775807LL - 1)"),
+            Some(int(i128::from(i64::MIN), "c_longlong"))
+        );
+        assert_eq!(
+            constant("0x20000000000001"),
+            Some(int(9_007_199_254_740_993, "c_long"))
+        );
+    }
+
+    #[test]
+    fn arithmetic_follows_c_conversions() {
+        assert_eq!(constant("-1U"), Some(int(i128::from(u32::MAX), "c_uint")));
+        assert_eq!(constant("((char)-1)"), Some(int(-1, "c_char")));
+        assert_eq!(constant("((unsigned char)300)"), Some(int(44, "c_uchar")));
+        assert_eq!(constant("(1 << 4) | 1"), Some(int(17, "c_int")));
+    }
+
+    #[test]
+    fn floats_and_strings() {
+        assert_eq!(constant("1.5f"), Some(Constant::Float(1.5, "c_float")));
+        assert_eq!(constant("2.0"), Some(Constant::Float(2.0, "c_double")));
+        assert_eq!(
+            constant("\"ab\" \"c\""),
+            Some(Constant::Str("abc".to_string()))
+        );
+        assert_eq!(constant("1 +"), None);
+    }
+}
@@ -0,0 +1,403 @@
+//! Size, alignment and field offsets of structs and unions.
+//!
+//! Layouts follow the LP64 System V ABI used by 64-bit Linux and macOS:
+//! `long` and pointers are 8 bytes, and bitfields are allocated from the
+//! least significant bit without straddling a unit of their declared type
+//! unless packed. Records containing a type whose layout isn't known, such as
+//! an opaque struct or `va_list`, or a field only declared on some platforms
+//! are left without annotations. A name defined differently per platform has
+//! a known layout only where all its definitions agree.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::{Annotation, AnnotationValue, Field, Item, Type, TypeKind, Variant};
+
+/// Definitions can't contain themselves by value; this only guards bad input.
+const MAX_DEPTH: usize = 32;
+
+/// Size and alignment in bytes.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    size: u64,
+    align: u64,
+}
+
+impl Layout {
+    fn scalar(size: u64) -> Self {
+        Self { size, align: size }
+    }
+}
+
+/// Layout of a struct or union, with the offset in bits of each field.
+struct Record {
+    layout: Layout,
+    offsets: Vec<u64>,
+}
+
+/// Annotate every struct and union whose layout is known with its `size` and
+/// `align`, and its fields with their `offset` in bytes or, for bitfields,
+/// their `bit_offset` in bits.
+pub fn annotate(items: &mut [Item]) {
+    let records: HashMap<usize, Record> = {
+        let mut layouts = Layouts {
+            types: HashMap::new(),
+            records: HashMap::new(),
+        };
+        for (index, item) in items.iter().enumerate() {
+            if let Item::Type(typ) = item
+                && let Some(name) = &typ.name
+            {
+                layouts.types.entry(name).or_default().push((index, typ));
+            }
+        }
+        let names: Vec<&str> = layouts.types.keys().copied().collect();
+        for name in names {
+            layouts.named(name, 0);
+        }
+        layouts
+            .records
+            .into_iter()
+            .filter_map(|(index, record)| Some((index, record?)))
+            .collect()
+    };
+
+    for (index, item) in items.iter_mut().enumerate() {
+        let Item::Type(typ) = item else {
+            continue;
+        };
+        let Some(record) = records.get(&index) else {
+            continue;
+        };
+        set(&mut typ.annotations, "size", record.layout.size);
+        set(&mut typ.annotations, "align", record.layout.align);
+        let TypeKind::Struct { fields } = &mut typ.kind else {
+            continue;
+        };
+        for (field, &bits) in fields.iter_mut().zip(&record.offsets) {
+            if number(&field.annotations, "bitfield_width").is_some() {
+                set(&mut field.annotations, "bit_offset", bits);
+            } else {
+                set(&mut field.annotations, "offset", bits / 8);
+            }
+        }
+    }
+}
+
+/// Layouts of named types, computed as they are first needed.
+struct Layouts<'a> {
+    /// Definitions of each name, with their index in the items.
+    types: HashMap<&'a str, Vec<(usize, &'a Type)>>,
+    /// Every struct and union reached so far by index, `None` if its layout
+    /// isn't known.
+    records: HashMap<usize, Option<Record>>,
+}
+
+impl<'a> Layouts<'a> {
+    fn of(&mut self, typ: &'a Type, depth: usize) -> Option<Layout> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match &typ.kind {
+            TypeKind::Ref { name } => match name.as_str() {
+                "Ptr" => Some(Layout::scalar(8)),
+                "Array" => {
+                    let element = self.of(typ.args.first()?, depth + 1)?;
+                    // Flexible array members have no length; unevaluated ones
+                    // have a string
+                    let length = match typ.annotations.iter().find(|a| a.kind == "array_length") {
+                        Some(_) => number(&typ.annotations, "array_length")?,
+                        None => 0,
+                    };
+                    Some(Layout {
+                        size: element.size * length,
+                        align: element.align,
+                    })
+                }
+                name => primitive(name)
+                    .map(Layout::scalar)
+                    .or_else(|| self.named(name, depth + 1)),
+            },
+            TypeKind::Struct { fields } => self.record(typ, fields, depth).map(|r| r.layout),
+            TypeKind::Enum { variants } => Some(enumeration(variants)),
+            _ => None,
+        }
+    }
+
+    fn named(&mut self, name: &str, depth: usize) -> Option<Layout> {
+        let definitions = self.types.get(name)?.clone();
+        let layouts: Vec<_> = definitions
+            .into_iter()
+            .map(|(index, typ)| self.definition(index, typ, depth))
+            .collect();
+        let layout = (*layouts.first()?)?;
+        layouts
+            .iter()
+            .all(|other| other.is_some_and(|o| (o.size, o.align) == (layout.size, layout.align)))
+            .then_some(layout)
+    }
+
+    fn definition(&mut self, index: usize, typ: &'a Type, depth: usize) -> Option<Layout> {
+        let TypeKind::Struct { fields } = &typ.kind else {
+            // Typedefs are laid out as their target
+            return self.of(typ, depth);
+        };
+        if !self.records.contains_key(&index) {
+            let record = self.record(typ, fields, depth);
+            self.records.insert(index, record);
+        }
+        self.records[&index].as_ref().map(|r| r.layout)
+    }
+
+    fn record(&mut self, typ: &'a Type, fields: &'a [Field], depth: usize) -> Option<Record> {
+        if has(&typ.annotations, "opaque") {
+            return None;
+        }
+        let union = has(&typ.annotations, "union");
+        let pack = packing(&typ.annotations);
+        let mut bit: u64 = 0;
+        let mut end = 0;
+        let mut align = 1;
+        let mut offsets = Vec::with_capacity(fields.len());
+        for field in fields {
+            if has(&field.annotations, "cfg") {
+                return None;
+            }
+            let layout = self.of(&field.typ, depth + 1)?;
+            let field_pack = packing(&field.annotations).or(pack);
+            let field_align = field_pack
+                .map_or(layout.align, |pack| layout.align.min(pack))
+                .max(requested_align(&field.annotations)?);
+            if union {
+                bit = 0;
+            }
+            let width = number(&field.annotations, "bitfield_width");
+            match width {
+                // A zero width only moves the next field to a new unit
+                Some(0) => bit = bit.next_multiple_of(layout.align * 8),
+                Some(width) => {
+                    let unit = layout.size * 8;
+                    let straddles = unit > 0 && bit / unit != (bit + width - 1) / unit;
+                    if field_pack.is_none() && straddles {
+                        bit = bit.next_multiple_of(layout.align * 8);
+                    }
+                    // Unnamed bitfields don't affect the record's alignment
+                    if field.name.is_some() {
+                        align = align.max(field_align);
+                    }
+                }
+                None => {
+                    bit = bit.next_multiple_of(field_align * 8);
+                    align = align.max(field_align);
+                }
+            }
+            offsets.push(bit);
+            bit += width.unwrap_or(layout.size * 8);
+            end = end.max(bit);
+        }
+        align = align.max(requested_align(&typ.annotations)?);
+        Some(Record {
+            layout: Layout {
+                size: end.div_ceil(8).next_multiple_of(align),
+                align,
+            },
+            offsets,
+        })
+    }
+}
+
+/// Size of a primitive type, which is also its alignment.
+pub(super) fn primitive(name: &str) -> Option<u64> {
+    Some(match name {
+        "bool" | "c_char" | "c_schar" | "c_uchar" | "i8" | "u8" => 1,
+        "c_short" | "c_ushort" | "i16" | "u16" => 2,
+        "c_int" | "c_uint" | "c_float" | "i32" | "u32" => 4,
+        "c_long" | "c_ulong" | "c_longlong" | "c_ulonglong" | "c_double" | "i64" | "u64"
+        | "isize" | "usize" => 8,
+        "c_longdouble" | "i128" | "u128" => 16,
+        _ => return None,
+    })
+}
+
+/// An enum is an `int` unless its values need an `unsigned int` or more.
+#[allow(clippy::cast_possible_truncation)]
+fn enumeration(variants: &[Variant]) -> Layout {
+    let values: Vec<i64> = variants
+        .iter()
+        .filter_map(|v| number_value(&v.annotations, "value").map(|n| n as i64))
+        .collect();
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+    let int = min >= i64::from(i32::MIN) && max <= i64::from(i32::MAX);
+    let uint = min >= 0 && max <= i64::from(u32::MAX);
+    Layout::scalar(if int || uint { 4 } else { 8 })
+}
+
+fn has(annotations: &[Annotation], kind: &str) -> bool {
+    annotations.iter().any(|a| a.kind == kind)
+}
+
+/// Maximum member alignment of a `packed` record or field: 1 for the
+/// attribute, or the size `#pragma pack` gave.
+fn packing(annotations: &[Annotation]) -> Option<u64> {
+    annotations
+        .iter()
+        .find(|a| a.kind == "packed")
+        .map(|_| number(annotations, "packed").unwrap_or(1))
+}
+
+/// Largest alignment requested with an `align` annotation, or 1. `None` if
+/// one couldn't be evaluated.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn requested_align(annotations: &[Annotation]) -> Option<u64> {
+    annotations
+        .iter()
+        .filter(|a| a.kind == "align")
+        .try_fold(1, |align, a| match a.value {
+            Some(AnnotationValue::Number(n)) => Some(align.max(n as u64)),
+            _ => None,
+        })
+}
+
+fn number_value(annotations: &[Annotation], kind: &str) -> Option<f64> {
+    annotations.iter().find_map(|a| match a.value {
+        Some(AnnotationValue::Number(n)) if a.kind == kind => Some(n),
+        _ => None,
+    })
+}
+
+/// A non-negative integer annotation.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn number(annotations: &[Annotation], kind: &str) -> Option<u64> {
+    number_value(annotations, kind)
+        .filter(|n| *n >= 0.0)
+        .map(|n| n as u64)
+}
+
+/// Replace any `kind` annotation with the number `value`.
+#[allow(clippy::cast_precision_loss)]
+fn set(annotations: &mut Vec<Annotation>, kind: &str, value: u64) {
+    annotations.retain(|a| a.kind != kind);
+    annotations.push(Annotation::with_number(kind, value as f64));
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::Module;
+
+    use super::*;
+    use crate::parser::c::{Options, parse_source};
+
+    /// Size, alignment and field offsets (bit offsets marked `b`) of the
+    /// struct or union `name`.
+    fn layout(module: &Module, name: &str) -> (Option<u64>, Option<u64>, Vec<String>) {
+        let typ = module
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Type(typ) if typ.name.as_deref() == Some(name) => Some(typ),
+                _ => None,
+            })
+            .unwrap();
+        let TypeKind::Struct { fields } = &typ.kind else {
+            panic!("`{name}` is not a struct");
+        };
+        let offsets = fields
+            .iter()
+            .filter_map(|f| {
+                number(&f.annotations, "offset")
+                    .map(|o| o.to_string())
+                    .or_else(|| number(&f.annotations, "bit_offset").map(|o| format!("{o}b")))
+            })
+            .collect();
+        (
+            number(&typ.annotations, "size"),
+            number(&typ.annotations, "align"),
+            offsets,
+        )
+    }
+
+    fn parse(source: &str) -> Module {
+        parse_source("layout", source, &Options::default()).0
+    }
+
+    #[test]
+    fn fields_are_aligned_to_their_type() {
+        let module = parse(
+            "struct s { char c; int i; double d; };\n\
+             struct t { char c; struct s s; short h; };\n\
+             struct p { void *p; char c[3]; long l; };\n",
+        );
+        assert_eq!(
+            layout(&module, "s"),
+            (Some(16), Some(8), vec!["0".into(), "4".into(), "8".into()])
+        );
+        assert_eq!(
+            layout(&module, "t"),
+            (Some(32), Some(8), vec!["0".into(), "8".into(), "24".into()])
+        );
+        assert_eq!(
+            layout(&module, "p"),
+            (Some(24), Some(8), vec!["0".into(), "8".into(), "16".into()])
+        );
+    }
+
+    #[test]
+    fn unions_overlap_their_members() {
+        let module = parse("union u { char c[9]; double d; };\n");
+        assert_eq!(
+            layout(&module, "u"),
+            (Some(16), Some(8), vec!["0".into(), "0".into()])
+        );
+    }
+
+    #[test]
+    fn packing_and_alignment_requests() {
+        let module = parse(
+            "struct __attribute__((packed)) a { char c; int i; };\n\
+             #pragma pack(2)\n\
+             struct b { char c; int i; };\n\
+             #pragma pack()\n\
+             struct c { char c; } __attribute__((aligned(16)));\n",
+        );
+        assert_eq!(
+            layout(&module, "a"),
+            (Some(5), Some(1), vec!["0".into(), "1".into()])
+        );
+        assert_eq!(
+            layout(&module, "b"),
+            (Some(6), Some(2), vec!["0".into(), "2".into()])
+        );
+        assert_eq!(layout(&module, "c"), (Some(16), Some(16), vec!["0".into()]));
+    }
+
+    #[test]
+    fn bitfields_do_not_straddle_their_unit() {
+        let module = parse(
+            "struct f { unsigned a : 3; unsigned b : 30; char c; };\n\
+             struct g { unsigned a : 3; char c; };\n",
+        );
+        assert_eq!(
+            layout(&module, "f"),
+            (
+                Some(12),
+                Some(4),
+                vec!["0b".into(), "32b".into(), "8".into()]
+            )
+        );
+        assert_eq!(
+            layout(&module, "g"),
+            (Some(4), Some(4), vec!["0b".into(), "1".into()])
+        );
+    }
+
+    #[test]
+    fn records_with_unknown_members_have_no_layout() {
+        let module = parse(
+            "struct opaque;\n\
+             struct h { int n; struct opaque *ok; };\n\
+             struct v { int n; struct opaque inner; };\n",
+        );
+        assert_eq!(layout(&module, "h").0, Some(16));
+        assert_eq!(layout(&module, "v"), (None, None, Vec::new()));
+    }
+}
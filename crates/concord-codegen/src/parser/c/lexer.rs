@@ -0,0 +1,292 @@
+//! Tokenizer for C headers.
+
+use std::rc::Rc;
+
+use rhi_concord_core::SourceLocation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Ident,
+    Number,
+    Str,
+    Char,
+    Punct,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: Kind,
+    pub text: String,
+    pub file: Rc<str>,
+    pub line: u32,
+    pub column: u32,
+    /// First token on its logical line, where directives start.
+    pub line_start: bool,
+    /// Preceded by whitespace or a comment.
+    pub space_before: bool,
+    /// Doc comment (`/** */`, `/*! */`, `///`, `//!`) right before the token.
+    pub doc: Option<String>,
+}
+
+impl Token {
+    /// Whether this is the identifier or punctuator `text`.
+    pub fn is(&self, text: &str) -> bool {
+        matches!(self.kind, Kind::Ident | Kind::Punct) && self.text == text
+    }
+
+    pub fn location(&self) -> SourceLocation {
+        SourceLocation {
+            file: self.file.to_string(),
+            pointer: None,
+            line: Some(self.line),
+            column: Some(self.column),
+        }
+    }
+}
+
+/// Punctuators, longest first so the first match is the longest one.
+const PUNCTUATORS: &[&str] = &[
+    "...", "<<=", ">>=", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=",
+    "/=", "%=", "+=", "-=", "&=", "^=", "|=", "##",
+];
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: u32,
+    column: u32,
+    file: Rc<str>,
+    tokens: Vec<Token>,
+    line_start: bool,
+    space_before: bool,
+    doc: Option<String>,
+    /// Newlines since the pending doc comment ended.
+    doc_lines: u32,
+}
+
+/// Split `source` into tokens, dropping comments except doc comments, which
+/// attach to the following token.
+pub fn tokenize(file: &Rc<str>, source: &str) -> Vec<Token> {
+    let mut lexer = Lexer {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+        file: file.clone(),
+        tokens: Vec::new(),
+        line_start: true,
+        space_before: false,
+        doc: None,
+        doc_lines: 0,
+    };
+    lexer.run();
+    lexer.tokens
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn run(&mut self) {
+        while let Some(c) = self.peek(0) {
+            if c == '\\' && self.continues_line() {
+                continue;
+            }
+            if c == '\n' {
+                self.bump();
+                self.line_start = true;
+                self.space_before = true;
+                // A blank line detaches a doc comment from what follows
+                self.doc_lines += 1;
+                if self.doc_lines > 1 {
+                    self.doc = None;
+                }
+            } else if c.is_whitespace() {
+                self.bump();
+                self.space_before = true;
+            } else if c == '/' && self.peek(1) == Some('/') {
+                self.line_comment();
+            } else if c == '/' && self.peek(1) == Some('*') {
+                self.block_comment();
+            } else {
+                self.token(c);
+            }
+        }
+    }
+
+    /// Skip a backslash-newline, joining the next line onto this one.
+    fn continues_line(&mut self) -> bool {
+        let skip = match (self.peek(1), self.peek(2)) {
+            (Some('\n'), _) => 2,
+            (Some('\r'), Some('\n')) => 3,
+            _ => return false,
+        };
+        for _ in 0..skip {
+            self.bump();
+        }
+        true
+    }
+
+    fn line_comment(&mut self) {
+        let start = self.pos;
+        while self.peek(0).is_some_and(|c| c != '\n') {
+            self.bump();
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        self.space_before = true;
+        let body = text
+            .strip_prefix("///")
+            .filter(|b| !b.starts_with('/'))
+            .or_else(|| text.strip_prefix("//!"))
+            // Trailing `///<` docs describe the previous member
+            .filter(|b| !b.starts_with('<'));
+        match body {
+            Some(body) => {
+                let line = body.strip_prefix(' ').unwrap_or(body).trim_end();
+                match &mut self.doc {
+                    Some(doc) if self.doc_lines <= 1 => {
+                        doc.push('\n');
+                        doc.push_str(line);
+                    }
+                    _ => self.doc = Some(line.to_string()),
+                }
+                self.doc_lines = 0;
+            }
+            None => self.doc = None,
+        }
+    }
+
+    fn block_comment(&mut self) {
+        let start = self.pos;
+        self.bump();
+        self.bump();
+        while self.peek(0).is_some() && !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+            self.bump();
+        }
+        self.bump();
+        self.bump();
+        let end = self.pos.min(self.chars.len());
+        let text: String = self.chars[start..end].iter().collect();
+        self.space_before = true;
+        let body = text
+            .strip_prefix("/**")
+            .filter(|b| !b.starts_with('*') && *b != "/")
+            .or_else(|| text.strip_prefix("/*!"))
+            .filter(|b| !b.starts_with('<'))
+            .and_then(|b| b.strip_suffix("*/"));
+        self.doc = body.map(clean_block_doc).filter(|d| !d.is_empty());
+        self.doc_lines = 0;
+    }
+
+    fn token(&mut self, c: char) {
+        let (line, column) = (self.line, self.column);
+        let start = self.pos;
+        let kind = if c.is_alphabetic() || c == '_' || c == '$' {
+            self.ident()
+        } else if c.is_ascii_digit()
+            || (c == '.' && self.peek(1).is_some_and(|n| n.is_ascii_digit()))
+        {
+            self.number();
+            Kind::Number
+        } else if c == '"' || c == '\'' {
+            self.quoted(c)
+        } else {
+            let rest: String = self.chars[self.pos..(self.pos + 3).min(self.chars.len())]
+                .iter()
+                .collect();
+            let len = PUNCTUATORS
+                .iter()
+                .find(|p| rest.starts_with(*p))
+                .map_or(1, |p| p.len());
+            for _ in 0..len {
+                self.bump();
+            }
+            Kind::Punct
+        };
+        self.tokens.push(Token {
+            kind,
+            text: self.chars[start..self.pos].iter().collect(),
+            file: self.file.clone(),
+            line,
+            column,
+            line_start: self.line_start,
+            space_before: self.space_before,
+            doc: self.doc.take(),
+        });
+        self.line_start = false;
+        self.space_before = false;
+        self.doc_lines = 0;
+    }
+
+    fn ident(&mut self) -> Kind {
+        let start = self.pos;
+        while self
+            .peek(0)
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            self.bump();
+        }
+        // Encoding prefixes: L"...", u8"...", U'x'
+        let prefix: String = self.chars[start..self.pos].iter().collect();
+        match self.peek(0) {
+            Some(q @ ('"' | '\'')) if matches!(prefix.as_str(), "L" | "u" | "U" | "u8") => {
+                self.quoted(q)
+            }
+            _ => Kind::Ident,
+        }
+    }
+
+    fn number(&mut self) {
+        while let Some(c) = self.peek(0) {
+            let exponent = matches!(c, '+' | '-')
+                && matches!(self.chars.get(self.pos - 1), Some('e' | 'E' | 'p' | 'P'));
+            if c.is_alphanumeric() || c == '.' || c == '_' || exponent {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> Kind {
+        self.bump();
+        while let Some(c) = self.peek(0) {
+            if c == '\n' {
+                break;
+            }
+            self.bump();
+            if c == '\\' {
+                self.bump();
+            } else if c == quote {
+                break;
+            }
+        }
+        if quote == '"' { Kind::Str } else { Kind::Char }
+    }
+}
+
+/// Strip the leading `*` column and indentation from a block doc comment.
+fn clean_block_doc(body: &str) -> String {
+    let lines: Vec<&str> = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect();
+    lines.join("\n").trim().to_string()
+}
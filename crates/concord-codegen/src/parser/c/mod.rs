@@ -0,0 +1,99 @@
+//! C header front-end for FFI bindings.
+//!
+//! Headers are preprocessed (includes, conditionals, macros), then their
+//! declarations become IR: structs and unions (`union` flag), enums with
+//! `value` annotations, typedefs as named aliases, function prototypes with a
+//! `calling_convention`, and pointers as `Ptr<T>` with `const` on whatever is
+//! const. Primitive types keep their C names (`c_int`, `c_char`, ...).
+//! Structs and unions whose members are all known also get their `size`,
+//! `align` and field offsets. Variadic functions are flagged `variadic`, and
+//! declarations under conditionals on platform macros get a `cfg` predicate.
+
+mod decl;
+mod expr;
+mod layout;
+mod lexer;
+mod preprocess;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use rhi_concord_core::{Metadata, Module, naming};
+
+use self::preprocess::Preprocessor;
+use crate::diagnostics::Diagnostics;
+use crate::transform;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Directories searched for `#include`s, after the including file's own.
+    pub include_paths: Vec<PathBuf>,
+    /// Macros defined before parsing, as `NAME` or `NAME=VALUE`.
+    pub defines: Vec<String>,
+    /// Platform macros whose conditionals are kept, as `NAME` for those
+    /// compilers define or `NAME=PREDICATE`.
+    pub platforms: Vec<String>,
+}
+
+/// Parse C headers into one module named after the first.
+///
+/// Declarations that can't be represented are skipped and reported to
+/// `diagnostics`.
+pub fn parse(
+    headers: &[PathBuf],
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> Result<Module> {
+    let platforms = options
+        .platforms
+        .iter()
+        .map(|spec| preprocess::platform(spec))
+        .collect::<Result<_>>()?;
+    let mut preprocessor = Preprocessor::new(
+        &options.include_paths,
+        &options.defines,
+        platforms,
+        diagnostics,
+    );
+    for header in headers {
+        preprocessor.run(header)?;
+    }
+    let Preprocessor {
+        output: tokens,
+        macros,
+        defined,
+        conditions,
+        ..
+    } = preprocessor;
+
+    let mut items = decl::Parser::new(&tokens, &conditions, diagnostics).parse(&macros, &defined);
+    layout::annotate(&mut items);
+
+    let mut module = Module {
+        name: headers
+            .first()
+            .and_then(|h| Path::file_stem(h))
+            .map_or_else(
+                || "ffi".to_string(),
+                |s| naming::to_snake_case(&s.to_string_lossy()),
+            ),
+        items,
+        submodules: Vec::new(),
+        annotations: Vec::new(),
+        metadata: Metadata::default(),
+    };
+    transform::propagate_confidence(&mut module);
+    Ok(module)
+}
+
+/// Parse `source` as a header named `name.h`, in a directory of its own
+/// removed afterwards, for tests.
+#[cfg(test)]
+pub(crate) fn parse_source(name: &str, source: &str, options: &Options) -> (Module, Diagnostics) {
+    let dir = tempfile::tempdir().unwrap();
+    let header = dir.path().join(format!("{name}.h"));
+    std::fs::write(&header, source).unwrap();
+    let mut diagnostics = Diagnostics::default();
+    let module = parse(&[header], options, &mut diagnostics).unwrap();
+    (module, diagnostics)
+}
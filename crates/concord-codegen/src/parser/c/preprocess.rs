@@ -0,0 +1,1154 @@
+//! A small C preprocessor: includes, conditionals and macro expansion.
+//!
+//! It handles what headers use to declare an API (include guards, feature
+//! and platform checks, export and calling-convention macros) rather than
+//! the whole standard. Macro expansion does not rescan past the end of a
+//! run of text lines, and `#include` of a path that isn't found under the
+//! including file's directory or an include path is skipped.
+//!
+//! Platform macros given a Rust `cfg` predicate are never defined. Both
+//! branches of a conditional on them are kept, and the output records which
+//! predicate each run of tokens is declared under. Macros keep a definition
+//! per predicate, and declarations using a macro defined differently per
+//! platform are expanded once per predicate.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{Context, Result, bail};
+
+use super::expr;
+use super::lexer::{Kind, Token, tokenize};
+use crate::diagnostics::{self, Diagnostics};
+use crate::predicate::{exclusive, implies};
+
+/// Macros every C17 compiler defines.
+const PREDEFINED: &[&str] = &[
+    "__STDC__=1",
+    "__STDC_VERSION__=201710L",
+    "__STDC_HOSTED__=1",
+];
+
+/// `cfg` predicates of the platform macros compilers define.
+const PLATFORMS: &[(&str, &str)] = &[
+    ("_WIN32", "windows"),
+    ("_WIN64", "all(windows, target_pointer_width = \"64\")"),
+    ("_MSC_VER", "target_env = \"msvc\""),
+    ("__MINGW32__", "all(windows, target_env = \"gnu\")"),
+    ("__CYGWIN__", "target_os = \"cygwin\""),
+    ("__APPLE__", "target_vendor = \"apple\""),
+    ("__linux__", "target_os = \"linux\""),
+    ("__ANDROID__", "target_os = \"android\""),
+    ("__FreeBSD__", "target_os = \"freebsd\""),
+    ("__OpenBSD__", "target_os = \"openbsd\""),
+    ("__NetBSD__", "target_os = \"netbsd\""),
+    ("__unix__", "unix"),
+    ("__EMSCRIPTEN__", "target_os = \"emscripten\""),
+];
+
+/// Nested includes beyond this depth are assumed to be recursive.
+const MAX_DEPTH: usize = 200;
+
+/// Declarations are expanded under at most this many nested predicates.
+const MAX_SPLITS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Macro {
+    /// Parameter names of a function-like macro.
+    pub params: Option<Vec<String>>,
+    /// Whether the last parameter is `...`.
+    pub variadic: bool,
+    pub body: Vec<Token>,
+    /// `cfg` predicate the macro was defined under.
+    pub cfg: Option<String>,
+}
+
+/// Definitions of each macro, one per `cfg` predicate it is defined under.
+/// Definitions of a macro never overlap: a later one narrows the others to
+/// where it doesn't hold.
+#[derive(Default)]
+pub struct Macros(HashMap<String, Vec<Macro>>);
+
+impl Macros {
+    /// Definitions of `name`, in order of definition.
+    pub fn definitions(&self, name: &str) -> &[Macro] {
+        self.0.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The definition of `name` that may hold under `cfg`, the last one if
+    /// several may.
+    pub fn get(&self, name: &str, cfg: Option<&str>) -> Option<&Macro> {
+        self.definitions(name)
+            .iter()
+            .rev()
+            .find(|m| overlap(m.cfg.as_deref(), cfg))
+    }
+
+    /// Predicates splitting where `cfg` holds, under each of which the
+    /// macros `tokens` use expand alike; just `cfg` if they do throughout.
+    pub fn expansions(&self, tokens: &[Token], cfg: Option<&str>) -> Vec<Option<String>> {
+        self.split(tokens, cfg, 0)
+    }
+
+    fn split(&self, tokens: &[Token], cfg: Option<&str>, depth: usize) -> Vec<Option<String>> {
+        // Bounded in case predicates can't be decided
+        let Some(predicates) = self.varying(tokens, cfg).filter(|_| depth < MAX_SPLITS) else {
+            return vec![cfg.map(String::from)];
+        };
+        predicates
+            .into_iter()
+            .flat_map(|predicate| {
+                let cfg = all(cfg
+                    .map(String::from)
+                    .into_iter()
+                    .chain([predicate])
+                    .collect());
+                self.split(tokens, cfg.as_deref(), depth + 1)
+            })
+            .collect()
+    }
+
+    /// Predicates to expand `tokens` under separately, if a macro they use is
+    /// defined differently where `cfg` holds: one per definition, and one for
+    /// where there is none.
+    fn varying(&self, tokens: &[Token], cfg: Option<&str>) -> Option<Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<&Token> = tokens.iter().collect();
+        while let Some(token) = pending.pop() {
+            if token.kind != Kind::Ident || !seen.insert(token.text.as_str()) {
+                continue;
+            }
+            let definitions: Vec<&Macro> = self
+                .definitions(&token.text)
+                .iter()
+                .filter(|m| overlap(m.cfg.as_deref(), cfg))
+                .collect();
+            if let [definition] = definitions[..]
+                && definition.cfg.as_deref().is_none_or(|d| holds(cfg, d))
+            {
+                pending.extend(&definition.body);
+                continue;
+            }
+            let Some(mut predicates) = definitions
+                .iter()
+                .map(|m| m.cfg.clone())
+                .collect::<Option<Vec<_>>>()
+                .filter(|p| !p.is_empty())
+            else {
+                continue;
+            };
+            let defined = any(predicates.clone());
+            if !holds(cfg, &defined) {
+                predicates.push(format!("not({defined})"));
+            }
+            return Some(predicates);
+        }
+        None
+    }
+
+    fn define(&mut self, name: &str, definition: Macro) {
+        self.undef(name, definition.cfg.as_deref());
+        self.0.entry(name.to_string()).or_default().push(definition);
+    }
+
+    /// Undefine `name` where `cfg` holds.
+    fn undef(&mut self, name: &str, cfg: Option<&str>) {
+        let Some(cfg) = cfg else {
+            self.0.remove(name);
+            return;
+        };
+        let Some(definitions) = self.0.get_mut(name) else {
+            return;
+        };
+        definitions.retain_mut(|m| {
+            let narrowed = match m.cfg.as_deref() {
+                Some(other) if exclusive(other, cfg) => return true,
+                Some(other) if implies(other, cfg) => return false,
+                other => all(other
+                    .into_iter()
+                    .map(String::from)
+                    .chain([format!("not({cfg})")])
+                    .collect()),
+            };
+            m.cfg = narrowed;
+            true
+        });
+    }
+}
+
+/// Whether predicates `a` and `b`, `None` for always, may both hold.
+fn overlap(a: Option<&str>, b: Option<&str>) -> bool {
+    a.zip(b).is_none_or(|(a, b)| !exclusive(a, b))
+}
+
+/// A platform macro and its `cfg` predicate, from `MACRO=PREDICATE` or a
+/// `MACRO` compilers define.
+pub fn platform(spec: &str) -> Result<(String, String)> {
+    if let Some((name, predicate)) = spec.split_once('=') {
+        return Ok((name.to_string(), predicate.to_string()));
+    }
+    match PLATFORMS.iter().find(|(name, _)| *name == spec) {
+        Some((name, predicate)) => Ok(((*name).to_string(), (*predicate).to_string())),
+        None => bail!("no known cfg predicate for `{spec}`; pass `{spec}=PREDICATE`"),
+    }
+}
+
+pub struct Preprocessor<'a> {
+    include_paths: &'a [PathBuf],
+    pub macros: Macros,
+    /// Files marked `#pragma once` that were already included.
+    once: HashSet<PathBuf>,
+    depth: usize,
+    /// Nesting of `#include <...>`d headers being preprocessed.
+    system: usize,
+    /// Object-like macros defined outside `#include <...>`d headers, in
+    /// order of definition.
+    pub defined: Vec<String>,
+    /// Expanded tokens of every active text line.
+    pub output: Vec<Token>,
+    /// `cfg` predicates of platform macros.
+    platforms: HashMap<String, String>,
+    /// Predicate the current line is declared under.
+    cfg: Option<String>,
+    /// Positions in `output` from which tokens are declared under a
+    /// predicate, or unconditionally.
+    pub conditions: Vec<(usize, Option<String>)>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+/// State of one `#if` ... `#endif` group.
+struct Conditional {
+    /// Whether the enclosing group is active.
+    parent: bool,
+    /// Whether the current branch is active.
+    active: bool,
+    /// Whether any branch so far was taken.
+    taken: bool,
+    /// Predicate the group is declared under.
+    outer: Option<String>,
+    /// Predicates of the branches so far that depend on a platform.
+    excluded: Vec<String>,
+}
+
+/// An `#if` condition, known or depending on platform macros.
+enum Condition {
+    Known(bool),
+    Platform(String),
+}
+
+impl Condition {
+    fn not(self) -> Self {
+        match self {
+            Self::Known(value) => Self::Known(!value),
+            Self::Platform(predicate) => Self::Platform(format!("not({predicate})")),
+        }
+    }
+}
+
+impl Conditional {
+    fn new(parent: bool, outer: Option<String>) -> Self {
+        Self {
+            parent,
+            active: false,
+            taken: false,
+            outer,
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Enter the next branch, returning the predicate it is declared under.
+    fn branch(&mut self, condition: Condition) -> Option<String> {
+        let mut predicates: Vec<String> = self.outer.iter().cloned().collect();
+        predicates.extend(self.excluded.iter().map(|p| format!("not({p})")));
+        self.active = self.parent && !self.taken;
+        match condition {
+            Condition::Known(taken) => {
+                self.active &= taken;
+                self.taken |= self.active;
+            }
+            Condition::Platform(predicate) => {
+                predicates.push(predicate.clone());
+                self.excluded.push(predicate);
+            }
+        }
+        if self.active { all(predicates) } else { None }
+    }
+}
+
+impl<'a> Preprocessor<'a> {
+    /// A preprocessor with `defines` (`NAME` or `NAME=VALUE`) predefined,
+    /// keeping conditionals on the `platforms` macros.
+    pub fn new(
+        include_paths: &'a [PathBuf],
+        defines: &[String],
+        platforms: HashMap<String, String>,
+        diagnostics: &'a mut Diagnostics,
+    ) -> Self {
+        let file: Rc<str> = Rc::from("<command line>");
+        let mut macros = Macros::default();
+        let predefined = PREDEFINED.iter().map(|d| (*d).to_string());
+        for define in predefined.chain(defines.iter().cloned()) {
+            let (name, value) = define.split_once('=').unwrap_or((&define, "1"));
+            macros.define(
+                name,
+                Macro {
+                    params: None,
+                    variadic: false,
+                    body: tokenize(&file, value),
+                    cfg: None,
+                },
+            );
+        }
+        Self {
+            include_paths,
+            macros,
+            once: HashSet::new(),
+            depth: 0,
+            system: 0,
+            defined: Vec::new(),
+            output: Vec::new(),
+            platforms,
+            cfg: None,
+            conditions: Vec::new(),
+            diagnostics,
+        }
+    }
+
+    /// Preprocess the file at `path`, appending to the output.
+    pub fn run(&mut self, path: &Path) -> Result<()> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.once.contains(&canonical) {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: Rc<str> = Rc::from(path.display().to_string());
+        let tokens = tokenize(&file, &content);
+
+        self.depth += 1;
+        let cfg = self.cfg.clone();
+        let result = self.process(path, &canonical, &tokens);
+        self.cfg = cfg;
+        self.depth -= 1;
+        result
+    }
+
+    fn process(&mut self, path: &Path, canonical: &Path, tokens: &[Token]) -> Result<()> {
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut text = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let end = tokens[i + 1..]
+                .iter()
+                .position(|t| t.line_start)
+                .map_or(tokens.len(), |p| i + 1 + p);
+            let line = &tokens[i..end];
+            i = end;
+
+            let active = conditionals.last().is_none_or(|c| c.active);
+            if !(line[0].is("#") && line[0].line_start) {
+                if active {
+                    text.extend_from_slice(line);
+                }
+                continue;
+            }
+            self.flush(&text);
+            text.clear();
+
+            let Some(directive) = line.get(1).filter(|t| t.kind == Kind::Ident) else {
+                continue;
+            };
+            let args = &line[2..];
+            if self.conditional(&directive.text, args, &mut conditionals) || !active {
+                continue;
+            }
+            match directive.text.as_str() {
+                "define" | "undef"
+                    if args
+                        .first()
+                        .is_some_and(|t| self.platforms.contains_key(&t.text)) => {}
+                "define" => self.define(args),
+                "undef" => {
+                    if let Some(name) = args.first() {
+                        self.macros.undef(&name.text, self.cfg.as_deref());
+                    }
+                }
+                "include" | "import" => self.include(path, None, directive, args)?,
+                "include_next" => self.include(path, Some(canonical), directive, args)?,
+                "pragma" if args.first().is_some_and(|t| t.is("once")) => {
+                    self.once.insert(canonical.to_path_buf());
+                }
+                // Packing changes the layout of the structs that follow, so
+                // the parser sees it as a `#pragma` token before its arguments
+                "pragma" if args.first().is_some_and(|t| t.is("pack")) => {
+                    let mut pragma = line[0].clone();
+                    pragma.text = "#pragma".to_string();
+                    let mut expanded = self.expand(args, &[]);
+                    expanded.insert(0, pragma);
+                    self.emit(expanded);
+                }
+                "error" => self.diagnostics.warning(
+                    diagnostics::PREPROCESSOR,
+                    Some(directive.location()),
+                    format!("#error {}", join(args)),
+                ),
+                _ => {}
+            }
+        }
+        self.flush(&text);
+        Ok(())
+    }
+
+    /// Expand and emit a run of text lines. Declarations using a macro
+    /// defined differently where the current predicate holds are expanded
+    /// once per predicate it is defined under.
+    fn flush(&mut self, text: &[Token]) {
+        if self.macros.varying(text, self.cfg.as_deref()).is_none() {
+            let expanded = self.expand(text, &[]);
+            self.emit(expanded);
+            return;
+        }
+        let outer = self.cfg.clone();
+        for declaration in declarations(text) {
+            for cfg in self.macros.expansions(declaration, outer.as_deref()) {
+                self.cfg = cfg;
+                let expanded = self.expand(declaration, &[]);
+                self.emit(expanded);
+            }
+        }
+        self.cfg = outer;
+    }
+
+    /// Append `tokens` to the output under the current predicate.
+    fn emit(&mut self, tokens: Vec<Token>) {
+        if tokens.is_empty() {
+            return;
+        }
+        let last = self.conditions.last().and_then(|(_, cfg)| cfg.as_ref());
+        if last != self.cfg.as_ref() {
+            self.conditions.push((self.output.len(), self.cfg.clone()));
+        }
+        self.output.extend(tokens);
+    }
+
+    /// Handle a conditional directive, returning whether it was one.
+    fn conditional(
+        &mut self,
+        directive: &str,
+        args: &[Token],
+        conditionals: &mut Vec<Conditional>,
+    ) -> bool {
+        let active = conditionals.last().is_none_or(|c| c.active);
+        match directive {
+            "if" | "ifdef" | "ifndef" => {
+                let mut group = Conditional::new(active, self.cfg.clone());
+                let condition = if active {
+                    self.evaluate(directive, args)
+                } else {
+                    Condition::Known(false)
+                };
+                self.cfg = group.branch(condition);
+                conditionals.push(group);
+            }
+            "elif" | "elifdef" | "elifndef" | "else" => {
+                let Some(group) = conditionals.last_mut() else {
+                    return true;
+                };
+                let condition = match directive {
+                    _ if !group.parent || group.taken => Condition::Known(false),
+                    "else" => Condition::Known(true),
+                    _ => self.evaluate(&directive[2..], args),
+                };
+                self.cfg = group.branch(condition);
+            }
+            "endif" => {
+                if let Some(group) = conditionals.pop() {
+                    self.cfg = group.outer;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Evaluate the condition of `#if`, `#ifdef` or `#ifndef`.
+    fn evaluate(&mut self, directive: &str, args: &[Token]) -> Condition {
+        let name = args.first().map(|t| t.text.as_str());
+        let platform = name.and_then(|name| self.platforms.get(name));
+        match directive {
+            "ifdef" | "ifndef" => {
+                let defined = match (platform, name) {
+                    (Some(predicate), _) => Condition::Platform(predicate.clone()),
+                    (None, Some(name)) => self.defined(name),
+                    (None, None) => Condition::Known(false),
+                };
+                if directive == "ifdef" {
+                    defined
+                } else {
+                    defined.not()
+                }
+            }
+            "if" if args.iter().any(|t| self.is_platform(&t.text)) => {
+                let mut pos = 0;
+                match self.platform_or(args, &mut pos) {
+                    Some(condition) if pos == args.len() => condition,
+                    _ => {
+                        self.diagnostics.warning(
+                            diagnostics::PREPROCESSOR,
+                            args.first().map(Token::location),
+                            format!(
+                                "cannot express `#if {}` as a cfg predicate; assuming its platform macros are undefined",
+                                join(args)
+                            ),
+                        );
+                        Condition::Known(self.condition(args))
+                    }
+                }
+            }
+            _ => Condition::Known(self.condition(args)),
+        }
+    }
+
+    /// Whether `name` is defined where the current predicate holds: known,
+    /// or depending on platform macros.
+    fn defined(&self, name: &str) -> Condition {
+        let cfg = self.cfg.as_deref();
+        let mut predicates = Vec::new();
+        for definition in self.macros.definitions(name) {
+            match definition.cfg.as_deref() {
+                Some(predicate) if !holds(cfg, predicate) => {
+                    if overlap(Some(predicate), cfg) {
+                        predicates.push(predicate.to_string());
+                    }
+                }
+                _ => return Condition::Known(true),
+            }
+        }
+        if predicates.is_empty() {
+            Condition::Known(false)
+        } else {
+            Condition::Platform(any(predicates))
+        }
+    }
+
+    /// Whether `name` is a platform macro or a macro only defined on some
+    /// platforms.
+    fn is_platform(&self, name: &str) -> bool {
+        self.platforms.contains_key(name) || matches!(self.defined(name), Condition::Platform(_))
+    }
+
+    /// `a || b`, in a condition on platform macros.
+    fn platform_or(&mut self, args: &[Token], pos: &mut usize) -> Option<Condition> {
+        let mut condition = self.platform_and(args, pos)?;
+        while args.get(*pos).is_some_and(|t| t.is("||")) {
+            *pos += 1;
+            condition = match (condition, self.platform_and(args, pos)?) {
+                (Condition::Known(true), _) | (_, Condition::Known(true)) => Condition::Known(true),
+                (Condition::Known(false), other) | (other, Condition::Known(false)) => other,
+                (Condition::Platform(a), Condition::Platform(b)) => {
+                    Condition::Platform(format!("any({a}, {b})"))
+                }
+            };
+        }
+        Some(condition)
+    }
+
+    /// `a && b`, in a condition on platform macros.
+    fn platform_and(&mut self, args: &[Token], pos: &mut usize) -> Option<Condition> {
+        let mut condition = self.platform_unary(args, pos)?;
+        while args.get(*pos).is_some_and(|t| t.is("&&")) {
+            *pos += 1;
+            condition = match (condition, self.platform_unary(args, pos)?) {
+                (Condition::Known(false), _) | (_, Condition::Known(false)) => {
+                    Condition::Known(false)
+                }
+                (Condition::Known(true), other) | (other, Condition::Known(true)) => other,
+                (Condition::Platform(a), Condition::Platform(b)) => {
+                    Condition::Platform(format!("all({a}, {b})"))
+                }
+            };
+        }
+        Some(condition)
+    }
+
+    /// `!a`, `(a)`, `defined(MACRO)` or a platform macro; any other operand
+    /// is evaluated, and can't mention platform macros.
+    fn platform_unary(&mut self, args: &[Token], pos: &mut usize) -> Option<Condition> {
+        let token = args.get(*pos)?;
+        if token.is("!") {
+            *pos += 1;
+            return Some(self.platform_unary(args, pos)?.not());
+        }
+        if token.is("(") {
+            *pos += 1;
+            let condition = self.platform_or(args, pos)?;
+            return args.get(*pos).filter(|t| t.is(")")).map(|_| {
+                *pos += 1;
+                condition
+            });
+        }
+        if token.is("defined") {
+            let parenthesized = args.get(*pos + 1).is_some_and(|t| t.is("("));
+            let name = args.get(*pos + 1 + usize::from(parenthesized))?;
+            let predicate = match self.platforms.get(&name.text) {
+                Some(predicate) => Some(predicate.clone()),
+                None => match self.defined(&name.text) {
+                    Condition::Platform(predicate) => Some(predicate),
+                    Condition::Known(_) => None,
+                },
+            };
+            if let Some(predicate) = predicate {
+                if parenthesized && !args.get(*pos + 3).is_some_and(|t| t.is(")")) {
+                    return None;
+                }
+                *pos += 2 + 2 * usize::from(parenthesized);
+                return Some(Condition::Platform(predicate));
+            }
+        }
+        if let Some(predicate) = self.platforms.get(&token.text) {
+            *pos += 1;
+            return Some(Condition::Platform(predicate.clone()));
+        }
+        // Up to the next `&&`, `||` or unbalanced `)`
+        let mut depth = 0usize;
+        let end = args[*pos..]
+            .iter()
+            .position(|t| {
+                match t.text.as_str() {
+                    "(" => depth += 1,
+                    ")" | "&&" | "||" if depth == 0 => return true,
+                    ")" => depth -= 1,
+                    _ => {}
+                }
+                false
+            })
+            .map_or(args.len(), |p| *pos + p);
+        let operand = &args[*pos..end];
+        if operand.iter().any(|t| self.is_platform(&t.text)) {
+            return None;
+        }
+        *pos = end;
+        Some(Condition::Known(self.condition(operand)))
+    }
+
+    /// Evaluate an `#if` condition; undefined identifiers are 0.
+    fn condition(&mut self, args: &[Token]) -> bool {
+        let mut resolved = Vec::new();
+        let mut i = 0;
+        while i < args.len() {
+            if args[i].is("defined") {
+                let parenthesized = args.get(i + 1).is_some_and(|t| t.is("("));
+                let name = args.get(i + 1 + usize::from(parenthesized));
+                let defined =
+                    name.is_some_and(|n| !matches!(self.defined(&n.text), Condition::Known(false)));
+                let mut token = args[i].clone();
+                token.kind = Kind::Number;
+                token.text = if defined { "1" } else { "0" }.to_string();
+                resolved.push(token);
+                i += 2 + 2 * usize::from(parenthesized);
+            } else {
+                resolved.push(args[i].clone());
+                i += 1;
+            }
+        }
+        let expanded = self.expand(&resolved, &[]);
+        if let Some(value) = expr::eval(&expanded, &|_| Some(0)) {
+            return value != 0;
+        }
+        self.diagnostics.warning(
+            diagnostics::PREPROCESSOR,
+            args.first().map(Token::location),
+            format!("cannot evaluate `#if {}`; assuming false", join(args)),
+        );
+        false
+    }
+
+    fn define(&mut self, args: &[Token]) {
+        let Some(name) = args.first().filter(|t| t.kind == Kind::Ident) else {
+            return;
+        };
+        let function_like = args.get(1).is_some_and(|t| t.is("(") && !t.space_before);
+        if !function_like {
+            if self.system == 0 && !self.defined.contains(&name.text) {
+                self.defined.push(name.text.clone());
+            }
+            self.macros.define(
+                &name.text,
+                Macro {
+                    params: None,
+                    variadic: false,
+                    body: args[1..].to_vec(),
+                    cfg: self.cfg.clone(),
+                },
+            );
+            return;
+        }
+        let close = args
+            .iter()
+            .position(|t| t.is(")"))
+            .unwrap_or(args.len() - 1);
+        let mut params = Vec::new();
+        let mut variadic = false;
+        for token in &args[2..close] {
+            match token.kind {
+                Kind::Ident => params.push(token.text.clone()),
+                _ if token.is("...") => {
+                    variadic = true;
+                    params.push("__VA_ARGS__".to_string());
+                }
+                _ => {}
+            }
+        }
+        self.macros.define(
+            &name.text,
+            Macro {
+                params: Some(params),
+                variadic,
+                body: args.get(close + 1..).unwrap_or_default().to_vec(),
+                cfg: self.cfg.clone(),
+            },
+        );
+    }
+
+    /// Include the file named by `args`. `#include_next` passes the
+    /// including file as `after`, searching the include paths after the one
+    /// it was found in.
+    fn include(
+        &mut self,
+        path: &Path,
+        after: Option<&Path>,
+        directive: &Token,
+        args: &[Token],
+    ) -> Result<()> {
+        let mut args = args.to_vec();
+        if args.first().is_some_and(|t| t.kind == Kind::Ident) {
+            args = self.expand(&args, &[]);
+        }
+        let (name, quoted) = match args.first() {
+            Some(t) if t.kind == Kind::Str => (t.text.trim_matches('"').to_string(), true),
+            Some(t) if t.is("<") => {
+                let end = args.iter().position(|t| t.is(">")).unwrap_or(args.len());
+                (join_tight(&args[1..end]), false)
+            }
+            _ => return Ok(()),
+        };
+        if self.depth >= MAX_DEPTH {
+            self.diagnostics.warning(
+                diagnostics::PREPROCESSOR,
+                Some(directive.location()),
+                format!("#include nested too deeply; skipping `{name}`"),
+            );
+            return Ok(());
+        }
+
+        let local = quoted
+            .then(|| path.parent().map(|dir| dir.join(&name)))
+            .flatten()
+            .filter(|_| after.is_none());
+        let mut dirs = self.include_paths.iter().map(|dir| dir.join(&name));
+        if let Some(after) = after {
+            let mut rest = dirs.clone();
+            if rest.any(|candidate| candidate.canonicalize().is_ok_and(|c| c == after)) {
+                dirs = rest;
+            }
+        }
+        let found = local
+            .into_iter()
+            .chain(dirs)
+            .find(|candidate| candidate.is_file());
+        match found {
+            Some(found) if quoted => self.run(&found),
+            Some(found) => {
+                self.system += 1;
+                let result = self.run(&found);
+                self.system -= 1;
+                result
+            }
+            // System headers are expected to be missing; their types are
+            // mapped by name
+            None if !quoted => Ok(()),
+            None => {
+                self.diagnostics.warning(
+                    diagnostics::PREPROCESSOR,
+                    Some(directive.location()),
+                    format!("cannot find `{name}` in the include paths; skipping it"),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Expand macros in `tokens`, not expanding the names in `hidden`.
+    fn expand(&self, tokens: &[Token], hidden: &[&str]) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            i += 1;
+            let Some(mac) = self
+                .macros
+                .get(&token.text, self.cfg.as_deref())
+                .filter(|_| token.kind == Kind::Ident && !hidden.contains(&token.text.as_str()))
+            else {
+                out.push(token.clone());
+                continue;
+            };
+            let mut hidden = hidden.to_vec();
+            hidden.push(&token.text);
+
+            let body = match &mac.params {
+                None => mac.body.clone(),
+                Some(params) => {
+                    if !tokens.get(i).is_some_and(|t| t.is("(")) {
+                        out.push(token.clone());
+                        continue;
+                    }
+                    let (args, end) = split_args(tokens, i + 1);
+                    i = end;
+                    self.substitute(mac, params, &args, &hidden)
+                }
+            };
+            let mut expanded = self.expand(&relocate(&body, token), &hidden);
+            if let Some(first) = expanded.first_mut() {
+                first.doc.clone_from(&token.doc);
+            }
+            out.extend(expanded);
+        }
+        out
+    }
+
+    /// Replace parameters in a function-like macro's body with `args`.
+    fn substitute(
+        &self,
+        mac: &Macro,
+        params: &[String],
+        args: &[Vec<Token>],
+        hidden: &[&str],
+    ) -> Vec<Token> {
+        let arg = |name: &str| -> Option<Vec<Token>> {
+            let index = params.iter().position(|p| p == name)?;
+            if mac.variadic && index == params.len() - 1 {
+                // `__VA_ARGS__` takes the remaining arguments with their commas
+                let rest = args.get(index..).unwrap_or_default();
+                let mut joined = Vec::new();
+                for (n, a) in rest.iter().enumerate() {
+                    if n > 0 {
+                        joined.push(comma(a.first().or(mac.body.first())?));
+                    }
+                    joined.extend(a.iter().cloned());
+                }
+                return Some(joined);
+            }
+            Some(args.get(index).cloned().unwrap_or_default())
+        };
+
+        let mut out: Vec<Token> = Vec::new();
+        let mut j = 0;
+        while j < mac.body.len() {
+            let token = &mac.body[j];
+            j += 1;
+            if token.is("#")
+                && let Some(next) = mac.body.get(j)
+                && let Some(value) = arg(&next.text)
+            {
+                j += 1;
+                let mut string = next.clone();
+                string.kind = Kind::Str;
+                string.text = format!("\"{}\"", join(&value).replace('"', "\\\""));
+                out.push(string);
+            } else if token.is("##") {
+                let Some(next) = mac.body.get(j) else {
+                    continue;
+                };
+                j += 1;
+                let right = arg(&next.text).unwrap_or_else(|| vec![next.clone()]);
+                match (out.last_mut(), right.split_first()) {
+                    (Some(left), Some((first, rest))) => {
+                        left.text.push_str(&first.text);
+                        if left.kind == Kind::Punct && first.kind != Kind::Punct {
+                            left.kind = first.kind;
+                        }
+                        out.extend(rest.iter().cloned());
+                    }
+                    (None, _) => out.extend(right),
+                    (_, None) => {}
+                }
+            } else if let Some(value) = arg(&token.text).filter(|_| token.kind == Kind::Ident) {
+                let pasted = mac.body.get(j).is_some_and(|t| t.is("##"));
+                if pasted {
+                    out.extend(value);
+                } else {
+                    out.extend(self.expand(&value, hidden));
+                }
+            } else {
+                out.push(token.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Split macro arguments starting after the opening parenthesis at
+/// `start`, returning them and the index after the closing parenthesis.
+fn split_args(tokens: &[Token], start: usize) -> (Vec<Vec<Token>>, usize) {
+    let mut args = vec![Vec::new()];
+    let mut depth = 0;
+    let mut i = start;
+    while let Some(token) = tokens.get(i) {
+        i += 1;
+        if token.is(")") && depth == 0 {
+            break;
+        }
+        if token.is("(") {
+            depth += 1;
+        } else if token.is(")") {
+            depth -= 1;
+        } else if token.is(",") && depth == 0 {
+            args.push(Vec::new());
+            continue;
+        }
+        if let Some(last) = args.last_mut() {
+            last.push(token.clone());
+        }
+    }
+    if args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    (args, i)
+}
+
+/// Move a macro body to where it is used, so diagnostics point at the use.
+fn relocate(body: &[Token], at: &Token) -> Vec<Token> {
+    body.iter()
+        .map(|t| Token {
+            file: at.file.clone(),
+            line: at.line,
+            column: at.column,
+            line_start: false,
+            doc: None,
+            ..t.clone()
+        })
+        .collect()
+}
+
+fn comma(at: &Token) -> Token {
+    Token {
+        kind: Kind::Punct,
+        text: ",".to_string(),
+        doc: None,
+        ..at.clone()
+    }
+}
+
+/// Source text of `tokens`, spaced as written.
+pub fn join(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        if token.space_before && !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(&token.text);
+    }
+    out
+}
+
+/// The conjunction of `predicates`, if any.
+fn all(mut predicates: Vec<String>) -> Option<String> {
+    match predicates.len() {
+        0 | 1 => predicates.pop(),
+        _ => Some(format!("all({})", predicates.join(", "))),
+    }
+}
+
+/// The disjunction of `predicates`.
+fn any(mut predicates: Vec<String>) -> String {
+    match predicates.len() {
+        1 => predicates.pop().unwrap_or_default(),
+        _ => format!("any({})", predicates.join(", ")),
+    }
+}
+
+/// Whether `predicate` holds wherever `cfg`, `None` for always, does.
+fn holds(cfg: Option<&str>, predicate: &str) -> bool {
+    implies(cfg.unwrap_or("all()"), predicate)
+}
+
+/// `tokens` split after each `;` outside parentheses and braces, roughly one
+/// declaration each.
+fn declarations(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut declarations = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text.as_str() {
+            "(" | "{" if token.kind == Kind::Punct => depth += 1,
+            ")" | "}" if token.kind == Kind::Punct => depth -= 1,
+            ";" if depth <= 0 => {
+                declarations.push(&tokens[start..=i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        declarations.push(&tokens[start..]);
+    }
+    declarations
+}
+
+/// Source text of `tokens` without spaces, as in `<sys/types.h>`.
+fn join_tight(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.text.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::{AnnotationValue, Item, Module};
+
+    use crate::parser::c::{Options, parse_source};
+
+    /// Each item's name with its `cfg` predicate, if any.
+    fn predicates(module: &Module) -> Vec<(String, Option<String>)> {
+        module
+            .items
+            .iter()
+            .map(|item| {
+                let (name, annotations) = match item {
+                    Item::Type(typ) => (typ.name.clone(), &typ.annotations),
+                    Item::Function(func) => (Some(func.name.clone()), &func.annotations),
+                    Item::Const {
+                        name, annotations, ..
+                    } => (Some(name.clone()), annotations),
+                };
+                let cfg = annotations.iter().find_map(|a| match &a.value {
+                    Some(AnnotationValue::String(cfg)) if a.kind == "cfg" => Some(cfg.clone()),
+                    _ => None,
+                });
+                (name.unwrap_or_default(), cfg)
+            })
+            .collect()
+    }
+
+    fn parse(source: &str, platforms: &[&str]) -> Vec<(String, Option<String>)> {
+        let options = Options {
+            platforms: platforms.iter().map(ToString::to_string).collect(),
+            ..Options::default()
+        };
+        predicates(&parse_source("platforms", source, &options).0)
+    }
+
+    fn item(name: &str, cfg: Option<&str>) -> (String, Option<String>) {
+        (name.to_string(), cfg.map(str::to_string))
+    }
+
+    #[test]
+    fn platform_conditionals_keep_both_branches() {
+        let items = parse(
+            "#ifdef _WIN32\n\
+             typedef void *handle;\n\
+             #else\n\
+             typedef int handle;\n\
+             #endif\n\
+             #if defined(__APPLE__)\n\
+             int plat(void);\n\
+             #elif defined(__linux__)\n\
+             long plat(void);\n\
+             #endif\n\
+             int always(void);\n",
+            &["_WIN32", "__APPLE__", "__linux__"],
+        );
+        assert_eq!(
+            items,
+            [
+                item("handle", Some("windows")),
+                item("handle", Some("not(windows)")),
+                item("plat", Some("target_vendor = \"apple\"")),
+                item(
+                    "plat",
+                    Some("all(not(target_vendor = \"apple\"), target_os = \"linux\")")
+                ),
+                item("always", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn platform_macros_need_a_predicate_unless_known() {
+        let items = parse(
+            "#ifdef USE_TLS\nint tls(void);\n#endif\n",
+            &["USE_TLS=feature = \"tls\""],
+        );
+        assert_eq!(items, [item("tls", Some("feature = \"tls\""))]);
+    }
+
+    #[test]
+    fn other_conditionals_are_evaluated() {
+        let items = parse(
+            "#define LEVEL 2\n\
+             #if LEVEL > 1\nint high(void);\n#else\nint low(void);\n#endif\n\
+             #ifdef _WIN32\nint win(void);\n#endif\n",
+            &[],
+        );
+        assert_eq!(items, [item("high", None), item("LEVEL", None)]);
+    }
+
+    #[test]
+    fn macros_keep_a_definition_per_predicate() {
+        let items = parse(
+            "#ifdef _WIN32\n#define PATH_SEP '\\\\'\n#else\n#define PATH_SEP '/'\n#endif\n\
+             #define SEP PATH_SEP\n\
+             #ifdef _WIN32\n#define WIN_ONLY 1\n#endif\n\
+             #ifdef WIN_ONLY\nint win_only(void);\n#endif\n",
+            &["_WIN32"],
+        );
+        assert_eq!(
+            items,
+            [
+                item("win_only", Some("windows")),
+                item("PATH_SEP", Some("windows")),
+                item("PATH_SEP", Some("not(windows)")),
+                item("SEP", Some("windows")),
+                item("SEP", Some("not(windows)")),
+                item("WIN_ONLY", Some("windows")),
+            ]
+        );
+    }
+
+    #[test]
+    fn declarations_are_expanded_per_predicate() {
+        let options = Options {
+            platforms: vec!["_WIN32".into()],
+            ..Options::default()
+        };
+        let (module, _) = parse_source(
+            "platforms",
+            "#ifdef _WIN32\n#define CALL __stdcall\n#else\n#define CALL\n#endif\n\
+             int CALL open_file(const char *path);\n\
+             int count(void);\n",
+            &options,
+        );
+        let conventions: Vec<_> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(func) => func.annotations.iter().find_map(|a| match &a.value {
+                    Some(AnnotationValue::String(name)) if a.kind == "calling_convention" => {
+                        Some(name.as_str())
+                    }
+                    _ => None,
+                }),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            predicates(&module),
+            [
+                item("open_file", Some("windows")),
+                item("open_file", Some("not(windows)")),
+                item("count", None),
+            ]
+        );
+        assert_eq!(conventions, ["stdcall", "cdecl", "cdecl"]);
+    }
+}
@@ -1 +1,3 @@
+pub mod c;
 pub mod openapi;
+pub mod source;
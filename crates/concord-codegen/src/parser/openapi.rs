@@ -1,101 +1,297 @@
 //! `OpenAPI` schema parser.
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
 use anyhow::{Context, Result};
-use openapiv3::{OpenAPI, ReferenceOr, Schema, SchemaKind, StringType, Type as OaType};
+use indexmap::IndexMap;
+use openapiv3::{
+    Example, OpenAPI, Operation, PathItem, ReferenceOr, RequestBody, Schema, SchemaKind,
+    StringType, Type as OaType,
+};
 use rhi_concord_core::{
-    Annotation, Field, Function, Item, Metadata, Module, Param, Type, TypeKind, Variant,
+    Annotation, AnnotationValue, Field, Function, Item, Metadata, Module, Param, Type, TypeKind,
+    Value, Variant, naming,
 };
+use serde::Deserialize;
+
+use super::source::{SourceMap, join};
+use crate::diagnostics::{self, Diagnostics};
+use crate::transform::{self, confidence};
+
+/// Name of the submodule holding types shared between tags.
+const COMMON_MODULE: &str = "types";
+
+/// Options controlling `OpenAPI` conversion.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Group operations into submodules by their first tag.
+    pub split_by_tag: bool,
+    /// Submodule for untagged operations when splitting by tag.
+    pub untagged_module: String,
+    /// Skip operations marked `deprecated`.
+    pub exclude_deprecated: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            split_by_tag: false,
+            untagged_module: "default".to_string(),
+            exclude_deprecated: false,
+        }
+    }
+}
 
 /// Parse an `OpenAPI` schema file into IR.
-pub fn parse(path: &Path) -> Result<Module> {
+///
+/// Constructs that cannot be represented exactly are approximated and
+/// reported to `diagnostics`.
+pub fn parse(path: &Path, options: &Options, diagnostics: &mut Diagnostics) -> Result<Module> {
     let content =
         fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
 
-    let spec: OpenAPI = if path.extension().is_some_and(|e| e == "yaml" || e == "yml") {
-        serde_yaml::from_str(&content)?
+    let (spec, extras): (OpenAPI, Extras) =
+        if path.extension().is_some_and(|e| e == "yaml" || e == "yml") {
+            (
+                serde_yaml::from_str(&content)?,
+                serde_yaml::from_str(&content)?,
+            )
+        } else {
+            (
+                serde_json::from_str(&content)?,
+                serde_json::from_str(&content)?,
+            )
+        };
+
+    let source = SourceMap::new(&path.display().to_string(), &content);
+    let converter = Converter::new(&spec, &extras, &source, options);
+    let mut module = converter.convert(diagnostics);
+    transform::merge_intersections(&mut module, diagnostics);
+    transform::propagate_confidence(&mut module);
+
+    if options.split_by_tag {
+        Ok(split_by_tag(module, &spec, options))
     } else {
-        serde_json::from_str(&content)?
-    };
+        Ok(module)
+    }
+}
 
-    let converter = Converter::new(&spec);
-    converter.convert()
+/// Document fields `openapiv3` does not model.
+#[derive(Default, Deserialize)]
+struct Extras {
+    /// `OpenAPI` 3.1 top-level webhooks.
+    #[serde(default)]
+    webhooks: IndexMap<String, ReferenceOr<PathItem>>,
 }
 
 struct Converter<'a> {
     spec: &'a OpenAPI,
+    extras: &'a Extras,
+    source: &'a SourceMap,
+    options: &'a Options,
     items: Vec<Item>,
+    diagnostics: RefCell<Diagnostics>,
 }
 
 impl<'a> Converter<'a> {
-    fn new(spec: &'a OpenAPI) -> Self {
+    fn new(
+        spec: &'a OpenAPI,
+        extras: &'a Extras,
+        source: &'a SourceMap,
+        options: &'a Options,
+    ) -> Self {
         Self {
             spec,
+            extras,
+            source,
+            options,
             items: Vec::new(),
+            diagnostics: RefCell::default(),
+        }
+    }
+
+    /// Metadata locating the node at `pointer` in the source document.
+    fn located(&self, pointer: &str) -> Metadata {
+        Metadata {
+            source: Some(self.source.locate(pointer)),
+            ..Default::default()
         }
     }
 
-    fn convert(mut self) -> Result<Module> {
+    /// Metadata for a node converted with reduced `confidence`.
+    fn lossy(&self, pointer: &str, confidence: f32) -> Metadata {
+        Metadata {
+            confidence: Some(confidence),
+            ..self.located(pointer)
+        }
+    }
+
+    fn warning(&self, code: &'static str, pointer: &str, message: impl Into<String>) {
+        self.diagnostics
+            .borrow_mut()
+            .warning(code, Some(self.source.locate(pointer)), message);
+    }
+
+    fn error(&self, code: &'static str, pointer: &str, message: impl Into<String>) {
+        self.diagnostics
+            .borrow_mut()
+            .error(code, Some(self.source.locate(pointer)), message);
+    }
+
+    /// Whether an operation is left out of the IR.
+    fn skip_operation(&self, op: &Operation) -> bool {
+        self.options.exclude_deprecated && op.deprecated
+    }
+
+    fn convert(mut self, diagnostics: &mut Diagnostics) -> Module {
         // Convert schemas (components/schemas)
         if let Some(components) = &self.spec.components {
             for (name, schema_ref) in &components.schemas {
                 if let ReferenceOr::Item(schema) = schema_ref {
-                    let typ = self.convert_schema(schema, Some(name.clone()))?;
+                    let pointer = join("#/components/schemas", &[name]);
+                    let typ = self.convert_schema(schema, Some(name.clone()), &pointer);
                     self.items.push(Item::Type(typ));
                 }
             }
         }
 
+        // Convert top-level servers
+        if !self.spec.servers.is_empty() {
+            self.convert_servers("Server", &self.spec.servers, "API servers.");
+        }
+
         // Convert paths to functions
         for (path, path_item_ref) in &self.spec.paths.paths {
-            if let ReferenceOr::Item(path_item) = path_item_ref {
-                self.convert_path_item(path, path_item)?;
+            match path_item_ref {
+                ReferenceOr::Item(path_item) => self.convert_path_item(path, path_item),
+                ReferenceOr::Reference { reference } => self.warning(
+                    diagnostics::SKIPPED_REF,
+                    &join("#/paths", &[path]),
+                    format!("referenced path item `{reference}` is not supported; skipped"),
+                ),
             }
         }
 
+        // Convert webhooks to inbound functions
+        self.convert_webhooks();
+
         let title = self.spec.info.title.clone();
+        diagnostics.extend(self.diagnostics.into_inner());
 
-        Ok(Module {
-            name: to_module_name(&title),
+        Module {
+            name: naming::to_snake_case(&title),
             items: self.items,
             submodules: Vec::new(),
             annotations: Vec::new(),
             metadata: Metadata {
                 docs: self.spec.info.description.clone(),
+                extra: extensions_extra(&self.spec.info.extensions),
                 ..Default::default()
             },
-        })
+        }
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn convert_schema(&self, schema: &Schema, name: Option<String>) -> Result<Type> {
+    fn convert_schema(&self, schema: &Schema, name: Option<String>, pointer: &str) -> Type {
+        if is_null_schema(schema) {
+            return Type {
+                metadata: self.located(pointer),
+                ..Type::reference("Null")
+            };
+        }
+
+        let (mut kind, mut args, confidence) = self.convert_schema_kind(schema, pointer);
+
+        let mut annotations = deprecation_annotations(
+            schema.schema_data.deprecated,
+            &schema.schema_data.extensions,
+        );
+        annotations.extend(inherited_required(schema));
+        if schema.schema_data.extensions.get("x-flags") == Some(&serde_json::Value::Bool(true)) {
+            match self.flag_variants(schema, name.is_some()) {
+                Some(variants) => {
+                    kind = TypeKind::Enum { variants };
+                    args = Vec::new();
+                    annotations.push(Annotation::flag("flags"));
+                }
+                None => self.warning(
+                    diagnostics::UNSUPPORTED_SCHEMA,
+                    pointer,
+                    "`x-flags` needs a component schema listing up to 64 string enum values; ignoring it",
+                ),
+            }
+        }
+
+        // Add format annotation if present
+        if let SchemaKind::Type(OaType::String(StringType { format, .. })) = &schema.schema_kind {
+            if let openapiv3::VariantOrUnknownOrEmpty::Item(f) = format {
+                annotations.push(Annotation::with_string(
+                    "format",
+                    format!("{f:?}").to_lowercase(),
+                ));
+            } else if let openapiv3::VariantOrUnknownOrEmpty::Unknown(f) = format {
+                annotations.push(Annotation::with_string("format", f.clone()));
+            }
+        }
+
+        let mut extra = self.examples_extra(schema.schema_data.example.as_ref(), &IndexMap::new());
+        if let Some(default) = &schema.schema_data.default {
+            extra.insert("default".to_string(), json_to_value(default));
+        }
+        extra.extend(extensions_extra(&schema.schema_data.extensions));
+
+        Type {
+            kind,
+            name,
+            params: Vec::new(),
+            args,
+            annotations,
+            metadata: Metadata {
+                docs: schema.schema_data.description.clone(),
+                confidence,
+                extra,
+                ..self.located(pointer)
+            },
+        }
+    }
+
+    /// The kind and arguments of a schema's type, and the confidence of
+    /// lossy conversions.
+    fn convert_schema_kind(
+        &self,
+        schema: &Schema,
+        pointer: &str,
+    ) -> (TypeKind, Vec<Type>, Option<f32>) {
+        let mut confidence = None;
         let (kind, args) = match &schema.schema_kind {
-            SchemaKind::Type(typ) => self.convert_schema_type(typ),
+            SchemaKind::Type(typ) => self.convert_schema_type(typ, pointer),
             SchemaKind::OneOf { one_of } => {
-                let members = one_of
-                    .iter()
-                    .filter_map(|r| self.resolve_schema_ref(r).ok())
-                    .collect();
+                let mut members = self.convert_members(one_of, pointer, "oneOf");
+                if schema.schema_data.nullable {
+                    members.push(Type::reference("Null"));
+                }
                 (TypeKind::Union { members }, Vec::new())
             }
             SchemaKind::AllOf { all_of } => {
-                let members = all_of
-                    .iter()
-                    .filter_map(|r| self.resolve_schema_ref(r).ok())
-                    .collect();
+                let members = self.convert_members(all_of, pointer, "allOf");
                 (TypeKind::Intersection { members }, Vec::new())
             }
             SchemaKind::AnyOf { any_of } => {
-                let members = any_of
-                    .iter()
-                    .filter_map(|r| self.resolve_schema_ref(r).ok())
-                    .collect();
+                let mut members = self.convert_members(any_of, pointer, "anyOf");
+                if schema.schema_data.nullable {
+                    members.push(Type::reference("Null"));
+                }
                 (TypeKind::Union { members }, Vec::new())
             }
             SchemaKind::Not { .. } => {
-                // Not types are tricky, just use Any for now
+                self.warning(
+                    diagnostics::UNSUPPORTED_SCHEMA,
+                    pointer,
+                    "`not` schemas are not supported; using `Any`",
+                );
+                confidence = Some(confidence::FALLBACK_ANY);
                 (
                     TypeKind::Ref {
                         name: "Any".to_string(),
@@ -105,7 +301,7 @@ impl<'a> Converter<'a> {
             }
             SchemaKind::Any(any) => {
                 // Try to infer from properties
-                if any.properties.is_empty() {
+                if any.properties.is_empty() && any.required.is_empty() {
                     (
                         TypeKind::Ref {
                             name: "Any".to_string(),
@@ -117,55 +313,45 @@ impl<'a> Converter<'a> {
                         .properties
                         .iter()
                         .map(|(name, schema_ref)| {
-                            let typ = self
-                                .resolve_boxed_schema_ref(schema_ref)
-                                .unwrap_or_else(|_| Type::reference("Any"));
-                            let required = any.required.contains(name);
-                            let typ = if required {
-                                typ
-                            } else {
-                                Type::generic("Option", vec![typ])
-                            };
-                            Field {
-                                name: Some(name.clone()),
-                                typ,
-                                annotations: Vec::new(),
-                            }
+                            self.convert_property(
+                                name,
+                                schema_ref,
+                                any.required.contains(name),
+                                &join(pointer, &["properties", name]),
+                            )
                         })
                         .collect();
                     (TypeKind::Struct { fields }, Vec::new())
                 }
             }
         };
+        (kind, args, confidence)
+    }
 
-        let mut annotations = Vec::new();
-
-        // Add format annotation if present
-        if let SchemaKind::Type(OaType::String(StringType { format, .. })) = &schema.schema_kind {
-            if let openapiv3::VariantOrUnknownOrEmpty::Item(f) = format {
-                annotations.push(Annotation::with_string(
-                    "format",
-                    format!("{f:?}").to_lowercase(),
-                ));
-            } else if let openapiv3::VariantOrUnknownOrEmpty::Unknown(f) = format {
-                annotations.push(Annotation::with_string("format", f.clone()));
+    /// Variants of a named `x-flags` array schema: the values of its string
+    /// enum items, inline or referenced.
+    fn flag_variants(&self, schema: &Schema, named: bool) -> Option<Vec<Variant>> {
+        let SchemaKind::Type(OaType::Array(array)) = &schema.schema_kind else {
+            return None;
+        };
+        let items = match array.items.as_ref()? {
+            ReferenceOr::Item(items) => items.as_ref(),
+            ReferenceOr::Reference { reference } => {
+                let name = reference.strip_prefix("#/components/schemas/")?;
+                match self.spec.components.as_ref()?.schemas.get(name)? {
+                    ReferenceOr::Item(items) => items,
+                    ReferenceOr::Reference { .. } => return None,
+                }
             }
-        }
-
-        Ok(Type {
-            kind,
-            name,
-            params: Vec::new(),
-            args,
-            annotations,
-            metadata: Metadata {
-                docs: schema.schema_data.description.clone(),
-                ..Default::default()
-            },
-        })
+        };
+        let SchemaKind::Type(OaType::String(values)) = &items.schema_kind else {
+            return None;
+        };
+        let variants = string_variants(&values.enumeration);
+        (named && !variants.is_empty() && variants.len() <= 64).then_some(variants)
     }
 
-    fn convert_schema_type(&self, typ: &OaType) -> (TypeKind, Vec<Type>) {
+    fn convert_schema_type(&self, typ: &OaType, pointer: &str) -> (TypeKind, Vec<Type>) {
         match typ {
             OaType::String(s) => {
                 if s.enumeration.is_empty() {
@@ -176,17 +362,7 @@ impl<'a> Converter<'a> {
                         Vec::new(),
                     )
                 } else {
-                    // Generate enum variants from string values
-                    let variants = s
-                        .enumeration
-                        .iter()
-                        .filter_map(|v| v.as_ref())
-                        .map(|v| Variant {
-                            name: to_pascal_case(v),
-                            fields: Vec::new(),
-                            annotations: vec![Annotation::with_string("serde_rename", v.clone())],
-                        })
-                        .collect();
+                    let variants = string_variants(&s.enumeration);
                     (TypeKind::Enum { variants }, Vec::new())
                 }
             }
@@ -213,30 +389,21 @@ impl<'a> Converter<'a> {
                     .properties
                     .iter()
                     .map(|(name, schema_ref)| {
-                        let typ = self
-                            .resolve_boxed_schema_ref(schema_ref)
-                            .unwrap_or_else(|_| Type::reference("Any"));
-                        let required = obj.required.contains(name);
-                        let typ = if required {
-                            typ
-                        } else {
-                            Type::generic("Option", vec![typ])
-                        };
-                        Field {
-                            name: Some(name.clone()),
-                            typ,
-                            annotations: Vec::new(),
-                        }
+                        self.convert_property(
+                            name,
+                            schema_ref,
+                            obj.required.contains(name),
+                            &join(pointer, &["properties", name]),
+                        )
                     })
                     .collect();
                 (TypeKind::Struct { fields }, Vec::new())
             }
             OaType::Array(arr) => {
-                let item_type = arr
-                    .items
-                    .as_ref()
-                    .and_then(|r| self.resolve_boxed_schema_ref(r).ok())
-                    .unwrap_or_else(|| Type::reference("Any"));
+                let item_type = arr.items.as_ref().map_or_else(
+                    || Type::reference("Any"),
+                    |r| self.resolve_boxed_schema_ref(r, &join(pointer, &["items"])),
+                );
                 (
                     TypeKind::Ref {
                         name: "Vec".to_string(),
@@ -247,32 +414,119 @@ impl<'a> Converter<'a> {
         }
     }
 
-    fn resolve_schema_ref(&self, schema_ref: &ReferenceOr<Schema>) -> Result<Type> {
-        match schema_ref {
-            ReferenceOr::Reference { reference } => {
-                // Extract name from #/components/schemas/Name
-                let name = reference
-                    .strip_prefix("#/components/schemas/")
-                    .unwrap_or(reference);
-                Ok(Type::reference(name))
+    /// Convert the members of a `oneOf`, `allOf` or `anyOf` composition.
+    fn convert_members(
+        &self,
+        members: &[ReferenceOr<Schema>],
+        pointer: &str,
+        keyword: &str,
+    ) -> Vec<Type> {
+        members
+            .iter()
+            .enumerate()
+            .map(|(i, r)| self.resolve_schema_ref(r, &join(pointer, &[keyword, &i.to_string()])))
+            .collect()
+    }
+
+    /// Convert an object property into a field, wrapping optional ones in `Option`.
+    fn convert_property(
+        &self,
+        name: &str,
+        schema_ref: &ReferenceOr<Box<Schema>>,
+        required: bool,
+        pointer: &str,
+    ) -> Field {
+        let typ = self.resolve_boxed_schema_ref(schema_ref, pointer);
+        let typ = if required {
+            typ
+        } else {
+            Type::generic("Option", vec![typ])
+        };
+
+        let mut default = None;
+        let mut annotations = Vec::new();
+        let mut extra = IndexMap::new();
+        if let ReferenceOr::Item(schema) = schema_ref {
+            let data = &schema.schema_data;
+            default = data.default.as_ref().map(json_to_value);
+            extra = extensions_extra(&data.extensions);
+            annotations.extend(deprecation_annotations(data.deprecated, &data.extensions));
+            if data.read_only {
+                annotations.push(Annotation::flag("read_only"));
             }
-            ReferenceOr::Item(schema) => self.convert_schema(schema, None),
+            if data.write_only {
+                annotations.push(Annotation::flag("write_only"));
+            }
+        }
+
+        Field {
+            name: Some(name.to_string()),
+            typ,
+            default,
+            annotations,
+            metadata: Metadata {
+                extra,
+                ..self.located(pointer)
+            },
         }
     }
 
-    fn resolve_boxed_schema_ref(&self, schema_ref: &ReferenceOr<Box<Schema>>) -> Result<Type> {
+    fn resolve_schema_ref(&self, schema_ref: &ReferenceOr<Schema>, pointer: &str) -> Type {
         match schema_ref {
-            ReferenceOr::Reference { reference } => {
-                let name = reference
-                    .strip_prefix("#/components/schemas/")
-                    .unwrap_or(reference);
-                Ok(Type::reference(name))
-            }
-            ReferenceOr::Item(schema) => self.convert_schema(schema, None),
+            ReferenceOr::Reference { reference } => self.resolve_reference(reference, pointer),
+            ReferenceOr::Item(schema) => self.convert_schema(schema, None, pointer),
+        }
+    }
+
+    fn resolve_boxed_schema_ref(
+        &self,
+        schema_ref: &ReferenceOr<Box<Schema>>,
+        pointer: &str,
+    ) -> Type {
+        match schema_ref {
+            ReferenceOr::Reference { reference } => self.resolve_reference(reference, pointer),
+            ReferenceOr::Item(schema) => self.convert_schema(schema, None, pointer),
+        }
+    }
+
+    /// Turn a schema `$ref` into a reference to the named type.
+    fn resolve_reference(&self, reference: &str, pointer: &str) -> Type {
+        // Extract name from #/components/schemas/Name
+        let Some(name) = reference.strip_prefix("#/components/schemas/") else {
+            self.warning(
+                diagnostics::UNSUPPORTED_REF,
+                pointer,
+                format!("`{reference}` is not a local schema reference; using `Any`"),
+            );
+            return Type {
+                metadata: self.lossy(pointer, confidence::FALLBACK_ANY),
+                ..Type::reference("Any")
+            };
+        };
+
+        let exists = self
+            .spec
+            .components
+            .as_ref()
+            .is_some_and(|c| c.schemas.contains_key(name));
+        let metadata = if exists {
+            self.located(pointer)
+        } else {
+            self.error(
+                diagnostics::UNRESOLVED_REF,
+                pointer,
+                format!("schema `{name}` referenced here does not exist"),
+            );
+            self.lossy(pointer, confidence::UNRESOLVED)
+        };
+
+        Type {
+            metadata,
+            ..Type::reference(name)
         }
     }
 
-    fn convert_path_item(&mut self, path: &str, item: &openapiv3::PathItem) -> Result<()> {
+    fn convert_path_item(&mut self, path: &str, item: &PathItem) {
         let operations = [
             ("get", &item.get),
             ("post", &item.post),
@@ -284,136 +538,1323 @@ impl<'a> Converter<'a> {
             ("trace", &item.trace),
         ];
 
+        // Path servers are shared by every operation on the path that doesn't
+        // override them, so their enum is converted once
+        let mut path_server: Option<String> = None;
+
         for (method, op) in operations {
-            if let Some(operation) = op {
-                let func = self.convert_operation(path, method, operation)?;
+            if let Some(operation) = op
+                && !self.skip_operation(operation)
+            {
+                let pointer = join("#/paths", &[path, method]);
+                let mut func = self.convert_operation(path, method, operation, &pointer);
+
+                // Operation servers override path servers, which override top-level servers
+                let server_name = if !operation.servers.is_empty() {
+                    let server_name = format!("{}Server", naming::to_pascal_case(&func.name));
+                    let docs = format!("Servers for `{}`.", func.name);
+                    self.convert_servers(&server_name, &operation.servers, &docs);
+                    Some(server_name)
+                } else if !item.servers.is_empty() {
+                    Some(path_server.get_or_insert_with(|| {
+                        let words = naming::to_pascal_case(&path.replace(['{', '}'], ""));
+                        let server_name =
+                            format!("{}Server", if words.is_empty() { "Root" } else { &words });
+                        let docs = format!("Servers for `{path}`.");
+                        self.convert_servers(&server_name, &item.servers, &docs);
+                        server_name
+                    }))
+                    .cloned()
+                } else {
+                    None
+                };
+                if let Some(server_name) = server_name {
+                    func.annotations.push(Annotation::with_type(
+                        "server",
+                        Type::reference(server_name),
+                    ));
+                }
+
+                let callbacks = self.convert_callbacks(&func, operation, &pointer);
+                self.items.push(Item::Function(func));
+                self.items.extend(callbacks);
+            }
+        }
+    }
+
+    /// Collect an `example` and named `examples` into an `examples` extra entry.
+    ///
+    /// Referenced examples are resolved from `components/examples`; external
+    /// examples are skipped.
+    fn examples_extra(
+        &self,
+        example: Option<&serde_json::Value>,
+        examples: &IndexMap<String, ReferenceOr<Example>>,
+    ) -> IndexMap<String, Value> {
+        let mut values: Vec<Value> = example.map(json_to_value).into_iter().collect();
+        for example_ref in examples.values() {
+            let example = match example_ref {
+                ReferenceOr::Item(example) => Some(example),
+                ReferenceOr::Reference { reference } => reference
+                    .strip_prefix("#/components/examples/")
+                    .and_then(|name| self.spec.components.as_ref()?.examples.get(name))
+                    .and_then(|r| match r {
+                        ReferenceOr::Item(example) => Some(example),
+                        ReferenceOr::Reference { .. } => None,
+                    }),
+            };
+            if let Some(value) = example.and_then(|e| e.value.as_ref()) {
+                values.push(json_to_value(value));
+            }
+        }
+
+        let mut extra = IndexMap::new();
+        if !values.is_empty() {
+            extra.insert("examples".to_string(), Value::List(values));
+        }
+        extra
+    }
+
+    /// Convert callbacks declared on an operation into inbound functions.
+    ///
+    /// All callbacks of one operation form a single webhook set. Callbacks
+    /// without tags inherit the tags of the operation declaring them.
+    fn convert_callbacks(&self, parent: &Function, op: &Operation, pointer: &str) -> Vec<Item> {
+        let set = format!("{}Callbacks", naming::to_pascal_case(&parent.name));
+        let parent_tags = parent.annotations.iter().find(|a| a.kind == "tags");
+
+        let mut items = Vec::new();
+        for (callback_name, callback) in &op.callbacks {
+            for (expression, path_item) in callback {
+                for (method, callback_op) in path_item.iter() {
+                    if self.skip_operation(callback_op) {
+                        continue;
+                    }
+                    let callback_pointer =
+                        join(pointer, &["callbacks", callback_name, expression, method]);
+                    let mut func =
+                        self.convert_operation(expression, method, callback_op, &callback_pointer);
+                    if callback_op.operation_id.is_none() {
+                        func.name = format!("{method}_{callback_name}");
+                    }
+                    if callback_op.tags.is_empty()
+                        && let Some(tags) = parent_tags
+                    {
+                        func.annotations.push(tags.clone());
+                    }
+                    func.annotations.extend([
+                        Annotation::flag("inbound"),
+                        Annotation::with_string("webhook_set", &set),
+                        Annotation::with_string("callback", callback_name),
+                    ]);
+                    items.push(Item::Function(func));
+                }
+            }
+        }
+
+        items
+    }
+
+    /// Convert top-level webhooks into inbound functions of the `Webhooks` set.
+    fn convert_webhooks(&mut self) {
+        for (name, item_ref) in &self.extras.webhooks {
+            let item = match item_ref {
+                ReferenceOr::Item(item) => item,
+                ReferenceOr::Reference { reference } => {
+                    self.warning(
+                        diagnostics::SKIPPED_REF,
+                        &join("#/webhooks", &[name]),
+                        format!("referenced webhook `{reference}` is not supported; skipped"),
+                    );
+                    continue;
+                }
+            };
+            for (method, op) in item.iter() {
+                if self.skip_operation(op) {
+                    continue;
+                }
+                let pointer = join("#/webhooks", &[name, method]);
+                let mut func = self.convert_operation(name, method, op, &pointer);
+                // Webhooks are keyed by name, not path
+                func.annotations.retain(|a| a.kind != "http_path");
+                func.annotations.extend([
+                    Annotation::flag("inbound"),
+                    Annotation::with_string("webhook_set", "Webhooks"),
+                    Annotation::with_string("webhook", name),
+                ]);
                 self.items.push(Item::Function(func));
             }
         }
+    }
+
+    /// Convert a server list into URL template constants and a server enum.
+    ///
+    /// Each server becomes a variant whose fields are its URL variables.
+    fn convert_servers(&mut self, name: &str, servers: &[openapiv3::Server], docs: &str) {
+        let mut variants: Vec<Variant> = Vec::new();
+
+        for (i, server) in servers.iter().enumerate() {
+            let variant_name = server
+                .description
+                .as_deref()
+                .map(naming::to_pascal_case)
+                .filter(|n| {
+                    n.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && !variants.iter().any(|v| &v.name == n)
+                })
+                .unwrap_or_else(|| format!("Server{i}"));
+
+            let const_name = format!(
+                "{}_{}_URL",
+                naming::to_screaming_snake_case(name),
+                naming::to_screaming_snake_case(&variant_name)
+            );
+            self.items.push(Item::Const {
+                name: const_name.clone(),
+                typ: Type::reference("String"),
+                value: Value::String(server.url.clone()),
+                annotations: Vec::new(),
+            });
 
-        Ok(())
+            let fields = server
+                .variables
+                .iter()
+                .flatten()
+                .map(|(var_name, var)| {
+                    let mut annotations = Vec::new();
+                    if !var.enumeration.is_empty() {
+                        annotations.push(Annotation {
+                            kind: "enum".to_string(),
+                            value: Some(AnnotationValue::List(
+                                var.enumeration
+                                    .iter()
+                                    .cloned()
+                                    .map(AnnotationValue::String)
+                                    .collect(),
+                            )),
+                        });
+                    }
+                    Field {
+                        name: Some(var_name.clone()),
+                        typ: Type::reference("String"),
+                        default: Some(Value::String(var.default.clone())),
+                        annotations,
+                        metadata: Metadata::default(),
+                    }
+                })
+                .collect();
+
+            let mut annotations = vec![
+                Annotation::with_string("server_url", &server.url),
+                Annotation::with_string("server_url_const", const_name),
+            ];
+            if let Some(description) = &server.description {
+                annotations.push(Annotation::with_string("description", description));
+            }
+
+            variants.push(Variant {
+                name: variant_name,
+                fields,
+                annotations,
+            });
+        }
+
+        self.items.push(Item::Type(Type {
+            kind: TypeKind::Enum { variants },
+            name: Some(name.to_string()),
+            params: Vec::new(),
+            args: Vec::new(),
+            annotations: vec![Annotation::flag("servers")],
+            metadata: Metadata {
+                docs: Some(docs.to_string()),
+                ..Default::default()
+            },
+        }));
+    }
+
+    fn convert_parameter(&self, param: &openapiv3::Parameter, pointer: &str) -> Param {
+        let param_data = match param {
+            openapiv3::Parameter::Query { parameter_data, .. }
+            | openapiv3::Parameter::Header { parameter_data, .. }
+            | openapiv3::Parameter::Path { parameter_data, .. }
+            | openapiv3::Parameter::Cookie { parameter_data, .. } => parameter_data,
+        };
+
+        let typ = match &param_data.format {
+            openapiv3::ParameterSchemaOrContent::Schema(s) => {
+                self.resolve_schema_ref(s, &join(pointer, &["schema"]))
+            }
+            openapiv3::ParameterSchemaOrContent::Content(_) => {
+                self.warning(
+                    diagnostics::PARAMETER_CONTENT,
+                    pointer,
+                    format!(
+                        "parameter `{}` uses `content`, which is not supported; using `String`",
+                        param_data.name
+                    ),
+                );
+                Type {
+                    metadata: self.lossy(pointer, confidence::FALLBACK_STRING),
+                    ..Type::reference("String")
+                }
+            }
+        };
+        let default = match &param_data.format {
+            openapiv3::ParameterSchemaOrContent::Schema(ReferenceOr::Item(schema)) => {
+                schema.schema_data.default.as_ref().map(json_to_value)
+            }
+            _ => None,
+        };
+
+        let typ = if param_data.required {
+            typ
+        } else {
+            Type::generic("Option", vec![typ])
+        };
+
+        Param {
+            name: Some(param_data.name.clone()),
+            typ,
+            default,
+            annotations: deprecation_annotations(
+                param_data.deprecated.unwrap_or(false),
+                &param_data.extensions,
+            ),
+            metadata: Metadata {
+                docs: param_data.description.clone(),
+                extra: self
+                    .examples_extra(param_data.example.as_ref(), &param_data.examples)
+                    .into_iter()
+                    .chain(extensions_extra(&param_data.extensions))
+                    .collect(),
+                ..self.located(pointer)
+            },
+        }
     }
 
     fn convert_operation(
         &self,
         path: &str,
         method: &str,
-        op: &openapiv3::Operation,
-    ) -> Result<Function> {
+        op: &Operation,
+        pointer: &str,
+    ) -> Function {
         let name = op.operation_id.clone().unwrap_or_else(|| {
             format!(
                 "{method}_{}",
                 path.replace('/', "_").replace(['{', '}'], "")
             )
         });
+        let mut metadata = Metadata {
+            docs: op.description.clone().or_else(|| op.summary.clone()),
+            ..self.located(pointer)
+        };
+        if op.operation_id.is_none() {
+            confidence::lower(&mut metadata, confidence::GUESSED_NAME);
+        }
 
         let mut args = Vec::new();
 
         // Convert parameters
-        for param_ref in &op.parameters {
-            if let ReferenceOr::Item(param) = param_ref {
-                let param_data = match param {
-                    openapiv3::Parameter::Query { parameter_data, .. }
-                    | openapiv3::Parameter::Header { parameter_data, .. }
-                    | openapiv3::Parameter::Path { parameter_data, .. }
-                    | openapiv3::Parameter::Cookie { parameter_data, .. } => parameter_data,
-                };
-
-                let typ = match &param_data.format {
-                    openapiv3::ParameterSchemaOrContent::Schema(s) => self
-                        .resolve_schema_ref(s)
-                        .unwrap_or_else(|_| Type::reference("String")),
-                    openapiv3::ParameterSchemaOrContent::Content(_) => Type::reference("String"),
-                };
-
-                let typ = if param_data.required {
-                    typ
-                } else {
-                    Type::generic("Option", vec![typ])
-                };
-
-                args.push(Param {
-                    name: Some(param_data.name.clone()),
-                    typ,
-                    default: None,
-                    annotations: Vec::new(),
-                });
+        for (i, param_ref) in op.parameters.iter().enumerate() {
+            let param_pointer = join(pointer, &["parameters", &i.to_string()]);
+            match param_ref {
+                ReferenceOr::Item(param) => {
+                    args.push(self.convert_parameter(param, &param_pointer));
+                }
+                ReferenceOr::Reference { reference } => {
+                    self.warning(
+                        diagnostics::SKIPPED_REF,
+                        &param_pointer,
+                        format!("referenced parameter `{reference}` is not supported; skipped"),
+                    );
+                    confidence::lower(&mut metadata, confidence::DROPPED);
+                }
             }
         }
 
         // Convert request body
-        if let Some(ReferenceOr::Item(body)) = &op.request_body
+        if let Some(body) = self.request_body(op, pointer)
             && let Some(content) = body.content.get("application/json")
             && let Some(schema_ref) = &content.schema
         {
-            let typ = self.resolve_schema_ref(schema_ref)?;
+            let body_pointer = join(pointer, &["requestBody"]);
+            let schema_pointer = join(&body_pointer, &["content", "application/json", "schema"]);
+            let typ = self.resolve_schema_ref(schema_ref, &schema_pointer);
             args.push(Param {
                 name: Some("body".to_string()),
                 typ,
                 default: None,
                 annotations: Vec::new(),
+                metadata: Metadata {
+                    docs: body.description.clone(),
+                    extra: self.examples_extra(content.example.as_ref(), &content.examples),
+                    ..self.located(&body_pointer)
+                },
             });
+        } else if op.request_body.as_ref().is_some_and(|body| match body {
+            ReferenceOr::Item(body) => !body.content.is_empty(),
+            ReferenceOr::Reference { .. } => true,
+        }) {
+            confidence::lower(&mut metadata, confidence::DROPPED);
+        }
+
+        let (ret, response_content) = self.convert_response(op, pointer);
+
+        let ret = Type::generic("Result", vec![ret, Type::reference("ApiError")]);
+
+        let mut annotations = vec![
+            Annotation::with_string("http_method", method.to_uppercase()),
+            Annotation::with_string("http_path", path),
+        ];
+        annotations.extend(deprecation_annotations(op.deprecated, &op.extensions));
+        if !op.tags.is_empty() {
+            annotations.push(Annotation {
+                kind: "tags".to_string(),
+                value: Some(AnnotationValue::List(
+                    op.tags
+                        .iter()
+                        .cloned()
+                        .map(AnnotationValue::String)
+                        .collect(),
+                )),
+            });
+        }
+
+        let mut extra = extensions_extra(&op.extensions);
+        if let Some(content) = response_content
+            && let Some(examples) = self
+                .examples_extra(content.example.as_ref(), &content.examples)
+                .shift_remove("examples")
+        {
+            extra.insert("response_examples".to_string(), examples);
+        }
+
+        Function {
+            name,
+            params: Vec::new(),
+            args,
+            ret,
+            annotations,
+            metadata: Metadata { extra, ..metadata },
+        }
+    }
+
+    /// The operation's inline request body, reporting ones that are skipped.
+    fn request_body<'o>(&self, op: &'o Operation, pointer: &str) -> Option<&'o RequestBody> {
+        let body_pointer = join(pointer, &["requestBody"]);
+        match op.request_body.as_ref()? {
+            ReferenceOr::Item(body) => {
+                if !body.content.is_empty() && !body.content.contains_key("application/json") {
+                    self.warning(
+                        diagnostics::NON_JSON_CONTENT,
+                        &body_pointer,
+                        "request body has no `application/json` content; skipped",
+                    );
+                }
+                Some(body)
+            }
+            ReferenceOr::Reference { reference } => {
+                self.warning(
+                    diagnostics::SKIPPED_REF,
+                    &body_pointer,
+                    format!("referenced request body `{reference}` is not supported; skipped"),
+                );
+                None
+            }
         }
+    }
 
-        // Convert response
-        let ret = op
+    /// Convert the `default` or `200` JSON response into a return type.
+    fn convert_response<'o>(
+        &self,
+        op: &'o Operation,
+        pointer: &str,
+    ) -> (Type, Option<&'o openapiv3::MediaType>) {
+        let response = op
             .responses
             .default
             .as_ref()
+            .map(|r| ("default", r))
             .or_else(|| {
                 op.responses
                     .responses
                     .get(&openapiv3::StatusCode::Code(200))
-            })
-            .and_then(|r| match r {
-                ReferenceOr::Item(resp) => resp.content.get("application/json"),
-                ReferenceOr::Reference { .. } => None,
-            })
+                    .map(|r| ("200", r))
+            });
+        let response_pointer = |status: &str| join(pointer, &["responses", status]);
+        let (status, response) = match response {
+            None => return (Type::reference("Unit"), None),
+            Some((status, ReferenceOr::Reference { reference })) => {
+                self.warning(
+                    diagnostics::SKIPPED_REF,
+                    &response_pointer(status),
+                    format!("referenced response `{reference}` is not supported; using `Unit`"),
+                );
+                let metadata = self.lossy(&response_pointer(status), confidence::DROPPED);
+                return (
+                    Type {
+                        metadata,
+                        ..Type::reference("Unit")
+                    },
+                    None,
+                );
+            }
+            Some((status, ReferenceOr::Item(response))) => (status, response),
+        };
+        let content = response.content.get("application/json");
+        if content.is_none() && !response.content.is_empty() {
+            self.warning(
+                diagnostics::NON_JSON_CONTENT,
+                &response_pointer(status),
+                "response has no `application/json` content; using `Unit`",
+            );
+            let metadata = self.lossy(&response_pointer(status), confidence::DROPPED);
+            return (
+                Type {
+                    metadata,
+                    ..Type::reference("Unit")
+                },
+                None,
+            );
+        }
+
+        let schema_pointer = join(
+            pointer,
+            &["responses", status, "content", "application/json", "schema"],
+        );
+        let ret = content
             .and_then(|content| content.schema.as_ref())
-            .and_then(|s| self.resolve_schema_ref(s).ok())
-            .unwrap_or_else(|| Type::reference("Unit"));
+            .map_or_else(
+                || Type::reference("Unit"),
+                |s| self.resolve_schema_ref(s, &schema_pointer),
+            );
+        (ret, content)
+    }
+}
 
-        let ret = Type::generic("Result", vec![ret, Type::reference("ApiError")]);
+/// `required` annotation for required properties the schema does not declare,
+/// as in `allOf` members adding requirements to inherited properties.
+fn inherited_required(schema: &Schema) -> Option<Annotation> {
+    let (properties, required) = match &schema.schema_kind {
+        SchemaKind::Type(OaType::Object(obj)) => (&obj.properties, &obj.required),
+        SchemaKind::Any(any) => (&any.properties, &any.required),
+        _ => return None,
+    };
+    let inherited: Vec<_> = required
+        .iter()
+        .filter(|name| !properties.contains_key(*name))
+        .cloned()
+        .map(AnnotationValue::String)
+        .collect();
+    (!inherited.is_empty()).then(|| Annotation {
+        kind: "required".to_string(),
+        value: Some(AnnotationValue::List(inherited)),
+    })
+}
 
-        Ok(Function {
-            name,
-            params: Vec::new(),
-            args,
-            ret,
-            annotations: vec![
-                Annotation::with_string("http_method", method.to_uppercase()),
-                Annotation::with_string("http_path", path),
-            ],
-            metadata: Metadata {
-                docs: op.description.clone().or_else(|| op.summary.clone()),
-                ..Default::default()
-            },
+/// Whether a schema only admits `null`: `type: 'null'`, `enum: [null]` or a
+/// bare `nullable: true`.
+fn is_null_schema(schema: &Schema) -> bool {
+    match &schema.schema_kind {
+        SchemaKind::Any(any) => {
+            any.typ.as_deref() == Some("null")
+                || (!any.enumeration.is_empty()
+                    && any.enumeration.iter().all(serde_json::Value::is_null))
+                || (schema.schema_data.nullable && *any == openapiv3::AnySchema::default())
+        }
+        SchemaKind::Type(OaType::String(s)) => {
+            !s.enumeration.is_empty() && s.enumeration.iter().all(Option::is_none)
+        }
+        _ => false,
+    }
+}
+
+/// Generate enum variants from string values.
+fn string_variants(values: &[Option<String>]) -> Vec<Variant> {
+    values
+        .iter()
+        .filter_map(|v| v.as_ref())
+        .map(|v| Variant {
+            name: naming::to_pascal_case(v),
+            fields: Vec::new(),
+            annotations: vec![Annotation::with_string("serde_rename", v.clone())],
         })
+        .collect()
+}
+
+/// Build `deprecated` annotations, with `deprecated_since` and `sunset` taken
+/// from the `x-deprecated-since` and `x-sunset` extensions.
+fn deprecation_annotations(
+    deprecated: bool,
+    extensions: &IndexMap<String, serde_json::Value>,
+) -> Vec<Annotation> {
+    if !deprecated {
+        return Vec::new();
     }
+
+    let mut annotations = vec![Annotation::flag("deprecated")];
+    for (extension, kind) in [
+        ("x-deprecated-since", "deprecated_since"),
+        ("x-sunset", "sunset"),
+    ] {
+        match extensions.get(extension) {
+            Some(serde_json::Value::String(text)) => {
+                annotations.push(Annotation::with_string(kind, text));
+            }
+            Some(other) => annotations.push(Annotation::with_string(kind, other.to_string())),
+            None => {}
+        }
+    }
+    annotations
 }
 
-fn to_module_name(title: &str) -> String {
-    title
-        .to_lowercase()
-        .replace(|c: char| !c.is_alphanumeric(), "_")
-        .trim_matches('_')
-        .to_string()
+/// Convert vendor extensions (`x-*` keys) into metadata extras, keyed as written.
+fn extensions_extra(extensions: &IndexMap<String, serde_json::Value>) -> IndexMap<String, Value> {
+    extensions
+        .iter()
+        .filter(|(key, _)| key.starts_with("x-"))
+        .map(|(key, value)| (key.clone(), json_to_value(value)))
+        .collect()
 }
 
-fn to_pascal_case(s: &str) -> String {
-    let mut result = String::new();
-    let mut capitalize_next = true;
+fn json_to_value(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Bool(*b),
+        serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(items) => Value::List(items.iter().map(json_to_value).collect()),
+        serde_json::Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), json_to_value(v)))
+                .collect(),
+        ),
+    }
+}
 
-    for c in s.chars() {
-        if c == '_' || c == '-' || c == ' ' {
-            capitalize_next = true;
-        } else if capitalize_next {
-            result.push(c.to_uppercase().next().unwrap());
-            capitalize_next = false;
-        } else {
-            result.push(c);
+/// Group operations into submodules by their first tag.
+///
+/// Types used by a single tag's operations move into that tag's submodule;
+/// everything else lands in the common module. Server URL constants follow
+/// the server enum that references them.
+fn split_by_tag(module: Module, spec: &OpenAPI, options: &Options) -> Module {
+    let Module {
+        name,
+        items,
+        annotations,
+        metadata,
+        ..
+    } = module;
+
+    // Module of each function, in order of first appearance
+    let mut order: Vec<String> = Vec::new();
+    let mut function_module: HashMap<String, String> = HashMap::new();
+    for item in &items {
+        if let Item::Function(func) = item {
+            let module = first_tag(func)
+                .map_or_else(|| options.untagged_module.clone(), naming::to_snake_case);
+            if !order.contains(&module) {
+                order.push(module.clone());
+            }
+            function_module.insert(func.name.clone(), module);
+        }
+    }
+
+    let users = type_users(&items, &function_module);
+
+    let type_module = |name: &str| -> String {
+        match users.get(name) {
+            Some(modules) if modules.len() == 1 => modules.iter().next().unwrap().clone(),
+            _ => COMMON_MODULE.to_string(),
+        }
+    };
+
+    // Server URL constants live next to the enum naming them
+    let mut const_owner: HashMap<String, String> = HashMap::new();
+    for item in &items {
+        if let Item::Type(Type {
+            name: Some(type_name),
+            kind: TypeKind::Enum { variants },
+            ..
+        }) = item
+        {
+            for variant in variants {
+                for a in &variant.annotations {
+                    if let (true, Some(AnnotationValue::String(c))) =
+                        (a.kind == "server_url_const", &a.value)
+                    {
+                        const_owner.insert(c.clone(), type_module(type_name));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut grouped: HashMap<String, Vec<Item>> = HashMap::new();
+    for item in items {
+        let module = match &item {
+            Item::Function(func) => function_module[&func.name].clone(),
+            Item::Type(typ) => type_module(typ.name.as_deref().unwrap_or_default()),
+            Item::Const { name, .. } => const_owner
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| COMMON_MODULE.to_string()),
+        };
+        grouped.entry(module).or_default().push(item);
+    }
+
+    let tag_docs: HashMap<String, String> = spec
+        .tags
+        .iter()
+        .filter_map(|t| Some((naming::to_snake_case(&t.name), t.description.clone()?)))
+        .collect();
+
+    let submodules = std::iter::once(COMMON_MODULE.to_string())
+        .chain(order)
+        .filter_map(|module| {
+            let items = grouped.remove(&module)?;
+            Some(Module {
+                metadata: Metadata {
+                    docs: tag_docs.get(&module).cloned(),
+                    ..Default::default()
+                },
+                name: module,
+                items,
+                submodules: Vec::new(),
+                annotations: Vec::new(),
+            })
+        })
+        .collect();
+
+    Module {
+        name,
+        items: Vec::new(),
+        submodules,
+        annotations,
+        metadata,
+    }
+}
+
+/// Map each type name to every module that reaches it, directly or through other types.
+fn type_users(
+    items: &[Item],
+    function_module: &HashMap<String, String>,
+) -> HashMap<String, HashSet<String>> {
+    let definitions: HashMap<&str, &Item> = items
+        .iter()
+        .filter(|i| matches!(i, Item::Type(_)))
+        .filter_map(|i| i.name().map(|n| (n, i)))
+        .collect();
+
+    let mut users: HashMap<String, HashSet<String>> = HashMap::new();
+    for item in items {
+        let Item::Function(func) = item else {
+            continue;
+        };
+        let module = &function_module[&func.name];
+        let mut pending = Vec::new();
+        func.visit_refs(&mut |r| pending.push(r.to_string()));
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(def) = definitions.get(name.as_str()) {
+                def.visit_refs(&mut |r| pending.push(r.to_string()));
+                users.entry(name).or_default().insert(module.clone());
+            }
+        }
+    }
+
+    users
+}
+
+fn first_tag(func: &Function) -> Option<&str> {
+    func.annotations
+        .iter()
+        .find(|a| a.kind == "tags")
+        .and_then(|a| match &a.value {
+            Some(AnnotationValue::List(tags)) => tags.first(),
+            _ => None,
+        })
+        .and_then(|t| match t {
+            AnnotationValue::String(s) => Some(s.as_str()),
+            _ => None,
+        })
+}
+
+/// Parse the YAML `spec` as `name.yaml`, in a directory of its own removed
+/// afterwards, for tests.
+#[cfg(test)]
+pub(crate) fn parse_spec(name: &str, spec: &str, options: &Options) -> (Module, Diagnostics) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(format!("{name}.yaml"));
+    fs::write(&path, spec).unwrap();
+    let mut diagnostics = Diagnostics::default();
+    let module = parse(&path, options, &mut diagnostics).unwrap();
+    (module, diagnostics)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn function<'a>(module: &'a Module, name: &str) -> &'a Function {
+        module
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Function(f) if f.name == name => Some(f),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn named<'a>(module: &'a Module, name: &str) -> &'a Type {
+        module
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Type(typ) if typ.name.as_deref() == Some(name) => Some(typ),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn annotation<'a>(annotations: &'a [Annotation], kind: &str) -> Option<&'a AnnotationValue> {
+        annotations
+            .iter()
+            .find(|a| a.kind == kind)
+            .and_then(|a| a.value.as_ref())
+    }
+
+    fn server(func: &Function) -> Option<&str> {
+        match annotation(&func.annotations, "server") {
+            Some(AnnotationValue::Type(typ)) => match &typ.kind {
+                TypeKind::Ref { name } => Some(name),
+                _ => None,
+            },
+            _ => None,
         }
     }
 
-    result
+    #[test]
+    fn servers_become_constants_and_an_enum() {
+        let (module, _) = parse_spec(
+            "servers",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+servers:
+  - url: https://{region}.example.com/v1
+    description: Production
+    variables:
+      region: {default: eu, enum: [eu, us]}
+  - url: http://localhost:8080
+paths: {}
+"#,
+            &Options::default(),
+        );
+        let consts: Vec<_> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Const { name, value, .. } => Some((name.as_str(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            consts,
+            [
+                (
+                    "SERVER_PRODUCTION_URL",
+                    Value::String("https://{region}.example.com/v1".to_string())
+                ),
+                (
+                    "SERVER_SERVER1_URL",
+                    Value::String("http://localhost:8080".to_string())
+                ),
+            ]
+        );
+        let TypeKind::Enum { variants } = &named(&module, "Server").kind else {
+            panic!("expected an enum");
+        };
+        let names: Vec<_> = variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["Production", "Server1"]);
+        let region = &variants[0].fields[0];
+        assert_eq!(region.name.as_deref(), Some("region"));
+        assert_eq!(region.default, Some(Value::String("eu".to_string())));
+    }
+
+    #[test]
+    fn path_servers_are_shared_and_operation_servers_override_them() {
+        let (module, _) = parse_spec(
+            "path_servers",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /pets/{id}:
+    servers:
+      - url: https://pets.example.com
+    get:
+      operationId: getPet
+      responses: {"200": {description: ok}}
+    delete:
+      operationId: deletePet
+      responses: {"200": {description: ok}}
+    put:
+      operationId: putPet
+      servers:
+        - url: https://put.example.com
+      responses: {"200": {description: ok}}
+"#,
+            &Options::default(),
+        );
+        assert_eq!(server(function(&module, "getPet")), Some("PetsIdServer"));
+        assert_eq!(server(function(&module, "deletePet")), Some("PetsIdServer"));
+        assert_eq!(server(function(&module, "putPet")), Some("PutPetServer"));
+        let enums = module
+            .items
+            .iter()
+            .filter(
+                |item| matches!(item, Item::Type(t) if t.name.as_deref() == Some("PetsIdServer")),
+            )
+            .count();
+        assert_eq!(enums, 1);
+    }
+
+    #[test]
+    fn split_by_tag_groups_operations_and_shares_common_types() {
+        let options = Options {
+            split_by_tag: true,
+            ..Options::default()
+        };
+        let (module, _) = parse_spec(
+            "tags",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+tags:
+  - {name: Store Admin, description: Store management.}
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      tags: [pets]
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Pet'}
+  /store:
+    get:
+      operationId: getStore
+      tags: [Store Admin, pets]
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Store'}
+  /ping:
+    get:
+      operationId: ping
+      responses: {"200": {description: ok}}
+components:
+  schemas:
+    Pet: {type: object, properties: {name: {type: string}}}
+    Store:
+      type: object
+      properties:
+        pets: {type: array, items: {$ref: '#/components/schemas/Pet'}}
+"#,
+            &options,
+        );
+        assert!(module.items.is_empty());
+        let submodules: Vec<_> = module
+            .submodules
+            .iter()
+            .map(|m| {
+                let items: Vec<_> = m.items.iter().filter_map(Item::name).collect();
+                (m.name.as_str(), items)
+            })
+            .collect();
+        assert_eq!(
+            submodules,
+            [
+                ("types", vec!["Pet"]),
+                ("pets", vec!["listPets"]),
+                ("store_admin", vec!["Store", "getStore"]),
+                ("default", vec!["ping"]),
+            ]
+        );
+        assert_eq!(
+            module.submodules[2].metadata.docs.as_deref(),
+            Some("Store management.")
+        );
+    }
+
+    #[test]
+    fn webhooks_and_callbacks_become_inbound_functions() {
+        let (module, _) = parse_spec(
+            "webhooks",
+            r#"
+openapi: 3.1.0
+info: {title: t, version: "1"}
+paths:
+  /subscriptions:
+    post:
+      operationId: subscribe
+      tags: [events]
+      responses: {"201": {description: ok}}
+      callbacks:
+        onEvent:
+          '{$request.body#/url}':
+            post:
+              requestBody:
+                content:
+                  application/json:
+                    schema: {type: string}
+              responses: {"200": {description: ok}}
+webhooks:
+  newPet:
+    post:
+      operationId: newPet
+      responses: {"200": {description: ok}}
+"#,
+            &Options::default(),
+        );
+        let callback = function(&module, "post_onEvent");
+        let annotations = |func: &Function| {
+            func.annotations
+                .iter()
+                .filter(|a| a.kind != "http_method")
+                .map(|a| (a.kind.clone(), a.value.clone()))
+                .collect::<Vec<_>>()
+        };
+        let string = |s: &str| Some(AnnotationValue::String(s.to_string()));
+        assert!(
+            annotations(callback)
+                .contains(&("webhook_set".to_string(), string("SubscribeCallbacks")))
+        );
+        assert!(annotations(callback).contains(&("callback".to_string(), string("onEvent"))));
+        assert!(annotations(callback).contains(&(
+            "tags".to_string(),
+            Some(AnnotationValue::List(vec![AnnotationValue::String(
+                "events".to_string()
+            )]))
+        )));
+
+        let webhook = annotations(function(&module, "newPet"));
+        assert!(webhook.contains(&("inbound".to_string(), None)));
+        assert!(webhook.contains(&("webhook_set".to_string(), string("Webhooks"))));
+        assert!(webhook.contains(&("webhook".to_string(), string("newPet"))));
+        assert!(!webhook.iter().any(|(kind, _)| kind == "http_path"));
+    }
+
+    const EXAMPLES: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      parameters:
+        - name: limit
+          in: query
+          schema: {type: integer}
+          example: 3
+        - name: kind
+          in: query
+          schema: {$ref: '#/components/schemas/Kind'}
+          example: dog
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Pet'}
+              example: {name: Rex}
+components:
+  schemas:
+    Kind: {type: string, enum: [dog, cat]}
+    Name: {type: string, example: Rex}
+    Pet:
+      type: object
+      example: {name: Rex}
+      required: [name]
+      properties:
+        name: {type: string, example: Rex}
+        kind: {$ref: '#/components/schemas/Kind'}
+"#;
+
+    #[test]
+    fn examples_are_kept_in_metadata() {
+        let (module, _) = parse_spec("examples", EXAMPLES, &Options::default());
+        let pet = Value::Object(IndexMap::from([(
+            "name".to_string(),
+            Value::String("Rex".to_string()),
+        )]));
+        assert_eq!(
+            named(&module, "Pet").metadata.extra.get("examples"),
+            Some(&Value::List(vec![pet.clone()]))
+        );
+        let list_pets = function(&module, "listPets");
+        assert_eq!(
+            list_pets.args[0].metadata.extra.get("examples"),
+            Some(&Value::List(vec![Value::Number(3.0)]))
+        );
+        assert_eq!(
+            list_pets.metadata.extra.get("response_examples"),
+            Some(&Value::List(vec![pet]))
+        );
+    }
+
+    pub(crate) const DEFAULTS: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      parameters:
+        - name: limit
+          in: query
+          schema: {type: integer, default: 20}
+      responses: {"200": {description: ok}}
+components:
+  schemas:
+    Kind: {type: string, enum: [dog, cat], default: cat}
+    Pet:
+      type: object
+      required: [name, kind, size]
+      properties:
+        name: {type: string, default: Rex}
+        kind: {$ref: '#/components/schemas/Kind'}
+        size: {type: number, default: 1.5}
+        tags: {type: array, items: {type: string}, default: []}
+"#;
+
+    #[test]
+    fn defaults_are_kept_on_fields_and_parameters() {
+        let (module, _) = parse_spec("defaults", DEFAULTS, &Options::default());
+        let TypeKind::Struct { fields } = &named(&module, "Pet").kind else {
+            panic!("expected a struct");
+        };
+        let defaults: Vec<_> = fields.iter().map(|f| f.default.clone()).collect();
+        assert_eq!(
+            defaults,
+            [
+                Some(Value::String("Rex".to_string())),
+                None,
+                Some(Value::Number(1.5)),
+                Some(Value::List(Vec::new())),
+            ]
+        );
+        assert_eq!(
+            function(&module, "listPets").args[0].default,
+            Some(Value::Number(20.0))
+        );
+    }
+
+    pub(crate) const DEPRECATED: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /old:
+    get:
+      operationId: oldList
+      deprecated: true
+      x-deprecated-since: "2.0"
+      x-sunset: "2025-01-01"
+      responses: {"200": {description: ok}}
+  /new:
+    get:
+      operationId: newList
+      parameters:
+        - {name: page, in: query, deprecated: true, schema: {type: integer}}
+      responses: {"200": {description: ok}}
+components:
+  schemas:
+    Pet:
+      type: object
+      deprecated: true
+      properties:
+        name: {type: string, deprecated: true}
+"#;
+
+    #[test]
+    fn deprecation_and_sunset_become_annotations() {
+        let (module, _) = parse_spec("deprecated", DEPRECATED, &Options::default());
+        let old = &function(&module, "oldList").annotations;
+        assert!(old.contains(&Annotation::flag("deprecated")));
+        assert!(old.contains(&Annotation::with_string("deprecated_since", "2.0")));
+        assert!(old.contains(&Annotation::with_string("sunset", "2025-01-01")));
+        let page = &function(&module, "newList").args[0];
+        assert!(page.annotations.contains(&Annotation::flag("deprecated")));
+        assert!(
+            named(&module, "Pet")
+                .annotations
+                .contains(&Annotation::flag("deprecated"))
+        );
+    }
+
+    #[test]
+    fn deprecated_operations_can_be_excluded() {
+        let options = Options {
+            exclude_deprecated: true,
+            ..Options::default()
+        };
+        let (module, _) = parse_spec("exclude_deprecated", DEPRECATED, &options);
+        let functions: Vec<_> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(functions, ["newList"]);
+    }
+
+    pub(crate) const EXTENSIONS: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1", x-api-owner: pets-team}
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      x-internal: true
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {type: array, items: {$ref: '#/components/schemas/Tag'}}
+components:
+  schemas:
+    Tag:
+      type: object
+      x-rust-skip: true
+      properties:
+        name: {type: string}
+    Unused:
+      type: object
+      x-rust-skip: true
+      properties:
+        name: {type: string}
+    Level:
+      type: string
+      enum: [lo, hi]
+      x-enum-varnames: [Low, High]
+    Pet:
+      type: object
+      x-go-name: PetModel
+      properties:
+        tag: {$ref: '#/components/schemas/Tag'}
+        born: {type: string, x-rust-type: "chrono::NaiveDate"}
+        secret: {type: string, x-rust-skip: true}
+        level: {$ref: '#/components/schemas/Level'}
+"#;
+
+    #[test]
+    fn extensions_are_copied_into_extra() {
+        let (module, _) = parse_spec("extensions", EXTENSIONS, &Options::default());
+        let string = |s: &str| Value::String(s.to_string());
+        assert_eq!(
+            module.metadata.extra.get("x-api-owner"),
+            Some(&string("pets-team"))
+        );
+        assert_eq!(
+            function(&module, "listPets")
+                .metadata
+                .extra
+                .get("x-internal"),
+            Some(&Value::Bool(true))
+        );
+        let pet = named(&module, "Pet");
+        assert_eq!(
+            pet.metadata.extra.get("x-go-name"),
+            Some(&string("PetModel"))
+        );
+        let TypeKind::Struct { fields } = &pet.kind else {
+            panic!("expected a struct");
+        };
+        assert_eq!(
+            fields[1].metadata.extra.get("x-rust-type"),
+            Some(&string("chrono::NaiveDate"))
+        );
+    }
+
+    #[test]
+    fn nodes_record_their_source_location() {
+        // Lines count the blank line opening `EXAMPLES`
+        let (module, _) = parse_spec("locations", EXAMPLES, &Options::default());
+        let location = |metadata: &Metadata| {
+            let source = metadata.source.as_ref().unwrap();
+            (source.pointer.clone().unwrap(), source.line, source.column)
+        };
+        assert_eq!(
+            location(&named(&module, "Pet").metadata),
+            ("#/components/schemas/Pet".to_string(), Some(28), Some(5))
+        );
+        let list_pets = function(&module, "listPets");
+        assert_eq!(
+            location(&list_pets.metadata),
+            ("#/paths/~1pets/get".to_string(), Some(6), Some(5))
+        );
+        assert_eq!(
+            location(&list_pets.args[1].metadata),
+            (
+                "#/paths/~1pets/get/parameters/1".to_string(),
+                Some(13),
+                Some(11)
+            )
+        );
+    }
+
+    #[test]
+    fn fallbacks_are_reported() {
+        let (_, diagnostics) = parse_spec(
+            "fallbacks",
+            r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /upload:
+    post:
+      operationId: upload
+      requestBody:
+        content:
+          text/plain:
+            schema: {type: string}
+      responses: {"200": {description: ok}}
+components:
+  schemas:
+    Pet:
+      type: object
+      properties:
+        owner: {$ref: '#/components/schemas/Owner'}
+"#,
+            &Options::default(),
+        );
+        let reported: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                let pointer = d.location.as_ref().and_then(|l| l.pointer.as_deref());
+                (d.code, pointer.unwrap_or_default().to_string())
+            })
+            .collect();
+        assert!(reported.contains(&(
+            diagnostics::UNRESOLVED_REF,
+            "#/components/schemas/Pet/properties/owner".to_string()
+        )));
+        assert!(reported.contains(&(
+            diagnostics::NON_JSON_CONTENT,
+            "#/paths/~1upload/post/requestBody".to_string()
+        )));
+    }
 }
@@ -0,0 +1,193 @@
+//! Map JSON pointers in a YAML or JSON document to source positions.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::SourceLocation;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// Positions of every node in a schema document, keyed by JSON pointer.
+///
+/// JSON is parsed as YAML, so one walker covers both formats. Mapping entries
+/// point at their key, sequence items at the item itself.
+pub struct SourceMap {
+    file: String,
+    positions: HashMap<String, (u32, u32)>,
+}
+
+impl SourceMap {
+    /// Index `content`, read from `file`.
+    ///
+    /// Content the YAML parser rejects still yields pointers, just without
+    /// line and column.
+    pub fn new(file: &str, content: &str) -> Self {
+        let mut walker = Walker::default();
+        let _ = Parser::new_from_str(content).load(&mut walker, false);
+        Self {
+            file: file.to_string(),
+            positions: walker.positions,
+        }
+    }
+
+    /// Location of the node at `pointer`.
+    pub fn locate(&self, pointer: &str) -> SourceLocation {
+        let position = self.positions.get(pointer);
+        SourceLocation {
+            file: self.file.clone(),
+            pointer: Some(pointer.to_string()),
+            line: position.map(|(line, _)| *line),
+            column: position.map(|(_, column)| *column),
+        }
+    }
+}
+
+/// Append reference tokens to a JSON pointer, escaping `~` and `/`.
+pub fn join(pointer: &str, tokens: &[&str]) -> String {
+    let mut pointer = pointer.to_string();
+    for token in tokens {
+        pointer.push('/');
+        pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+    }
+    pointer
+}
+
+/// 1-based line and column of `mark`.
+#[allow(clippy::cast_possible_truncation)]
+fn position(mark: Marker) -> (u32, u32) {
+    (mark.line() as u32, mark.col() as u32 + 1)
+}
+
+enum Frame {
+    Map {
+        pointer: String,
+        /// Key awaiting its value, or `None` when a key comes next.
+        key: Option<String>,
+    },
+    Seq {
+        pointer: String,
+        index: usize,
+    },
+}
+
+#[derive(Default)]
+struct Walker {
+    stack: Vec<Frame>,
+    positions: HashMap<String, (u32, u32)>,
+}
+
+impl Walker {
+    fn record(&mut self, pointer: &str, mark: Marker) {
+        self.positions
+            .entry(pointer.to_string())
+            .or_insert(position(mark));
+    }
+
+    /// Pointer of the value starting at `mark`.
+    fn value_pointer(&mut self, mark: Marker) -> String {
+        let pointer = match self.stack.last() {
+            None => "#".to_string(),
+            Some(Frame::Map { pointer, key }) => join(pointer, &[key.as_deref().unwrap_or("")]),
+            Some(Frame::Seq { pointer, index }) => join(pointer, &[&index.to_string()]),
+        };
+        self.record(&pointer, mark);
+        pointer
+    }
+
+    /// Advance the enclosing container past a finished value.
+    fn finish_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Map { key, .. }) => *key = None,
+            Some(Frame::Seq { index, .. }) => *index += 1,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for Walker {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map { pointer, key }) = self.stack.last_mut()
+                    && key.is_none()
+                {
+                    // Block mappings inside sequences are marked after their
+                    // first key, so pull the mapping back to its keys.
+                    if let Some(start) = self.positions.get_mut(pointer.as_str()) {
+                        *start = (*start).min(position(mark));
+                    }
+                    let entry = join(pointer, &[&value]);
+                    *key = Some(value);
+                    self.record(&entry, mark);
+                } else {
+                    self.value_pointer(mark);
+                    self.finish_value();
+                }
+            }
+            Event::Alias(_) => {
+                self.value_pointer(mark);
+                self.finish_value();
+            }
+            Event::MappingStart(..) => {
+                let pointer = self.value_pointer(mark);
+                self.stack.push(Frame::Map { pointer, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let pointer = self.value_pointer(mark);
+                self.stack.push(Frame::Seq { pointer, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.finish_value();
+            }
+            Event::Nothing
+            | Event::StreamStart
+            | Event::StreamEnd
+            | Event::DocumentStart
+            | Event::DocumentEnd => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(map: &SourceMap, pointer: &str) -> Option<(u32, u32)> {
+        let location = map.locate(pointer);
+        location.line.zip(location.column)
+    }
+
+    #[test]
+    fn pointers_map_to_keys_and_items() {
+        let map = SourceMap::new(
+            "spec.yaml",
+            "paths:\n  /pets/{id}:\n    get:\n      parameters:\n        - name: id\n          in: path\n        - $ref: '#/p'\n",
+        );
+        assert_eq!(position(&map, "#/paths"), Some((1, 1)));
+        assert_eq!(position(&map, "#/paths/~1pets~1{id}/get"), Some((3, 5)));
+        assert_eq!(
+            position(&map, "#/paths/~1pets~1{id}/get/parameters/0"),
+            Some((5, 11))
+        );
+        assert_eq!(
+            position(&map, "#/paths/~1pets~1{id}/get/parameters/1/$ref"),
+            Some((7, 11))
+        );
+        assert_eq!(position(&map, "#/missing"), None);
+        assert_eq!(map.locate("#/missing").file, "spec.yaml");
+    }
+
+    #[test]
+    fn json_is_indexed_too() {
+        let map = SourceMap::new(
+            "spec.json",
+            "{\n  \"components\": {\n    \"schemas\": {}\n  }\n}\n",
+        );
+        assert_eq!(position(&map, "#/components/schemas"), Some((3, 5)));
+    }
+
+    #[test]
+    fn tokens_are_escaped() {
+        assert_eq!(join("#/paths", &["/a~b", "get"]), "#/paths/~1a~0b/get");
+    }
+}
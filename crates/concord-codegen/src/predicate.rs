@@ -0,0 +1,169 @@
+//! Reasoning about Rust `cfg` predicates, as recorded in `cfg` annotations.
+//!
+//! Predicates are decided by trying every assignment of their atoms (`unix`,
+//! `target_os = "linux"`), treating atoms that give a single-valued key
+//! different values as exclusive. Predicates that don't parse or have too
+//! many atoms are never proven anything.
+
+/// Keys a target has exactly one value of.
+const SINGLE_VALUED: &[&str] = &[
+    "target_os",
+    "target_env",
+    "target_vendor",
+    "target_arch",
+    "target_pointer_width",
+    "target_endian",
+];
+
+/// Beyond this many atoms, predicates are too costly to enumerate.
+const MAX_ATOMS: usize = 12;
+
+enum Predicate {
+    Atom(usize),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Whether the predicate holds when the atoms in the bit set `set` do.
+    fn eval(&self, set: u32) -> bool {
+        match self {
+            Self::Atom(i) => set & (1 << i) != 0,
+            Self::All(all) => all.iter().all(|p| p.eval(set)),
+            Self::Any(any) => any.iter().any(|p| p.eval(set)),
+            Self::Not(p) => !p.eval(set),
+        }
+    }
+}
+
+/// Whether `a` and `b` provably never both hold.
+pub fn exclusive(a: &str, b: &str) -> bool {
+    never(&format!("all({a}, {b})"))
+}
+
+/// Whether `b` provably holds whenever `a` does.
+pub fn implies(a: &str, b: &str) -> bool {
+    never(&format!("all({a}, not({b}))"))
+}
+
+/// Whether no target satisfies `predicate`.
+fn never(predicate: &str) -> bool {
+    let mut atoms = Vec::new();
+    let mut rest = predicate;
+    let Some(parsed) = parse(&mut rest, &mut atoms) else {
+        return false;
+    };
+    if !rest.trim().is_empty() || atoms.len() > MAX_ATOMS {
+        return false;
+    }
+    let keys: Vec<Option<(&str, &str)>> = atoms
+        .iter()
+        .map(|atom| {
+            atom.split_once('=')
+                .filter(|(key, _)| SINGLE_VALUED.contains(key))
+        })
+        .collect();
+    // No two atoms holding give a single-valued key different values
+    let possible = |set: u32| {
+        let held: Vec<_> = (0..atoms.len())
+            .filter(|i| set & (1 << i) != 0)
+            .filter_map(|i| keys[i])
+            .collect();
+        held.iter()
+            .all(|(key, value)| held.iter().all(|(k, v)| k != key || v == value))
+    };
+    (0..1u32 << atoms.len()).all(|set| !possible(set) || !parsed.eval(set))
+}
+
+/// Parse the predicate at the start of `rest`, advancing past it and
+/// numbering its atoms in `atoms`.
+fn parse(rest: &mut &str, atoms: &mut Vec<String>) -> Option<Predicate> {
+    *rest = rest.trim_start();
+    for (name, list) in [("all(", true), ("any(", true), ("not(", false)] {
+        let Some(after) = rest.strip_prefix(name) else {
+            continue;
+        };
+        *rest = after;
+        let mut args = Vec::new();
+        while !rest.trim_start().starts_with(')') {
+            if !args.is_empty() {
+                *rest = rest.trim_start().strip_prefix(',')?;
+            }
+            args.push(parse(rest, atoms)?);
+        }
+        *rest = rest.trim_start().strip_prefix(')')?;
+        return match name {
+            "all(" => Some(Predicate::All(args)),
+            "any(" => Some(Predicate::Any(args)),
+            _ if !list && args.len() == 1 => Some(Predicate::Not(Box::new(args.pop()?))),
+            _ => None,
+        };
+    }
+    // Up to the next `,` or `)` outside quotes, without spaces outside them
+    let mut atom = String::new();
+    let mut quoted = false;
+    let end = rest
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                '"' => quoted = !quoted,
+                ',' | ')' if !quoted => return true,
+                c if c.is_whitespace() && !quoted => return false,
+                _ => {}
+            }
+            atom.push(c);
+            false
+        })
+        .map_or(rest.len(), |(i, _)| i);
+    if atom.is_empty() || atom.contains('(') {
+        return None;
+    }
+    *rest = &rest[end..];
+    let index = atoms.iter().position(|a| *a == atom).unwrap_or_else(|| {
+        atoms.push(atom);
+        atoms.len() - 1
+    });
+    Some(Predicate::Atom(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusive_predicates() {
+        assert!(exclusive("windows", "not(windows)"));
+        assert!(exclusive(
+            "all(not(windows), target_os = \"linux\")",
+            "windows"
+        ));
+        assert!(exclusive(
+            "target_vendor = \"apple\"",
+            "all(not(target_vendor = \"apple\"), not(target_os = \"linux\"))"
+        ));
+        assert!(exclusive("target_os = \"linux\"", "target_os=\"macos\""));
+        assert!(exclusive(
+            "any(windows, unix)",
+            "all(not(windows), not(unix))"
+        ));
+        assert!(!exclusive("unix", "windows"));
+        assert!(!exclusive("target_os = \"linux\"", "target_os = \"linux\""));
+        assert!(!exclusive("any(windows, unix)", "not(windows)"));
+        assert!(!exclusive("windows", "not(windows"));
+    }
+
+    #[test]
+    fn implied_predicates() {
+        assert!(implies("all(windows, unix)", "windows"));
+        assert!(implies("windows", "any(windows, unix)"));
+        assert!(implies("all()", "any(windows, not(windows))"));
+        assert!(implies(
+            "all(not(windows), not(unix))",
+            "not(any(windows, unix))"
+        ));
+        assert!(!implies("any(windows, unix)", "windows"));
+        assert!(!implies("all()", "windows"));
+        assert!(!implies("all()", "any()"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Pair C callbacks with the user-data pointers passed alongside them.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::{Annotation, Function, Item, Module, Param, Type, TypeKind};
+
+use super::confidence;
+use super::intersection::collect_types;
+
+/// Mark function pointer arguments that come with a `void *` user-data
+/// argument handed back to them.
+///
+/// A callback is an argument pointing to a function (through typedefs) that
+/// takes a `void *`, next to an unclaimed `void *` argument of the function
+/// registering it, the one after it preferred. A following argument pointing
+/// to a `void (*)(void *)` is taken as the function releasing the user data.
+/// The callback argument gets:
+///
+/// - `user_data_arg`: index of the registering function's user-data argument
+/// - `user_data_param`: index of the callback's user-data parameter
+/// - `destroy_notify_arg`: index of the releasing function's argument, if any
+///
+/// Variadic functions and function types are left alone, since Rust can't
+/// forward their arguments. Functions with callbacks are lowered to
+/// [`confidence::CALLBACK_PAIRED`].
+pub fn detect_callbacks(module: &mut Module) {
+    let mut types = HashMap::new();
+    collect_types(module, &mut types);
+    detect_module(module, &types);
+}
+
+fn detect_module(module: &mut Module, types: &HashMap<String, Type>) {
+    for item in &mut module.items {
+        if let Item::Function(func) = item
+            && !is_variadic(&func.annotations)
+        {
+            detect(func, types);
+        }
+    }
+    for sub in &mut module.submodules {
+        detect_module(sub, types);
+    }
+}
+
+fn detect(func: &mut Function, types: &HashMap<String, Type>) {
+    let mut claimed = vec![false; func.args.len()];
+    let mut found = Vec::new();
+    for i in 0..func.args.len() {
+        if claimed[i] {
+            continue;
+        }
+        let Some((params, _)) = signature(&func.args[i].typ, types, 0) else {
+            continue;
+        };
+        let Some(param) = params
+            .iter()
+            .position(|p| is_void_pointer(&p.typ, types, 0))
+        else {
+            continue;
+        };
+        let user_data = [i + 1, i.wrapping_sub(1)].into_iter().find(|&k| {
+            func.args
+                .get(k)
+                .is_some_and(|a| !claimed[k] && is_void_pointer(&a.typ, types, 0))
+        });
+        let Some(user_data) = user_data else {
+            continue;
+        };
+        claimed[i] = true;
+        claimed[user_data] = true;
+
+        let notify = i.max(user_data) + 1;
+        let notify = func
+            .args
+            .get(notify)
+            .is_some_and(|a| !claimed[notify] && is_destroy_notify(&a.typ, types))
+            .then_some(notify);
+        if let Some(notify) = notify {
+            claimed[notify] = true;
+        }
+        found.push((i, user_data, param, notify));
+    }
+
+    for &(i, user_data, param, notify) in &found {
+        let annotations = &mut func.args[i].annotations;
+        annotations.push(index_annotation("user_data_arg", user_data));
+        annotations.push(index_annotation("user_data_param", param));
+        if let Some(notify) = notify {
+            annotations.push(index_annotation("destroy_notify_arg", notify));
+        }
+    }
+    if !found.is_empty() {
+        confidence::lower(&mut func.metadata, confidence::CALLBACK_PAIRED);
+    }
+}
+
+#[expect(clippy::cast_precision_loss, reason = "argument indices are small")]
+fn index_annotation(kind: &str, index: usize) -> Annotation {
+    Annotation::with_number(kind, index as f64)
+}
+
+/// Parameters and return type of the function `typ` points to.
+fn signature<'t>(
+    typ: &'t Type,
+    types: &'t HashMap<String, Type>,
+    depth: usize,
+) -> Option<(&'t [Param], &'t Type)> {
+    let TypeKind::Ref { name } = &typ.kind else {
+        return None;
+    };
+    if name == "Ptr" {
+        return function(typ.args.first()?, types, depth);
+    }
+    // Bounded in case of a typedef cycle
+    if depth > 16 || !typ.args.is_empty() {
+        return None;
+    }
+    signature(types.get(name)?, types, depth + 1)
+}
+
+fn function<'t>(
+    typ: &'t Type,
+    types: &'t HashMap<String, Type>,
+    depth: usize,
+) -> Option<(&'t [Param], &'t Type)> {
+    match &typ.kind {
+        TypeKind::Function { params, ret } if !is_variadic(&typ.annotations) => Some((params, ret)),
+        TypeKind::Ref { name } if depth <= 16 && typ.args.is_empty() => {
+            function(types.get(name)?, types, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+fn is_variadic(annotations: &[Annotation]) -> bool {
+    annotations.iter().any(|a| a.kind == "variadic")
+}
+
+/// Whether `typ` is a `void *`, through typedefs such as `gpointer`.
+fn is_void_pointer(typ: &Type, types: &HashMap<String, Type>, depth: usize) -> bool {
+    let TypeKind::Ref { name } = &typ.kind else {
+        return false;
+    };
+    if name == "Ptr" {
+        return typ
+            .args
+            .first()
+            .is_some_and(|p| matches!(&p.kind, TypeKind::Ref { name } if name == "c_void"));
+    }
+    depth <= 16
+        && typ.args.is_empty()
+        && types
+            .get(name)
+            .is_some_and(|t| is_void_pointer(t, types, depth + 1))
+}
+
+/// Whether `typ` points to a `void (*)(void *)`.
+fn is_destroy_notify(typ: &Type, types: &HashMap<String, Type>) -> bool {
+    signature(typ, types, 0).is_some_and(|(params, ret)| {
+        matches!(params, [p] if is_void_pointer(&p.typ, types, 0))
+            && matches!(&ret.kind, TypeKind::Ref { name } if name == "Unit")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::AnnotationValue;
+
+    use super::*;
+    use crate::parser::c::{Options, parse_source};
+
+    /// The callback annotations on each function's arguments, as
+    /// `function.arg: kind=index ...`.
+    fn callbacks(source: &str) -> Vec<String> {
+        let (mut module, _) = parse_source("callbacks", source, &Options::default());
+        detect_callbacks(&mut module);
+        let mut callbacks = Vec::new();
+        for item in &module.items {
+            let Item::Function(func) = item else {
+                continue;
+            };
+            for arg in &func.args {
+                let annotations: Vec<_> = arg
+                    .annotations
+                    .iter()
+                    .filter_map(|a| match a.value {
+                        Some(AnnotationValue::Number(n)) => Some(format!("{}={n}", a.kind)),
+                        _ => None,
+                    })
+                    .collect();
+                if !annotations.is_empty() {
+                    let name = arg.name.as_deref().unwrap_or_default();
+                    callbacks.push(format!("{}.{name}: {}", func.name, annotations.join(" ")));
+                }
+            }
+        }
+        callbacks
+    }
+
+    #[test]
+    fn callbacks_pair_with_user_data() {
+        let callbacks = callbacks(
+            "typedef void (*visit_fn)(int value, void *data);\n\
+             typedef void (*free_fn)(void *data);\n\
+             void list_each(visit_fn visit, void *data);\n\
+             void list_before(void *data, visit_fn visit);\n\
+             void list_watch(visit_fn visit, void *data, free_fn release);\n\
+             void list_two(visit_fn a, void *x, visit_fn b, void *y);\n\
+             void list_alone(visit_fn visit);\n\
+             void list_log(visit_fn visit, void *data, ...);\n",
+        );
+        assert_eq!(
+            callbacks,
+            [
+                "list_each.visit: user_data_arg=1 user_data_param=1",
+                "list_before.visit: user_data_arg=0 user_data_param=1",
+                "list_watch.visit: user_data_arg=1 user_data_param=1 destroy_notify_arg=2",
+                "list_two.a: user_data_arg=1 user_data_param=1",
+                "list_two.b: user_data_arg=3 user_data_param=1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,645 @@
+//! Detect and resolve names that collide once converted to target identifiers.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::naming::{Case, Names, Target};
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Field, Item, Module, Param, SourceLocation, Type, TypeKind,
+    Variant,
+};
+
+use crate::diagnostics::{self, Diagnostics};
+use crate::predicate::exclusive;
+
+/// How a target names each kind of item.
+pub struct Style {
+    pub target: Target,
+    pub types: Case,
+    pub functions: Case,
+    pub fields: Case,
+    pub params: Case,
+    pub variants: Case,
+}
+
+/// Convert type names to target identifiers and rename items whose
+/// identifiers collide, reporting every collision.
+///
+/// Fields, functions and variants keep their names unless they collide, since
+/// generators convert those themselves. Scopes follow Rust: type names are
+/// global (generators import them across modules), functions are per module
+/// (inbound ones per webhook set), fields per struct or variant, parameters
+/// per function and variants per enum. The first claimant keeps its name,
+/// except that types named after their definition site (such as
+/// `#/components/schemas/Pet`) win over derived ones; later claimants get a
+/// numeric suffix. Items declared under `cfg` predicates that exclude each
+/// other, such as `windows` and `not(windows)`, are alternatives and may share
+/// a name. Renamed fields, parameters and variants keep their wire name in a
+/// `serde_rename` annotation, and references to renamed types are rewritten.
+pub fn resolve_collisions(module: &mut Module, style: &Style, diagnostics: &mut Diagnostics) {
+    resolve_types(module, style, diagnostics);
+    resolve_members(module, style, diagnostics);
+}
+
+fn resolve_types(module: &mut Module, style: &Style, diagnostics: &mut Diagnostics) {
+    let mut types = Vec::new();
+    collect_types(module, &mut types);
+
+    // Declared types claim first, then derived ones, each in tree order
+    let mut order: Vec<usize> = (0..types.len()).collect();
+    order.sort_by_key(|&i| !types[i].declared);
+
+    let mut scope = Scope::new(&style.target);
+    let mut renames = HashMap::new();
+    for i in order {
+        let candidate = &types[i];
+        let ident = style.target.ident(&candidate.name, style.types);
+        let cfg = candidate.cfg.as_deref();
+        let claimed = scope.claim(&candidate.name, &ident, cfg, |owner, renamed| {
+            report(
+                diagnostics,
+                candidate.location.clone(),
+                "type",
+                &candidate.name,
+                owner,
+                renamed,
+            );
+        });
+        if claimed != candidate.name {
+            renames.insert(i, claimed);
+        }
+    }
+    if renames.is_empty() {
+        return;
+    }
+
+    // References can only follow a rename when every type of the old name,
+    // such as alternatives for different platforms, got the same new one
+    let mut idents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (i, candidate) in types.iter().enumerate() {
+        let ident = renames.get(&i).unwrap_or(&candidate.name);
+        idents.entry(&candidate.name).or_default().push(ident);
+    }
+    let refs: HashMap<String, String> = renames
+        .iter()
+        .filter(|(i, renamed)| {
+            idents[types[**i].name.as_str()]
+                .iter()
+                .all(|r| r == renamed)
+        })
+        .map(|(i, renamed)| (types[*i].name.clone(), renamed.clone()))
+        .collect();
+
+    rename_types(module, &renames, &mut 0);
+    rename_module_refs(module, &refs);
+}
+
+/// A named type competing for its identifier.
+struct Candidate {
+    name: String,
+    /// Whether the type is named after where it is defined.
+    declared: bool,
+    cfg: Option<String>,
+    location: Option<SourceLocation>,
+}
+
+fn collect_types(module: &Module, types: &mut Vec<Candidate>) {
+    for item in &module.items {
+        if let Item::Type(typ) = item
+            && let Some(name) = &typ.name
+        {
+            let source = typ.metadata.source.as_ref();
+            types.push(Candidate {
+                name: name.clone(),
+                declared: source
+                    .and_then(|s| s.pointer.as_deref())
+                    .is_some_and(|p| p.ends_with(&format!("/{name}"))),
+                cfg: cfg(&typ.annotations).map(str::to_string),
+                location: source.cloned(),
+            });
+        }
+    }
+    for sub in &module.submodules {
+        collect_types(sub, types);
+    }
+}
+
+/// Apply renames keyed by position in [`collect_types`] order.
+fn rename_types(module: &mut Module, renames: &HashMap<usize, String>, index: &mut usize) {
+    for item in &mut module.items {
+        if let Item::Type(typ) = item
+            && typ.name.is_some()
+        {
+            if let Some(renamed) = renames.get(index) {
+                typ.name = Some(renamed.clone());
+            }
+            *index += 1;
+        }
+    }
+    for sub in &mut module.submodules {
+        rename_types(sub, renames, index);
+    }
+}
+
+pub(super) fn rename_module_refs(module: &mut Module, refs: &HashMap<String, String>) {
+    for item in &mut module.items {
+        match item {
+            Item::Type(typ) | Item::Const { typ, .. } => rename_refs(typ, refs),
+            Item::Function(func) => {
+                for arg in &mut func.args {
+                    rename_refs(&mut arg.typ, refs);
+                    rename_annotation_refs(&mut arg.annotations, refs);
+                }
+                rename_refs(&mut func.ret, refs);
+                rename_annotation_refs(&mut func.annotations, refs);
+            }
+        }
+    }
+    for sub in &mut module.submodules {
+        rename_module_refs(sub, refs);
+    }
+}
+
+fn rename_refs(typ: &mut Type, refs: &HashMap<String, String>) {
+    match &mut typ.kind {
+        TypeKind::Ref { name } => {
+            if let Some(renamed) = refs.get(name.as_str()) {
+                name.clone_from(renamed);
+            }
+        }
+        TypeKind::Struct { fields } => {
+            for field in fields {
+                rename_refs(&mut field.typ, refs);
+                rename_annotation_refs(&mut field.annotations, refs);
+            }
+        }
+        TypeKind::Enum { variants } => {
+            for variant in variants {
+                for field in &mut variant.fields {
+                    rename_refs(&mut field.typ, refs);
+                }
+                rename_annotation_refs(&mut variant.annotations, refs);
+            }
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                rename_refs(&mut param.typ, refs);
+            }
+            rename_refs(ret, refs);
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            for member in members {
+                rename_refs(member, refs);
+            }
+        }
+    }
+    for arg in &mut typ.args {
+        rename_refs(arg, refs);
+    }
+    rename_annotation_refs(&mut typ.annotations, refs);
+}
+
+fn rename_annotation_refs(annotations: &mut [Annotation], refs: &HashMap<String, String>) {
+    fn rename_value(value: &mut AnnotationValue, refs: &HashMap<String, String>) {
+        match value {
+            AnnotationValue::Type(typ) => rename_refs(typ, refs),
+            AnnotationValue::List(values) => {
+                for value in values {
+                    rename_value(value, refs);
+                }
+            }
+            _ => {}
+        }
+    }
+    for value in annotations.iter_mut().filter_map(|a| a.value.as_mut()) {
+        rename_value(value, refs);
+    }
+}
+
+fn resolve_members(module: &mut Module, style: &Style, diagnostics: &mut Diagnostics) {
+    // Outbound functions share the module; inbound ones their handler trait
+    let mut functions: HashMap<Option<String>, Scope> = HashMap::new();
+    for item in &mut module.items {
+        match item {
+            Item::Type(typ) => resolve_type_members(typ, style, diagnostics),
+            Item::Function(func) => {
+                let scope = func
+                    .annotations
+                    .iter()
+                    .find(|a| a.kind == "webhook_set")
+                    .and_then(|a| match &a.value {
+                        Some(AnnotationValue::String(set)) => Some(set.clone()),
+                        _ => None,
+                    });
+                let ident = style.target.ident(&func.name, style.functions);
+                let mut renamed = None;
+                let cfg = cfg(&func.annotations);
+                functions
+                    .entry(scope)
+                    .or_insert_with(|| Scope::new(&style.target))
+                    .claim(&func.name, &ident, cfg, |owner, new| {
+                        report(
+                            diagnostics,
+                            func.metadata.source.clone(),
+                            "function",
+                            &func.name,
+                            owner,
+                            new,
+                        );
+                        renamed = Some(new.to_string());
+                    });
+                if let Some(renamed) = renamed {
+                    func.name = renamed;
+                }
+                resolve_params(&mut func.args, style, diagnostics);
+                for arg in &mut func.args {
+                    resolve_type_members(&mut arg.typ, style, diagnostics);
+                }
+                resolve_type_members(&mut func.ret, style, diagnostics);
+            }
+            Item::Const { .. } => {}
+        }
+    }
+    for sub in &mut module.submodules {
+        resolve_members(sub, style, diagnostics);
+    }
+}
+
+/// Resolve fields and variants of `typ` and any inline types within it.
+fn resolve_type_members(typ: &mut Type, style: &Style, diagnostics: &mut Diagnostics) {
+    match &mut typ.kind {
+        TypeKind::Struct { fields } => {
+            resolve_fields(fields, style, diagnostics);
+            for field in fields {
+                resolve_type_members(&mut field.typ, style, diagnostics);
+            }
+        }
+        TypeKind::Enum { variants } => {
+            resolve_variants(variants, typ.metadata.source.as_ref(), style, diagnostics);
+            for variant in variants {
+                resolve_fields(&mut variant.fields, style, diagnostics);
+                for field in &mut variant.fields {
+                    resolve_type_members(&mut field.typ, style, diagnostics);
+                }
+            }
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                resolve_type_members(&mut param.typ, style, diagnostics);
+            }
+            resolve_type_members(ret, style, diagnostics);
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            for member in members {
+                resolve_type_members(member, style, diagnostics);
+            }
+        }
+        TypeKind::Ref { .. } => {}
+    }
+    for arg in &mut typ.args {
+        resolve_type_members(arg, style, diagnostics);
+    }
+}
+
+fn resolve_fields(fields: &mut [Field], style: &Style, diagnostics: &mut Diagnostics) {
+    let mut scope = Scope::new(&style.target);
+    for field in fields {
+        let Some(name) = field.name.clone() else {
+            continue;
+        };
+        let ident = style.target.ident(&name, style.fields);
+        let mut renamed = None;
+        scope.claim(&name, &ident, cfg(&field.annotations), |owner, new| {
+            report(
+                diagnostics,
+                field.metadata.source.clone(),
+                "field",
+                &name,
+                owner,
+                new,
+            );
+            renamed = Some(new.to_string());
+        });
+        if let Some(renamed) = renamed {
+            keep_wire_name(&mut field.annotations, &name);
+            field.name = Some(renamed);
+        }
+    }
+}
+
+fn resolve_params(params: &mut [Param], style: &Style, diagnostics: &mut Diagnostics) {
+    let mut scope = Scope::new(&style.target);
+    for param in params {
+        let Some(name) = param.name.clone() else {
+            continue;
+        };
+        let ident = style.target.ident(&name, style.params);
+        let mut renamed = None;
+        scope.claim(&name, &ident, None, |owner, new| {
+            report(
+                diagnostics,
+                param.metadata.source.clone(),
+                "parameter",
+                &name,
+                owner,
+                new,
+            );
+            renamed = Some(new.to_string());
+        });
+        if let Some(renamed) = renamed {
+            keep_wire_name(&mut param.annotations, &name);
+            param.name = Some(renamed);
+        }
+    }
+}
+
+fn resolve_variants(
+    variants: &mut [Variant],
+    location: Option<&SourceLocation>,
+    style: &Style,
+    diagnostics: &mut Diagnostics,
+) {
+    let mut scope = Scope::new(&style.target);
+    for variant in variants {
+        let ident = style.target.ident(&variant.name, style.variants);
+        // Enum values are clearer than the variant names derived from them
+        let shown = variant
+            .annotations
+            .iter()
+            .find(|a| a.kind == "serde_rename")
+            .and_then(|a| match &a.value {
+                Some(AnnotationValue::String(wire)) => Some(wire.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| variant.name.clone());
+        let mut renamed = None;
+        scope.claim(&shown, &ident, cfg(&variant.annotations), |owner, new| {
+            report(
+                diagnostics,
+                location.cloned(),
+                "variant",
+                &shown,
+                owner,
+                new,
+            );
+            renamed = Some(new.to_string());
+        });
+        if let Some(renamed) = renamed {
+            keep_wire_name(&mut variant.annotations, &variant.name);
+            variant.name = renamed;
+        }
+    }
+}
+
+/// Identifiers claimed in one scope and the source names that claimed them.
+#[derive(Default)]
+pub(super) struct Scope {
+    names: Names,
+    owners: HashMap<String, String>,
+    /// Predicates each identifier was claimed under, `None` for always.
+    conditions: HashMap<String, Vec<Option<String>>>,
+    /// Prefix escaping keywords, dropped from renamed identifiers.
+    raw_prefix: Option<&'static str>,
+}
+
+impl Scope {
+    pub(super) fn new(target: &Target) -> Self {
+        Self {
+            raw_prefix: target.raw_prefix,
+            ..Self::default()
+        }
+    }
+
+    /// Claim `ident` for `name` declared under `cfg`, returning the identifier
+    /// it ends up with.
+    ///
+    /// On a collision `collided` gets the name already holding the identifier
+    /// and the suffixed identifier assigned instead. An identifier only ever
+    /// claimed under predicates [`exclusive`] with `cfg` is shared.
+    pub(super) fn claim(
+        &mut self,
+        name: &str,
+        ident: &str,
+        cfg: Option<&str>,
+        collided: impl FnOnce(&str, &str),
+    ) -> String {
+        let conditions = self.conditions.entry(ident.to_string()).or_default();
+        let alternative = alternative(conditions, cfg);
+        conditions.push(cfg.map(str::to_string));
+        if alternative {
+            return ident.to_string();
+        }
+        let claimed = match self.raw_prefix.and_then(|p| ident.strip_prefix(p)) {
+            // A suffixed keyword is no longer a keyword
+            Some(keyword) if self.names.contains(ident) => {
+                self.names.reserve(keyword);
+                self.names.claim(keyword)
+            }
+            _ => self.names.claim(ident),
+        };
+        if claimed != ident {
+            let owner = self.owners.get(ident).map_or(ident, String::as_str);
+            collided(owner, &claimed);
+        }
+        self.owners.insert(claimed.clone(), name.to_string());
+        claimed
+    }
+
+    /// Reserve `ident` for an item declared under `cfg`, even if taken.
+    pub(super) fn reserve(&mut self, ident: &str, cfg: Option<&str>) {
+        self.names.reserve(ident);
+        self.conditions
+            .entry(ident.to_string())
+            .or_default()
+            .push(cfg.map(str::to_string));
+    }
+
+    /// Reserve `ident` under every predicate `name` was reserved under, if
+    /// it is free or only taken by alternatives under all of them.
+    pub(super) fn reserve_like(&mut self, ident: &str, name: &str) -> bool {
+        let cfgs = self.conditions.get(name).cloned().unwrap_or_default();
+        let taken = self.conditions.get(ident);
+        let free = cfgs.iter().all(|cfg| {
+            !self.names.contains(ident)
+                || taken.is_some_and(|taken| alternative(taken, cfg.as_deref()))
+        });
+        if free {
+            for cfg in &cfgs {
+                self.reserve(ident, cfg.as_deref());
+            }
+        }
+        free
+    }
+}
+
+/// Whether an identifier claimed under `conditions` can be shared with an
+/// item declared under `cfg`.
+fn alternative(conditions: &[Option<String>], cfg: Option<&str>) -> bool {
+    cfg.is_some_and(|cfg| {
+        !conditions.is_empty()
+            && conditions
+                .iter()
+                .all(|c| c.as_deref().is_some_and(|c| exclusive(c, cfg)))
+    })
+}
+
+/// Predicate in a `cfg` annotation.
+pub(super) fn cfg(annotations: &[Annotation]) -> Option<&str> {
+    annotations.iter().find_map(|a| match &a.value {
+        Some(AnnotationValue::String(cfg)) if a.kind == "cfg" => Some(cfg.as_str()),
+        _ => None,
+    })
+}
+
+/// Record `name` as the serialized name unless one is already recorded.
+fn keep_wire_name(annotations: &mut Vec<Annotation>, name: &str) {
+    if !annotations.iter().any(|a| a.kind == "serde_rename") {
+        annotations.push(Annotation::with_string("serde_rename", name));
+    }
+}
+
+fn report(
+    diagnostics: &mut Diagnostics,
+    location: Option<SourceLocation>,
+    kind: &str,
+    name: &str,
+    owner: &str,
+    renamed: &str,
+) {
+    diagnostics.warning(
+        diagnostics::RENAMED,
+        location,
+        format!("{kind} `{name}` collides with `{owner}`; renamed to `{renamed}`"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::naming::{Case, RUST};
+    use rhi_concord_core::{Function, Item, Metadata, Module, Param, Type, TypeKind};
+
+    use super::*;
+
+    const STYLE: Style = Style {
+        target: RUST,
+        types: Case::Pascal,
+        functions: Case::Snake,
+        fields: Case::Snake,
+        params: Case::Snake,
+        variants: Case::Pascal,
+    };
+
+    fn module(items: Vec<Item>) -> Module {
+        Module {
+            name: "api".to_string(),
+            items,
+            submodules: Vec::new(),
+            annotations: Vec::new(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    fn function(name: &str, args: &[&str]) -> Item {
+        Item::Function(Function {
+            name: name.to_string(),
+            params: Vec::new(),
+            args: args
+                .iter()
+                .map(|arg| Param {
+                    name: Some((*arg).to_string()),
+                    typ: Type::reference("String"),
+                    default: None,
+                    annotations: Vec::new(),
+                    metadata: Metadata::default(),
+                })
+                .collect(),
+            ret: Type::reference("Unit"),
+            annotations: Vec::new(),
+            metadata: Metadata::default(),
+        })
+    }
+
+    fn structure(name: &str, cfg: Option<&str>) -> Item {
+        let mut typ = Type {
+            kind: TypeKind::Struct { fields: Vec::new() },
+            name: Some(name.to_string()),
+            ..Type::reference("")
+        };
+        if let Some(cfg) = cfg {
+            typ.annotations.push(Annotation::with_string("cfg", cfg));
+        }
+        Item::Type(typ)
+    }
+
+    fn resolve(module: &mut Module) -> Vec<String> {
+        let mut diagnostics = Diagnostics::default();
+        resolve_collisions(module, &STYLE, &mut diagnostics);
+        diagnostics
+            .iter()
+            .map(|d| format!("{}: {}", d.code, d.message))
+            .collect()
+    }
+
+    fn names(module: &Module) -> Vec<&str> {
+        module.items.iter().filter_map(Item::name).collect()
+    }
+
+    #[test]
+    fn colliding_parameters_are_renamed_and_keep_their_wire_name() {
+        let mut module = module(vec![function("find", &["type", "Type", "limit"])]);
+        let diagnostics = resolve(&mut module);
+
+        let Item::Function(find) = &module.items[0] else {
+            unreachable!();
+        };
+        let args: Vec<_> = find.args.iter().filter_map(|a| a.name.as_deref()).collect();
+        assert_eq!(args, ["type", "type2", "limit"]);
+        assert_eq!(
+            find.args[1].annotations,
+            [Annotation::with_string("serde_rename", "Type")]
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].starts_with("C0011: parameter `Type`"));
+    }
+
+    #[test]
+    fn parameters_of_different_functions_do_not_collide() {
+        let mut module = module(vec![
+            function("find", &["limit"]),
+            function("list", &["limit"]),
+        ]);
+        assert!(resolve(&mut module).is_empty());
+    }
+
+    #[test]
+    fn types_colliding_as_identifiers_get_a_suffix() {
+        let mut module = module(vec![
+            structure("pet_kind", None),
+            structure("PetKind", None),
+        ]);
+        let diagnostics = resolve(&mut module);
+        assert_eq!(names(&module), ["PetKind", "PetKind2"]);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn exclusive_platform_alternatives_share_a_name() {
+        let mut module = module(vec![
+            structure("handle", Some("windows")),
+            structure("handle", Some("not(windows)")),
+            structure("stat", Some("target_os = \"linux\"")),
+            structure("stat", Some("target_os = \"macos\"")),
+        ]);
+        assert!(resolve(&mut module).is_empty());
+        assert_eq!(names(&module), ["Handle", "Handle", "Stat", "Stat"]);
+    }
+
+    #[test]
+    fn overlapping_predicates_are_renamed() {
+        let mut module = module(vec![
+            structure("handle", Some("unix")),
+            structure("handle", Some("feature = \"tls\"")),
+        ]);
+        assert_eq!(resolve(&mut module).len(), 1);
+        assert_eq!(names(&module), ["Handle", "Handle2"]);
+    }
+}
@@ -0,0 +1,249 @@
+//! Confidence levels for lossy conversions and their propagation.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::{Item, Metadata, Module, Type, TypeKind, Value};
+
+use super::intersection::collect_types;
+
+/// A reference whose target does not exist.
+pub const UNRESOLVED: f32 = 0.1;
+/// Rendered as a placeholder that needs a hand-written replacement.
+pub const PLACEHOLDER: f32 = 0.2;
+/// Replaced by `Any`, accepting values the schema would reject.
+pub const FALLBACK_ANY: f32 = 0.3;
+/// Part of an operation's input or output was left out.
+pub const DROPPED: f32 = 0.4;
+/// Replaced by `String`, which only fits some encodings.
+pub const FALLBACK_STRING: f32 = 0.5;
+/// Only the first member of a composition is used.
+pub const FIRST_MEMBER: f32 = 0.5;
+/// Pointer ownership was inferred from names rather than declared.
+pub const OWNERSHIP_GUESSED: f32 = 0.7;
+/// The name was derived rather than declared, so it may change.
+pub const GUESSED_NAME: f32 = 0.8;
+/// An enum was taken for a set of flags from its values alone.
+pub const FLAGS_GUESSED: f32 = 0.8;
+/// Attached to a type as a method by only its name or only its signature.
+pub const METHOD_GUESSED: f32 = 0.6;
+/// A callback was paired with its user-data pointer by position.
+pub const CALLBACK_PAIRED: f32 = 0.9;
+/// Attached to a type as a method by both its name and its signature.
+pub const METHOD_MATCHED: f32 = 0.9;
+
+/// Lower the confidence recorded in `metadata` to at most `confidence`.
+pub fn lower(metadata: &mut Metadata, confidence: f32) {
+    metadata.confidence = Some(
+        metadata
+            .confidence
+            .map_or(confidence, |c| c.min(confidence)),
+    );
+}
+
+/// Lower every named type and function to the weakest confidence among the
+/// types it is built from, following references.
+///
+/// Items lowered this way record the JSON pointer of the weakest node in
+/// `metadata.extra["confidence_source"]`.
+pub fn propagate_confidence(module: &mut Module) {
+    let mut types = HashMap::new();
+    collect_types(module, &mut types);
+    let mut propagator = Propagator {
+        types,
+        memo: HashMap::new(),
+    };
+    propagator.apply(module);
+}
+
+/// Lowest confidence found under a node and where it was found.
+#[derive(Clone)]
+struct Weakest {
+    confidence: f32,
+    pointer: Option<String>,
+}
+
+impl Weakest {
+    fn of(metadata: &Metadata) -> Option<Self> {
+        Some(Self {
+            confidence: metadata.confidence?,
+            pointer: metadata.source.as_ref().and_then(|s| s.pointer.clone()),
+        })
+    }
+
+    fn min(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if b.confidence < a.confidence { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+struct Propagator {
+    types: HashMap<String, Type>,
+    /// Results per named type; `None` while a type is being visited, so
+    /// recursive types terminate.
+    memo: HashMap<String, Option<Weakest>>,
+}
+
+impl Propagator {
+    fn apply(&mut self, module: &mut Module) {
+        for item in &mut module.items {
+            match item {
+                Item::Type(typ) => {
+                    let weakest = match &typ.name {
+                        Some(name) => self.named(name),
+                        None => self.weakest(typ),
+                    };
+                    lower_to(&mut typ.metadata, weakest);
+                }
+                Item::Function(func) => {
+                    let mut weakest = self.weakest(&func.ret);
+                    for arg in &func.args {
+                        weakest = Weakest::min(weakest, Weakest::of(&arg.metadata));
+                        weakest = Weakest::min(weakest, self.weakest(&arg.typ));
+                    }
+                    lower_to(&mut func.metadata, weakest);
+                }
+                Item::Const { .. } => {}
+            }
+        }
+        for sub in &mut module.submodules {
+            self.apply(sub);
+        }
+    }
+
+    fn named(&mut self, name: &str) -> Option<Weakest> {
+        if let Some(known) = self.memo.get(name) {
+            return known.clone();
+        }
+        let typ = self.types.get(name)?.clone();
+        self.memo.insert(name.to_string(), None);
+        let weakest = self.weakest(&typ);
+        self.memo.insert(name.to_string(), weakest.clone());
+        weakest
+    }
+
+    fn weakest(&mut self, typ: &Type) -> Option<Weakest> {
+        let mut weakest = Weakest::of(&typ.metadata);
+        match &typ.kind {
+            TypeKind::Ref { name } => weakest = Weakest::min(weakest, self.named(name)),
+            TypeKind::Struct { fields } => {
+                for field in fields {
+                    weakest = Weakest::min(weakest, Weakest::of(&field.metadata));
+                    weakest = Weakest::min(weakest, self.weakest(&field.typ));
+                }
+            }
+            TypeKind::Enum { variants } => {
+                for field in variants.iter().flat_map(|v| &v.fields) {
+                    weakest = Weakest::min(weakest, self.weakest(&field.typ));
+                }
+            }
+            TypeKind::Function { params, ret } => {
+                for param in params {
+                    weakest = Weakest::min(weakest, self.weakest(&param.typ));
+                }
+                weakest = Weakest::min(weakest, self.weakest(ret));
+            }
+            TypeKind::Union { members } | TypeKind::Intersection { members } => {
+                for member in members {
+                    weakest = Weakest::min(weakest, self.weakest(member));
+                }
+            }
+        }
+        for arg in &typ.args {
+            weakest = Weakest::min(weakest, self.weakest(arg));
+        }
+        weakest
+    }
+}
+
+/// Lower an item to `weakest`, noting where it came from.
+fn lower_to(metadata: &mut Metadata, weakest: Option<Weakest>) {
+    let Some(weakest) = weakest else {
+        return;
+    };
+    if metadata.confidence.is_some_and(|c| c <= weakest.confidence) {
+        return;
+    }
+    metadata.confidence = Some(weakest.confidence);
+    let own = metadata.source.as_ref().and_then(|s| s.pointer.as_ref());
+    if let Some(pointer) = weakest.pointer
+        && own != Some(&pointer)
+    {
+        metadata
+            .extra
+            .insert("confidence_source".to_string(), Value::String(pointer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::openapi::{self, parse_spec};
+
+    const UNRESOLVED_OWNER: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths:
+  /stores:
+    get:
+      operationId: getStore
+      responses:
+        "200":
+          description: ok
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Store'}
+components:
+  schemas:
+    Pet:
+      type: object
+      properties:
+        owner: {$ref: '#/components/schemas/Owner'}
+    Store:
+      type: object
+      properties:
+        pets: {type: array, items: {$ref: '#/components/schemas/Pet'}}
+    Fine:
+      type: object
+      properties:
+        name: {type: string}
+"#;
+
+    #[test]
+    fn confidence_propagates_through_references() {
+        let (module, _) = parse_spec("confidence", UNRESOLVED_OWNER, &openapi::Options::default());
+        let source = Value::String("#/components/schemas/Pet/properties/owner".to_string());
+        let scores: Vec<_> = module
+            .items
+            .iter()
+            .map(|item| {
+                let metadata = match item {
+                    Item::Type(typ) => &typ.metadata,
+                    Item::Function(func) => &func.metadata,
+                    Item::Const { .. } => unreachable!(),
+                };
+                let source = metadata.extra.get("confidence_source");
+                (item.name().unwrap(), metadata.confidence, source)
+            })
+            .collect();
+        assert_eq!(
+            scores,
+            [
+                ("Pet", Some(UNRESOLVED), Some(&source)),
+                ("Store", Some(UNRESOLVED), Some(&source)),
+                ("Fine", None, None),
+                ("getStore", Some(UNRESOLVED), Some(&source)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_keeps_the_weakest_score() {
+        let mut metadata = Metadata::default();
+        lower(&mut metadata, GUESSED_NAME);
+        lower(&mut metadata, DROPPED);
+        lower(&mut metadata, FLAGS_GUESSED);
+        assert_eq!(metadata.confidence, Some(DROPPED));
+    }
+}
@@ -0,0 +1,174 @@
+//! Recognize enums whose variants are bits combined into a set.
+
+use rhi_concord_core::{Annotation, AnnotationValue, Item, Module, Type, TypeKind, Variant};
+
+use super::confidence;
+use crate::diagnostics::{self, Diagnostics};
+
+/// Mark enums whose variants are bits meant to be combined as `flags`.
+///
+/// Enums named in `names` are always flags. Others are when every variant's
+/// `value` is zero, a single bit, or a combination of other variants' bits,
+/// with at least three distinct bits and values that don't simply count up
+/// (`0, 1, 2, 3, 4, 5` is an ordinary enum). Enums flagged by their values
+/// alone are lowered to [`confidence::FLAGS_GUESSED`]. Names that match no
+/// enum are reported as [`diagnostics::UNMATCHED_RULE`] warnings.
+pub fn detect_flags(module: &mut Module, names: &[String], diagnostics: &mut Diagnostics) {
+    let mut matched = vec![false; names.len()];
+    detect_module(module, names, &mut matched);
+    for (name, matched) in names.iter().zip(matched) {
+        if !matched {
+            diagnostics.warning(
+                diagnostics::UNMATCHED_RULE,
+                None,
+                format!("`--flags {name}` matches no enum"),
+            );
+        }
+    }
+}
+
+fn detect_module(module: &mut Module, names: &[String], matched: &mut [bool]) {
+    for item in &mut module.items {
+        if let Item::Type(typ) = item {
+            detect(typ, names, matched);
+        }
+    }
+    for sub in &mut module.submodules {
+        detect_module(sub, names, matched);
+    }
+}
+
+fn detect(typ: &mut Type, names: &[String], matched: &mut [bool]) {
+    let TypeKind::Enum { variants } = &typ.kind else {
+        return;
+    };
+    if typ.annotations.iter().any(|a| a.kind == "flags") {
+        return;
+    }
+    let explicit = names.iter().position(|n| typ.name.as_ref() == Some(n));
+    if let Some(i) = explicit {
+        matched[i] = true;
+    } else if values(variants).is_some_and(|values| is_bit_set(&values)) {
+        confidence::lower(&mut typ.metadata, confidence::FLAGS_GUESSED);
+    } else {
+        return;
+    }
+    typ.annotations.push(Annotation::flag("flags"));
+}
+
+/// The `value` of every variant, if all have one.
+#[allow(clippy::cast_possible_truncation)]
+fn values(variants: &[Variant]) -> Option<Vec<i64>> {
+    variants
+        .iter()
+        .map(|v| {
+            v.annotations.iter().find_map(|a| match a.value {
+                Some(AnnotationValue::Number(n)) if a.kind == "value" => Some(n as i64),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+fn is_bit_set(values: &[i64]) -> bool {
+    let bits = values
+        .iter()
+        .filter(|&&v| v > 0 && v.count_ones() == 1)
+        .fold(0, |bits, v| bits | v);
+    let combined = values.iter().all(|&v| v >= 0 && v & !bits == 0);
+
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let counting = sorted.windows(2).all(|w| w[1] == w[0] + 1);
+    combined && bits.count_ones() >= 3 && !counting
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::Metadata;
+
+    use super::*;
+
+    fn enumeration(name: &str, values: &[i64]) -> Item {
+        #[allow(clippy::cast_precision_loss)]
+        let variants = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Variant {
+                name: format!("V{i}"),
+                fields: Vec::new(),
+                annotations: vec![Annotation::with_number("value", value as f64)],
+            })
+            .collect();
+        Item::Type(Type {
+            kind: TypeKind::Enum { variants },
+            name: Some(name.to_string()),
+            ..Type::reference("")
+        })
+    }
+
+    /// Run [`detect_flags`] over `items`, returning the enums it flagged with
+    /// their confidence.
+    fn detect(names: &[&str], items: Vec<Item>) -> (Vec<(String, Option<f32>)>, Diagnostics) {
+        let mut module = Module {
+            name: "api".to_string(),
+            items,
+            submodules: Vec::new(),
+            annotations: Vec::new(),
+            metadata: Metadata::default(),
+        };
+        let names: Vec<_> = names.iter().map(ToString::to_string).collect();
+        let mut diagnostics = Diagnostics::default();
+        detect_flags(&mut module, &names, &mut diagnostics);
+        let flags = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Type(typ) if typ.annotations.iter().any(|a| a.kind == "flags") => {
+                    Some((typ.name.clone()?, typ.metadata.confidence))
+                }
+                _ => None,
+            })
+            .collect();
+        (flags, diagnostics)
+    }
+
+    #[test]
+    fn bit_sets() {
+        assert!(is_bit_set(&[1, 2, 4]));
+        assert!(is_bit_set(&[0, 1, 2, 4, 8, 15]));
+        assert!(is_bit_set(&[1, 2, 4, 6]));
+        assert!(!is_bit_set(&[1, 2]));
+        assert!(!is_bit_set(&[0, 1, 2, 3, 4, 5]));
+        assert!(!is_bit_set(&[1, 2, 4, 9]));
+        assert!(!is_bit_set(&[-1, 1, 2, 4]));
+    }
+
+    #[test]
+    fn enums_of_bits_are_guessed_to_be_flags() {
+        let (flags, diagnostics) = detect(
+            &[],
+            vec![
+                enumeration("mode", &[1, 2, 4]),
+                enumeration("level", &[0, 1, 2]),
+            ],
+        );
+        assert_eq!(
+            flags,
+            [("mode".to_string(), Some(confidence::FLAGS_GUESSED))]
+        );
+        assert_eq!(diagnostics.iter().count(), 0);
+    }
+
+    #[test]
+    fn named_enums_are_flags_and_unmatched_names_are_reported() {
+        let (flags, diagnostics) = detect(
+            &["level", "missing"],
+            vec![enumeration("level", &[0, 1, 2])],
+        );
+        assert_eq!(flags, [("level".to_string(), None)]);
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [diagnostics::UNMATCHED_RULE]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Merge intersections (`allOf`) into plain structs.
+
+use std::collections::{HashMap, HashSet};
+
+use indexmap::IndexMap;
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Field, Item, Metadata, Module, Type, TypeKind,
+};
+
+use crate::diagnostics::{self, Diagnostics};
+
+/// Replace intersections throughout the tree with structs.
+///
+/// When every member resolves to an object, their fields are merged into one
+/// struct: a field is required if any member requires it, and properties
+/// declared with different types are listed in a `conflicts` annotation (the
+/// first declaration wins) and reported. Otherwise each member becomes a
+/// `flatten` field. Single-member intersections collapse to the member itself.
+pub fn merge_intersections(module: &mut Module, diagnostics: &mut Diagnostics) {
+    let mut types = HashMap::new();
+    collect_types(module, &mut types);
+    let resolver = Resolver { types };
+    resolver.normalize_module(module, diagnostics);
+}
+
+pub(super) fn collect_types(module: &Module, types: &mut HashMap<String, Type>) {
+    for item in &module.items {
+        if let Item::Type(typ) = item
+            && let Some(name) = &typ.name
+        {
+            types.insert(name.clone(), typ.clone());
+        }
+    }
+    for sub in &module.submodules {
+        collect_types(sub, types);
+    }
+}
+
+/// Fields contributed by an object-shaped member.
+struct Object {
+    fields: Vec<Field>,
+    /// Names required without being declared here.
+    required: Vec<String>,
+}
+
+struct Resolver {
+    /// Named types as parsed, before normalization.
+    types: HashMap<String, Type>,
+}
+
+impl Resolver {
+    fn normalize_module(&self, module: &mut Module, diagnostics: &mut Diagnostics) {
+        for item in &mut module.items {
+            match item {
+                Item::Type(typ) => self.normalize(typ, diagnostics),
+                Item::Function(func) => {
+                    for arg in &mut func.args {
+                        self.normalize(&mut arg.typ, diagnostics);
+                    }
+                    self.normalize(&mut func.ret, diagnostics);
+                }
+                Item::Const { .. } => {}
+            }
+        }
+        for sub in &mut module.submodules {
+            self.normalize_module(sub, diagnostics);
+        }
+    }
+
+    fn normalize(&self, typ: &mut Type, diagnostics: &mut Diagnostics) {
+        match &mut typ.kind {
+            TypeKind::Ref { .. } => {}
+            TypeKind::Struct { fields } => {
+                for field in fields {
+                    self.normalize(&mut field.typ, diagnostics);
+                }
+            }
+            TypeKind::Enum { variants } => {
+                for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                    self.normalize(&mut field.typ, diagnostics);
+                }
+            }
+            TypeKind::Function { params, ret } => {
+                for param in params {
+                    self.normalize(&mut param.typ, diagnostics);
+                }
+                self.normalize(ret, diagnostics);
+            }
+            TypeKind::Union { members } | TypeKind::Intersection { members } => {
+                for member in members {
+                    self.normalize(member, diagnostics);
+                }
+            }
+        }
+        for arg in &mut typ.args {
+            self.normalize(arg, diagnostics);
+        }
+
+        let TypeKind::Intersection { members } = &typ.kind else {
+            return;
+        };
+
+        if let [member] = members.as_slice() {
+            let member = member.clone();
+            typ.kind = member.kind;
+            typ.args = member.args;
+            typ.annotations.extend(member.annotations);
+            return;
+        }
+
+        let objects: Option<Vec<Object>> = members
+            .iter()
+            .map(|m| self.object(m, &mut HashSet::new()))
+            .collect();
+        let fields = match objects {
+            Some(objects) => {
+                let (fields, conflicts) = merge_objects(objects);
+                for conflict in &conflicts {
+                    diagnostics.warning(
+                        diagnostics::CONFLICTING_MEMBERS,
+                        typ.metadata.source.clone(),
+                        format!(
+                            "`allOf` members declare `{conflict}` with different types; using the first"
+                        ),
+                    );
+                }
+                if !conflicts.is_empty() {
+                    typ.annotations.push(Annotation {
+                        kind: "conflicts".to_string(),
+                        value: Some(AnnotationValue::List(
+                            conflicts.into_iter().map(AnnotationValue::String).collect(),
+                        )),
+                    });
+                }
+                fields
+            }
+            None => members
+                .iter()
+                .enumerate()
+                .map(|(i, member)| Field {
+                    name: Some(match &member.kind {
+                        TypeKind::Ref { name } => name.clone(),
+                        _ => format!("part{i}"),
+                    }),
+                    typ: member.clone(),
+                    default: None,
+                    annotations: vec![Annotation::flag("flatten")],
+                    metadata: member.metadata.clone(),
+                })
+                .collect(),
+        };
+        typ.kind = TypeKind::Struct { fields };
+    }
+
+    /// Resolve a member to its object fields, or `None` if it is not an object.
+    fn object(&self, typ: &Type, seen: &mut HashSet<String>) -> Option<Object> {
+        let required = typ
+            .annotations
+            .iter()
+            .filter(|a| a.kind == "required")
+            .filter_map(|a| match &a.value {
+                Some(AnnotationValue::List(names)) => Some(names),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|name| match name {
+                AnnotationValue::String(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        match &typ.kind {
+            TypeKind::Struct { fields } => Some(Object {
+                fields: fields.clone(),
+                required,
+            }),
+            TypeKind::Ref { name } if typ.args.is_empty() => {
+                if !seen.insert(name.clone()) {
+                    return None;
+                }
+                let target = self.types.get(name)?;
+                let object = self.object(target, seen);
+                seen.remove(name);
+                object
+            }
+            TypeKind::Intersection { members } => {
+                let objects = members
+                    .iter()
+                    .map(|m| self.object(m, seen))
+                    .collect::<Option<Vec<_>>>()?;
+                let (fields, _) = merge_objects(objects);
+                Some(Object { fields, required })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Merge object fields in declaration order, returning conflicting names.
+fn merge_objects(objects: Vec<Object>) -> (Vec<Field>, Vec<String>) {
+    let required: HashSet<String> = objects
+        .iter()
+        .flat_map(|o| o.required.iter().cloned())
+        .chain(
+            objects
+                .iter()
+                .flat_map(|o| &o.fields)
+                .filter(|f| !is_option(&f.typ))
+                .filter_map(|f| f.name.clone()),
+        )
+        .collect();
+
+    let mut merged: IndexMap<String, Field> = IndexMap::new();
+    let mut conflicts = Vec::new();
+    for field in objects.into_iter().flat_map(|o| o.fields) {
+        let Some(name) = field.name.clone() else {
+            continue;
+        };
+        match merged.get_mut(&name) {
+            Some(existing) => {
+                if same_shape(unwrap_option(&existing.typ), unwrap_option(&field.typ)) {
+                    if existing.default.is_none() {
+                        existing.default = field.default;
+                    }
+                } else if !conflicts.contains(&name) {
+                    conflicts.push(name);
+                }
+            }
+            None => {
+                merged.insert(name, field);
+            }
+        }
+    }
+
+    let fields = merged
+        .into_iter()
+        .map(|(name, mut field)| {
+            let inner = unwrap_option(&field.typ).clone();
+            field.typ = if required.contains(&name) {
+                inner
+            } else {
+                Type::generic("Option", vec![inner])
+            };
+            field
+        })
+        .collect();
+    (fields, conflicts)
+}
+
+fn is_option(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "Option")
+}
+
+fn unwrap_option(typ: &Type) -> &Type {
+    match (&typ.kind, typ.args.as_slice()) {
+        (TypeKind::Ref { name }, [inner]) if name == "Option" => inner,
+        _ => typ,
+    }
+}
+
+/// Compare types structurally, ignoring docs and other metadata.
+fn same_shape(a: &Type, b: &Type) -> bool {
+    strip_metadata(a) == strip_metadata(b)
+}
+
+fn strip_metadata(typ: &Type) -> Type {
+    let mut typ = typ.clone();
+    typ.metadata = Metadata::default();
+    match &mut typ.kind {
+        TypeKind::Ref { .. } => {}
+        TypeKind::Struct { fields } => {
+            for field in fields {
+                field.typ = strip_metadata(&field.typ);
+                field.metadata = Metadata::default();
+            }
+        }
+        TypeKind::Enum { variants } => {
+            for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                field.typ = strip_metadata(&field.typ);
+                field.metadata = Metadata::default();
+            }
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                param.typ = strip_metadata(&param.typ);
+                param.metadata = Metadata::default();
+            }
+            **ret = strip_metadata(ret);
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            for member in members {
+                *member = strip_metadata(member);
+            }
+        }
+    }
+    typ.args = typ.args.iter().map(strip_metadata).collect();
+    typ
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::openapi::{self, parse_spec};
+
+    /// Each field of the struct `name` with its type, and whether it is
+    /// flattened.
+    fn fields(module: &Module, name: &str) -> Vec<(String, String, bool)> {
+        let typ = module
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Type(typ) if typ.name.as_deref() == Some(name) => Some(typ),
+                _ => None,
+            })
+            .unwrap();
+        let TypeKind::Struct { fields } = &typ.kind else {
+            panic!("`{name}` is not a struct");
+        };
+        fields
+            .iter()
+            .map(|f| {
+                let typ = match &f.typ.kind {
+                    TypeKind::Ref { name } if f.typ.args.is_empty() => name.clone(),
+                    TypeKind::Ref { name } => format!("{name}<..>"),
+                    _ => "other".to_string(),
+                };
+                let flatten = f.annotations.iter().any(|a| a.kind == "flatten");
+                (f.name.clone().unwrap_or_default(), typ, flatten)
+            })
+            .collect()
+    }
+
+    fn field(name: &str, typ: &str, flatten: bool) -> (String, String, bool) {
+        (name.to_string(), typ.to_string(), flatten)
+    }
+
+    const ALL_OF: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths: {}
+components:
+  schemas:
+    Base:
+      type: object
+      required: [id]
+      properties:
+        id: {type: integer}
+        note: {type: string}
+    Pet:
+      allOf:
+        - $ref: '#/components/schemas/Base'
+        - type: object
+          required: [name, note]
+          properties:
+            name: {type: string}
+            id: {type: string}
+    Tagged:
+      allOf:
+        - $ref: '#/components/schemas/Base'
+        - type: string
+"#;
+
+    #[test]
+    fn object_members_merge_into_one_struct() {
+        let (module, diagnostics) = parse_spec("all_of", ALL_OF, &openapi::Options::default());
+        assert_eq!(
+            fields(&module, "Pet"),
+            [
+                field("id", "i64", false),
+                field("note", "String", false),
+                field("name", "String", false),
+            ]
+        );
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [diagnostics::CONFLICTING_MEMBERS]);
+    }
+
+    #[test]
+    fn other_members_are_flattened() {
+        let (module, _) = parse_spec("all_of_mixed", ALL_OF, &openapi::Options::default());
+        assert_eq!(
+            fields(&module, "Tagged"),
+            [field("Base", "Base", true), field("String", "String", true)]
+        );
+    }
+}
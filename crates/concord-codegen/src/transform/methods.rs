@@ -0,0 +1,324 @@
+//! Attach free functions of handle-based C APIs to their handle types.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::naming;
+use rhi_concord_core::{
+    Annotation, AnnotationValue, Function, Item, Module, Param, Type, TypeKind,
+};
+
+use super::collision::{Scope, cfg};
+use super::confidence;
+
+/// Final words of functions that free their handle.
+pub(super) const DESTRUCTORS: &[&str] = &[
+    "destroy", "free", "close", "release", "unref", "delete", "finalize", "dispose",
+];
+
+/// Attach functions to the struct types they operate on.
+///
+/// A function whose first argument points to a struct becomes a method of
+/// that struct; otherwise a function named after a struct becomes a static
+/// function of it, the longest name winning. The method is named by what
+/// follows the type's words (`wlr_output_destroy` → `destroy`) or keeps the
+/// function's name when it doesn't start with them. Functions get:
+///
+/// - `method_of`: the struct type, following typedefs
+/// - `receiver`: `static`; `owned` if the handle's `ownership` is `owned` or
+///   the name ends in `_destroy`, `_free` and the like; `ref` for pointers to
+///   const; `mut` otherwise
+/// - `method_name`: unique per type, but for functions declared under
+///   different `cfg` predicates
+///
+/// Attachments supported by both name and signature lower the function's
+/// confidence to [`confidence::METHOD_MATCHED`], those supported by only one
+/// to [`confidence::METHOD_GUESSED`]. Variadic functions stay free, since a
+/// method can't forward their arguments.
+pub fn attach_methods(module: &mut Module) {
+    let mut attacher = Attacher {
+        structs: Structs::collect(module),
+        methods: HashMap::new(),
+    };
+    attacher.attach_module(module);
+}
+
+/// What a named type is, as far as handles are concerned.
+enum Shape {
+    Struct,
+    /// A typedef of another named type.
+    Alias(String),
+    Other,
+}
+
+/// Named types of a module tree, for finding the struct behind a handle.
+pub(super) struct Structs(HashMap<String, Shape>);
+
+impl Structs {
+    pub(super) fn collect(module: &Module) -> Self {
+        let mut structs = Self(HashMap::new());
+        structs.collect_module(module);
+        structs
+    }
+
+    fn collect_module(&mut self, module: &Module) {
+        for item in &module.items {
+            if let Item::Type(typ) = item
+                && let Some(name) = &typ.name
+            {
+                let shape = match &typ.kind {
+                    TypeKind::Struct { .. } => Shape::Struct,
+                    TypeKind::Ref { name } if typ.args.is_empty() => Shape::Alias(name.clone()),
+                    _ => Shape::Other,
+                };
+                self.0.insert(name.clone(), shape);
+            }
+        }
+        for sub in &module.submodules {
+            self.collect_module(sub);
+        }
+    }
+
+    /// The struct a type name denotes, following typedefs.
+    pub(super) fn resolve(&self, name: &str) -> Option<String> {
+        let mut name = name;
+        // Bounded in case of a typedef cycle
+        for _ in 0..16 {
+            match self.0.get(name)? {
+                Shape::Struct => return Some(name.to_string()),
+                Shape::Alias(target) => name = target,
+                Shape::Other => return None,
+            }
+        }
+        None
+    }
+
+    /// The struct a pointer points to, if any.
+    pub(super) fn pointee(&self, typ: &Type) -> Option<String> {
+        match &pointee(typ)?.kind {
+            TypeKind::Ref { name } => self.resolve(name),
+            _ => None,
+        }
+    }
+}
+
+struct Attacher {
+    structs: Structs,
+    /// Method names taken per type.
+    methods: HashMap<String, Scope>,
+}
+
+impl Attacher {
+    fn attach_module(&mut self, module: &mut Module) {
+        for item in &mut module.items {
+            if let Item::Function(func) = item
+                && !func
+                    .annotations
+                    .iter()
+                    .any(|a| a.kind == "method_of" || a.kind == "variadic")
+            {
+                self.attach(func);
+            }
+        }
+        for sub in &mut module.submodules {
+            self.attach_module(sub);
+        }
+    }
+
+    fn attach(&mut self, func: &mut Function) {
+        let words = naming::words(&func.name);
+        let handle = func.args.first().and_then(|arg| {
+            let pointee = pointee(&arg.typ)?;
+            let TypeKind::Ref { name } = &pointee.kind else {
+                return None;
+            };
+            Some((name.clone(), self.structs.resolve(name)?))
+        });
+
+        let named = self.named_owner(&words);
+        let (owner, method, matched, receiver) = match (handle, named) {
+            // The name agrees with the first argument: a method
+            (Some((written, owner)), _)
+                if let Some(method) = strip_type_words(&words, &written)
+                    .or_else(|| strip_type_words(&words, &owner)) =>
+            {
+                let receiver = receiver(&func.args[0], &words);
+                (owner, method, true, receiver)
+            }
+            // Named after another struct, such as a constructor taking its parent
+            (_, Some((owner, method))) => {
+                let returns_handle = self.structs.pointee(&func.ret).as_ref() == Some(&owner);
+                (owner, method, returns_handle, "static")
+            }
+            (Some((_, owner)), None) => {
+                let receiver = receiver(&func.args[0], &words);
+                (owner, func.name.clone(), false, receiver)
+            }
+            (None, None) => return,
+        };
+        let method = self.methods.entry(owner.clone()).or_default().claim(
+            &func.name,
+            &method,
+            cfg(&func.annotations),
+            |_, _| {},
+        );
+
+        func.annotations
+            .push(Annotation::with_type("method_of", Type::reference(&owner)));
+        func.annotations
+            .push(Annotation::with_string("receiver", receiver));
+        func.annotations
+            .push(Annotation::with_string("method_name", &method));
+        let score = if matched {
+            confidence::METHOD_MATCHED
+        } else {
+            confidence::METHOD_GUESSED
+        };
+        confidence::lower(&mut func.metadata, score);
+    }
+
+    /// The struct with the longest name that `words` start with, and the
+    /// words after it.
+    fn named_owner(&self, words: &[String]) -> Option<(String, String)> {
+        self.structs
+            .0
+            .iter()
+            .filter(|(_, shape)| matches!(shape, Shape::Struct))
+            .filter_map(|(name, _)| {
+                strip_type_words(words, name).map(|method| (name.clone(), method))
+            })
+            .max_by_key(|(name, _)| (naming::words(name).len(), name.clone()))
+    }
+}
+
+/// How a method takes its handle.
+fn receiver(arg: &Param, words: &[String]) -> &'static str {
+    let owned = arg.annotations.iter().chain(&arg.typ.annotations).any(|a| {
+        a.kind == "ownership"
+            && matches!(&a.value, Some(AnnotationValue::String(s)) if s == "owned")
+    });
+    let destructor = words
+        .last()
+        .is_some_and(|w| DESTRUCTORS.contains(&w.to_lowercase().as_str()));
+    if owned || destructor {
+        "owned"
+    } else if pointee(&arg.typ).is_some_and(|p| p.annotations.iter().any(|a| a.kind == "const")) {
+        "ref"
+    } else {
+        "mut"
+    }
+}
+
+/// What a `Ptr` points to.
+pub(super) fn pointee(typ: &Type) -> Option<&Type> {
+    match &typ.kind {
+        TypeKind::Ref { name } if name == "Ptr" => typ.args.first(),
+        _ => None,
+    }
+}
+
+/// The words of `name` after those of `type_name`, joined with `_`, if
+/// `name` starts with them. A trailing `t` (`foo_t`) is not required.
+fn strip_type_words(words: &[String], type_name: &str) -> Option<String> {
+    let mut type_words = naming::words(type_name);
+    if type_words.len() > 1 && type_words.last().is_some_and(|w| w == "t") {
+        type_words.pop();
+    }
+    let rest = words.get(type_words.len()..)?;
+    let starts = words
+        .iter()
+        .zip(&type_words)
+        .all(|(w, t)| w.eq_ignore_ascii_case(t));
+    (starts && !rest.is_empty()).then(|| rest.join("_").to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::c::{Options, parse_source};
+
+    /// Each attached function as `name: Owner::method (receiver)`, with its
+    /// confidence.
+    fn attached(source: &str) -> Vec<(String, Option<f32>)> {
+        let (mut module, _) = parse_source("methods", source, &Options::default());
+        attach_methods(&mut module);
+        module
+            .items
+            .iter()
+            .filter_map(|item| {
+                let Item::Function(func) = item else {
+                    return None;
+                };
+                let value = |kind| func.annotations.iter().find(|a| a.kind == kind);
+                let owner = match &value("method_of")?.value {
+                    Some(AnnotationValue::Type(typ)) => match &typ.kind {
+                        TypeKind::Ref { name } => name.clone(),
+                        _ => String::new(),
+                    },
+                    _ => String::new(),
+                };
+                let string = |kind| match value(kind).and_then(|a| a.value.as_ref()) {
+                    Some(AnnotationValue::String(s)) => s.clone(),
+                    _ => String::new(),
+                };
+                Some((
+                    format!(
+                        "{}: {owner}::{} ({})",
+                        func.name,
+                        string("method_name"),
+                        string("receiver")
+                    ),
+                    func.metadata.confidence,
+                ))
+            })
+            .collect()
+    }
+
+    fn method(description: &str, confidence: f32) -> (String, Option<f32>) {
+        (description.to_string(), Some(confidence))
+    }
+
+    #[test]
+    fn functions_attach_to_their_handles() {
+        let methods = attached(
+            "struct wlr_output { int width; };\n\
+             typedef struct wlr_output wlr_output_t;\n\
+             struct wlr_output *wlr_output_create(int width);\n\
+             int wlr_output_count(void);\n\
+             void wlr_output_destroy(struct wlr_output *output);\n\
+             int wlr_output_width(const wlr_output_t *output);\n\
+             void wlr_output_set_width(struct wlr_output *output, int width);\n\
+             void output_log(struct wlr_output *output);\n\
+             void wlr_output_printf(struct wlr_output *output, const char *fmt, ...);\n\
+             int unrelated(int n);\n",
+        );
+        assert_eq!(
+            methods,
+            [
+                method(
+                    "wlr_output_create: wlr_output::create (static)",
+                    confidence::METHOD_MATCHED
+                ),
+                method(
+                    "wlr_output_count: wlr_output::count (static)",
+                    confidence::METHOD_GUESSED
+                ),
+                method(
+                    "wlr_output_destroy: wlr_output::destroy (owned)",
+                    confidence::METHOD_MATCHED
+                ),
+                method(
+                    "wlr_output_width: wlr_output::width (ref)",
+                    confidence::METHOD_MATCHED
+                ),
+                method(
+                    "wlr_output_set_width: wlr_output::set_width (mut)",
+                    confidence::METHOD_MATCHED
+                ),
+                method(
+                    "output_log: wlr_output::output_log (mut)",
+                    confidence::METHOD_GUESSED
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Source-independent IR normalization passes.
+
+mod callbacks;
+mod collision;
+pub mod confidence;
+mod flags;
+mod intersection;
+mod methods;
+mod ownership;
+mod prefix;
+mod union;
+
+pub use callbacks::detect_callbacks;
+pub use collision::{Style, resolve_collisions};
+pub use confidence::propagate_confidence;
+pub use flags::detect_flags;
+pub use intersection::merge_intersections;
+pub use methods::attach_methods;
+pub use ownership::{OwnershipRule, infer_ownership};
+pub use prefix::{PrefixRule, strip_prefixes};
+pub use union::name_unions;
@@ -0,0 +1,343 @@
+//! Infer who owns the pointers passed to and returned from C functions.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use rhi_concord_core::naming;
+use rhi_concord_core::{Annotation, AnnotationValue, Function, Item, Module, Type, TypeKind};
+
+use super::confidence;
+use super::intersection::collect_types;
+use super::methods::{DESTRUCTORS, Structs};
+use crate::diagnostics::{self, Diagnostics};
+
+/// Final words of functions that return a new handle the caller owns.
+const CONSTRUCTORS: &[&str] = &["create", "new", "open", "alloc", "dup", "copy", "clone"];
+
+/// A declared ownership or nullability for one pointer of a function.
+#[derive(Debug, Clone)]
+pub struct OwnershipRule {
+    /// Function name, before or after prefix stripping.
+    pub function: String,
+    /// Argument name, or `None` for the return value.
+    pub arg: Option<String>,
+    /// `owned`, `borrowed`, `nullable` or `nonnull`.
+    pub value: String,
+}
+
+impl OwnershipRule {
+    /// Parse `FUNCTION[.ARG]=VALUE`, where `FUNCTION.return` and `FUNCTION`
+    /// both name the return value.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((target, value)) = spec.split_once('=') else {
+            bail!("Expected `FUNCTION[.ARG]=VALUE` in ownership rule `{spec}`");
+        };
+        if !matches!(value, "owned" | "borrowed" | "nullable" | "nonnull") {
+            bail!(
+                "Unknown ownership `{value}` in `{spec}`; expected owned, borrowed, nullable or nonnull"
+            );
+        }
+        let (function, arg) = match target.split_once('.') {
+            Some((function, "return")) => (function, None),
+            Some((function, arg)) => (function, Some(arg.to_string())),
+            None => (target, None),
+        };
+        Ok(Self {
+            function: function.to_string(),
+            arg,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Annotate pointers to structs with who owns them.
+///
+/// `rules` are applied first and always win. Then, for pointers whose
+/// `ownership` is still unknown:
+///
+/// - a function taking a single pointer to a struct and ending in `_destroy`,
+///   `_free` and the like (or whose argument is already `owned`) becomes that
+///   struct's `destructor`, recorded by C symbol, and its argument is `owned`
+/// - pointers returned by functions ending in `_create`, `_new`, `_open` and
+///   the like are `owned` when their struct has a destructor
+/// - other pointers to structs with a destructor are `borrowed`
+///
+/// Ownership is a `String` annotation on the `Ptr` type; nullability is a
+/// `nullable` or `nonnull` flag next to it. Functions given an ownership by
+/// these heuristics are lowered to [`confidence::OWNERSHIP_GUESSED`]. Rules
+/// that match no function or argument are reported as
+/// [`diagnostics::UNMATCHED_RULE`] warnings.
+pub fn infer_ownership(
+    module: &mut Module,
+    rules: &[OwnershipRule],
+    diagnostics: &mut Diagnostics,
+) {
+    let mut types = HashMap::new();
+    collect_types(module, &mut types);
+    let mut matched = vec![false; rules.len()];
+    for_each_function(module, &mut |func| {
+        apply_rules(func, rules, &types, &mut matched);
+    });
+    for (rule, matched) in rules.iter().zip(matched) {
+        if !matched {
+            let target = rule.arg.as_deref().unwrap_or("return");
+            diagnostics.warning(
+                diagnostics::UNMATCHED_RULE,
+                None,
+                format!(
+                    "Ownership rule for `{}.{target}` matches no pointer",
+                    rule.function
+                ),
+            );
+        }
+    }
+
+    let structs = Structs::collect(module);
+    let mut destructors = HashMap::new();
+    for_each_function(module, &mut |func| {
+        if let Some(owner) = destructor_of(func, &structs) {
+            let arg = &mut func.args[0].typ;
+            if ownership(arg).is_none() {
+                arg.annotations
+                    .push(Annotation::with_string("ownership", "owned"));
+                confidence::lower(&mut func.metadata, confidence::OWNERSHIP_GUESSED);
+            }
+            let symbol = symbol(func).unwrap_or(&func.name).to_string();
+            destructors.entry(owner).or_insert(symbol);
+        }
+    });
+    for_each_function(module, &mut |func| infer(func, &structs, &destructors));
+    mark_destructors(module, &destructors);
+}
+
+fn for_each_function(module: &mut Module, f: &mut impl FnMut(&mut Function)) {
+    for item in &mut module.items {
+        if let Item::Function(func) = item {
+            f(func);
+        }
+    }
+    for sub in &mut module.submodules {
+        for_each_function(sub, f);
+    }
+}
+
+fn apply_rules(
+    func: &mut Function,
+    rules: &[OwnershipRule],
+    types: &HashMap<String, Type>,
+    matched: &mut [bool],
+) {
+    let symbol = symbol(func).map(String::from);
+    for (rule, matched) in rules.iter().zip(matched) {
+        if rule.function != func.name && Some(&rule.function) != symbol.as_ref() {
+            continue;
+        }
+        let typ = match &rule.arg {
+            Some(name) => match func.args.iter_mut().find(|a| a.name.as_ref() == Some(name)) {
+                Some(arg) => &mut arg.typ,
+                None => continue,
+            },
+            None => &mut func.ret,
+        };
+        if !is_pointer(typ, types, 0) {
+            continue;
+        }
+        *matched = true;
+        let opposite = match rule.value.as_str() {
+            "owned" | "borrowed" => "ownership",
+            "nullable" => "nonnull",
+            _ => "nullable",
+        };
+        typ.annotations
+            .retain(|a| a.kind != opposite && a.kind != rule.value);
+        typ.annotations.push(match opposite {
+            "ownership" => Annotation::with_string("ownership", &rule.value),
+            _ => Annotation::flag(&rule.value),
+        });
+    }
+}
+
+/// The struct `func` releases, if it takes a single pointer to one and is
+/// named or declared as releasing it.
+fn destructor_of(func: &Function, structs: &Structs) -> Option<String> {
+    let [arg] = func.args.as_slice() else {
+        return None;
+    };
+    let owner = structs.pointee(&arg.typ)?;
+    let releases = match ownership(&arg.typ) {
+        Some(ownership) => ownership == "owned",
+        None => last_word_in(&func.name, DESTRUCTORS),
+    };
+    releases.then_some(owner)
+}
+
+fn infer(func: &mut Function, structs: &Structs, destructors: &HashMap<String, String>) {
+    let constructor = last_word_in(&func.name, CONSTRUCTORS);
+    let mut guessed = false;
+    let ret = (
+        &mut func.ret,
+        if constructor { "owned" } else { "borrowed" },
+    );
+    let args = func.args.iter_mut().map(|arg| (&mut arg.typ, "borrowed"));
+    for (typ, inferred) in std::iter::once(ret).chain(args) {
+        let handle = structs
+            .pointee(typ)
+            .is_some_and(|owner| destructors.contains_key(&owner));
+        if handle && ownership(typ).is_none() {
+            typ.annotations
+                .push(Annotation::with_string("ownership", inferred));
+            guessed = true;
+        }
+    }
+    if guessed {
+        confidence::lower(&mut func.metadata, confidence::OWNERSHIP_GUESSED);
+    }
+}
+
+fn mark_destructors(module: &mut Module, destructors: &HashMap<String, String>) {
+    for item in &mut module.items {
+        if let Item::Type(typ) = item
+            && let Some(destructor) = typ.name.as_ref().and_then(|n| destructors.get(n))
+            && !typ.annotations.iter().any(|a| a.kind == "destructor")
+        {
+            typ.annotations
+                .push(Annotation::with_string("destructor", destructor));
+        }
+    }
+    for sub in &mut module.submodules {
+        mark_destructors(sub, destructors);
+    }
+}
+
+/// The C symbol recorded by prefix stripping, if any.
+fn symbol(func: &Function) -> Option<&str> {
+    func.annotations.iter().find_map(|a| match &a.value {
+        Some(AnnotationValue::String(s)) if a.kind == "c_symbol" => Some(s.as_str()),
+        _ => None,
+    })
+}
+
+fn ownership(typ: &Type) -> Option<&str> {
+    typ.annotations.iter().find_map(|a| match &a.value {
+        Some(AnnotationValue::String(s)) if a.kind == "ownership" => Some(s.as_str()),
+        _ => None,
+    })
+}
+
+/// Whether `typ` is a pointer, through typedefs such as callback types.
+fn is_pointer(typ: &Type, types: &HashMap<String, Type>, depth: usize) -> bool {
+    match &typ.kind {
+        TypeKind::Ref { name } if name == "Ptr" => true,
+        // Bounded in case of a typedef cycle
+        TypeKind::Ref { name } if depth <= 16 && typ.args.is_empty() => types
+            .get(name)
+            .is_some_and(|t| is_pointer(t, types, depth + 1)),
+        _ => false,
+    }
+}
+
+fn last_word_in(name: &str, words: &[&str]) -> bool {
+    naming::words(name)
+        .last()
+        .is_some_and(|w| words.contains(&w.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::c::{Options, parse_source};
+
+    /// The ownership and nullability annotations of each function's pointers,
+    /// as `function.arg: annotations`.
+    fn pointers(source: &str, rules: &[&str]) -> (Vec<String>, Module, Diagnostics) {
+        let (mut module, mut diagnostics) = parse_source("ownership", source, &Options::default());
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|r| OwnershipRule::parse(r).unwrap())
+            .collect();
+        infer_ownership(&mut module, &rules, &mut diagnostics);
+        let mut pointers = Vec::new();
+        for item in &module.items {
+            let Item::Function(func) = item else {
+                continue;
+            };
+            let positions = func
+                .args
+                .iter()
+                .map(|a| (a.name.clone().unwrap_or_default(), &a.typ))
+                .chain([("return".to_string(), &func.ret)]);
+            for (name, typ) in positions {
+                let annotations: Vec<_> = typ
+                    .annotations
+                    .iter()
+                    .filter_map(|a| match &a.value {
+                        Some(AnnotationValue::String(s)) if a.kind == "ownership" => {
+                            Some(s.clone())
+                        }
+                        None if a.kind == "nullable" || a.kind == "nonnull" => Some(a.kind.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if !annotations.is_empty() {
+                    pointers.push(format!("{}.{name}: {}", func.name, annotations.join(" ")));
+                }
+            }
+        }
+        (pointers, module, diagnostics)
+    }
+
+    const WIDGETS: &str = "struct widget { int n; };\n\
+                           struct widget *widget_new(void);\n\
+                           void widget_destroy(struct widget *w);\n\
+                           int widget_size(struct widget *w);\n\
+                           struct widget *widget_parent(struct widget *w);\n";
+
+    #[test]
+    fn destructors_and_constructors_are_inferred() {
+        let (pointers, module, _) = pointers(WIDGETS, &[]);
+        assert_eq!(
+            pointers,
+            [
+                "widget_new.return: owned",
+                "widget_destroy.w: owned",
+                "widget_size.w: borrowed",
+                "widget_parent.w: borrowed",
+                "widget_parent.return: borrowed",
+            ]
+        );
+        let Some(Item::Type(widget)) = module.items.first() else {
+            panic!("expected the struct first");
+        };
+        assert!(
+            widget
+                .annotations
+                .contains(&Annotation::with_string("destructor", "widget_destroy"))
+        );
+    }
+
+    #[test]
+    fn rules_win_and_unmatched_rules_are_reported() {
+        let (pointers, _, diagnostics) = pointers(
+            WIDGETS,
+            &[
+                "widget_parent=owned",
+                "widget_size.w=nonnull",
+                "widget_gone.w=owned",
+            ],
+        );
+        assert!(pointers.contains(&"widget_parent.return: owned".to_string()));
+        assert!(pointers.contains(&"widget_size.w: nonnull borrowed".to_string()));
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [diagnostics::UNMATCHED_RULE]);
+    }
+
+    #[test]
+    fn rules_parse() {
+        let rule = OwnershipRule::parse("f.return=nullable").unwrap();
+        assert_eq!((rule.function.as_str(), rule.arg), ("f", None));
+        let rule = OwnershipRule::parse("f.arg=owned").unwrap();
+        assert_eq!(rule.arg.as_deref(), Some("arg"));
+        assert!(OwnershipRule::parse("f=maybe").is_err());
+        assert!(OwnershipRule::parse("f").is_err());
+    }
+}
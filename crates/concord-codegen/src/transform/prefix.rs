@@ -0,0 +1,389 @@
+//! Strip C library prefixes and group prefixed items into submodules.
+
+use std::collections::HashMap;
+
+use rhi_concord_core::naming::Names;
+use rhi_concord_core::{Annotation, Item, Metadata, Module, TypeKind, Variant};
+
+use super::collision::{Scope, cfg, rename_module_refs};
+
+/// A library prefix such as `wlr_`, optionally mapped to a submodule.
+#[derive(Debug, Clone)]
+pub struct PrefixRule {
+    /// Prefix matched case-insensitively at the start of names.
+    pub prefix: String,
+    /// Submodule receiving the items whose names carry the prefix.
+    pub module: Option<String>,
+}
+
+impl PrefixRule {
+    /// Parse `PREFIX` or `PREFIX=MODULE`.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once('=') {
+            Some((prefix, module)) => Self {
+                prefix: prefix.to_string(),
+                module: Some(module.to_string()).filter(|m| !m.is_empty()),
+            },
+            None => Self {
+                prefix: spec.to_string(),
+                module: None,
+            },
+        }
+    }
+}
+
+/// Strip library prefixes from type, function, constant and enum variant
+/// names, then move items whose rule names a module into that submodule.
+///
+/// The longest matching prefix wins. `wlr_output` and `WLR_OUTPUT_MODE_ALL`
+/// both match `wlr_`; a prefix without a trailing `_` only matches at a word
+/// boundary (`xkb` matches `xkb_keymap` and `XkbKeymap`, `SDL` doesn't match
+/// `SDLK_a`). Enum variants also lose the words all of them share, so
+/// `WLR_OUTPUT_MODE_CURRENT` becomes `CURRENT`. A name is kept when stripping
+/// would leave nothing, a leading digit, or a name already taken in its
+/// scope by other than [`cfg` alternatives](super::resolve_collisions); a
+/// name declared under several predicates is stripped for all of them or
+/// none. Functions record their original name in a `c_symbol` annotation
+/// for the generator to link against, and references follow renamed types.
+pub fn strip_prefixes(module: &mut Module, rules: &[PrefixRule]) {
+    if rules.is_empty() {
+        return;
+    }
+    let mut types = Scope::default();
+    reserve_types(module, &mut types);
+    let mut refs = HashMap::new();
+    strip_module(module, rules, &mut types, &mut refs);
+    rename_module_refs(module, &refs);
+}
+
+fn reserve_types(module: &Module, types: &mut Scope) {
+    for item in &module.items {
+        if let Item::Type(typ) = item
+            && let Some(name) = &typ.name
+        {
+            types.reserve(name, cfg(&typ.annotations));
+        }
+    }
+    for sub in &module.submodules {
+        reserve_types(sub, types);
+    }
+}
+
+fn strip_module(
+    module: &mut Module,
+    rules: &[PrefixRule],
+    types: &mut Scope,
+    refs: &mut HashMap<String, String>,
+) {
+    // Functions and constants share a scope per module
+    let mut values = Scope::default();
+    for item in &module.items {
+        let annotations = match item {
+            Item::Function(func) => &func.annotations,
+            Item::Const { annotations, .. } => annotations,
+            Item::Type(_) => continue,
+        };
+        values.reserve(item.name().unwrap_or_default(), cfg(annotations));
+    }
+    let mut renamed = HashMap::new();
+
+    let mut groups: Vec<(String, Vec<Item>)> = Vec::new();
+    let mut kept = Vec::with_capacity(module.items.len());
+    for mut item in std::mem::take(&mut module.items) {
+        let rule = item
+            .name()
+            .and_then(|name| matching_rule(name, rules))
+            .map(|(rule, stripped)| (rule, stripped.to_string()));
+        match &mut item {
+            Item::Type(typ) => {
+                if let TypeKind::Enum { variants } = &mut typ.kind {
+                    strip_variants(variants, rules);
+                }
+                if let (Some(name), Some((_, stripped))) = (&mut typ.name, &rule)
+                    && let Some(stripped) = rename(types, refs, name, stripped)
+                {
+                    *name = stripped;
+                }
+            }
+            Item::Function(func) => {
+                if !func.annotations.iter().any(|a| a.kind == "c_symbol") {
+                    func.annotations
+                        .push(Annotation::with_string("c_symbol", &func.name));
+                }
+                if let Some((_, stripped)) = &rule
+                    && let Some(stripped) = rename(&mut values, &mut renamed, &func.name, stripped)
+                {
+                    func.name = stripped;
+                }
+            }
+            Item::Const { name, .. } => {
+                if let Some((_, stripped)) = &rule
+                    && let Some(stripped) = rename(&mut values, &mut renamed, name, stripped)
+                {
+                    *name = stripped;
+                }
+            }
+        }
+        match rule.and_then(|(rule, _)| rule.module.as_ref()) {
+            Some(target) => match groups.iter_mut().find(|(m, _)| m == target) {
+                Some((_, items)) => items.push(item),
+                None => groups.push((target.clone(), vec![item])),
+            },
+            None => kept.push(item),
+        }
+    }
+    module.items = kept;
+
+    for sub in &mut module.submodules {
+        strip_module(sub, rules, types, refs);
+    }
+    for (name, items) in groups {
+        match module.submodules.iter_mut().find(|m| m.name == name) {
+            Some(sub) => sub.items.extend(items),
+            None => module.submodules.push(Module {
+                name,
+                items,
+                submodules: Vec::new(),
+                annotations: Vec::new(),
+                metadata: Metadata::default(),
+            }),
+        }
+    }
+}
+
+/// The name `name` takes in `scope` when stripped to `stripped`, if it is
+/// free there. The choice is made once in `renamed` for every declaration of
+/// `name`, so `cfg` alternatives stay alike.
+fn rename(
+    scope: &mut Scope,
+    renamed: &mut HashMap<String, String>,
+    name: &str,
+    stripped: &str,
+) -> Option<String> {
+    if let Some(stripped) = renamed.get(name) {
+        return Some(stripped.clone());
+    }
+    if !scope.reserve_like(stripped, name) {
+        return None;
+    }
+    renamed.insert(name.to_string(), stripped.to_string());
+    Some(stripped.to_string())
+}
+
+/// The longest rule whose prefix `name` carries, and the name without it.
+fn matching_rule<'r, 'n>(
+    name: &'n str,
+    rules: &'r [PrefixRule],
+) -> Option<(&'r PrefixRule, &'n str)> {
+    rules
+        .iter()
+        .filter_map(|rule| strip(name, &rule.prefix).map(|stripped| (rule, stripped)))
+        .max_by_key(|(rule, _)| rule.prefix.len())
+}
+
+/// `name` without `prefix` and the underscores after it, if it starts with
+/// `prefix` at a word boundary and something usable is left.
+fn strip<'n>(name: &'n str, prefix: &str) -> Option<&'n str> {
+    let head = name.get(..prefix.len())?;
+    if prefix.is_empty() || !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = &name[prefix.len()..];
+    let boundary = prefix.ends_with('_')
+        || rest.starts_with('_')
+        || (rest.starts_with(char::is_uppercase) && !head.ends_with(char::is_uppercase));
+    let rest = rest.trim_start_matches('_');
+    (boundary && rest.starts_with(char::is_alphabetic)).then_some(rest)
+}
+
+/// Strip the library prefix and then the words shared by every variant.
+fn strip_variants(variants: &mut [Variant], rules: &[PrefixRule]) {
+    let stripped: Vec<&str> = variants
+        .iter()
+        .map(|v| matching_rule(&v.name, rules).map_or(v.name.as_str(), |(_, s)| s))
+        .collect();
+
+    let shared = if stripped.len() > 1 {
+        shared_words(&stripped)
+    } else {
+        0
+    };
+    let renamed: Vec<String> = stripped
+        .iter()
+        .map(|name| {
+            name.splitn(shared + 1, '_')
+                .last()
+                .unwrap_or(name)
+                .to_string()
+        })
+        .collect();
+
+    let mut taken = Names::default();
+    for (variant, name) in variants.iter_mut().zip(renamed) {
+        if !taken.contains(&name) {
+            taken.reserve(&name);
+            variant.name = name;
+        }
+    }
+}
+
+/// Number of leading `_`-separated words all `names` share, leaving each of
+/// them a remainder that starts with a letter.
+fn shared_words(names: &[&str]) -> usize {
+    let words: Vec<Vec<&str>> = names.iter().map(|n| n.split('_').collect()).collect();
+    let mut shared = 0;
+    while words.iter().all(|w| {
+        w.len() > shared + 1
+            && w[shared] == words[0][shared]
+            && w[shared + 1].starts_with(char::is_alphabetic)
+    }) {
+        shared += 1;
+    }
+    shared
+}
+
+#[cfg(test)]
+mod tests {
+    use rhi_concord_core::{AnnotationValue, Type};
+
+    use super::*;
+    use crate::parser::c::{Options, parse_source};
+
+    /// Names of the items of `module` and its submodules, with the variants
+    /// of enums and the `c_symbol` of functions.
+    fn names(module: &Module) -> Vec<String> {
+        let mut names = Vec::new();
+        for item in &module.items {
+            let name = item.name().unwrap_or_default();
+            names.push(match item {
+                Item::Type(Type {
+                    kind: TypeKind::Enum { variants },
+                    ..
+                }) => {
+                    let variants: Vec<_> = variants.iter().map(|v| v.name.as_str()).collect();
+                    format!("{name} {{ {} }}", variants.join(", "))
+                }
+                Item::Function(func) => {
+                    let symbol = func.annotations.iter().find_map(|a| match &a.value {
+                        Some(AnnotationValue::String(s)) if a.kind == "c_symbol" => Some(s),
+                        _ => None,
+                    });
+                    format!("{name}() = {}", symbol.map_or("-", String::as_str))
+                }
+                _ => name.to_string(),
+            });
+        }
+        for sub in &module.submodules {
+            names.extend(names_in(sub));
+        }
+        names
+    }
+
+    fn names_in(module: &Module) -> Vec<String> {
+        names(module)
+            .into_iter()
+            .map(|name| format!("{}::{name}", module.name))
+            .collect()
+    }
+
+    #[test]
+    fn prefixes_are_stripped_and_grouped() {
+        let (mut module, _) = parse_source(
+            "prefixes",
+            "typedef struct wlr_output wlr_output;\n\
+             enum wlr_output_mode { WLR_OUTPUT_MODE_CURRENT, WLR_OUTPUT_MODE_ALL };\n\
+             wlr_output *wlr_output_create(void);\n\
+             typedef struct xkb_keymap xkb_keymap;\n\
+             typedef struct xkb_state xkb_state;\n\
+             typedef struct XkbContext XkbContext;\n\
+             typedef struct xkbcommon xkbcommon;\n\
+             typedef struct keymap keymap;\n\
+             typedef struct wlr_keymap wlr_keymap;\n",
+            &Options::default(),
+        );
+        strip_prefixes(
+            &mut module,
+            &[PrefixRule::parse("wlr_=wlr"), PrefixRule::parse("xkb")],
+        );
+        assert_eq!(
+            names(&module),
+            [
+                // `keymap` is taken
+                "xkb_keymap",
+                "state",
+                "Context",
+                "xkbcommon",
+                "keymap",
+                "wlr::output",
+                "wlr::output_mode { CURRENT, ALL }",
+                "wlr::output_create() = wlr_output_create",
+                "wlr::wlr_keymap",
+            ]
+        );
+    }
+
+    #[test]
+    fn cfg_alternatives_are_stripped_alike() {
+        let options = Options {
+            platforms: vec!["_WIN32".to_string()],
+            ..Options::default()
+        };
+        let (mut module, _) = parse_source(
+            "alternatives",
+            "#ifdef _WIN32\n\
+             typedef unsigned long long mylib_handle;\n\
+             typedef int mylib_thing;\n\
+             typedef int thing;\n\
+             typedef int mylib_other;\n\
+             void mylib_poll(mylib_handle h);\n\
+             #else\n\
+             typedef int mylib_handle;\n\
+             typedef int mylib_thing;\n\
+             typedef int other;\n\
+             void mylib_poll(mylib_handle h);\n\
+             #endif\n\
+             void mylib_close(mylib_handle h);\n",
+            &options,
+        );
+        strip_prefixes(&mut module, &[PrefixRule::parse("mylib_")]);
+        assert_eq!(
+            names(&module),
+            [
+                "handle",
+                // `thing` is taken on Windows
+                "mylib_thing",
+                "thing",
+                "other",
+                "poll() = mylib_poll",
+                "handle",
+                "mylib_thing",
+                "other",
+                "poll() = mylib_poll",
+                "close() = mylib_close",
+            ]
+        );
+        let Some(Item::Function(close)) = module.items.last() else {
+            panic!("expected `close` last");
+        };
+        assert_eq!(close.args[0].typ, Type::reference("handle"));
+    }
+
+    #[test]
+    fn rules_parse_an_optional_module() {
+        let rule = PrefixRule::parse("wlr_=wlr");
+        assert_eq!(
+            (rule.prefix.as_str(), rule.module.as_deref()),
+            ("wlr_", Some("wlr"))
+        );
+        let rule = PrefixRule::parse("SDL_=");
+        assert_eq!(
+            (rule.prefix.as_str(), rule.module.as_deref()),
+            ("SDL_", None)
+        );
+        let rule = PrefixRule::parse("xkb");
+        assert_eq!(
+            (rule.prefix.as_str(), rule.module.as_deref()),
+            ("xkb", None)
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Name anonymous unions so they can be rendered as enums.
+
+use rhi_concord_core::naming::{self, Names};
+use rhi_concord_core::{Item, Metadata, Module, Type, TypeKind};
+
+/// Hoist anonymous unions out of field, parameter and return positions.
+///
+/// Each union becomes a named type derived from where it appears:
+/// `{Owner}{Field}`, `{Owner}{Variant}{Field}`, `{Function}{Param}` or
+/// `{Function}Response`, with a numeric suffix if the name is taken. Nested
+/// anonymous unions are flattened, members of the same shape are kept once and
+/// `Null` members turn the position into an `Option`; a union left with one
+/// member is replaced by that member.
+pub fn name_unions(module: &mut Module) {
+    let mut taken = Names::default();
+    collect_names(module, &mut taken);
+    let mut hoister = Hoister {
+        taken,
+        hoisted: Vec::new(),
+    };
+    hoister.name_module(module);
+}
+
+fn collect_names(module: &Module, taken: &mut Names) {
+    for item in &module.items {
+        if let Some(name) = item.name() {
+            taken.reserve(name);
+        }
+    }
+    for sub in &module.submodules {
+        collect_names(sub, taken);
+    }
+}
+
+struct Hoister {
+    /// Names already used anywhere in the tree.
+    taken: Names,
+    /// Unions hoisted from the item being visited.
+    hoisted: Vec<Item>,
+}
+
+impl Hoister {
+    fn name_module(&mut self, module: &mut Module) {
+        let mut items = Vec::with_capacity(module.items.len());
+        for mut item in std::mem::take(&mut module.items) {
+            match &mut item {
+                Item::Type(typ) => {
+                    if let Some(owner) = typ.name.clone() {
+                        self.visit_named(typ, &owner);
+                    }
+                    // Hoisted unions follow the type using them
+                    items.push(item);
+                    items.append(&mut self.hoisted);
+                }
+                Item::Function(func) => {
+                    let owner = naming::to_pascal_case(&func.name);
+                    for arg in &mut func.args {
+                        let name = arg.name.as_deref().unwrap_or("Arg");
+                        self.visit(
+                            &mut arg.typ,
+                            &format!("{owner}{}", naming::to_pascal_case(name)),
+                        );
+                    }
+                    self.visit(&mut func.ret, &format!("{owner}Response"));
+                    // Hoisted unions precede the function using them
+                    items.append(&mut self.hoisted);
+                    items.push(item);
+                }
+                Item::Const { .. } => items.push(item),
+            }
+        }
+        module.items = items;
+
+        for sub in &mut module.submodules {
+            self.name_module(sub);
+        }
+    }
+
+    /// Visit the children of a named type, which keeps its own name.
+    fn visit_named(&mut self, typ: &mut Type, owner: &str) {
+        match &mut typ.kind {
+            TypeKind::Union { members } => {
+                flatten_members(members);
+                for member in members.iter_mut() {
+                    self.visit_children(member, owner);
+                }
+                // `T | null` is just an optional `T`
+                if let [a, b] = members.as_slice()
+                    && (is_null(a) || is_null(b))
+                {
+                    let inner = if is_null(a) { b.clone() } else { a.clone() };
+                    typ.kind = TypeKind::Ref {
+                        name: "Option".to_string(),
+                    };
+                    typ.args = vec![inner];
+                } else if let [member] = members.as_mut_slice() {
+                    let member = std::mem::replace(member, Type::reference("Null"));
+                    typ.kind = member.kind;
+                    typ.args = member.args;
+                }
+            }
+            _ => self.visit_children(typ, owner),
+        }
+    }
+
+    fn visit_children(&mut self, typ: &mut Type, owner: &str) {
+        match &mut typ.kind {
+            TypeKind::Struct { fields } => {
+                for field in fields {
+                    let name = field.name.as_deref().unwrap_or("Field");
+                    self.visit(
+                        &mut field.typ,
+                        &format!("{owner}{}", naming::to_pascal_case(name)),
+                    );
+                }
+            }
+            TypeKind::Enum { variants } => {
+                for variant in variants {
+                    let prefix = format!("{owner}{}", naming::to_pascal_case(&variant.name));
+                    for field in &mut variant.fields {
+                        let name = field.name.as_deref().unwrap_or("");
+                        self.visit(
+                            &mut field.typ,
+                            &format!("{prefix}{}", naming::to_pascal_case(name)),
+                        );
+                    }
+                }
+            }
+            TypeKind::Ref { .. }
+            | TypeKind::Function { .. }
+            | TypeKind::Union { .. }
+            | TypeKind::Intersection { .. } => {}
+        }
+        for arg in &mut typ.args {
+            self.visit(arg, owner);
+        }
+
+        // An optional nullable union needs only one `Option`
+        if is_option(typ)
+            && let [inner] = typ.args.as_slice()
+            && is_option(inner)
+        {
+            typ.args = inner.args.clone();
+        }
+    }
+
+    /// Visit a type in a field, parameter or return position named `name`.
+    fn visit(&mut self, typ: &mut Type, name: &str) {
+        if typ.name.is_some() {
+            return;
+        }
+        let TypeKind::Union { members } = &mut typ.kind else {
+            self.visit_children(typ, name);
+            return;
+        };
+
+        flatten_members(members);
+        let nullable = members.iter().any(is_null);
+        members.retain(|m| !is_null(m));
+
+        let mut inner = match members.len() {
+            0 => Type::reference("Null"),
+            1 => {
+                let mut member = members.remove(0);
+                self.visit(&mut member, name);
+                member
+            }
+            _ => {
+                for member in members.iter_mut() {
+                    self.visit_children(member, name);
+                }
+                let hoisted_name = self.taken.claim(name);
+                let mut hoisted = typ.clone();
+                hoisted.name = Some(hoisted_name.clone());
+                self.hoisted.push(Item::Type(hoisted));
+                Type::reference(hoisted_name)
+            }
+        };
+        if typ.metadata.docs.is_some() && inner.metadata.docs.is_none() {
+            inner.metadata.docs.clone_from(&typ.metadata.docs);
+        }
+
+        *typ = if nullable && !is_null(&inner) {
+            Type::generic("Option", vec![inner])
+        } else {
+            inner
+        };
+    }
+}
+
+/// Splice members of nested anonymous unions into their parent, dropping
+/// members that repeat an earlier one's shape.
+fn flatten_members(members: &mut Vec<Type>) {
+    let mut flat = Vec::with_capacity(members.len());
+    for member in members.drain(..) {
+        match member.kind {
+            TypeKind::Union {
+                members: mut nested,
+            } if member.name.is_none() => {
+                flatten_members(&mut nested);
+                flat.extend(nested);
+            }
+            _ => flat.push(member),
+        }
+    }
+
+    // Untagged deserialization could never pick the later of two equal members
+    members.clear();
+    for member in flat {
+        let key = shape(&member);
+        if !members.iter().any(|m| shape(m) == key) {
+            members.push(member);
+        }
+    }
+}
+
+/// A copy of `typ` without docs, source locations or confidence.
+fn shape(typ: &Type) -> Type {
+    let mut shape = typ.clone();
+    clear_metadata(&mut shape);
+    shape
+}
+
+fn clear_metadata(typ: &mut Type) {
+    typ.metadata = Metadata::default();
+    for arg in &mut typ.args {
+        clear_metadata(arg);
+    }
+    match &mut typ.kind {
+        TypeKind::Ref { .. } => {}
+        TypeKind::Struct { fields } => {
+            for field in fields {
+                field.metadata = Metadata::default();
+                clear_metadata(&mut field.typ);
+            }
+        }
+        TypeKind::Enum { variants } => {
+            for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                field.metadata = Metadata::default();
+                clear_metadata(&mut field.typ);
+            }
+        }
+        TypeKind::Function { params, ret } => {
+            for param in params {
+                param.metadata = Metadata::default();
+                clear_metadata(&mut param.typ);
+            }
+            clear_metadata(ret);
+        }
+        TypeKind::Union { members } | TypeKind::Intersection { members } => {
+            for member in members {
+                clear_metadata(member);
+            }
+        }
+    }
+}
+
+fn is_option(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "Option")
+}
+
+fn is_null(typ: &Type) -> bool {
+    matches!(&typ.kind, TypeKind::Ref { name } if name == "Null")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::openapi::{self, parse_spec};
+
+    /// `typ` in a compact form: `Option<String>`, `String | i64`.
+    fn show(typ: &Type) -> String {
+        match &typ.kind {
+            TypeKind::Ref { name } if typ.args.is_empty() => name.clone(),
+            TypeKind::Ref { name } => {
+                let args: Vec<_> = typ.args.iter().map(show).collect();
+                format!("{name}<{}>", args.join(", "))
+            }
+            TypeKind::Union { members } => {
+                let members: Vec<_> = members.iter().map(show).collect();
+                members.join(" | ")
+            }
+            TypeKind::Struct { fields } => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name.as_deref().unwrap_or("_"), show(&f.typ)))
+                    .collect();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            _ => "other".to_string(),
+        }
+    }
+
+    const UNIONS: &str = r#"
+openapi: 3.0.0
+info: {title: t, version: "1"}
+paths: {}
+components:
+  schemas:
+    Dup:
+      oneOf: [{type: string}, {type: string, description: other}]
+    NullDup:
+      oneOf: [{type: string}, {type: string}, {type: "null"}]
+    Pet:
+      type: object
+      properties:
+        a:
+          oneOf: [{type: string}, {type: string}]
+        b:
+          nullable: true
+          oneOf: [{type: integer}, {type: integer}, {$ref: '#/components/schemas/Dup'}]
+        c:
+          oneOf: [{type: integer}, {type: string}, {type: integer}]
+"#;
+
+    #[test]
+    fn unions_are_named_deduplicated_and_fold_null() {
+        let (mut module, _) = parse_spec("unions", UNIONS, &openapi::Options::default());
+        name_unions(&mut module);
+        let types: Vec<_> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Type(typ) => Some((typ.name.clone()?, show(typ))),
+                _ => None,
+            })
+            .collect();
+        let expected = [
+            ("Dup", "String"),
+            ("NullDup", "Option<String>"),
+            (
+                "Pet",
+                "{ a: Option<String>, b: Option<PetB>, c: Option<PetC> }",
+            ),
+            ("PetB", "i64 | Dup"),
+            ("PetC", "i64 | String"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, typ)| ((*name).to_string(), (*typ).to_string()))
+            .collect();
+        assert_eq!(types, expected);
+    }
+}
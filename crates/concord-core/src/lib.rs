@@ -9,6 +9,8 @@
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+pub mod naming;
+
 /// A type in the IR.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Type {
@@ -87,8 +89,13 @@ pub struct Field {
     pub name: Option<String>,
     #[serde(rename = "type")]
     pub typ: Type,
+    /// Value assumed when the field is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub annotations: Vec<Annotation>,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
 }
 
 /// A variant in an enum.
@@ -114,6 +121,8 @@ pub struct Param {
     pub default: Option<Value>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub annotations: Vec<Annotation>,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
 }
 
 /// A runtime value (for defaults, constants).
@@ -122,6 +131,8 @@ pub struct Param {
 pub enum Value {
     Null,
     Bool(bool),
+    /// An integer known exactly, such as a C constant.
+    Int(i128),
     Number(f64),
     String(String),
     List(Vec<Value>),
@@ -169,6 +180,8 @@ pub enum Item {
         #[serde(rename = "type")]
         typ: Type,
         value: Value,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        annotations: Vec<Annotation>,
     },
 }
 
@@ -201,6 +214,9 @@ impl Metadata {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SourceLocation {
     pub file: String,
+    /// JSON pointer into the source document, e.g. `#/paths/~1pets/get`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub line: Option<u32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -268,3 +284,128 @@ impl Annotation {
         }
     }
 }
+
+// Traversal
+
+impl Type {
+    /// Call `f` with the name of every type this type references.
+    ///
+    /// Walks generic arguments, fields, variants, members, function
+    /// signatures and type-valued annotations.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        if let TypeKind::Ref { name } = &self.kind {
+            f(name);
+        }
+        for arg in &self.args {
+            arg.visit_refs(f);
+        }
+        match &self.kind {
+            TypeKind::Ref { .. } => {}
+            TypeKind::Struct { fields } => {
+                for field in fields {
+                    field.visit_refs(f);
+                }
+            }
+            TypeKind::Enum { variants } => {
+                for variant in variants {
+                    for field in &variant.fields {
+                        field.visit_refs(f);
+                    }
+                    for annotation in &variant.annotations {
+                        annotation.visit_refs(f);
+                    }
+                }
+            }
+            TypeKind::Function { params, ret } => {
+                for param in params {
+                    param.visit_refs(f);
+                }
+                ret.visit_refs(f);
+            }
+            TypeKind::Union { members } | TypeKind::Intersection { members } => {
+                for member in members {
+                    member.visit_refs(f);
+                }
+            }
+        }
+        for annotation in &self.annotations {
+            annotation.visit_refs(f);
+        }
+    }
+}
+
+impl Field {
+    /// Call `f` with the name of every type this field references.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        self.typ.visit_refs(f);
+        for annotation in &self.annotations {
+            annotation.visit_refs(f);
+        }
+    }
+}
+
+impl Param {
+    /// Call `f` with the name of every type this parameter references.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        self.typ.visit_refs(f);
+        for annotation in &self.annotations {
+            annotation.visit_refs(f);
+        }
+    }
+}
+
+impl Function {
+    /// Call `f` with the name of every type this function references.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        for arg in &self.args {
+            arg.visit_refs(f);
+        }
+        self.ret.visit_refs(f);
+        for annotation in &self.annotations {
+            annotation.visit_refs(f);
+        }
+    }
+}
+
+impl Annotation {
+    /// Call `f` with the name of every type referenced by a type-valued annotation.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        if let Some(value) = &self.value {
+            value.visit_refs(f);
+        }
+    }
+}
+
+impl AnnotationValue {
+    fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        match self {
+            Self::Type(typ) => typ.visit_refs(f),
+            Self::List(values) => {
+                for value in values {
+                    value.visit_refs(f);
+                }
+            }
+            Self::String(_) | Self::Number(_) | Self::Bool(_) => {}
+        }
+    }
+}
+
+impl Item {
+    /// Call `f` with the name of every type this item references.
+    pub fn visit_refs<F: FnMut(&str)>(&self, f: &mut F) {
+        match self {
+            Self::Type(typ) | Self::Const { typ, .. } => typ.visit_refs(f),
+            Self::Function(func) => func.visit_refs(f),
+        }
+    }
+
+    /// The declared name of this item, if any.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Type(typ) => typ.name.as_deref(),
+            Self::Function(func) => Some(&func.name),
+            Self::Const { name, .. } => Some(name),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Identifier naming shared by parsers and generators.
+//!
+//! Source names (`HTTPServer`, `user_id`, `application/json`, `+1`) are split
+//! into words, recased, then made valid for a target language by [`Target`].
+//! Conversions are deterministic so regenerating produces the same names.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Split a name into words.
+///
+/// Words break at separators (whitespace and most punctuation), at
+/// lower-to-upper transitions (`userID` → `user`, `ID`) and before the last
+/// capital of an acronym followed by lowercase (`HTTPServer` → `HTTP`,
+/// `Server`). Digits stay with the word they follow (`base64`, `v2`).
+/// Symbols that distinguish values are spelled out (`+1` → `plus`, `1`;
+/// `-1` → `minus`, `1`) so they don't collapse into the same name.
+#[must_use]
+pub fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let chars: Vec<char> = s.chars().collect();
+
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1).copied();
+
+        if !c.is_alphanumeric() {
+            flush(&mut word, &mut words);
+            let leading_minus = c == '-'
+                && prev.is_none_or(|p| !p.is_alphanumeric())
+                && next.is_some_and(|n| n.is_ascii_digit());
+            if leading_minus {
+                words.push("minus".to_string());
+            } else if let Some(name) = symbol_name(c) {
+                words.push(name.to_string());
+            }
+            continue;
+        }
+
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+        if boundary {
+            flush(&mut word, &mut words);
+        }
+        word.push(c);
+    }
+    flush(&mut word, &mut words);
+    words
+}
+
+fn flush(word: &mut String, words: &mut Vec<String>) {
+    if !word.is_empty() {
+        words.push(std::mem::take(word));
+    }
+}
+
+/// Word for a symbol that carries meaning in enum values and operators.
+fn symbol_name(c: char) -> Option<&'static str> {
+    Some(match c {
+        '+' => "plus",
+        '*' => "star",
+        '%' => "percent",
+        '&' => "and",
+        '|' => "or",
+        '@' => "at",
+        '#' => "hash",
+        '=' => "eq",
+        '<' => "lt",
+        '>' => "gt",
+        '!' => "not",
+        '$' => "dollar",
+        '~' => "tilde",
+        '^' => "caret",
+        _ => return None,
+    })
+}
+
+/// `snake_case`.
+#[must_use]
+pub fn to_snake_case(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `SCREAMING_SNAKE_CASE`.
+#[must_use]
+pub fn to_screaming_snake_case(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|w| w.to_uppercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `PascalCase`, with acronyms as ordinary words (`HttpServer`, `UserId`).
+///
+/// Adjacent words that would run digits together are joined with `_`
+/// (`v1_2`), keeping `1.2` and `12` apart.
+#[must_use]
+pub fn to_pascal_case(s: &str) -> String {
+    let mut out = String::new();
+    for word in words(s) {
+        if out.ends_with(|c: char| c.is_numeric()) && word.starts_with(char::is_numeric) {
+            out.push('_');
+        }
+        out.push_str(&capitalize(&word));
+    }
+    out
+}
+
+/// `camelCase`.
+#[must_use]
+pub fn to_camel_case(s: &str) -> String {
+    let pascal = to_pascal_case(s);
+    let mut chars = pascal.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_lowercase().chain(chars).collect()
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect()
+    })
+}
+
+/// Identifier casing conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Snake,
+    ScreamingSnake,
+    Pascal,
+    Camel,
+}
+
+impl Case {
+    /// Convert `s` to this case.
+    #[must_use]
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Self::Snake => to_snake_case(s),
+            Self::ScreamingSnake => to_screaming_snake_case(s),
+            Self::Pascal => to_pascal_case(s),
+            Self::Camel => to_camel_case(s),
+        }
+    }
+}
+
+/// Identifier rules of a target language.
+#[derive(Debug, Clone, Copy)]
+pub struct Target {
+    /// Words that can't be used as plain identifiers.
+    pub keywords: &'static [&'static str],
+    /// Prefix that makes a keyword usable as an identifier, e.g. `r#`.
+    pub raw_prefix: Option<&'static str>,
+    /// Keywords the raw prefix can't escape; these get a trailing `_`.
+    pub non_raw: &'static [&'static str],
+    /// Whether identifiers must be ASCII.
+    pub ascii_only: bool,
+}
+
+/// Rust 2024: strict and reserved keywords, including `gen`.
+pub const RUST: Target = Target {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do",
+        "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized", "virtual",
+        "yield",
+    ],
+    raw_prefix: Some("r#"),
+    non_raw: &["self", "Self", "super", "crate", "_"],
+    ascii_only: true,
+};
+
+impl Target {
+    /// Whether `s` is reserved in this target.
+    #[must_use]
+    pub fn is_keyword(&self, s: &str) -> bool {
+        self.keywords.contains(&s) || self.non_raw.contains(&s)
+    }
+
+    /// Convert `s` to `case` and make it a valid identifier.
+    ///
+    /// Non-ASCII letters are folded or escaped as `uXXXX` where required, a
+    /// leading digit gets a `_` prefix, an empty result becomes `empty`, and
+    /// keywords are escaped.
+    #[must_use]
+    pub fn ident(&self, s: &str, case: Case) -> String {
+        let source = if self.ascii_only {
+            fold_ascii(s)
+        } else {
+            s.to_string()
+        };
+        let mut ident = case.apply(&source);
+        if ident.is_empty() {
+            ident = case.apply("empty");
+        }
+        if ident.starts_with(|c: char| c.is_numeric()) {
+            ident.insert(0, '_');
+        }
+        self.escape(&ident)
+    }
+
+    /// Escape `ident` if it is a keyword.
+    #[must_use]
+    pub fn escape(&self, ident: &str) -> String {
+        if self.non_raw.contains(&ident) {
+            return format!("{ident}_");
+        }
+        match self.raw_prefix {
+            Some(prefix) if self.keywords.contains(&ident) => format!("{prefix}{ident}"),
+            None if self.keywords.contains(&ident) => format!("{ident}_"),
+            _ => ident.to_string(),
+        }
+    }
+}
+
+/// Replace non-ASCII characters: common Latin letters lose their accents,
+/// anything else alphanumeric becomes a `uXXXX` word.
+fn fold_ascii(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if let Some(folded) = fold_latin(c) {
+            out.push_str(&folded);
+        } else if c.is_alphanumeric() {
+            let _ = write!(out, " u{:04x} ", u32::from(c));
+        } else {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+fn fold_latin(c: char) -> Option<String> {
+    let folded = match c.to_lowercase().next()? {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'ç' => "c",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'ñ' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'ý' | 'ÿ' => "y",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        _ => return None,
+    };
+    Some(if c.is_uppercase() {
+        capitalize(folded)
+    } else {
+        folded.to_string()
+    })
+}
+
+/// Names claimed within one scope.
+///
+/// The first claimant keeps a name; later ones get the lowest free numeric
+/// suffix starting at 2 (`Pet`, `Pet2`, `Pet3`), so results depend only on
+/// claim order.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    taken: HashSet<String>,
+}
+
+impl Names {
+    /// Mark `name` as used without renaming it.
+    pub fn reserve(&mut self, name: &str) {
+        self.taken.insert(name.to_string());
+    }
+
+    /// Whether `name` is already used.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.taken.contains(name)
+    }
+
+    /// Claim `base`, or the first free suffixed variant of it.
+    pub fn claim(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 2;
+        while self.taken.contains(&name) {
+            name = format!("{base}{n}");
+            n += 1;
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_split_at_case_and_separators() {
+        assert_eq!(words("HTTPServer"), ["HTTP", "Server"]);
+        assert_eq!(words("userID"), ["user", "ID"]);
+        assert_eq!(words("user_id"), ["user", "id"]);
+        assert_eq!(words("application/json"), ["application", "json"]);
+        assert_eq!(words("base64Data"), ["base64", "Data"]);
+        assert_eq!(words("+1"), ["plus", "1"]);
+        assert_eq!(words("-1"), ["minus", "1"]);
+        assert_eq!(words("a-1"), ["a", "1"]);
+    }
+
+    #[test]
+    fn cases() {
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_screaming_snake_case("userId"), "USER_ID");
+        assert_eq!(to_pascal_case("user_id"), "UserId");
+        assert_eq!(to_pascal_case("v1.2"), "V1_2");
+        assert_eq!(to_pascal_case("v12"), "V12");
+        assert_eq!(to_camel_case("HTTP server"), "httpServer");
+    }
+
+    #[test]
+    fn rust_identifiers_are_valid() {
+        assert_eq!(RUST.ident("type", Case::Snake), "r#type");
+        assert_eq!(RUST.ident("self", Case::Snake), "self_");
+        assert_eq!(RUST.ident("Self", Case::Pascal), "Self_");
+        assert_eq!(RUST.ident("gen", Case::Snake), "r#gen");
+        assert_eq!(RUST.ident("2fa", Case::Snake), "_2fa");
+        assert_eq!(RUST.ident("", Case::Pascal), "Empty");
+        assert_eq!(RUST.ident("café", Case::Pascal), "Cafe");
+        assert_eq!(RUST.ident("名前", Case::Snake), "u540d_u524d");
+        assert!(RUST.is_keyword("crate"));
+        assert!(!RUST.is_keyword("pet"));
+    }
+
+    #[test]
+    fn names_get_the_lowest_free_suffix() {
+        let mut names = Names::default();
+        names.reserve("Pet2");
+        assert_eq!(names.claim("Pet"), "Pet");
+        assert_eq!(names.claim("Pet"), "Pet3");
+        assert_eq!(names.claim("Pet"), "Pet4");
+        assert!(names.contains("Pet2"));
+    }
+}